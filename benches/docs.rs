@@ -0,0 +1,67 @@
+/*!
+Benchmark merging heavily documented re-exports. Every re-export appears on
+both sides of a conflict, with docs that either match, extend each other, or
+differ, so that each of the ways docs are merged is exercised.
+ */
+
+use std::fmt::Write as _;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use usefix::{fix_file, MergeOptions};
+
+/// A file with `count` documented re-exports on each side of a conflict
+fn documented_reexports(count: usize) -> String {
+    let mut left = String::new();
+    let mut right = String::new();
+
+    for index in 0..count {
+        let docs: String = (0..8)
+            .map(|line| {
+                format!("/// Line {line} of the docs for `Item{index}`, which re-exports it\n")
+            })
+            .collect();
+
+        writeln!(
+            left,
+            "{docs}pub use crate::module{}::Item{index};",
+            index % 20
+        )
+        .unwrap();
+
+        match index % 3 {
+            0 => writeln!(right, "{docs}pub use crate::module{}::Item{index};", index % 20),
+            1 => writeln!(
+                right,
+                "{docs}/// More docs from the other branch\npub use crate::module{}::Item{index};",
+                index % 20
+            ),
+            _ => writeln!(
+                right,
+                "/// Entirely different docs for `Item{index}`\npub use crate::module{}::Item{index};",
+                index % 20
+            ),
+        }
+        .unwrap();
+    }
+
+    format!("<<<<<<< HEAD\n{left}=======\n{right}>>>>>>> branch\n\nfn main() {{}}\n")
+}
+
+fn merge_docs(c: &mut Criterion) {
+    let options = MergeOptions::default();
+    let mut group = c.benchmark_group("documented re-exports");
+    group.sample_size(20);
+
+    for count in [100, 500] {
+        let input = documented_reexports(count);
+
+        group.bench_with_input(BenchmarkId::from_parameter(count), &input, |b, input| {
+            b.iter(|| fix_file(input, &options).expect("the input can be fixed"))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, merge_docs);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+/*
+C declarations for the usefix library, built from the `usefix-ffi` crate as
+`libusefix_ffi`. See `src/lib.rs` for details.
+ */
+
+#ifndef USEFIX_H
+#define USEFIX_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum UsefixStatus {
+    USEFIX_OK = 0,
+    USEFIX_INVALID_ARGUMENT = 1,
+    USEFIX_INVALID_OPTIONS = 2,
+    USEFIX_MALFORMED_CONFLICT = 3,
+    USEFIX_NO_CONFLICTS = 4,
+    USEFIX_RUST_SYNTAX = 5,
+    USEFIX_UNSUPPORTED = 6,
+    USEFIX_FORMATTER = 7,
+    USEFIX_PANIC = 8,
+    USEFIX_DENIED_WILDCARD = 9,
+    USEFIX_INTERNAL = 10,
+} UsefixStatus;
+
+/*
+Fix a file containing git conflicts. `input` is the UTF-8 content of the file,
+and `args` is an array of NUL-terminated command line arguments (like
+`--hoist`), parsed like the arguments to the `usefix` command line tool.
+
+On success, `*output` is set to the fixed file; on failure, it's set to a
+description of the error. Either way, it must be freed with `usefix_free`.
+ */
+UsefixStatus usefix_merge(
+    const uint8_t *input,
+    size_t input_len,
+    const char *const *args,
+    size_t args_len,
+    uint8_t **output,
+    size_t *output_len
+);
+
+/* Free a buffer returned by `usefix_merge`. */
+void usefix_free(uint8_t *ptr, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* USEFIX_H */
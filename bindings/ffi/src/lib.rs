@@ -0,0 +1,173 @@
+/*!
+A C-compatible interface to usefix, for tools written in other languages that
+would rather link against usefix than spawn a process for each file. See
+`include/usefix.h` for the C declarations.
+
+All strings are UTF-8 buffers with an explicit length; they don't need to be
+NUL-terminated. Options are passed as an array of command line arguments
+(like `["--hoist", "--cfg-order", "complexity"]`), which are parsed exactly
+like the arguments to the `usefix` command line tool.
+ */
+
+use std::{
+    ffi::{c_char, CStr},
+    panic, ptr, slice, str,
+};
+
+use usefix::{fix_file, Error, MergeOptions};
+
+/// The result of a call to `usefix_merge`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsefixStatus {
+    /// The file was fixed successfully
+    Ok = 0,
+
+    /// A pointer argument was null, or the input wasn't valid UTF-8
+    InvalidArgument = 1,
+
+    /// The options couldn't be parsed, or were inconsistent with each other
+    InvalidOptions = 2,
+
+    /// The git conflict markers in the file were malformed
+    MalformedConflict = 3,
+
+    /// The file didn't contain any conflicts, and `--require-conflict` was
+    /// given
+    NoConflicts = 4,
+
+    /// One version of the file wasn't valid rust
+    RustSyntax = 5,
+
+    /// The file contains something that usefix can't safely rewrite, or a
+    /// conflict outside of the region given by `--managed-region`
+    Unsupported = 6,
+
+    /// The external formatter failed
+    Formatter = 7,
+
+    /// usefix panicked. This is always a bug.
+    Panic = 8,
+
+    /// The merged file would have a glob import from only one side of a
+    /// conflict, and `--wildcards deny` was given
+    DeniedWildcard = 9,
+
+    /// usefix reached a state it thought was impossible. This is always a
+    /// bug.
+    Internal = 10,
+}
+
+impl From<&Error> for UsefixStatus {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::MalformedConflict { .. } => UsefixStatus::MalformedConflict,
+            Error::NoConflicts => UsefixStatus::NoConflicts,
+            Error::RustSyntax { .. } => UsefixStatus::RustSyntax,
+            Error::Unsupported { .. } => UsefixStatus::Unsupported,
+            Error::ConflictOutsideRegion { .. } => UsefixStatus::Unsupported,
+            // Only import models can cause this, and they aren't exposed here
+            Error::NoSection { .. } => UsefixStatus::Unsupported,
+            Error::DeniedWildcard { .. } => UsefixStatus::DeniedWildcard,
+            Error::Formatter { .. } => UsefixStatus::Formatter,
+            Error::InvalidOptions(_) => UsefixStatus::InvalidOptions,
+            Error::Internal { .. } => UsefixStatus::Internal,
+            // Errors added to usefix after this status was defined
+            _ => UsefixStatus::Unsupported,
+        }
+    }
+}
+
+/// Fix a file containing git conflicts.
+///
+/// On success, `*output` and `*output_len` are set to the fixed file, and
+/// `UsefixStatus::Ok` is returned. On failure, they're set to a description
+/// of the error instead (also UTF-8). Either way, the output must be freed
+/// with `usefix_free`.
+///
+/// # Safety
+///
+/// - `input` must point to `input_len` readable bytes.
+/// - `args` must point to `args_len` pointers, each of which points to a
+///   NUL-terminated string. `args` may be null if `args_len` is 0.
+/// - `output` and `output_len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn usefix_merge(
+    input: *const u8,
+    input_len: usize,
+    args: *const *const c_char,
+    args_len: usize,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> UsefixStatus {
+    if output.is_null() || output_len.is_null() {
+        return UsefixStatus::InvalidArgument;
+    }
+
+    let (status, message) = match panic::catch_unwind(|| merge(input, input_len, args, args_len)) {
+        Ok(Ok(fixed)) => (UsefixStatus::Ok, fixed),
+        Ok(Err((status, message))) => (status, message),
+        Err(_) => (UsefixStatus::Panic, "usefix panicked".to_owned()),
+    };
+
+    let message: Box<[u8]> = message.into_bytes().into_boxed_slice();
+    *output_len = message.len();
+    *output = Box::into_raw(message).cast();
+
+    status
+}
+
+/// Free a buffer returned by `usefix_merge`.
+///
+/// # Safety
+///
+/// `ptr` and `len` must have been returned together by `usefix_merge`, and
+/// must not have been freed already. `ptr` may be null, in which case this
+/// does nothing.
+#[no_mangle]
+pub unsafe extern "C" fn usefix_free(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+}
+
+/// The safe(ish) part of `usefix_merge`
+unsafe fn merge(
+    input: *const u8,
+    input_len: usize,
+    args: *const *const c_char,
+    args_len: usize,
+) -> Result<String, (UsefixStatus, String)> {
+    let invalid_argument = |message: &str| (UsefixStatus::InvalidArgument, message.to_owned());
+
+    if input.is_null() {
+        return Err(invalid_argument("input was null"));
+    }
+
+    if args.is_null() && args_len > 0 {
+        return Err(invalid_argument("args was null"));
+    }
+
+    let input = str::from_utf8(slice::from_raw_parts(input, input_len))
+        .map_err(|_| invalid_argument("input wasn't valid UTF-8"))?;
+
+    let args = match args_len {
+        0 => &[],
+        _ => slice::from_raw_parts(args, args_len),
+    };
+
+    let args = args
+        .iter()
+        .map(|&arg| match arg.is_null() {
+            true => Err(invalid_argument("an argument was null")),
+            false => CStr::from_ptr(arg)
+                .to_str()
+                .map_err(|_| invalid_argument("an argument wasn't valid UTF-8")),
+        })
+        .collect::<Result<Vec<&str>, _>>()?;
+
+    let options = MergeOptions::from_args(args)
+        .map_err(|err| (UsefixStatus::InvalidOptions, err.to_string()))?;
+
+    fix_file(input, &options).map_err(|err| (UsefixStatus::from(&err), err.full_message()))
+}
@@ -0,0 +1,204 @@
+/*!
+Node.js bindings for usefix. Options are passed as an object, with keys
+named after the command line flags in camelCase (so `--cfg-order complexity`
+is `{ cfgOrder: "complexity" }`):
+
+```js
+const usefix = require("usefix");
+
+const fixed = usefix.merge(source, { hoist: true, sortOrder: ["locality", "root"] });
+```
+ */
+
+use std::collections::HashMap;
+
+use napi::{bindgen_prelude::Either4, Error, Status};
+use napi_derive::napi;
+use usefix::{ConflictedFile, ImportLeaf, MergeOptions};
+
+/// An option value: `true` is a flag, `false` is omitted, and arrays are
+/// joined with commas.
+type OptionValue = Option<Either4<bool, u32, String, Vec<String>>>;
+
+/// The result of `report`
+#[napi(object)]
+pub struct Report {
+    /// True if the file contained git conflicts
+    pub conflicted: bool,
+
+    /// The fixed file
+    pub output: String,
+
+    /// The formatted use items for each section of the file
+    pub sections: Vec<String>,
+
+    /// The merged imports, one for each imported name
+    pub imports: Vec<Import>,
+}
+
+/// A single imported name; see `usefix::Import`
+#[napi(object)]
+pub struct Import {
+    pub rooted: bool,
+    pub path: Vec<String>,
+
+    /// The imported name, or `*` for a wildcard
+    pub name: String,
+    pub alias: Option<String>,
+    pub cfgs: Vec<String>,
+    pub visibility: Option<String>,
+    pub docs: Vec<String>,
+
+    /// The path to the inline module containing this import
+    pub module: Vec<String>,
+    pub left: bool,
+    pub right: bool,
+
+    /// The (one-indexed) lines of the original file this import came from
+    pub lines: Vec<u32>,
+}
+
+fn to_js_err(err: usefix::Error) -> Error {
+    let status = match err {
+        usefix::Error::InvalidOptions(_) => Status::InvalidArg,
+        _ => Status::GenericFailure,
+    };
+
+    Error::new(status, err.full_message())
+}
+
+/// Convert `camelCase` to `--kebab-case`
+fn flag_name(key: &str) -> String {
+    let mut flag = String::from("--");
+
+    for c in key.chars() {
+        if c.is_ascii_uppercase() {
+            flag.push('-');
+            flag.push(c.to_ascii_lowercase());
+        } else {
+            flag.push(c);
+        }
+    }
+
+    flag
+}
+
+/// Convert an options object into command line arguments, and parse them.
+fn parse_options(
+    options: Option<HashMap<String, OptionValue>>,
+    defaults: &[&str],
+) -> napi::Result<MergeOptions> {
+    let options = options.unwrap_or_default();
+    let mut args: Vec<String> = Vec::new();
+
+    for (key, value) in &options {
+        let flag = flag_name(key);
+
+        let value = match value {
+            None | Some(Either4::A(false)) => continue,
+            Some(Either4::A(true)) => {
+                args.push(flag);
+                continue;
+            }
+            Some(Either4::B(number)) => number.to_string(),
+            Some(Either4::C(value)) => value.clone(),
+            Some(Either4::D(values)) => values.join(","),
+        };
+
+        args.push(flag);
+        args.push(value);
+    }
+
+    args.extend(
+        defaults
+            .iter()
+            .filter(|&&flag| !options.keys().any(|key| flag_name(key) == flag))
+            .map(|&flag| flag.to_owned()),
+    );
+
+    MergeOptions::from_args(args).map_err(|err| Error::new(Status::InvalidArg, err.to_string()))
+}
+
+/// Merge the use items in a file containing git conflicts, and return the
+/// fixed file. Throws if there aren't any conflicts, unless
+/// `requireConflict: false` is given.
+#[napi]
+pub fn merge(
+    source: String,
+    options: Option<HashMap<String, OptionValue>>,
+) -> napi::Result<String> {
+    let options = parse_options(options, &["--require-conflict"])?;
+    usefix::fix_file(&source, &options).map_err(to_js_err)
+}
+
+/// Sort, group, and format the use items in a file, which may or may not
+/// contain git conflicts, and return the fixed file.
+#[napi]
+pub fn organize(
+    source: String,
+    options: Option<HashMap<String, OptionValue>>,
+) -> napi::Result<String> {
+    let options = parse_options(options, &[])?;
+    usefix::fix_file(&source, &options).map_err(to_js_err)
+}
+
+/// Fix a file, and describe the result: whether the file was conflicted,
+/// the fixed file, the formatted use items for each section, and the merged
+/// imports (with the lines they came from).
+#[napi]
+pub fn report(
+    source: String,
+    options: Option<HashMap<String, OptionValue>>,
+) -> napi::Result<Report> {
+    let options = parse_options(options, &[])?;
+
+    let file = match options.fragment {
+        true => ConflictedFile::parse_fragment(&source),
+        false => ConflictedFile::parse(&source),
+    }
+    .map_err(to_js_err)?;
+    let merged = file.merge_imports(&options).map_err(to_js_err)?;
+
+    let imports = file
+        .import_model(&options)
+        .imports
+        .into_iter()
+        .map(|import| {
+            let (name, alias) = match import.leaf {
+                ImportLeaf::Name(name) => (name, None),
+                ImportLeaf::Renamed { name, alias } => (name, Some(alias)),
+                ImportLeaf::Wildcard => ("*".to_owned(), None),
+            };
+
+            Import {
+                rooted: import.rooted,
+                path: import.path,
+                name,
+                alias,
+                cfgs: import.cfgs,
+                visibility: import.visibility,
+                docs: import
+                    .docs
+                    .iter()
+                    .map(|block| block.as_str().to_owned())
+                    .collect(),
+                module: import.provenance.module,
+                left: import.provenance.left,
+                right: import.provenance.right,
+                lines: import
+                    .provenance
+                    .lines
+                    .into_iter()
+                    .map(|line| line as u32)
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Ok(Report {
+        conflicted: file.contains_conflict(),
+        output: merged.render(),
+        sections: merged.sections().map(str::to_owned).collect(),
+        imports,
+    })
+}
@@ -0,0 +1,257 @@
+/*!
+Python bindings for usefix. Options are passed as keyword arguments, named
+after the command line flags (so `--cfg-order complexity` is
+`cfg_order="complexity"`):
+
+```python
+import usefix
+
+fixed = usefix.merge(source, hoist=True, sort_order=["locality", "root"])
+```
+ */
+
+use pyo3::{
+    create_exception,
+    exceptions::PyException,
+    prelude::*,
+    types::{PyBool, PyDict, PyList},
+};
+use usefix::{ConflictedFile, DocBlock, Error, ImportLeaf, MergeOptions};
+
+create_exception!(
+    usefix,
+    UsefixError,
+    PyException,
+    "Base class for usefix errors"
+);
+create_exception!(
+    usefix,
+    MalformedConflictError,
+    UsefixError,
+    "The git conflict markers were malformed"
+);
+create_exception!(
+    usefix,
+    NoConflictsError,
+    UsefixError,
+    "The file didn't contain any conflicts"
+);
+create_exception!(
+    usefix,
+    RustSyntaxError,
+    UsefixError,
+    "One version of the file wasn't valid rust"
+);
+create_exception!(
+    usefix,
+    UnsupportedError,
+    UsefixError,
+    "The file can't be safely rewritten"
+);
+create_exception!(
+    usefix,
+    FormatterError,
+    UsefixError,
+    "The external formatter failed"
+);
+create_exception!(
+    usefix,
+    DeniedWildcardError,
+    UsefixError,
+    "A glob import from one side of a conflict was denied by wildcards=\"deny\""
+);
+create_exception!(
+    usefix,
+    InvalidOptionsError,
+    UsefixError,
+    "The options were invalid"
+);
+
+fn to_py_err(err: Error) -> PyErr {
+    let message = err.full_message();
+
+    match err {
+        Error::MalformedConflict { .. } => MalformedConflictError::new_err(message),
+        Error::NoConflicts => NoConflictsError::new_err(message),
+        Error::RustSyntax { .. } => RustSyntaxError::new_err(message),
+        Error::Unsupported { .. } | Error::ConflictOutsideRegion { .. } => {
+            UnsupportedError::new_err(message)
+        }
+        Error::DeniedWildcard { .. } => DeniedWildcardError::new_err(message),
+        Error::Formatter { .. } => FormatterError::new_err(message),
+        Error::InvalidOptions(_) => InvalidOptionsError::new_err(message),
+        // Including `NoSection`, which import models can't cause here, and
+        // `Internal`, which is always a bug
+        _ => UsefixError::new_err(message),
+    }
+}
+
+/// Convert keyword arguments into command line arguments, and parse them.
+/// `True` is a flag, `False` and `None` are omitted, and lists are joined
+/// with commas.
+fn parse_options(kwargs: Option<&Bound<'_, PyDict>>, defaults: &[&str]) -> PyResult<MergeOptions> {
+    let mut args: Vec<String> = Vec::new();
+    let mut given: Vec<String> = Vec::new();
+
+    for (key, value) in kwargs.into_iter().flat_map(|kwargs| kwargs.iter()) {
+        let flag = format!("--{}", key.extract::<String>()?.replace('_', "-"));
+        given.push(flag.clone());
+
+        if value.is_none() {
+            continue;
+        }
+
+        if let Ok(enabled) = value.downcast::<PyBool>() {
+            if enabled.is_true() {
+                args.push(flag);
+            }
+            continue;
+        }
+
+        let value = match value.downcast::<PyList>() {
+            Ok(list) => list
+                .iter()
+                .map(|item| Ok(item.str()?.to_string()))
+                .collect::<PyResult<Vec<String>>>()?
+                .join(","),
+            Err(_) => value.str()?.to_string(),
+        };
+
+        args.push(flag);
+        args.push(value);
+    }
+
+    args.extend(
+        defaults
+            .iter()
+            .filter(|&&flag| !given.iter().any(|given| given == flag))
+            .map(|&flag| flag.to_owned()),
+    );
+
+    MergeOptions::from_args(args).map_err(|err| InvalidOptionsError::new_err(err.to_string()))
+}
+
+/// Merge the use items in a file containing git conflicts, and return the
+/// fixed file. Raises `NoConflictsError` if there aren't any conflicts,
+/// unless `require_conflict=False` is given.
+#[pyfunction]
+#[pyo3(signature = (source, **options))]
+fn merge(py: Python<'_>, source: &str, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let options = parse_options(options, &["--require-conflict"])?;
+    py.allow_threads(|| usefix::fix_file(source, &options))
+        .map_err(to_py_err)
+}
+
+/// Sort, group, and format the use items in a file, which may or may not
+/// contain git conflicts, and return the fixed file.
+#[pyfunction]
+#[pyo3(signature = (source, **options))]
+fn organize(py: Python<'_>, source: &str, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let options = parse_options(options, &[])?;
+    py.allow_threads(|| usefix::fix_file(source, &options))
+        .map_err(to_py_err)
+}
+
+/// Fix a file, and return a dict describing the result: whether the file was
+/// conflicted, the fixed file, the formatted use items for each section, and
+/// the merged imports (with the lines they came from).
+#[pyfunction]
+#[pyo3(signature = (source, **options))]
+fn report<'py>(
+    py: Python<'py>,
+    source: &str,
+    options: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let options = parse_options(options, &[])?;
+
+    let (conflicted, output, sections, model) = py
+        .allow_threads(|| {
+            let file = match options.fragment {
+                true => ConflictedFile::parse_fragment(source)?,
+                false => ConflictedFile::parse(source)?,
+            };
+            let merged = file.merge_imports(&options)?;
+
+            Ok((
+                file.contains_conflict(),
+                merged.render(),
+                merged
+                    .sections()
+                    .map(str::to_owned)
+                    .collect::<Vec<String>>(),
+                file.import_model(&options),
+            ))
+        })
+        .map_err(to_py_err)?;
+
+    let imports = model
+        .imports
+        .iter()
+        .map(|import| {
+            let dict = PyDict::new_bound(py);
+
+            let (name, alias) = match import.leaf {
+                ImportLeaf::Name(ref name) => (name.as_str(), None),
+                ImportLeaf::Renamed {
+                    ref name,
+                    ref alias,
+                } => (name.as_str(), Some(alias.as_str())),
+                ImportLeaf::Wildcard => ("*", None),
+            };
+
+            dict.set_item("rooted", import.rooted)?;
+            dict.set_item("path", &import.path)?;
+            dict.set_item("name", name)?;
+            dict.set_item("alias", alias)?;
+            dict.set_item("cfgs", &import.cfgs)?;
+            dict.set_item("visibility", import.visibility.as_deref())?;
+            dict.set_item(
+                "docs",
+                import.docs.iter().map(DocBlock::as_str).collect::<Vec<_>>(),
+            )?;
+            dict.set_item("module", &import.provenance.module)?;
+            dict.set_item("left", import.provenance.left)?;
+            dict.set_item("right", import.provenance.right)?;
+            dict.set_item("lines", &import.provenance.lines)?;
+
+            Ok(dict)
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+
+    let report = PyDict::new_bound(py);
+    report.set_item("conflicted", conflicted)?;
+    report.set_item("output", output)?;
+    report.set_item("sections", sections)?;
+    report.set_item("imports", PyList::new_bound(py, imports))?;
+
+    Ok(report)
+}
+
+#[pymodule]
+#[pyo3(name = "usefix")]
+fn usefix_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(merge, m)?)?;
+    m.add_function(wrap_pyfunction!(organize, m)?)?;
+    m.add_function(wrap_pyfunction!(report, m)?)?;
+
+    let py = m.py();
+    m.add("UsefixError", py.get_type_bound::<UsefixError>())?;
+    m.add(
+        "MalformedConflictError",
+        py.get_type_bound::<MalformedConflictError>(),
+    )?;
+    m.add("NoConflictsError", py.get_type_bound::<NoConflictsError>())?;
+    m.add("RustSyntaxError", py.get_type_bound::<RustSyntaxError>())?;
+    m.add("UnsupportedError", py.get_type_bound::<UnsupportedError>())?;
+    m.add(
+        "DeniedWildcardError",
+        py.get_type_bound::<DeniedWildcardError>(),
+    )?;
+    m.add("FormatterError", py.get_type_bound::<FormatterError>())?;
+    m.add(
+        "InvalidOptionsError",
+        py.get_type_bound::<InvalidOptionsError>(),
+    )?;
+
+    Ok(())
+}
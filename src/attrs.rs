@@ -0,0 +1,248 @@
+/*!
+Crate-level inner attributes (`#![...]`), which are merged with
+`--crate-attrs`. Nightly crates tend to have a list of `#![feature(...)]`
+attributes at the top of `lib.rs`, and it conflicts in nearly every merge.
+
+The crate attributes from both versions of the file form a section of their
+own, which is rewritten in place like any other section. Feature lists are
+unioned (separately for each `#![cfg_attr(predicate, feature(...))]`
+predicate), and any other attributes are deduplicated, but otherwise kept
+exactly as they were written. Inner doc comments (`//!`) aren't managed.
+ */
+
+use std::{fmt::Write, ops::RangeInclusive};
+
+use itertools::Itertools;
+use proc_macro2::{Span, TokenStream};
+use syn::{punctuated::Punctuated, spanned::Spanned, Meta, Token};
+
+/// A single `#![...]` attribute at the top level of a file
+#[derive(Debug)]
+pub struct CrateAttr {
+    pub kind: CrateAttrKind,
+
+    /// The (one-indexed) lines of the syn attribute from which this was
+    /// generated
+    pub lines: RangeInclusive<usize>,
+}
+
+#[derive(Debug)]
+pub enum CrateAttrKind {
+    /// `#![feature(a, b)]`, or `#![cfg_attr(predicate, feature(a, b))]`
+    Features {
+        predicate: Option<String>,
+        features: Vec<String>,
+    },
+
+    /// Any other attribute, as it was written. These are only deduplicated.
+    Other(String),
+}
+
+impl CrateAttr {
+    /// Convert a syn attribute, given the lines of the file it came from.
+    /// Returns `None` for outer attributes and docs.
+    pub fn from_syn_attribute(attr: syn::Attribute, lines: &[&str]) -> Option<Self> {
+        if !matches!(attr.style, syn::AttrStyle::Inner(_)) || attr.path().is_ident("doc") {
+            return None;
+        }
+
+        let span = attr.span();
+        let text = span_text(span, lines)?;
+
+        let kind = match attr.meta {
+            Meta::List(list) if list.path.is_ident("feature") => {
+                feature_names(&list).map(|features| CrateAttrKind::Features {
+                    predicate: None,
+                    features,
+                })
+            }
+            Meta::List(list) if list.path.is_ident("cfg_attr") => list
+                .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()
+                .and_then(
+                    |args| match args.into_iter().collect::<Vec<_>>().as_slice() {
+                        [predicate, Meta::List(feature)] if feature.path.is_ident("feature") => {
+                            Some(CrateAttrKind::Features {
+                                predicate: Some(span_text(predicate.span(), lines)?),
+                                features: feature_names(feature)?,
+                            })
+                        }
+                        _ => None,
+                    },
+                ),
+            _ => None,
+        }
+        .unwrap_or(CrateAttrKind::Other(text));
+
+        Some(Self {
+            kind,
+            lines: span.start().line..=span.end().line,
+        })
+    }
+}
+
+/// The features in a `feature(a, b)` list
+fn feature_names(list: &syn::MetaList) -> Option<Vec<String>> {
+    list.parse_args_with(Punctuated::<syn::Ident, Token![,]>::parse_terminated)
+        .ok()
+        .map(|features| features.iter().map(|feature| feature.to_string()).collect())
+}
+
+/// Get the source text covered by a span
+fn span_text(span: Span, lines: &[&str]) -> Option<String> {
+    let start = span.start();
+    let end = span.end();
+
+    (start.line..=end.line)
+        .map(|line| {
+            let content = lines.get(line.checked_sub(1)?)?;
+            let from = if line == start.line { start.column } else { 0 };
+            let to = if line == end.line {
+                end.column
+            } else {
+                usize::MAX
+            };
+
+            Some(
+                content
+                    .chars()
+                    .skip(from)
+                    .take(to - from)
+                    .collect::<String>(),
+            )
+        })
+        .collect::<Option<Vec<String>>>()
+        .map(|text| text.join("\n"))
+}
+
+/// Normalize the formatting of some tokens, so that attributes that only
+/// differ in their formatting are considered identical
+fn normalize(text: &str) -> String {
+    match text.parse::<TokenStream>() {
+        Ok(tokens) => tokens.to_string(),
+        Err(_) => text.split_whitespace().join(" "),
+    }
+}
+
+/// A group of merged crate attributes, rendered together
+enum MergedAttr<'a> {
+    Features {
+        predicate: Option<&'a str>,
+        features: Vec<&'a str>,
+
+        /// If true, every feature gets its own attribute, because that's
+        /// how all of the original attributes were written
+        separate: bool,
+    },
+    Other(&'a str),
+}
+
+/// Merge the crate attributes from both versions of a file, and render them.
+/// Attributes are kept in the order they first appear, left side first.
+/// Feature lists longer than `max_width` are written one feature per line,
+/// like rustfmt would.
+pub fn render_crate_attrs<'a>(
+    attrs: impl IntoIterator<Item = &'a CrateAttr>,
+    max_width: usize,
+) -> String {
+    let mut merged: Vec<MergedAttr<'a>> = Vec::new();
+
+    for attr in attrs {
+        match attr.kind {
+            CrateAttrKind::Features {
+                ref predicate,
+                ref features,
+            } => {
+                let predicate = predicate.as_deref();
+
+                let existing = merged.iter_mut().find_map(|merged| match merged {
+                    MergedAttr::Features {
+                        predicate: existing,
+                        features,
+                        separate,
+                    } if existing.map(normalize) == predicate.map(normalize) => {
+                        Some((features, separate))
+                    }
+                    _ => None,
+                });
+
+                match existing {
+                    Some((existing, separate)) => {
+                        *separate &= features.len() == 1;
+
+                        for feature in features {
+                            if !existing.contains(&feature.as_str()) {
+                                existing.push(feature.as_str());
+                            }
+                        }
+                    }
+                    None => merged.push(MergedAttr::Features {
+                        predicate,
+                        features: features.iter().map(String::as_str).unique().collect(),
+                        separate: features.len() == 1,
+                    }),
+                }
+            }
+            CrateAttrKind::Other(ref text) => {
+                let is_duplicate = merged.iter().any(|merged| {
+                    matches!(merged, MergedAttr::Other(existing) if normalize(existing) == normalize(text))
+                });
+
+                if !is_duplicate {
+                    merged.push(MergedAttr::Other(text));
+                }
+            }
+        }
+    }
+
+    let mut rendered = String::new();
+
+    for attr in merged {
+        match attr {
+            MergedAttr::Features {
+                predicate,
+                features,
+                separate: true,
+            } => features.iter().for_each(|feature| {
+                render_features(&mut rendered, predicate, &[feature], max_width)
+            }),
+            MergedAttr::Features {
+                predicate,
+                features,
+                separate: false,
+            } => render_features(&mut rendered, predicate, &features, max_width),
+            MergedAttr::Other(text) => {
+                rendered.push_str(text);
+                rendered.push('\n');
+            }
+        }
+    }
+
+    rendered
+}
+
+fn render_features(
+    dest: &mut String,
+    predicate: Option<&str>,
+    features: &[&str],
+    max_width: usize,
+) {
+    let list = features.join(", ");
+
+    let single_line = match predicate {
+        None => format!("#![feature({list})]"),
+        Some(predicate) => format!("#![cfg_attr({predicate}, feature({list}))]"),
+    };
+
+    if single_line.len() <= max_width || predicate.is_some() {
+        dest.push_str(&single_line);
+        dest.push('\n');
+        return;
+    }
+
+    dest.push_str("#![feature(\n");
+    for feature in features {
+        writeln!(dest, "    {feature},").expect("writing to a string is infallible");
+    }
+    dest.push_str(")]\n");
+}
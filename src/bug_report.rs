@@ -0,0 +1,354 @@
+/*!
+`--report-bug`: package everything needed to reproduce a failure into a single
+file that can be attached to an issue.
+
+A failure is usefix returning an error (or panicking) while fixing the input,
+or producing output that fails the `--verify` checks. The input is minimized
+first: whole conflicts, and then single lines (but never conflict markers on
+their own), are removed for as long as the same failure still happens, where
+two failures are the same if their messages only differ in their numbers. If
+nothing fails, the input is included as it is.
+
+With `--anonymize`, every identifier in the minimized input (including those
+in comments and string literals) is consistently replaced with a meaningless
+one, like `ident3` or `Ident4`, keeping keywords, the standard library
+crates, and the names of common attributes and cfg predicates. Error messages
+usually name the identifiers involved, so the anonymized input only has to
+fail in the same way (with the same kind of error, or a panic, or a failed
+`--verify` check), rather than with the same message; if it doesn't, no
+report is written. The report then shows the failure of the anonymized
+input, and the `--path` is replaced everywhere in the report, including in
+the command line, which otherwise isn't anonymized.
+
+The report contains the command line, the failure, the minimized input, the
+fixed output (if there is one), and the intermediate stages of fixing the
+minimized input (see `--debug-stages`), each under a `===== NAME =====`
+header.
+ */
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    env,
+    fmt::Write as _,
+    fs, mem,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+use anyhow::Context;
+use itertools::Itertools;
+use usefix::MergeOptions;
+
+/// Identifiers that `--anonymize` leaves alone, either because they're
+/// keywords, or because usefix treats them specially
+const PRESERVED: &str = "\
+    as async await break const continue crate dyn else enum extern false fn \
+    for if impl in let loop match mod move mut pub ref return self Self static \
+    struct super trait true type union unsafe use where while alloc core std \
+    all allow any cfg cfg_attr debug_assertions deny doc feature inline \
+    macro_use no_inline not path target_os test unix warn windows";
+
+/// Write a bug report for fixing `input` to `path` (see the module docs)
+pub fn write_bug_report(
+    path: &Path,
+    input: &str,
+    options: &MergeOptions,
+    anonymize: bool,
+) -> anyhow::Result<()> {
+    // Minimizing the input runs usefix many times, and any panics are caught,
+    // so they shouldn't each be printed.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let report = build_report(input, options, anonymize);
+    panic::set_hook(hook);
+
+    fs::write(path, report?).with_context(|| format!("i/o error writing {}", path.display()))
+}
+
+/// A failure while fixing a file
+struct Failure {
+    kind: FailureKind,
+    message: String,
+}
+
+/// The kind of a failure, which is what `--anonymize` compares, since
+/// renaming the identifiers in the input usually changes the message
+#[derive(Debug, PartialEq, Eq)]
+enum FailureKind {
+    /// `fix_file` returned an error
+    Error(mem::Discriminant<usefix::Error>),
+
+    /// The fixed file failed the `--verify` checks
+    Verify,
+
+    /// usefix panicked
+    Panic,
+}
+
+fn build_report(input: &str, options: &MergeOptions, anonymize: bool) -> anyhow::Result<String> {
+    let failure = run(input, options).err();
+
+    let minimized = match failure {
+        Some(ref failure) => minimize(input, options, &normalize(&failure.message)),
+        None => input.to_owned(),
+    };
+
+    let (minimized, failure) = match anonymize {
+        false => (minimized, failure),
+        true => {
+            let anonymized = anonymize_identifiers(&minimized);
+            let anonymized_failure = run(&anonymized, options).err();
+
+            let same_kind = match (&failure, &anonymized_failure) {
+                (Some(failure), Some(anonymized)) => failure.kind == anonymized.kind,
+                (None, None) => true,
+                _ => false,
+            };
+
+            if !same_kind {
+                anyhow::bail!(
+                    "--report-bug: the failure doesn't happen once the input is anonymized, \
+                     so no report was written; try again without --anonymize"
+                );
+            }
+
+            (anonymized, anonymized_failure)
+        }
+    };
+
+    let mut report = String::new();
+    let mut section = |name: &str, content: &str| {
+        let newline = if content.ends_with('\n') { "" } else { "\n" };
+        write!(report, "===== {name} =====\n{content}{newline}")
+            .expect("writing to a string is infallible");
+    };
+
+    section(
+        "report",
+        &format!(
+            "usefix {}\ncommand: {}\nfailure: {}\ninput: {} of {} lines kept\n",
+            env!("CARGO_PKG_VERSION"),
+            env::args().skip(1).collect::<Vec<_>>().join(" "),
+            failure
+                .as_ref()
+                .map_or("none", |failure| failure.message.as_str()),
+            minimized.lines().count(),
+            input.lines().count(),
+        ),
+    );
+    section("input.rs", &minimized);
+
+    match catch(|| usefix::fix_file(&minimized, options).map_err(anyhow::Error::from)) {
+        Ok(fixed) => section("output.rs", &fixed),
+        Err(err) => section("output.rs", &format!("(no output: {err:#})")),
+    }
+
+    let stages = catch(|| {
+        crate::parse_file(&minimized, options)?
+            .debug_stages(options)
+            .map_err(anyhow::Error::from)
+    });
+
+    match stages {
+        Ok(stages) => stages
+            .iter()
+            .for_each(|(name, content)| section(name, content)),
+        Err(err) => section("stages", &format!("(no stages: {err:#})")),
+    }
+
+    // The path usually names the project, and appears in the command line
+    // and in the options
+    if let Some(path) = options.path.as_ref().filter(|_| anonymize) {
+        let path = path.display().to_string();
+
+        if !path.is_empty() {
+            report = report.replace(&path, "anonymized.rs");
+        }
+    }
+
+    Ok(report)
+}
+
+/// Fix a file and verify the result, returning the first failure
+fn run(input: &str, options: &MergeOptions) -> Result<(), Failure> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let fixed = usefix::fix_file(input, options).map_err(|err| Failure {
+            kind: FailureKind::Error(mem::discriminant(&err)),
+            message: format!("{:#}", anyhow::Error::from(err)),
+        })?;
+
+        crate::verify_fixed(&fixed, options).map_err(|err| Failure {
+            kind: FailureKind::Verify,
+            message: format!("{err:#}"),
+        })
+    }));
+
+    result.unwrap_or_else(|payload| {
+        Err(Failure {
+            kind: FailureKind::Panic,
+            message: format!("usefix panicked: {}", panic_message(&payload)),
+        })
+    })
+}
+
+/// Call `f`, turning a panic into an error
+fn catch<T>(f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(anyhow::anyhow!(
+            "usefix panicked: {}",
+            panic_message(&payload)
+        ))
+    })
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "(unknown)".to_owned(),
+        },
+    }
+}
+
+/// Replace every number in a failure message with `N`, so that failures that
+/// only differ in where they happened compare equal
+fn normalize(failure: &str) -> String {
+    failure
+        .chars()
+        .dedup_by(|a, b| a.is_ascii_digit() && b.is_ascii_digit())
+        .map(|c| if c.is_ascii_digit() { 'N' } else { c })
+        .collect()
+}
+
+fn is_marker(line: &str) -> bool {
+    ["<<<<<<<", "|||||||", "=======", ">>>>>>>"]
+        .iter()
+        .any(|marker| line.starts_with(marker))
+}
+
+/// Remove as much of the input as possible while it still fails with the
+/// same (normalized) message
+fn minimize(input: &str, options: &MergeOptions, failure: &str) -> String {
+    let lines: Vec<&str> = input.split_inclusive('\n').collect();
+    let mut keep = vec![true; lines.len()];
+
+    let render = |keep: &[bool]| -> String {
+        Iterator::zip(lines.iter(), keep)
+            .filter(|&(_, &keep)| keep)
+            .map(|(line, _)| *line)
+            .collect()
+    };
+    let reproduces = |keep: &[bool]| {
+        run(&render(keep), options).is_err_and(|err| normalize(&err.message) == failure)
+    };
+
+    // First try removing whole conflicts, from their opening marker to their
+    // closing marker
+    let mut conflicts = Vec::new();
+    let mut start = None;
+
+    for (index, line) in lines.iter().enumerate() {
+        if line.starts_with("<<<<<<<") {
+            start = Some(index);
+        } else if line.starts_with(">>>>>>>") {
+            if let Some(start) = start.take() {
+                conflicts.push((start..=index).collect());
+            }
+        }
+    }
+
+    reduce(&mut keep, conflicts, reproduces);
+
+    // Then try removing single lines, inside or outside of conflicts
+    let singles = (0..lines.len())
+        .filter(|&index| keep[index] && !is_marker(lines[index]))
+        .map(|index| vec![index])
+        .collect();
+
+    reduce(&mut keep, singles, reproduces);
+
+    render(&keep)
+}
+
+/// Remove groups of lines from `keep`, in chunks that shrink from half of
+/// the groups down to a single group, as long as the failure `reproduces`
+fn reduce(keep: &mut Vec<bool>, mut groups: Vec<Vec<usize>>, reproduces: impl Fn(&[bool]) -> bool) {
+    let mut chunk = groups.len().div_ceil(2);
+
+    while chunk > 0 {
+        let mut index = 0;
+
+        while index < groups.len() {
+            let end = usize::min(index + chunk, groups.len());
+            let mut candidate = keep.clone();
+
+            groups[index..end]
+                .iter()
+                .flatten()
+                .for_each(|&line| candidate[line] = false);
+
+            if reproduces(&candidate) {
+                *keep = candidate;
+                groups.drain(index..end);
+            } else {
+                index = end;
+            }
+        }
+
+        chunk /= 2;
+    }
+}
+
+/// Consistently replace every identifier in a file, preserving whether it's
+/// capitalized (or entirely uppercase), so that the file's imports are
+/// classified the same way (see the module docs)
+fn anonymize_identifiers(input: &str) -> String {
+    let mut names: HashMap<&str, String> = HashMap::new();
+    let mut anonymized = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+        let (before, word) = rest.split_at(start);
+        let end = word
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(word.len());
+        let (word, after) = word.split_at(end);
+
+        anonymized.push_str(before);
+
+        // Numbers (and their suffixes), escape sequences, char literals,
+        // raw identifiers, and literal prefixes like `b"..."` are kept
+        let keep = word.starts_with(|c: char| c.is_ascii_digit())
+            || before.ends_with('\\')
+            || (before.ends_with('\'') && after.starts_with('\''))
+            || (matches!(word, "r" | "b" | "br" | "c" | "cr")
+                && after.starts_with(['#', '"', '\'']))
+            || PRESERVED
+                .split_whitespace()
+                .any(|preserved| preserved == word);
+
+        if keep {
+            anonymized.push_str(word);
+        } else {
+            let count = names.len() + 1;
+            let name = names.entry(word).or_insert_with(|| {
+                if word.len() > 1 && !word.contains(|c: char| c.is_ascii_lowercase()) {
+                    format!("IDENT{count}")
+                } else if word.starts_with(|c: char| c.is_ascii_uppercase()) {
+                    format!("Ident{count}")
+                } else {
+                    format!("ident{count}")
+                }
+            });
+
+            anonymized.push_str(name);
+        }
+
+        rest = after;
+    }
+
+    anonymized.push_str(rest);
+    anonymized
+}
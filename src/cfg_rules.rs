@@ -0,0 +1,146 @@
+/*!
+`--cfg-rules`: project-specific relations between cfg predicates, which
+usefix can't work out from the predicates themselves. Each line of a rules
+file is a single rule, written with the predicates as they'd appear inside of
+`#[cfg(...)]`:
+
+```text
+# Whenever the full runtime is enabled, tokio is too
+feature = "tokio-full" implies feature = "tokio"
+
+# These are the only unix platforms we support
+unix == any(target_os = "linux", target_os = "macos")
+```
+
+`A implies B` means that B holds whenever A does, and `A == B` means that
+each implies the other. Implications are transitive. Blank lines, and lines
+starting with `#`, are ignored. Predicates are compared in their canonical
+form (see `Config::from_tokens`), so their formatting doesn't matter.
+
+When the same import appears under two sets of configs, and one of them
+implies the other, the import under the implying configs is redundant, so it's
+merged into the other one, just like a conditional import is merged into an
+unconditional import of the same item. If each implies the other, the import
+under the simpler configs is kept.
+ */
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    str::FromStr,
+};
+
+use crate::tree::{Config, ConfigsList};
+
+/// A set of relations between cfg predicates (see the module docs)
+#[derive(Debug, Clone, Default)]
+pub struct CfgRules {
+    /// For each predicate, every other predicate it implies, directly or
+    /// transitively
+    implied: BTreeMap<Config, BTreeSet<Config>>,
+}
+
+impl CfgRules {
+    /// Read a rules file. This is the parser for `--cfg-rules`.
+    pub fn from_file(path: &str) -> Result<Self, InvalidCfgRulesError> {
+        fs::read_to_string(path)
+            .map_err(|source| InvalidCfgRulesError::Io {
+                path: path.to_owned(),
+                source,
+            })?
+            .parse()
+    }
+
+    /// True if `b` holds whenever `a` does
+    fn implies(&self, a: &Config, b: &Config) -> bool {
+        a == b
+            || self
+                .implied
+                .get(a)
+                .is_some_and(|implied| implied.contains(b))
+    }
+
+    /// True if every config in `b` holds whenever all of the configs in `a`
+    /// do: that is, if each config in `b` is implied by some config in `a`.
+    pub fn list_implies(&self, a: &ConfigsList, b: &ConfigsList) -> bool {
+        b.configs().all(|b| a.configs().any(|a| self.implies(a, b)))
+    }
+
+    /// Add `a implies b`, along with everything that follows from it
+    fn add_implication(&mut self, a: Config, b: Config) {
+        if self.implies(&a, &b) {
+            return;
+        }
+
+        // Everything that implies `a` (including `a`) now implies `b`, and
+        // everything that `b` implies
+        let mut consequences = self.implied.get(&b).cloned().unwrap_or_default();
+        consequences.insert(b);
+
+        let mut antecedents: Vec<Config> = self
+            .implied
+            .iter()
+            .filter(|(_, implied)| implied.contains(&a))
+            .map(|(antecedent, _)| antecedent.clone())
+            .collect();
+
+        antecedents.push(a);
+
+        for antecedent in antecedents {
+            let implied = self.implied.entry(antecedent.clone()).or_default();
+            implied.extend(consequences.iter().cloned());
+            implied.remove(&antecedent);
+        }
+    }
+}
+
+impl FromStr for CfgRules {
+    type Err = InvalidCfgRulesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Self::default();
+
+        for (line, content) in Iterator::zip(1.., s.lines()) {
+            let content = content.trim();
+
+            if content.is_empty() || content.starts_with('#') {
+                continue;
+            }
+
+            let predicate = |text: &str| {
+                Config::parse(text).ok_or_else(|| InvalidCfgRulesError::InvalidPredicate {
+                    line,
+                    predicate: text.trim().to_owned(),
+                })
+            };
+
+            if let Some((a, b)) = content.split_once(" implies ") {
+                rules.add_implication(predicate(a)?, predicate(b)?);
+            } else if let Some((a, b)) = content.split_once("==") {
+                let (a, b) = (predicate(a)?, predicate(b)?);
+                rules.add_implication(a.clone(), b.clone());
+                rules.add_implication(b, a);
+            } else {
+                return Err(InvalidCfgRulesError::MalformedRule { line });
+            }
+        }
+
+        Ok(rules)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum InvalidCfgRulesError {
+    #[error("i/o error reading {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("line {line}: expected `PREDICATE implies PREDICATE` or `PREDICATE == PREDICATE`")]
+    MalformedRule { line: usize },
+
+    #[error("line {line}: `{predicate}` isn't a cfg predicate")]
+    InvalidPredicate { line: usize, predicate: String },
+}
@@ -1,3 +1,102 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    hash::{Hash, Hasher},
+    ptr,
+};
+
+/// An identifier, as it was written (so a raw identifier keeps its `r#`).
+/// This is an owned stand-in for `syn::Ident`, which carries a `Span` and so
+/// can't be sent between threads.
+///
+/// Comparing an identifier with itself (the same `&Ident`) only compares
+/// addresses, rather than text. During a merge, every spelling of an
+/// identifier is the same `&Ident` (see `Interner`), so that's the usual case
+/// when two identifiers are equal.
+#[derive(Debug, Clone)]
+pub struct Ident(String);
+
+impl Ident {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The identifier without its `r#`, if it's a raw identifier
+    pub fn unraw(&self) -> &str {
+        self.0.strip_prefix("r#").unwrap_or(&self.0)
+    }
+}
+
+impl From<syn::Ident> for Ident {
+    fn from(ident: syn::Ident) -> Self {
+        Self(ident.to_string())
+    }
+}
+
+impl PartialEq for Ident {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self, other) || self.0 == other.0
+    }
+}
+
+impl Eq for Ident {}
+
+impl PartialOrd for Ident {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ident {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match ptr::eq(self, other) {
+            true => Ordering::Equal,
+            false => Ord::cmp(&self.0, &other.0),
+        }
+    }
+}
+
+impl Hash for Ident {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl PartialEq<str> for Ident {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Ident {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Display for Ident {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The identifiers in a single merge. Interning an identifier returns the
+/// first `&Ident` that was interned with the same text, so that every
+/// spelling of an identifier in the merge is the same `&Ident`, and comparing
+/// them only compares their addresses. The identifiers are borrowed from the
+/// use items being merged, so the table is dropped along with the merge.
+#[derive(Debug, Default)]
+pub struct Interner<'a> {
+    idents: HashMap<&'a str, &'a Ident>,
+}
+
+impl<'a> Interner<'a> {
+    pub fn intern(&mut self, ident: &'a Ident) -> &'a Ident {
+        self.idents.entry(ident.as_str()).or_insert(ident)
+    }
+}
+
 /// If a name is being imported, it either keeps its own name or is renamed
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum NameUse<I> {
@@ -17,8 +116,77 @@ impl<I> NameUse<I> {
     }
 }
 
+impl<'a> NameUse<&'a Ident> {
+    /// True if this is an anonymous rename (`name as _`), which only serves
+    /// to bring trait methods into scope.
+    pub fn is_anonymous(&self) -> bool {
+        matches!(*self, NameUse::Renamed(renamed) if renamed == "_")
+    }
+
+    /// Get the key used to order this usage relative to other usages of the
+    /// same name. See `UsageOrder` for details.
+    pub fn order(&self) -> UsageOrder<'a> {
+        match *self {
+            NameUse::Used => UsageOrder::Used,
+            NameUse::Renamed(_) if self.is_anonymous() => UsageOrder::Anonymous,
+            NameUse::Renamed(renamed) => UsageOrder::Renamed(Unraw(renamed)),
+        }
+    }
+}
+
+/// The order of different usages of a single name. This order is used
+/// everywhere that usages are sorted, so that `{B, B as C, B as _}` is always
+/// normalized and printed in that order: the plain usage, then any renames
+/// (ordered by the unescaped alias), then the anonymous rename.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum UsageOrder<'a> {
+    /// `name`
+    Used,
+
+    /// `name as alias`
+    Renamed(Unraw<'a>),
+
+    /// `name as _`
+    Anonymous,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Rooted {
     Rooted,
     Unrooted,
 }
+
+/// An identifier that compares by its unescaped value, so that `r#try` and
+/// `try` are considered equal.
+#[derive(Debug, Clone, Copy)]
+pub struct Unraw<'a>(pub &'a Ident);
+
+impl Unraw<'_> {
+    /// True if this identifier was written as a raw identifier (`r#ident`)
+    pub fn is_raw(&self) -> bool {
+        self.0.as_str().starts_with("r#")
+    }
+}
+
+impl PartialEq for Unraw<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Unraw<'_> {}
+
+impl PartialOrd for Unraw<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Unraw<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match ptr::eq(self.0, other.0) {
+            true => Ordering::Equal,
+            false => Ord::cmp(self.0.unraw(), other.0.unraw()),
+        }
+    }
+}
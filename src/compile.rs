@@ -0,0 +1,78 @@
+/*!
+`--ensure-compiles`: after merging, check that the merged file compiles, so
+that usefix can run unattended in merge automation. The merged file is
+written in place of the file at `--path` and checked with `cargo check`
+(which checks the package containing the file), and the file is restored
+afterwards, even if usefix panics in the meantime. If cargo reports any
+errors in that file, the merge is rejected: the errors are reported, and the
+original file is kept instead of the merged one.
+
+If cargo fails without reporting any errors in the file (because there's no
+package, or because errors elsewhere, like a parse error in another module,
+stopped the compiler before it got to the file), the check is inconclusive,
+and the merge is rejected as well. Errors elsewhere can hide errors in the
+merged file (and can even be caused by it, like a removed re-export), so a
+failed check never counts as a pass.
+ */
+
+use std::path::Path;
+
+use crate::{
+    diagnostics::{Diagnostic, Level, MessageFormat},
+    json::Json,
+    prune,
+};
+
+/// Check that a merged file compiles (see the module docs). If it doesn't,
+/// the compiler errors in the file are reported, and this fails. This also
+/// fails if cargo fails without reporting any errors in the file.
+pub fn ensure_compiles(path: &Path, fixed: &str, format: MessageFormat) -> anyhow::Result<()> {
+    let replaced = prune::ReplacedFile::write(path, fixed)?;
+    let check = prune::cargo_check(path, &[]);
+    replaced.restore()?;
+
+    let check = check?;
+
+    let errors: Vec<Diagnostic> = check
+        .diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.get("level").and_then(Json::as_str) == Some("error"))
+        .filter_map(|diagnostic| {
+            let span = check.spans_in_file(diagnostic).next()?;
+            let position = |key: &str| span.get(key).and_then(Json::as_usize).unwrap_or(1);
+            let (line, column) = (position("line_start"), position("column_start"));
+
+            let message = diagnostic
+                .get("message")
+                .and_then(Json::as_str)
+                .unwrap_or("unknown error");
+
+            // Human-readable messages don't have a location of their own
+            let message = match format {
+                MessageFormat::Human => format!("{message} (at line {line}, column {column})"),
+                MessageFormat::Short => message.to_owned(),
+            };
+
+            Some(Diagnostic::new(Level::Error, Some(path), message).at(line, column))
+        })
+        .collect();
+
+    if errors.is_empty() {
+        if check.success {
+            return Ok(());
+        }
+
+        anyhow::bail!(
+            "cargo check failed without reporting any errors in {}, so the merged file \
+             couldn't be checked; keeping the original file",
+            path.display()
+        );
+    }
+
+    errors.iter().for_each(|error| error.emit(format));
+
+    anyhow::bail!(
+        "the merged file doesn't compile ({} error(s)); keeping the original file",
+        errors.len()
+    )
+}
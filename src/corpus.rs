@@ -0,0 +1,176 @@
+/*!
+`usefix test-corpus`: check usefix against a corpus of conflicted files, to
+make sure that it handles a codebase's import styles before trusting it as a
+merge driver.
+
+A corpus is a directory tree of pairs of files: `NAME.input.rs`, a conflicted
+file, and `NAME.expected.rs`, the way it should be fixed. Each input is fixed
+with the given options and compared with its expected output.
+
+With `--revisions OURS THEIRS`, the corpus is generated instead, by merging
+two revisions of the git repository containing the directory: every rust file
+under the directory that conflicts when they're merged is fixed, and checked
+the same way as `--verify` checks a fixed file (fixing it again must not
+change it, and if no conflicts are left, it must be valid rust), since there's
+no expected output to compare it with.
+
+Each mismatch is reported, followed by a summary, and the exit status is 1 if
+there were any.
+ */
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use itertools::{EitherOrBoth, Itertools};
+use usefix::{ConflictedFile, MergeOptions};
+
+use crate::{git, stats};
+
+/// The result of checking a corpus
+#[derive(Debug, Default)]
+pub struct CorpusReport {
+    passed: usize,
+
+    /// The path of each file that didn't match, and why
+    failures: Vec<(PathBuf, String)>,
+}
+
+impl CorpusReport {
+    fn pass(&mut self) {
+        self.passed += 1;
+    }
+
+    fn fail(&mut self, path: &Path, message: String) {
+        self.failures.push((path.to_owned(), message));
+    }
+
+    /// True if any of the files didn't match
+    pub fn failed(&self) -> bool {
+        !self.failures.is_empty()
+    }
+
+    /// Render a line for every mismatch, followed by a summary
+    pub fn render(&self) -> String {
+        let mut report = String::new();
+
+        for (path, message) in &self.failures {
+            writeln!(report, "FAIL {}: {message}", path.display())
+                .expect("writing to a string is infallible");
+        }
+
+        writeln!(
+            report,
+            "{} file(s): {} passed, {} failed",
+            self.passed + self.failures.len(),
+            self.passed,
+            self.failures.len(),
+        )
+        .expect("writing to a string is infallible");
+
+        report
+    }
+}
+
+/// Fix every `.input.rs` file under `dir` (skipping those that git ignores,
+/// unless `no_ignore` is set), and compare it with the `.expected.rs` file
+/// next to it.
+pub fn test_pairs(
+    dir: &Path,
+    no_ignore: bool,
+    options: &MergeOptions,
+) -> anyhow::Result<CorpusReport> {
+    let paths = stats::rust_files(dir, no_ignore)?;
+
+    let mut report = CorpusReport::default();
+
+    for input_path in &paths {
+        let Some(name) = input_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".input.rs"))
+        else {
+            continue;
+        };
+
+        let expected_path = input_path.with_file_name(format!("{name}.expected.rs"));
+        let relative = input_path.strip_prefix(dir).unwrap_or(input_path);
+
+        let input = fs::read_to_string(input_path)
+            .with_context(|| format!("i/o error reading {}", input_path.display()))?;
+        let expected = match fs::read_to_string(&expected_path) {
+            Ok(expected) => expected,
+            Err(_) => {
+                report.fail(relative, format!("missing {}", expected_path.display()));
+                continue;
+            }
+        };
+
+        match usefix::fix_file(&input, options) {
+            Err(err) => report.fail(relative, format!("{err}")),
+            Ok(fixed) => match first_difference(&fixed, &expected) {
+                Some(line) => report.fail(
+                    relative,
+                    format!("the fixed file differs from the expected one at line {line}"),
+                ),
+                None => report.pass(),
+            },
+        }
+    }
+
+    Ok(report)
+}
+
+/// Merge two revisions of the git repository containing `dir`, and fix and
+/// verify every rust file under `dir` that conflicts.
+pub fn test_revisions(
+    dir: &Path,
+    ours: &str,
+    theirs: &str,
+    options: &MergeOptions,
+) -> anyhow::Result<CorpusReport> {
+    let options = MergeOptions {
+        require_conflict: false,
+        ..options.clone()
+    };
+
+    let mut report = CorpusReport::default();
+
+    for (path, merged) in git::simulate_merge(dir, ours, theirs)? {
+        let conflicted = match ConflictedFile::parse(&merged) {
+            Ok(file) => file.contains_conflict(),
+            Err(_) => true,
+        };
+
+        if !conflicted {
+            continue;
+        }
+
+        let result = usefix::fix_file(&merged, &options)
+            .map_err(anyhow::Error::from)
+            .and_then(|fixed| crate::verify_fixed(&fixed, &options));
+
+        match result {
+            Ok(()) => report.pass(),
+            Err(err) => report.fail(&path, format!("{err:#}")),
+        }
+    }
+
+    Ok(report)
+}
+
+/// The first (one-indexed) line at which two files differ, if they do
+fn first_difference(a: &str, b: &str) -> Option<usize> {
+    if a == b {
+        return None;
+    }
+
+    // If every line matches, only the line endings are different
+    Iterator::zip(1.., a.lines().zip_longest(b.lines()))
+        .find(|(_, lines)| !matches!(lines, EitherOrBoth::Both(a, b) if a == b))
+        .map(|(line, _)| line)
+        .or(Some(1))
+}
@@ -0,0 +1,117 @@
+/*!
+`--message-format`: how warnings, notes, and errors are printed. The default,
+`human`, prints them the way they've always been printed, which isn't meant
+to be parsed. `short` prints exactly one line for each diagnostic, with a
+grammar that won't change, for editor problem matchers and `grep`:
+
+```text
+<file>:<line>:<column>: <level>: <message>
+```
+
+`<file>` is the file the diagnostic is about, as it was given on the command
+line (or `--path`), or `<stdin>` if it's unknown. `<line>` and `<column>` are
+one-indexed, and are both `1` if the diagnostic isn't about a particular
+location in the file. `<level>` is `error`, `warning`, or `note`. `<message>`
+never contains a newline.
+ */
+
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+use usefix::MergedImports;
+
+/// How diagnostics are printed (see the module docs)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MessageFormat {
+    /// Human-readable messages, which may change between versions
+    #[default]
+    Human,
+
+    /// One `file:line:column: level: message` line per diagnostic
+    Short,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+        })
+    }
+}
+
+/// A single finding, about a file or a location in a file
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub level: Level,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic about a whole file
+    pub fn new(level: Level, file: Option<&Path>, message: impl Into<String>) -> Self {
+        Self {
+            file: file.map(Path::to_path_buf),
+            line: 1,
+            column: 1,
+            level,
+            message: message.into(),
+        }
+    }
+
+    /// Attach a (one-indexed) location to this diagnostic
+    #[must_use]
+    pub fn at(self, line: usize, column: usize) -> Self {
+        Self {
+            line,
+            column,
+            ..self
+        }
+    }
+
+    /// Render this diagnostic in a particular format, without a trailing
+    /// newline.
+    pub fn render(&self, format: MessageFormat) -> String {
+        match format {
+            MessageFormat::Human => format!("{}: {}", self.level, self.message),
+            MessageFormat::Short => format!(
+                "{}:{}:{}: {}: {}",
+                match self.file {
+                    Some(ref file) => file.display().to_string(),
+                    None => String::from("<stdin>"),
+                },
+                self.line,
+                self.column,
+                self.level,
+                self.message.split_whitespace().join(" "),
+            ),
+        }
+    }
+
+    /// Print this diagnostic to stderr
+    pub fn emit(&self, format: MessageFormat) {
+        eprintln!("{}", self.render(format));
+    }
+}
+
+/// Print the warnings from merging a file (see `MergedImports::warnings`)
+pub fn emit_merge_warnings(merged: &MergedImports<'_>, file: Option<&Path>, format: MessageFormat) {
+    merged
+        .warnings()
+        .iter()
+        .for_each(|warning| Diagnostic::new(Level::Warning, file, warning.as_str()).emit(format));
+}
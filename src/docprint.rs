@@ -7,17 +7,28 @@ use std::{
     ops::ControlFlow,
 };
 
-use crate::tree::DocsList;
+use crate::tree::{DocBlock, DocsList};
 
 impl Display for DocsList {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         self.blocks()
             .iter()
-            .try_for_each(|block| match categorize_doc(&block) {
-                DocCategory::SingleLine => writeln!(f, "///{block}"),
-                DocCategory::Block => writeln!(f, "/**{block}*/"),
-                DocCategory::Attribute => writeln!(f, "#[doc = {block:?}]"),
-            })
+            .try_for_each(|block| writeln!(f, "{block}"))
+    }
+}
+
+/// Renders the block as a single doc comment or attribute, without a
+/// trailing newline
+impl Display for DocBlock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DocBlock::Text(text) => match categorize_doc(text) {
+                DocCategory::SingleLine => write!(f, "///{text}"),
+                DocCategory::Block => write!(f, "/**{text}*/"),
+                DocCategory::Attribute => write!(f, "#[doc = {text:?}]"),
+            },
+            DocBlock::Expr(expr) => write!(f, "#[doc = {expr}]"),
+        }
     }
 }
 
@@ -47,21 +58,21 @@ fn categorize_doc(doc: &str) -> DocCategory {
 
 /// Check if the given comment contains balanced /* */ comments
 fn contains_balanced_blocks(comment: &str) -> bool {
-    match comment
-        .as_bytes()
-        .windows(2)
-        .try_fold((0u32, false), |(depth, skip), pair| {
-            match match (skip, pair) {
-                (true, _) => return ControlFlow::Continue((depth, false)),
-                (false, b"/*") => depth.checked_add(1),
-                (false, b"*/") => depth.checked_sub(1),
-                (false, _) => return ControlFlow::Continue((depth, false)),
-            } {
-                Some(depth) => ControlFlow::Continue((depth, true)),
-                None => ControlFlow::Break(()),
-            }
-        }) {
-        ControlFlow::Continue((0, _)) => true,
-        _ => false,
-    }
+    matches!(
+        comment
+            .as_bytes()
+            .windows(2)
+            .try_fold((0u32, false), |(depth, skip), pair| {
+                match match (skip, pair) {
+                    (true, _) => return ControlFlow::Continue((depth, false)),
+                    (false, b"/*") => depth.checked_add(1),
+                    (false, b"*/") => depth.checked_sub(1),
+                    (false, _) => return ControlFlow::Continue((depth, false)),
+                } {
+                    Some(depth) => ControlFlow::Continue((depth, true)),
+                    None => ControlFlow::Break(()),
+                }
+            }),
+        ControlFlow::Continue((0, _))
+    )
 }
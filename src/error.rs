@@ -0,0 +1,161 @@
+/*!
+The error type for the public API. Line and column numbers are always
+one-indexed, and refer to the original file (including any conflict markers),
+rather than to either version of the file.
+ */
+
+use std::path::PathBuf;
+
+use nom_supreme::{error::ErrorTree, final_parser::Location};
+
+use crate::{gitfile::Side, options::InvalidOptionsError, pretty::FormatterError};
+
+/// An error from fixing a file with usefix. New variants may be added in
+/// the future, so matches on it outside of usefix need a fallback arm.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The git conflict markers in the file were malformed; for instance, a
+    /// conflict was never closed with `>>>>>>>`.
+    #[error("error parsing git conflicts in file at line {line}, column {column}")]
+    MalformedConflict { line: usize, column: usize },
+
+    /// The file didn't contain any git conflicts, and
+    /// `MergeOptions::require_conflict` was set.
+    #[error("the file doesn't contain any git conflicts")]
+    NoConflicts,
+
+    /// One version of the file wasn't valid rust. `side` is `None` if the file
+    /// doesn't contain any conflicts.
+    #[error(
+        "error parsing rust syntax{} at line {line}, column {column}",
+        match side {
+            Some(side) => format!(" in the {side} side of the conflicted file"),
+            None => String::new(),
+        }
+    )]
+    RustSyntax {
+        side: Option<Side>,
+        line: usize,
+        column: usize,
+
+        #[source]
+        source: syn::Error,
+    },
+
+    /// The file contains something that usefix can't safely rewrite: a line
+    /// with part of a use item also contains some other code.
+    #[error(
+        "line {line}, column {column} contains code other than a use item; usefix \
+        requires every use item to be on its own lines, so that it can remove \
+        them without deleting anything else"
+    )]
+    Unsupported { line: usize, column: usize },
+
+    /// An import model being applied to a file (see
+    /// `ConflictedFile::apply_model`) has imports in a module that doesn't
+    /// have any use items in the file, so there's nowhere to put them.
+    #[error(
+        "the file has no use items in {}, so there's nowhere to put the imports for it",
+        match module.as_slice() {
+            [] => String::from("its top level"),
+            module => format!("module {}", module.join("::")),
+        }
+    )]
+    NoSection { module: Vec<String> },
+
+    /// The merged file would have a glob import that was only on one side
+    /// of a conflict, and `MergeOptions::wildcards` is `deny`.
+    #[error(
+        "`{path}` is a glob import from only one side of a conflict, and --wildcards deny \
+        doesn't allow adding glob imports"
+    )]
+    DeniedWildcard { path: String, line: usize },
+
+    /// A conflict touches use items outside of the managed regions, which
+    /// `MergeOptions::managed_region` would leave alone, conflict markers
+    /// and all.
+    #[error(
+        "line {line} is part of a conflict in use items outside of the managed region, \
+        which --managed-region would leave unresolved"
+    )]
+    ConflictOutsideRegion { line: usize },
+
+    /// The external formatter failed.
+    #[error("error formatting with external subcommand '{}'", command.display())]
+    Formatter {
+        command: PathBuf,
+
+        #[source]
+        source: FormatterError,
+    },
+
+    /// usefix reached a state that should be impossible, which is a bug in
+    /// usefix. The file is left untouched.
+    #[error("internal error ({reason}); this is a bug in usefix")]
+    Internal { reason: &'static str },
+
+    /// The options were inconsistent with each other.
+    #[error(transparent)]
+    InvalidOptions(#[from] InvalidOptionsError),
+}
+
+impl Error {
+    /// The message for this error, followed by each of its causes, separated
+    /// by `: `. Useful for bindings to other languages, which don't have
+    /// anything like `anyhow` to print the whole chain.
+    pub fn full_message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+
+        while let Some(cause) = source {
+            message.push_str(": ");
+            message.push_str(&cause.to_string());
+            source = cause.source();
+        }
+
+        message
+    }
+
+    /// The location in the file that caused this error, as a `(line,
+    /// column)` pair, if the error came from a particular location.
+    pub fn location(&self) -> Option<(usize, usize)> {
+        match *self {
+            Error::MalformedConflict { line, column }
+            | Error::RustSyntax { line, column, .. }
+            | Error::Unsupported { line, column } => Some((line, column)),
+            Error::DeniedWildcard { line, .. } | Error::ConflictOutsideRegion { line } => {
+                Some((line, 1))
+            }
+            Error::NoConflicts
+            | Error::NoSection { .. }
+            | Error::Formatter { .. }
+            | Error::Internal { .. }
+            | Error::InvalidOptions(_) => None,
+        }
+    }
+
+    /// Create an error from a failure to parse the git conflicts in a file
+    pub(crate) fn from_conflict_error(err: &ErrorTree<Location>) -> Self {
+        let location = error_location(err);
+
+        Error::MalformedConflict {
+            line: location.line,
+            column: location.column,
+        }
+    }
+}
+
+/// Find the location of the deepest error in an error tree. For alternatives,
+/// that's whichever alternative got furthest.
+fn error_location(err: &ErrorTree<Location>) -> Location {
+    match err {
+        ErrorTree::Base { location, .. } => *location,
+        ErrorTree::Stack { base, .. } => error_location(base),
+        ErrorTree::Alt(alternatives) => alternatives
+            .iter()
+            .map(error_location)
+            .max_by_key(|location| (location.line, location.column))
+            .unwrap_or(Location { line: 1, column: 1 }),
+    }
+}
@@ -0,0 +1,435 @@
+/*!
+`usefix explain`: a description of every intermediate stage of the merge
+algorithm, for each section of a file. When a merge produces something
+surprising, this makes it possible to see which step is responsible: the
+parsed use items from each side, the flattened list of paths (after configs
+are normalized), the paths grouped by configs (after wildcards are
+normalized), and finally the printable use items, along with the keys that
+determine how they're sorted and grouped.
+
+`--debug-stages` is the same idea in a form that's suited to bug reports:
+every intermediate artifact of fixing a file (each derived version of the
+file, with its line mappings, the extracted use items, and the text of each
+section before and after formatting) is returned as a separate file.
+
+`usefix why` is a more focused version of the same thing, which follows a
+single path through each of those stages: which use items on each side
+imported it, how their configs, visibilities, and docs were merged, and
+whether a glob import subsumed it.
+ */
+
+use std::fmt::{self, Write};
+
+use itertools::Itertools;
+
+use crate::{
+    fix_import_section, flatten_items, flatten_side,
+    flattened::NormalizedUsedItems,
+    gitfile::{GitFile, Side},
+    group_flattened_items_normalize_wildcards,
+    model::{Import, ImportLeaf},
+    printable::PrintOptions,
+    printable_items,
+    sections::ImportSection,
+    AnnotatedUseItem, Error, MergeOptions,
+};
+
+/// Describe each stage of the merge of each section
+pub fn explain_sections(
+    options: &MergeOptions,
+    sections: &[ImportSection<'_>],
+) -> Result<String, Error> {
+    let print_options = options.print_options()?;
+    let mut dest = String::new();
+
+    for (index, section) in sections.iter().enumerate() {
+        if index > 0 {
+            dest.push('\n');
+        }
+
+        explain_section(&mut dest, options, index, section, print_options)
+            .expect("writing to a string is infallible");
+    }
+
+    Ok(dest)
+}
+
+fn explain_section(
+    dest: &mut String,
+    options: &MergeOptions,
+    index: usize,
+    section: &ImportSection<'_>,
+    print_options: PrintOptions,
+) -> fmt::Result {
+    write!(dest, "section {}", index + 1)?;
+
+    match section.module() {
+        [] => writeln!(dest)?,
+        module => writeln!(dest, " (in module {})", module.join("::"))?,
+    }
+
+    explain_items(dest, "left", &section.left)?;
+    explain_items(dest, "right", &section.right)?;
+
+    let flattened = flatten_items(options, &section.left, &section.right);
+
+    writeln!(dest, "  flattened:")?;
+    for import in flattened_imports(&flattened) {
+        write_indented(dest, 4, &import.to_string())?;
+    }
+
+    let grouped = group_flattened_items_normalize_wildcards(&flattened, &options.policy);
+
+    writeln!(dest, "  grouped by configs:")?;
+    for (&configs, items) in grouped.iter().sorted_by_key(|&(&configs, _)| configs) {
+        match configs.is_empty() {
+            true => writeln!(dest, "    unconditional:")?,
+            false => writeln!(dest, "    cfg({}):", configs.predicate())?,
+        }
+
+        for (&path, properties) in items {
+            let import = Import::from_flattened(
+                path,
+                configs,
+                properties.visibility.map(|vis| vis.to_string()),
+                properties.docs.blocks().to_vec(),
+                properties.doc_attrs.to_vec(),
+            );
+
+            write_indented(dest, 6, &import.to_string())?;
+        }
+    }
+
+    writeln!(dest, "  printable:")?;
+    match printable_items(print_options, &section.left, &section.right, &grouped) {
+        Ok(printable) => {
+            for (key, item) in printable.explain_keys() {
+                writeln!(dest, "    {key}")?;
+                write_indented(dest, 6, &item)?;
+            }
+        }
+        Err(err) => writeln!(dest, "    ({err})")?,
+    }
+
+    Ok(())
+}
+
+/// Collect every intermediate artifact of fixing a file, as a list of file
+/// names and their contents (see the module docs)
+pub fn debug_stages(
+    options: &MergeOptions,
+    git_file: &GitFile<'_>,
+    items: [&[AnnotatedUseItem]; 2],
+    sections: &[ImportSection<'_>],
+) -> Result<Vec<(String, String)>, Error> {
+    let print_options = options.print_options()?;
+    let rustfmt_settings = options.rustfmt_settings();
+    let mut artifacts = vec![(String::from("options.txt"), format!("{options:#?}\n"))];
+
+    for (side, items) in Iterator::zip([Side::Left, Side::Right].into_iter(), items) {
+        let name = match side {
+            Side::Left => "left",
+            Side::Right => "right",
+        };
+
+        let derived_file = git_file.build_derived_file(side);
+        let mut line_map = String::new();
+        let mut item_list = String::new();
+
+        for (derived_line, original_line) in derived_file.line_mappings() {
+            writeln!(
+                line_map,
+                "{} -> {}",
+                derived_line.get(),
+                original_line.get()
+            )
+            .expect("writing to a string is infallible");
+        }
+
+        for (index, item) in items.iter().enumerate() {
+            let lines = item
+                .touched_original_lines
+                .iter()
+                .map(|line| line.get())
+                .sorted()
+                .join(", ");
+
+            writeln!(
+                item_list,
+                "item {}: section {}, module [{}], original lines [{lines}]",
+                index + 1,
+                item.section,
+                item.module.join("::"),
+            )
+            .and_then(|()| write_indented(&mut item_list, 4, &item.original_text))
+            .expect("writing to a string is infallible");
+        }
+
+        artifacts.push((format!("{name}.rs"), derived_file.content().to_owned()));
+        artifacts.push((format!("{name}-lines.txt"), line_map));
+        artifacts.push((format!("{name}-items.txt"), item_list));
+    }
+
+    for (index, section) in sections.iter().enumerate() {
+        let flattened = flatten_items(options, &section.left, &section.right);
+        let grouped = group_flattened_items_normalize_wildcards(&flattened, &options.policy);
+        let rendered =
+            printable_items(print_options, &section.left, &section.right, &grouped)?.to_string();
+
+        let formatted = fix_import_section(
+            options,
+            rustfmt_settings.as_ref(),
+            print_options,
+            section,
+            &mut Vec::new(),
+        )?;
+
+        artifacts.push((format!("section-{}-rendered.rs", index + 1), rendered));
+        artifacts.push((
+            format!("section-{}-formatted.rs", index + 1),
+            String::from_utf8_lossy(&formatted).into_owned(),
+        ));
+    }
+
+    Ok(artifacts)
+}
+
+/// Describe the parsed items from one side of a section
+fn explain_items(dest: &mut String, side: &str, items: &[&AnnotatedUseItem]) -> fmt::Result {
+    writeln!(dest, "  {side}:")?;
+
+    if items.is_empty() {
+        writeln!(dest, "    (none)")?;
+    }
+
+    for item in items {
+        let lines = item.item.lines();
+        writeln!(dest, "    lines {}-{}:", lines.start(), lines.end())?;
+        write_indented(dest, 6, &item.original_text)?;
+    }
+
+    Ok(())
+}
+
+/// A path being queried with `usefix why`, like `serde::de::DeserializeOwned`
+/// or `std::io::*`
+struct PathQuery<'q> {
+    rooted: bool,
+    segments: Vec<&'q str>,
+}
+
+impl<'q> PathQuery<'q> {
+    fn parse(query: &'q str) -> Self {
+        let (rooted, query) = match query.trim().strip_prefix("::") {
+            Some(query) => (true, query),
+            None => (false, query.trim()),
+        };
+
+        Self {
+            rooted,
+            segments: query.split("::").map(str::trim).collect(),
+        }
+    }
+
+    /// True if this import imports the queried path (possibly under another
+    /// name)
+    fn matches(&self, import: &Import) -> bool {
+        let leaf = match import.leaf {
+            ImportLeaf::Name(ref name) | ImportLeaf::Renamed { ref name, .. } => name.as_str(),
+            ImportLeaf::Wildcard => "*",
+        };
+
+        self.rooted == import.rooted
+            && Iterator::eq(
+                self.segments.iter().copied().map(unraw),
+                import
+                    .path
+                    .iter()
+                    .map(String::as_str)
+                    .chain([leaf])
+                    .map(unraw),
+            )
+    }
+
+    /// True if this import is a glob import of the queried path's parent,
+    /// which might subsume it
+    fn is_parent_glob(&self, import: &Import) -> bool {
+        let Some((_, parent)) = self.segments.split_last() else {
+            return false;
+        };
+
+        import.leaf == ImportLeaf::Wildcard
+            && self.rooted == import.rooted
+            && Iterator::eq(
+                parent.iter().copied().map(unraw),
+                import.path.iter().map(String::as_str).map(unraw),
+            )
+    }
+}
+
+fn unraw(ident: &str) -> &str {
+    ident.strip_prefix("r#").unwrap_or(ident)
+}
+
+/// Explain why a particular path is (or isn't) imported by the merged file
+pub fn explain_path(
+    options: &MergeOptions,
+    sections: &[ImportSection<'_>],
+    query: &str,
+) -> Result<String, Error> {
+    let query = PathQuery::parse(query);
+    let mut dest = String::new();
+
+    for (index, section) in sections.iter().enumerate() {
+        explain_path_in_section(&mut dest, options, index, section, &query)
+            .expect("writing to a string is infallible");
+    }
+
+    if dest.is_empty() {
+        writeln!(
+            dest,
+            "`{}` isn't imported by either version of the file",
+            query.segments.join("::")
+        )
+        .expect("writing to a string is infallible");
+    }
+
+    Ok(dest)
+}
+
+fn explain_path_in_section(
+    dest: &mut String,
+    options: &MergeOptions,
+    index: usize,
+    section: &ImportSection<'_>,
+    query: &PathQuery<'_>,
+) -> fmt::Result {
+    // The imports of each use item that imported the path, on its own
+    let occurrences: Vec<(&str, &AnnotatedUseItem, Vec<Import>)> =
+        [("left", &section.left), ("right", &section.right)]
+            .into_iter()
+            .flat_map(|(side, items)| items.iter().map(move |&item| (side, item)))
+            .filter_map(|(side, item)| {
+                let imports = flattened_imports(&flatten_side(options, &options.policy, [item]))
+                    .into_iter()
+                    .filter(|import| query.matches(import))
+                    .collect_vec();
+
+                (!imports.is_empty()).then_some((side, item, imports))
+            })
+            .collect();
+
+    if occurrences.is_empty() {
+        return Ok(());
+    }
+
+    if !dest.is_empty() {
+        dest.push('\n');
+    }
+
+    write!(dest, "section {}", index + 1)?;
+    match section.module() {
+        [] => writeln!(dest)?,
+        module => writeln!(dest, " (in module {})", module.join("::"))?,
+    }
+
+    writeln!(dest, "  imported by:")?;
+    for (side, item, imports) in &occurrences {
+        let lines = item.item.lines();
+        writeln!(dest, "    {side}, lines {}-{}:", lines.start(), lines.end())?;
+        write_indented(dest, 6, &item.original_text)?;
+
+        if occurrences.len() > 1 {
+            writeln!(dest, "      which imports:")?;
+            for import in imports {
+                write_indented(dest, 8, &import.to_string())?;
+            }
+        }
+    }
+
+    let flattened = flatten_items(options, &section.left, &section.right);
+    let merged = flattened_imports(&flattened)
+        .into_iter()
+        .filter(|import| query.matches(import))
+        .collect_vec();
+
+    writeln!(dest, "  after merging configs, visibilities, and docs:")?;
+    if merged.is_empty() {
+        writeln!(
+            dest,
+            "    (discarded by --strategy, a rename policy, or --prune-unused)"
+        )?;
+    }
+    for import in &merged {
+        write_indented(dest, 4, &import.to_string())?;
+    }
+
+    let grouped = group_flattened_items_normalize_wildcards(&flattened, &options.policy);
+    let grouped_imports = grouped
+        .iter()
+        .sorted_by_key(|&(&configs, _)| configs)
+        .flat_map(|(&configs, items)| {
+            items.iter().map(move |(&path, properties)| {
+                Import::from_flattened(
+                    path,
+                    configs,
+                    properties.visibility.map(|vis| vis.to_string()),
+                    properties.docs.blocks().to_vec(),
+                    properties.doc_attrs.to_vec(),
+                )
+            })
+        })
+        .collect_vec();
+
+    let kept = grouped_imports
+        .iter()
+        .filter(|import| query.matches(import))
+        .collect_vec();
+    let globs = grouped_imports
+        .iter()
+        .filter(|import| query.is_parent_glob(import))
+        .collect_vec();
+
+    if !merged.is_empty() {
+        writeln!(dest, "  in the output:")?;
+        if kept.is_empty() {
+            writeln!(dest, "    (subsumed by a glob import)")?;
+        }
+        for import in &kept {
+            write_indented(dest, 4, &import.to_string())?;
+        }
+    }
+
+    if !globs.is_empty() {
+        writeln!(dest, "  glob imports of the same module, in the output:")?;
+        for import in globs {
+            write_indented(dest, 4, &import.to_string())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Every import in a flattened set of use items
+fn flattened_imports(flattened: &NormalizedUsedItems<'_>) -> Vec<Import> {
+    flattened
+        .items
+        .iter()
+        .flat_map(|(path, properties)| {
+            properties.iter().map(move |(&configs, properties)| {
+                Import::from_flattened(
+                    path,
+                    configs,
+                    properties.visibility.map(|vis| vis.to_string()),
+                    properties.docs.blocks().to_vec(),
+                    properties.doc_attrs.to_vec(),
+                )
+            })
+        })
+        .collect()
+}
+
+fn write_indented(dest: &mut String, indent: usize, text: &str) -> fmt::Result {
+    text.lines()
+        .try_for_each(|line| writeln!(dest, "{:indent$}{line}", ""))
+}
@@ -27,30 +27,137 @@ use b::bbb::bbb2 as bbb3;
 Once in this form, it's easier to reason about certain normalizations.
  */
 
-use std::collections::BTreeMap;
+use std::{
+    cmp::Ordering,
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    iter, mem,
+    sync::Arc,
+};
 
-use syn::Ident;
+use itertools::Itertools;
 
 use crate::{
-    common::{NameUse, Rooted},
-    tree::{Branches, ConfigsList, DocsList, UseItem, Visibility},
+    cfg_rules::CfgRules,
+    common::{Ident, Interner, NameUse, Rooted, Unraw, UsageOrder},
+    policy::{Choice, Policy, RenameConflict},
+    tree::{Branches, ConfigsList, DocAttrsList, DocsList, UseItem, Visibility},
 };
 
+/// How to treat a leading `self::` in an import path. In 2018 edition and
+/// later, `use self::foo::Bar` and `use foo::Bar` usually refer to the same
+/// item, but not always (`foo` might be a dependency rather than a local
+/// module), so merging them is opt-in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SelfPrefixStyle {
+    /// `self::foo` and `foo` are distinct imports, and are rendered as they
+    /// were written
+    #[default]
+    Distinct,
+
+    /// `self::foo` and `foo` are the same import. If any spelling of the
+    /// import used `self::`, it's rendered with `self::`.
+    Explicit,
+
+    /// `self::foo` and `foo` are the same import, and it's always rendered
+    /// without `self::`
+    Implicit,
+}
+
+/// A rust edition, which determines what a leading `::` in an import path
+/// means (see `NormalizedUsedItems::normalize_roots`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Edition {
+    #[value(name = "2015")]
+    E2015,
+
+    #[value(name = "2018")]
+    E2018,
+
+    #[value(name = "2021")]
+    E2021,
+
+    #[value(name = "2024")]
+    E2024,
+}
+
+impl Edition {
+    /// Parse an edition the way it's written in a `Cargo.toml`
+    pub fn from_manifest(edition: &str) -> Option<Self> {
+        match edition {
+            "2015" => Some(Edition::E2015),
+            "2018" => Some(Edition::E2018),
+            "2021" => Some(Edition::E2021),
+            "2024" => Some(Edition::E2024),
+            _ => None,
+        }
+    }
+}
+
+/// Which imports from each side of a conflict end up in the merged output.
+/// Whichever imports are kept, they're normalized and formatted the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MergeStrategy {
+    /// Keep every import from both sides
+    #[default]
+    Union,
+
+    /// Only keep the imports that are on both sides (including imports that
+    /// are covered by a glob import on the other side)
+    Intersection,
+
+    /// Only keep the imports from the left side (`<<<<<<<`)
+    Ours,
+
+    /// Only keep the imports from the right side (`>>>>>>>`)
+    Theirs,
+}
+
 /// The very last item of a flattened import: either an identifier, a renamed
-/// identifier, or a wildcard.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+/// identifier, or a wildcard. Identifiers are compared by their unescaped
+/// value (see `Unraw`).
+#[derive(Debug, Clone)]
 pub enum UsedItemLeaf<'a> {
     // Note: it is important for correctness that `Wildcard` is the first
     // item in this list. It needs to be sorted earlier, so that it can be
     // merged with other items correctly during iteration.
     //
     // Similarly, it is important that `Used` is before rename, because renames
-    // towards `_` can be subsumed by identical uses or wildcards
+    // towards `_` can be subsumed by identical uses or wildcards. Usages of
+    // the same name are ordered by `UsageOrder`.
     Wildcard,
     Plain(&'a Ident, NameUse<&'a Ident>),
 }
 
-impl UsedItemLeaf<'_> {
+impl<'a> UsedItemLeaf<'a> {
+    fn sort_key(&self) -> Option<(Unraw<'a>, UsageOrder<'a>)> {
+        match *self {
+            UsedItemLeaf::Wildcard => None,
+            UsedItemLeaf::Plain(ident, usage) => Some((Unraw(ident), usage.order())),
+        }
+    }
+
+    /// True if this leaf is an anonymous import (`Trait as _`)
+    pub fn is_anonymous(&self) -> bool {
+        matches!(*self, UsedItemLeaf::Plain(_, usage) if usage.is_anonymous())
+    }
+
+    /// The number of raw identifiers (`r#ident`) in this leaf
+    fn raw_count(&self) -> usize {
+        match *self {
+            UsedItemLeaf::Wildcard => 0,
+            UsedItemLeaf::Plain(ident, usage) => {
+                let renamed = match usage {
+                    NameUse::Used => None,
+                    NameUse::Renamed(renamed) => Some(renamed),
+                };
+
+                Iterator::chain(iter::once(ident), renamed)
+                    .filter(|&ident| Unraw(ident).is_raw())
+                    .count()
+            }
+        }
+    }
+
     /// Check if this leaf is subsumed by another leaf. If it is, this leaf
     /// can be safely discarded (assuming that everything else lines up; ie,
     /// they both have identical visibilities, configs, etc). There are
@@ -65,41 +172,147 @@ impl UsedItemLeaf<'_> {
         match (possible_parent, self) {
             (UsedItemLeaf::Wildcard, UsedItemLeaf::Plain(_, usage)) => match usage {
                 NameUse::Used => true,
-                NameUse::Renamed(renamed) => *renamed == "_",
+                NameUse::Renamed(_) => usage.is_anonymous(),
             },
-            (
-                UsedItemLeaf::Plain(name1, NameUse::Used),
-                UsedItemLeaf::Plain(name2, NameUse::Renamed(renamed)),
-            ) => name1 == name2 && *renamed == "_",
+            (UsedItemLeaf::Plain(name1, NameUse::Used), UsedItemLeaf::Plain(name2, usage)) => {
+                Unraw(name1) == Unraw(name2) && usage.is_anonymous()
+            }
             _ => false,
         }
     }
+
+    /// Get all of the leaves that could subsume this one (see
+    /// `is_subsumed_by`).
+    fn possible_parents(&self) -> impl Iterator<Item = Self> {
+        let this = self.clone();
+
+        let parents = match *self {
+            UsedItemLeaf::Wildcard => [None, None],
+            UsedItemLeaf::Plain(name, _) => [
+                Some(UsedItemLeaf::Wildcard),
+                Some(UsedItemLeaf::Plain(name, NameUse::Used)),
+            ],
+        };
+
+        parents
+            .into_iter()
+            .flatten()
+            .filter(move |parent| this.is_subsumed_by(parent))
+    }
 }
 
-/// A complete path of a flattened import.
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+impl PartialEq for UsedItemLeaf<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.sort_key() == other.sort_key()
+    }
+}
+
+impl Eq for UsedItemLeaf<'_> {}
+
+impl PartialOrd for UsedItemLeaf<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for UsedItemLeaf<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // `None` sorts before `Some`, so wildcards come first
+        Ord::cmp(&self.sort_key(), &other.sort_key())
+    }
+}
+
+/// A complete path of a flattened import. Identifiers are compared by their
+/// unescaped value (see `Unraw`), so `core::r#try` and `core::try` are the
+/// same import.
+#[derive(Debug, Clone)]
 pub struct SingleUsedItem<'a> {
     /// If Rooted, there is a leading `::`
     pub rooted: Rooted,
 
-    /// The path segments preceding the leaf
-    pub path: Vec<&'a Ident>,
+    /// The path segments preceding the leaf. Every import under the same
+    /// prefix shares the same path.
+    pub path: Arc<[&'a Ident]>,
 
     /// The actual item being imoported
     pub leaf: UsedItemLeaf<'a>,
 }
 
-impl SingleUsedItem<'_> {
-    /// Check if this path is subsumed by another path. One path subsumes
-    /// if the prefix is identical (rooted with the same path) and one leaf
-    /// subsumes the other. See `UsedItemLeaf::is_subsumed_by` for more details.
+impl<'a> SingleUsedItem<'a> {
+    /// The first segment of this path, which is the leaf if the path has
+    /// no other segments
+    fn first_segment(&self) -> Option<&'a Ident> {
+        match (self.path.first(), &self.leaf) {
+            (Some(&first), _) => Some(first),
+            (None, &UsedItemLeaf::Plain(ident, _)) => Some(ident),
+            (None, UsedItemLeaf::Wildcard) => None,
+        }
+    }
+
+    /// The local name that this import brings into scope, if it brings a
+    /// single name into scope. An import of a crate under its own name
+    /// (`use ::serde;`) doesn't count, since the name still refers to the
+    /// crate.
+    fn bound_name(&self) -> Option<&'a Ident> {
+        match self.leaf {
+            UsedItemLeaf::Wildcard => None,
+            UsedItemLeaf::Plain(_, usage) if usage.is_anonymous() => None,
+            UsedItemLeaf::Plain(_, NameUse::Used)
+                if self.rooted == Rooted::Rooted && self.path.is_empty() =>
+            {
+                None
+            }
+            UsedItemLeaf::Plain(name, NameUse::Used) => Some(name),
+            UsedItemLeaf::Plain(_, NameUse::Renamed(alias)) => Some(alias),
+        }
+    }
+
+    fn unraw_path(&self) -> impl Iterator<Item = Unraw<'_>> + '_ {
+        self.path.iter().map(|&ident| Unraw(ident))
+    }
+
+    /// The number of raw identifiers (`r#ident`) in this path. When two
+    /// spellings of the same path are merged, we prefer the one with more
+    /// raw identifiers, since it's more likely to be valid in every edition.
+    fn raw_count(&self) -> usize {
+        self.unraw_path().filter(Unraw::is_raw).count() + self.leaf.raw_count()
+    }
+
+    /// Get all of the paths that could subsume this one. One path subsumes
+    /// another if the prefix is identical (rooted with the same path) and
+    /// one leaf subsumes the other. See `UsedItemLeaf::is_subsumed_by` for
+    /// more details.
     ///
     /// If a path is subsumed, it can be safely discarded, assuming that
-    /// everything else lines up (identical visibilities, configs, etv).
-    pub fn is_subsumed_by(&self, possible_parent: &Self) -> bool {
-        self.rooted == possible_parent.rooted
-            && self.path == possible_parent.path
-            && self.leaf.is_subsumed_by(&possible_parent.leaf)
+    /// everything else lines up (identical visibilities, configs, etc).
+    pub fn possible_parents(&self) -> impl Iterator<Item = Self> + '_ {
+        self.leaf.possible_parents().map(|leaf| SingleUsedItem {
+            rooted: self.rooted,
+            path: self.path.clone(),
+            leaf,
+        })
+    }
+}
+
+impl PartialEq for SingleUsedItem<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SingleUsedItem<'_> {}
+
+impl PartialOrd for SingleUsedItem<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SingleUsedItem<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Ord::cmp(&self.rooted, &other.rooted)
+            .then_with(|| Iterator::cmp(self.unraw_path(), other.unraw_path()))
+            .then_with(|| Ord::cmp(&self.leaf, &other.leaf))
     }
 }
 
@@ -111,37 +324,79 @@ impl SingleUsedItem<'_> {
 pub struct UsedItemPropertiesGroup<'a> {
     pub visibility: Option<&'a Visibility>,
     pub docs: DocsList,
+
+    /// The number of imports in the use item that the docs came from (see
+    /// `UseItem::import_count`). The fewer there are, the more specifically
+    /// the docs are about this import.
+    pub docs_breadth: usize,
+
+    /// List-style doc attributes, like `#[doc(hidden)]`
+    pub doc_attrs: DocAttrsList,
 }
 
 impl<'a> UsedItemPropertiesGroup<'a> {
-    pub fn merge(&mut self, visibility: Option<&'a Visibility>, docs: &DocsList) {
-        self.visibility = merge_visibilities(self.visibility, visibility);
-        self.docs.combine(docs);
+    pub fn merge(&mut self, other: &Self, policy: &Policy) {
+        self.visibility = merge_visibilities(policy, self.visibility, other.visibility);
+        self.doc_attrs.union(&other.doc_attrs);
+
+        let docs_differ =
+            self.docs.is_not_empty() && other.docs.is_not_empty() && self.docs != other.docs;
+
+        match docs_differ.then(|| policy.merge_docs(&self.docs, &other.docs)) {
+            Some(Some(docs)) => self.docs = docs,
+
+            // If one side documented this import on its own, and the other
+            // only documented it as part of a larger group, the narrower docs
+            // are the ones that belong to it
+            Some(None) if self.docs_breadth != other.docs_breadth => {
+                if other.docs_breadth < self.docs_breadth {
+                    self.docs = other.docs.clone();
+                    self.docs_breadth = other.docs_breadth;
+                }
+            }
+            _ => {
+                if other.docs.is_not_empty() {
+                    self.docs_breadth = match self.docs.is_not_empty() {
+                        true => usize::min(self.docs_breadth, other.docs_breadth),
+                        false => other.docs_breadth,
+                    };
+                }
+
+                self.docs.combine(&other.docs)
+            }
+        }
     }
 }
 
-/// Merge a pair of visibilities. The "more public" visibility takes priority.
+/// Merge a pair of visibilities. The "more public" visibility takes priority,
+/// unless the policy says otherwise.
 fn merge_visibilities<'a>(
+    policy: &Policy,
     vis1: Option<&'a Visibility>,
     vis2: Option<&'a Visibility>,
 ) -> Option<&'a Visibility> {
     use Visibility::*;
 
+    if vis1 != vis2 {
+        match policy.merge_visibility(vis1, vis2) {
+            Some(Choice::First) => return vis1,
+            Some(Choice::Second) => return vis2,
+            None => {}
+        }
+    }
+
     match (vis1, vis2) {
         (None, vis) | (vis, None) => vis,
         (Some(vis1), Some(vis2)) => Some(match (vis1, vis2) {
-            (This, vis) | (vis, This) => vis,
             (Super, vis) | (vis, Super) => vis,
 
             // Paths are always an ancestor module, so whichever one is shorter
             // is more public. We assume (technically incorrectly) that a
             // `self` and `super` path is always more private than an `in` path.
-            (vis1 @ In(ref path1), vis2 @ In(ref path2)) => {
-                match path1.segments.len() < path2.segments.len() {
-                    true => vis1,
-                    false => vis2,
-                }
-            }
+            (vis1 @ In(ref path1), vis2 @ In(ref path2)) => match path1.len() < path2.len() {
+                true => vis1,
+                false => vis2,
+            },
 
             (In(_), vis) | (vis, In(_)) => vis,
             (Crate, vis) | (vis, Crate) => vis,
@@ -150,44 +405,56 @@ fn merge_visibilities<'a>(
     }
 }
 
-/// Add the properties of a use item to the set of groups associated with
-/// a particular path. In addition to an insertion, this function takes care
-/// of:
+/// The more public of a pair of visibilities, as they'd be merged without a
+/// policy
+pub(crate) fn wider_visibility<'a>(
+    vis1: Option<&'a Visibility>,
+    vis2: Option<&'a Visibility>,
+) -> Option<&'a Visibility> {
+    merge_visibilities(&Policy::default(), vis1, vis2)
+}
+
+/// Add a set of properties, under some configs, to the set of groups
+/// associated with a particular path. In addition to an insertion, this
+/// function takes care of:
 ///
 /// - merging properties that exist under identical configs
 /// - merging ALL properties if ANY unconditional properties exist. We do this
 ///   because we should never perform a conditional import and an unconditional
 ///   import of the same item.
-fn add_properties<'a>(
+pub fn add_properties<'a>(
     properties_groups: &mut BTreeMap<&'a ConfigsList, UsedItemPropertiesGroup<'a>>,
-    item: &'a UseItem,
+    configs: &'a ConfigsList,
+    properties: UsedItemPropertiesGroup<'a>,
+    policy: &Policy,
 ) {
     // If there's an unconditional group, merge into it
-    let group = if let Some(unconditional_group) = properties_groups.get_mut(&ConfigsList::EMPTY) {
-        unconditional_group
-    }
-    // If the incoming item is unconditional, merge ALL groups and replace
-    // with a new unconditional group
-    else if item.configs.is_empty() {
-        let merged = properties_groups.values().fold(
-            UsedItemPropertiesGroup::default(),
-            |mut merged, props| {
-                merged.merge(props.visibility, &props.docs);
+    if let Some(unconditional_group) = properties_groups.get_mut(&ConfigsList::EMPTY) {
+        unconditional_group.merge(&properties, policy);
+    }
+    // If the incoming properties are unconditional, merge ALL groups and
+    // replace with a new unconditional group
+    else if configs.is_empty() {
+        let merged = mem::take(properties_groups)
+            .into_values()
+            .chain([properties])
+            .reduce(|mut merged, props| {
+                merged.merge(&props, policy);
                 merged
-            },
-        );
+            })
+            .expect("there's always at least one group");
 
-        properties_groups.clear();
-        properties_groups
-            .entry(const { &ConfigsList::EMPTY })
-            .or_insert(merged)
+        properties_groups.insert(const { &ConfigsList::EMPTY }, merged);
     }
     // Otherwise, merge into the existing group
     else {
-        properties_groups.entry(&item.configs).or_default()
-    };
-
-    group.merge(item.visibility.as_ref(), &item.docs);
+        match properties_groups.entry(configs) {
+            Entry::Occupied(group) => group.into_mut().merge(&properties, policy),
+            Entry::Vacant(group) => {
+                group.insert(properties);
+            }
+        }
+    }
 }
 
 /// A flattened list of import paths, associated with all of the properties
@@ -196,89 +463,401 @@ fn add_properties<'a>(
 #[derive(Default)]
 pub struct NormalizedUsedItems<'a> {
     pub items: BTreeMap<SingleUsedItem<'a>, BTreeMap<&'a ConfigsList, UsedItemPropertiesGroup<'a>>>,
+
+    self_prefix_style: SelfPrefixStyle,
+    policy: Policy,
+
+    /// When `self::` prefixes are being normalized, this is the set of
+    /// (stripped) paths that were written with a `self::` prefix in at least
+    /// one of their spellings, along with the `self` identifier itself.
+    self_prefixed: BTreeMap<SingleUsedItem<'a>, &'a Ident>,
+
+    /// The identifiers in the paths, so that each spelling of an identifier
+    /// is only ever compared by address
+    interner: Interner<'a>,
+
+    /// The conflict half that the docs of the use item currently being added
+    /// came from (see `add_tree_from`)
+    docs_origin: Option<Arc<str>>,
 }
 
 impl<'a> NormalizedUsedItems<'a> {
+    pub fn new(self_prefix_style: SelfPrefixStyle, policy: Policy) -> Self {
+        Self {
+            self_prefix_style,
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// Add the entire tree of a `UseItem` to this list, tagging its docs
+    /// with the label of the conflict half it came from (see
+    /// `DocsList::with_origin`).
+    pub fn add_tree_from(&mut self, items: &'a UseItem, origin: Option<Arc<str>>) {
+        self.docs_origin = origin;
+        self.add_tree(items);
+        self.docs_origin = None;
+    }
+
     /// Add the entire tree of a `UseItem` to this list.
     pub fn add_tree(&mut self, items: &'a UseItem) {
         for (root, branches) in &items.children {
-            self.add_branches(
-                root.rooted,
-                PathChain {
-                    prev: None,
-                    ident: &root.identifier,
-                },
-                &items,
-                branches,
-            )
+            let strip_self = self.self_prefix_style != SelfPrefixStyle::Distinct
+                && root.rooted == Rooted::Unrooted
+                && root.identifier == "self";
+
+            if strip_self {
+                // Strip the `self::` and treat each of its children as a root
+                // in its own right. `self::*` can't be expressed without the
+                // prefix, so it keeps it.
+                let path = Arc::from([]);
+                self.add_leaves(root.rooted, &path, &root.identifier, items, branches, None);
+
+                for (child, subtree) in &branches.children {
+                    let self_prefix = Some(&root.identifier);
+                    self.add_branches(root.rooted, &path, child, items, subtree, self_prefix)
+                }
+            } else {
+                let path = Arc::from([]);
+                self.add_branches(root.rooted, &path, &root.identifier, items, branches, None)
+            }
         }
     }
 
-    /// Add a set of branches, at a path prefix, to this list.
-    fn add_branches(
+    /// Add a single flattened path to this list. If `self_prefix` is given,
+    /// a leading `self::` was stripped from this path.
+    fn add_item(
+        &mut self,
+        item: SingleUsedItem<'a>,
+        use_item: &'a UseItem,
+        self_prefix: Option<&'a Ident>,
+    ) {
+        if let Some(self_prefix) = self_prefix {
+            let self_prefix = self.interner.intern(self_prefix);
+            self.self_prefixed.insert(item.clone(), self_prefix);
+        }
+
+        let properties = UsedItemPropertiesGroup {
+            visibility: use_item.visibility.as_ref(),
+            docs: use_item.docs.clone().with_origin(self.docs_origin.clone()),
+            docs_breadth: use_item.import_count,
+            doc_attrs: use_item.doc_attrs.clone(),
+        };
+
+        self.merge_item(item, [(&use_item.configs, properties)]);
+    }
+
+    /// Add a path with some properties to this list, merging them with the
+    /// properties of the same path if it's already present. If it was added
+    /// with a different spelling (`r#try` vs `try`), keep whichever spelling
+    /// has more raw identifiers.
+    fn merge_item(
+        &mut self,
+        item: SingleUsedItem<'a>,
+        groups: impl IntoIterator<Item = (&'a ConfigsList, UsedItemPropertiesGroup<'a>)>,
+    ) {
+        let (item, mut properties) = match self.items.remove_entry(&item) {
+            Some((existing, properties)) if existing.raw_count() >= item.raw_count() => {
+                (existing, properties)
+            }
+            Some((_, properties)) => (item, properties),
+            None => (item, BTreeMap::new()),
+        };
+
+        for (configs, group) in groups {
+            add_properties(&mut properties, configs, group, &self.policy);
+        }
+
+        self.items.insert(item, properties);
+    }
+
+    /// Add the wildcard and usages of `ident`, at the end of a path, to this
+    /// list, without descending into the children.
+    fn add_leaves(
         &mut self,
         rooted: Rooted,
-        prefix: PathChain<'_, 'a>,
+        path: &Arc<[&'a Ident]>,
+        ident: &'a Ident,
         use_item: &'a UseItem,
         branches: &'a Branches,
+        self_prefix: Option<&'a Ident>,
     ) {
+        let ident = self.interner.intern(ident);
+
         if branches.wildcard {
             let item = SingleUsedItem {
                 rooted,
-                path: prefix.to_list(),
+                path: extend_path(path, ident),
                 leaf: UsedItemLeaf::Wildcard,
             };
 
-            let entry = self.items.entry(item).or_default();
-            add_properties(entry, use_item);
+            self.add_item(item, use_item, self_prefix);
         }
 
-        let leaf = prefix.ident;
-        let path = prefix.prev;
-
         for usage in &branches.used {
+            let usage = match usage.as_ref() {
+                NameUse::Used => NameUse::Used,
+                NameUse::Renamed(alias) => NameUse::Renamed(self.interner.intern(alias)),
+            };
+
             let item = SingleUsedItem {
                 rooted,
-                path: path.map(PathChain::to_list).unwrap_or_default(),
-                leaf: UsedItemLeaf::Plain(leaf, usage.as_ref()),
+                path: Arc::clone(path),
+                leaf: UsedItemLeaf::Plain(ident, usage),
             };
 
-            let entry = self.items.entry(item).or_default();
-            add_properties(entry, use_item);
+            self.add_item(item, use_item, self_prefix);
         }
+    }
+
+    /// Add a set of branches, under `ident` at the end of a path, to this
+    /// list.
+    fn add_branches(
+        &mut self,
+        rooted: Rooted,
+        path: &Arc<[&'a Ident]>,
+        ident: &'a Ident,
+        use_item: &'a UseItem,
+        branches: &'a Branches,
+        self_prefix: Option<&'a Ident>,
+    ) {
+        let ident = self.interner.intern(ident);
+        self.add_leaves(rooted, path, ident, use_item, branches, self_prefix);
+
+        if branches.children.is_empty() {
+            return;
+        }
+
+        let path = extend_path(path, ident);
 
         for (child, subtree) in &branches.children {
-            let prefix = PathChain {
-                prev: Some(&prefix),
-                ident: child,
+            self.add_branches(rooted, &path, child, use_item, subtree, self_prefix)
+        }
+    }
+
+    /// Discard every private anonymous import (`use a::Trait as _`) whose
+    /// item is also imported by name (`use a::Trait`) or by a wildcard
+    /// (`use a::*`), even if the two imports have different configs. Unlike
+    /// the usual subsumption, this can lose an import in some configurations,
+    /// so it's opt-in. Public anonymous imports are always kept, since they
+    /// re-export the trait.
+    pub fn discard_shadowed_anonymous_imports(&mut self) {
+        let shadowed: Vec<SingleUsedItem<'a>> = self
+            .items
+            .iter()
+            .filter(|(item, _)| item.leaf.is_anonymous())
+            .filter(|(_, properties)| {
+                properties
+                    .values()
+                    .all(|properties| properties.visibility.is_none())
+            })
+            .filter(|&(item, properties)| {
+                item.possible_parents().any(|parent| {
+                    self.items
+                        .get_key_value(&parent)
+                        .is_some_and(|(parent, parent_properties)| {
+                            properties.iter().all(|(&configs, properties)| {
+                                parent_properties.iter().all(
+                                    |(&parent_configs, parent_properties)| {
+                                        self.policy.allow_subsumption(
+                                            (item, configs, properties),
+                                            (parent, parent_configs, parent_properties),
+                                        )
+                                    },
+                                )
+                            })
+                        })
+                })
+            })
+            .map(|(item, _)| item.clone())
+            .collect();
+
+        shadowed.iter().for_each(|item| {
+            self.items.remove(item);
+        });
+    }
+
+    /// Resolve every item that's imported under more than one name (see
+    /// `RenameConflict`) with the policy. When the policy picks a single
+    /// name, the other imports are discarded, and their properties are
+    /// merged into the one that's kept. This must be called after
+    /// `apply_self_prefix_style`.
+    pub fn resolve_rename_conflicts(&mut self) {
+        if self.policy.is_default() {
+            return;
+        }
+
+        // Imports of the same name are adjacent, since they're sorted by
+        // path and then by name.
+        let mut conflicts: Vec<Vec<SingleUsedItem<'a>>> = Vec::new();
+
+        for item in self.items.keys().filter(|item| !item.leaf.is_anonymous()) {
+            let UsedItemLeaf::Plain(name, _) = item.leaf else {
+                continue;
             };
 
-            self.add_branches(rooted, prefix, use_item, subtree)
+            match conflicts.last_mut() {
+                Some(conflict)
+                    if conflict.first().is_some_and(|first| {
+                        first.rooted == item.rooted
+                            && Iterator::eq(first.unraw_path(), item.unraw_path())
+                            && matches!(first.leaf, UsedItemLeaf::Plain(first, _) if Unraw(first) == Unraw(name))
+                    }) =>
+                {
+                    conflict.push(item.clone())
+                }
+                _ => conflicts.push(vec![item.clone()]),
+            }
+        }
+
+        for conflict in conflicts.into_iter().filter(|conflict| conflict.len() > 1) {
+            let first = &conflict[0];
+            let UsedItemLeaf::Plain(name, _) = first.leaf else {
+                continue;
+            };
+
+            let description = RenameConflict {
+                rooted: first.rooted == Rooted::Rooted,
+                path: first.path.iter().map(|ident| ident.to_string()).collect(),
+                name: name.to_string(),
+                aliases: conflict
+                    .iter()
+                    .map(|item| match item.leaf {
+                        UsedItemLeaf::Plain(_, NameUse::Renamed(alias)) => Some(alias.to_string()),
+                        _ => None,
+                    })
+                    .collect(),
+            };
+
+            let Some(keep) = self.policy.resolve_rename_conflict(&description) else {
+                continue;
+            };
+
+            for (index, item) in conflict.iter().enumerate() {
+                if index == keep {
+                    continue;
+                }
+
+                let Some(discarded) = self.items.remove(item) else {
+                    continue;
+                };
+
+                let kept = self
+                    .items
+                    .get_mut(&conflict[keep])
+                    .expect("kept import is still present");
+
+                for (configs, properties) in discarded {
+                    add_properties(kept, configs, properties, &self.policy);
+                }
+            }
         }
     }
-}
 
-/// Linked list structure describing the path of a set of branches.
-struct PathChain<'s, 'ident> {
-    prev: Option<&'s PathChain<'s, 'ident>>,
-    ident: &'ident Ident,
-}
+    /// Merge each import whose configs imply the configs of another import
+    /// of the same path into that import, since it's redundant (see
+    /// `CfgRules`). If two sets of configs imply each other, the import
+    /// under the simpler configs is kept.
+    pub fn apply_cfg_rules(&mut self, rules: &CfgRules) {
+        let simpler = |a: &ConfigsList, b: &ConfigsList| {
+            Ord::cmp(&(a.complexity(), a), &(b.complexity(), b)).is_lt()
+        };
+
+        for groups in self.items.values_mut() {
+            loop {
+                let redundant =
+                    groups
+                        .keys()
+                        .cartesian_product(groups.keys())
+                        .find(|&(&configs, &implied)| {
+                            configs != implied
+                                && rules.list_implies(configs, implied)
+                                && (!rules.list_implies(implied, configs)
+                                    || simpler(implied, configs))
+                        });
+
+                let Some((&configs, &implied)) = redundant else {
+                    break;
+                };
+
+                let properties = groups.remove(configs).expect("the group is in the map");
+
+                groups
+                    .get_mut(implied)
+                    .expect("the group is in the map")
+                    .merge(&properties, &self.policy);
+            }
+        }
+    }
 
-impl<'s, 'ident> PathChain<'s, 'ident> {
-    /// Recursive helper for `to_list` that builds up the capacity of the
-    /// vector with each step.
-    fn to_list_capacity(&self, capacity: usize) -> Vec<&'ident Ident> {
-        let mut vec = self
-            .prev
-            .map(|prev| prev.to_list_capacity(capacity + 1))
-            .unwrap_or_else(|| Vec::with_capacity(capacity));
+    /// Drop the docs from every import (see `MergeOptions::strip_docs`)
+    pub fn strip_docs(&mut self) {
+        self.items
+            .values_mut()
+            .flat_map(BTreeMap::values_mut)
+            .for_each(|properties| properties.docs = DocsList::default());
+    }
+
+    /// Finish normalizing `self::` prefixes. When the style is `Explicit`,
+    /// every path that was written with `self::` in any of its spellings gets
+    /// it back. This must be called after all of the trees have been added.
+    pub fn apply_self_prefix_style(&mut self) {
+        if self.self_prefix_style != SelfPrefixStyle::Explicit {
+            return;
+        }
 
-        vec.push(self.ident);
-        vec
+        let self_prefixed = mem::take(&mut self.self_prefixed);
+
+        self.items = mem::take(&mut self.items)
+            .into_iter()
+            .map(|(mut item, properties)| {
+                if let Some(&self_prefix) = self_prefixed.get(&item) {
+                    item.path = iter::once(self_prefix)
+                        .chain(item.path.iter().copied())
+                        .collect();
+                }
+
+                (item, properties)
+            })
+            .collect();
     }
 
-    /// Convert this path chain into a vector of identifiers.
-    pub fn to_list(&self) -> Vec<&'ident Ident> {
-        self.to_list_capacity(1)
+    /// Remove the leading `::` from each path that has one, so that paths
+    /// are written the same way regardless of which edition's style they
+    /// were written in. In the 2015 edition, `::a` and `a` are always the
+    /// same path (both are relative to the crate root). In later editions,
+    /// `::a` is always a crate, but `a` might be a local name instead, so the
+    /// `::` is kept if `a` is also imported here or is one of `local_names`
+    /// (like the module declarations in the same section). This must be
+    /// called after `apply_self_prefix_style`.
+    pub fn normalize_roots(
+        &mut self,
+        edition: Edition,
+        local_names: impl IntoIterator<Item = &'a Ident>,
+    ) {
+        let local_names: BTreeSet<Unraw<'a>> = Iterator::chain(
+            local_names.into_iter(),
+            self.items.keys().filter_map(SingleUsedItem::bound_name),
+        )
+        .map(Unraw)
+        .collect();
+
+        for (mut item, properties) in mem::take(&mut self.items) {
+            let strip = item.rooted == Rooted::Rooted
+                && item.first_segment().is_some_and(|first| {
+                    edition == Edition::E2015 || !local_names.contains(&Unraw(first))
+                });
+
+            if strip {
+                item.rooted = Rooted::Unrooted;
+            }
+
+            self.merge_item(item, properties);
+        }
     }
 }
+
+/// Add an identifier to the end of a path
+fn extend_path<'a>(path: &[&'a Ident], ident: &'a Ident) -> Arc<[&'a Ident]> {
+    path.iter().copied().chain([ident]).collect()
+}
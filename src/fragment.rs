@@ -0,0 +1,111 @@
+/*!
+Extraction of use items from a fragment of a file, such as a single conflicted
+hunk with a few lines of context (see `ConflictedFile::parse_fragment`). A
+fragment usually isn't valid rust on its own (it might start in the middle of
+a function, or end in the middle of a use item), so we can't hand the whole
+thing to `syn`. Instead, we scan it line by line for anything that looks like
+the start of a use item (or module declaration), and parse each candidate on
+its own.
+
+Each candidate is parsed with enough leading newlines that `syn` reports the
+same line numbers it would have if it had parsed the whole fragment, so the
+rest of the pipeline can't tell the difference.
+ */
+
+use crate::{SectionTracker, SynItem, SynUseItem};
+
+/// Find all of the use items and module declarations in a fragment of one
+/// version of a file, given its lines. Use items are split into sections by
+/// any other non-blank line, except for plain comments and `extern crate`
+/// items.
+pub fn collect_fragment_use_items(lines: &[&str]) -> Vec<SynUseItem> {
+    let mut sections = SectionTracker::default();
+    let mut use_items = Vec::new();
+    let mut line = 0;
+
+    while let Some(content) = lines.get(line).map(|content| content.trim()) {
+        if content.is_empty() {
+            line += 1;
+            continue;
+        }
+
+        if let Some((item, end)) = parse_item_at(lines, line) {
+            use_items.push(SynUseItem {
+                item,
+                module: Vec::new(),
+                module_start: None,
+                section: sections.add_use_item(),
+                preamble_end: None,
+            });
+
+            line = end + 1;
+            continue;
+        }
+
+        if !is_neutral_line(content) {
+            sections.end_section();
+        }
+
+        line += 1;
+    }
+
+    use_items
+}
+
+/// True if this (trimmed, non-blank) line can appear between use items
+/// without ending their section
+fn is_neutral_line(content: &str) -> bool {
+    let is_plain_comment =
+        content.starts_with("//") && !content.starts_with("///") && !content.starts_with("//!");
+
+    let is_extern_crate = content.starts_with("extern crate ") && content.ends_with(';');
+
+    is_plain_comment || is_extern_crate
+}
+
+/// If a use item or module declaration starts at the given (zero-indexed)
+/// line, parse it, and return it along with the index of its last line.
+fn parse_item_at(lines: &[&str], start: usize) -> Option<(SynItem, usize)> {
+    let first = lines[start].trim_start();
+
+    let looks_like_item = ["use ", "use::", "mod ", "pub ", "pub(", "#[", "///", "/**"]
+        .iter()
+        .any(|prefix| first.starts_with(prefix));
+
+    if !looks_like_item {
+        return None;
+    }
+
+    // The item ends at the first `;` outside of any braces. We don't
+    // need to be precise about it, since `syn` has the final say.
+    let mut depth: isize = 0;
+
+    let end = lines[start..].iter().position(|line| {
+        line.chars().any(|c| {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ';' if depth <= 0 => return true,
+                _ => {}
+            }
+
+            false
+        })
+    })? + start;
+
+    // Pad with newlines so that the line numbers in the spans are relative
+    // to the fragment, rather than to the start of the item
+    let mut source = "\n".repeat(start);
+    lines[start..=end].iter().for_each(|line| {
+        source.push_str(line);
+        source.push('\n');
+    });
+
+    match syn::parse_str(&source).ok()? {
+        syn::Item::Use(use_item) => Some((SynItem::Use(use_item), end)),
+        syn::Item::Mod(mod_item @ syn::ItemMod { content: None, .. }) => {
+            Some((SynItem::Mod(mod_item), end))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,460 @@
+/*!
+`--from-index`: merge a conflicted file from the versions in the git index,
+rather than from the conflict markers in the worktree file. An unmerged path
+has up to three stages in the index: the common ancestor (stage 1), our
+version (stage 2), and their version (stage 3). Those are merged with `git
+merge-file` into a file with diff3-style conflicts, regardless of how the
+worktree file was rendered (`merge.conflictStyle`, rerere, or hand edits),
+and then the use items are merged as usual and the result is written to the
+worktree file.
+
+Because the common ancestor is available, the merge is deletion-aware: an
+import that was in the ancestor and was deleted on one side, but left alone
+on the other, is discarded (see `MergeOptions::discard`), instead of being
+brought back by the union of both sides.
+
+`--merge-base` brings the same deletion-awareness to a conflicted file read
+from stdin, even if its conflicts don't include the common ancestor (the
+default two-way conflict style). The ancestor, our version, and their
+version of the file at `--path` are read from the commits involved in the
+merge, rebase, or cherry-pick that's in progress, and only used to find the
+deleted imports; the file itself is merged as usual.
+
+If the file was deleted on one side and modified on the other (a
+delete/modify conflict), there are no use items to merge: merging the
+remaining side's use items would bring back a file that one side deleted, so
+usefix refuses, with a `DeleteModifyConflict` error, and leaves the worktree
+file alone. Whether to keep the file is up to whoever is resolving the merge,
+with `git add` or `git rm`. A side that emptied the file, rather than deleting
+it, is merged as usual, and contributes no imports.
+
+Everything is read with the `git` command, which must be on the `PATH`.
+ */
+
+use std::{
+    collections::HashSet,
+    env,
+    ffi::OsStr,
+    fmt, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::Context;
+use usefix::{
+    ConflictedFile, Import, ImportChange, ImportModel, MergeOptions, Outcome, RemainingConflict,
+};
+
+/// The result of merging the stages of an unmerged path
+pub struct IndexMerge {
+    /// The merged file, to be written to the worktree file
+    pub fixed: String,
+
+    /// How many of the conflicts were resolved
+    pub outcome: Outcome,
+
+    /// The conflicts left in the merged file
+    pub remaining_conflicts: Vec<RemainingConflict>,
+}
+
+/// The error for an unmerged path that was deleted on one side of the merge
+/// (see the module docs). usefix exits with `DeleteModifyConflict::STATUS`
+/// when it fails with this error, so that scripts can tell it apart.
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "{} was deleted in {deleted} version, so there are no use items to merge; resolve it with \
+     `git add` or `git rm`",
+    path.display()
+)]
+pub struct DeleteModifyConflict {
+    path: PathBuf,
+    deleted: DeletedSide,
+}
+
+impl DeleteModifyConflict {
+    /// The exit status for this error
+    pub const STATUS: i32 = 3;
+}
+
+/// Which side of a delete/modify conflict deleted the file
+#[derive(Debug, Clone, Copy)]
+enum DeletedSide {
+    Ours,
+    Theirs,
+    Both,
+}
+
+impl fmt::Display for DeletedSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DeletedSide::Ours => "our",
+            DeletedSide::Theirs => "their",
+            DeletedSide::Both => "each",
+        })
+    }
+}
+
+/// Merge the stages of an unmerged path in the index, and return the merged
+/// file (to be written to the worktree file at that path).
+pub fn fix_from_index(path: &Path, options: &MergeOptions) -> anyhow::Result<IndexMerge> {
+    let stages = unmerged_stages(path)?;
+
+    let (ours, theirs) = match (stages[1].as_deref(), stages[2].as_deref()) {
+        (Some(ours), Some(theirs)) => (ours, theirs),
+        (ours, theirs) => {
+            let deleted = match (ours, theirs) {
+                (None, Some(_)) => DeletedSide::Ours,
+                (Some(_), None) => DeletedSide::Theirs,
+                _ => DeletedSide::Both,
+            };
+
+            return Err(DeleteModifyConflict {
+                path: path.to_owned(),
+                deleted,
+            }
+            .into());
+        }
+    };
+    let base = stages[0].as_deref();
+
+    let conflicted = merge_file(base.unwrap_or(""), ours, theirs)?;
+
+    let discard = match base {
+        Some(base) => deleted_imports(base, ours, theirs, options)?,
+        None => Vec::new(),
+    };
+
+    let options = MergeOptions {
+        require_conflict: false,
+        discard: Iterator::chain(options.discard.iter().cloned(), discard).collect(),
+        ..options.clone()
+    };
+
+    let file = ConflictedFile::parse(&conflicted)?;
+    let fixed = file.merge_imports(&options)?.render();
+
+    Ok(IndexMerge {
+        outcome: file.outcome(&fixed, &options),
+        remaining_conflicts: file.remaining_conflicts(&fixed),
+        fixed,
+    })
+}
+
+/// Read the contents of each stage (ancestor, ours, and theirs) of an
+/// unmerged path from the index. A stage is `None` if it's missing, like the
+/// ancestor of a file that was added on both sides.
+fn unmerged_stages(path: &Path) -> anyhow::Result<[Option<String>; 3]> {
+    let (dir, name) = split_path(path)?;
+
+    let listing = git(
+        dir,
+        Command::new("git")
+            .args(["ls-files", "--unmerged", "-z", "--"])
+            .arg(name),
+    )?;
+
+    let mut stages = [None, None, None];
+
+    // Each entry looks like `<mode> <object> <stage>\t<path>`
+    for entry in listing.split('\0').filter(|entry| !entry.is_empty()) {
+        let (info, _) = entry
+            .split_once('\t')
+            .with_context(|| format!("unexpected output from git ls-files: {entry:?}"))?;
+
+        let (object, stage) = match info.split(' ').collect::<Vec<_>>().as_slice() {
+            &[_, object, stage] => (object, stage),
+            _ => anyhow::bail!("unexpected output from git ls-files: {entry:?}"),
+        };
+
+        let slot = match stage {
+            "1" => &mut stages[0],
+            "2" => &mut stages[1],
+            "3" => &mut stages[2],
+            _ => anyhow::bail!("unexpected stage in git ls-files: {entry:?}"),
+        };
+
+        *slot = Some(git(
+            dir,
+            Command::new("git").args(["cat-file", "blob", object]),
+        )?);
+    }
+
+    if stages.iter().all(Option::is_none) {
+        anyhow::bail!("{} isn't an unmerged path in the git index", path.display());
+    }
+
+    Ok(stages)
+}
+
+/// Find the imports that were deleted on one side of the merge, rebase, or
+/// cherry-pick in progress in the repository containing `path`, and left
+/// unchanged on the other, using the versions of that file in the commits
+/// involved.
+pub fn merge_base_deleted_imports(
+    path: &Path,
+    options: &MergeOptions,
+) -> anyhow::Result<Vec<Import>> {
+    let (dir, name) = split_path(path)?;
+    let rev_exists = |rev: &str| {
+        Command::new("git")
+            .args(["rev-parse", "--verify", "--quiet", rev])
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    };
+
+    // A merge's ancestor is the merge base, but a rebase or cherry-pick
+    // applies a single commit, so its ancestor is that commit's parent
+    let (theirs, base) = if rev_exists("MERGE_HEAD") {
+        let base = git(
+            dir,
+            Command::new("git").args(["merge-base", "HEAD", "MERGE_HEAD"]),
+        )?;
+        ("MERGE_HEAD", base.trim().to_owned())
+    } else if let Some(head) = ["REBASE_HEAD", "CHERRY_PICK_HEAD"]
+        .into_iter()
+        .find(|&head| rev_exists(head))
+    {
+        (head, format!("{head}^"))
+    } else {
+        anyhow::bail!("--merge-base requires a merge, rebase, or cherry-pick in progress");
+    };
+
+    let show = |rev: &str| -> anyhow::Result<Option<String>> {
+        let spec = format!("{rev}:./{}", name.to_string_lossy());
+
+        match rev_exists(&spec) {
+            false => Ok(None),
+            true => git(dir, Command::new("git").args(["show", &spec])).map(Some),
+        }
+    };
+
+    // If the file is missing from any version, there's nothing to compare
+    match (show(&base)?, show("HEAD")?, show(theirs)?) {
+        (Some(base), Some(ours), Some(theirs)) => deleted_imports(&base, &ours, &theirs, options),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Simulate a merge of two revisions of the repository containing `dir`: for
+/// each rust file under `dir` that was changed on both sides since their
+/// merge base, merge the two versions with `git merge-file`, like `git merge`
+/// would. Returns the path of each file (relative to `dir`), along with its
+/// merged contents, which may or may not have conflicts. Files that were
+/// deleted on either side are skipped.
+pub fn simulate_merge(
+    dir: &Path,
+    ours: &str,
+    theirs: &str,
+) -> anyhow::Result<Vec<(PathBuf, String)>> {
+    let base = git(dir, Command::new("git").args(["merge-base", ours, theirs]))?;
+    let base = base.trim();
+
+    let changed = |rev: &str| -> anyhow::Result<Vec<String>> {
+        let listing = git(
+            dir,
+            Command::new("git")
+                .args(["diff", "--name-only", "--relative", "-z", base, rev, "--"])
+                .arg("*.rs"),
+        )?;
+
+        Ok(listing
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(str::to_owned)
+            .collect())
+    };
+
+    let ours_changed = changed(ours)?;
+    let theirs_changed = changed(theirs)?;
+
+    // A file that's missing from a revision is `None`
+    let show = |rev: &str, path: &str| -> anyhow::Result<Option<String>> {
+        let spec = format!("{rev}:./{path}");
+
+        let exists = Command::new("git")
+            .args(["cat-file", "-e", &spec])
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+
+        match exists {
+            false => Ok(None),
+            true => git(dir, Command::new("git").args(["show", &spec])).map(Some),
+        }
+    };
+
+    let mut merged = Vec::new();
+
+    for path in ours_changed
+        .iter()
+        .filter(|path| theirs_changed.contains(path))
+    {
+        if let (Some(ours), Some(theirs)) = (show(ours, path)?, show(theirs, path)?) {
+            let base = show(base, path)?.unwrap_or_default();
+            merged.push((PathBuf::from(path), merge_file(&base, &ours, &theirs)?));
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Find the files and directories under `dir` that git ignores (with
+/// `.gitignore`, `.git/info/exclude`, or the global excludes file), joined
+/// onto `dir`. An ignored directory is listed instead of its contents. If
+/// `dir` isn't in a git repository, nothing is ignored.
+pub fn ignored_paths(dir: &Path) -> anyhow::Result<HashSet<PathBuf>> {
+    let in_repository = Command::new("git")
+        .args(["rev-parse", "--is-inside-work-tree"])
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+
+    if !in_repository {
+        return Ok(HashSet::new());
+    }
+
+    let listing = git(
+        dir,
+        Command::new("git").args([
+            "ls-files",
+            "--others",
+            "--ignored",
+            "--exclude-standard",
+            "--directory",
+            "-z",
+        ]),
+    )?;
+
+    Ok(listing
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(|path| dir.join(path.trim_end_matches('/')))
+        .collect())
+}
+
+/// Merge three versions of a file with `git merge-file`, producing a file
+/// with diff3-style conflicts. The versions are written to temporary files,
+/// since that's the only way `git merge-file` will take them.
+fn merge_file(base: &str, ours: &str, theirs: &str) -> anyhow::Result<String> {
+    let dir = env::temp_dir();
+    let temp_path =
+        |name: &str| -> PathBuf { dir.join(format!("usefix-{}-{name}.rs", std::process::id())) };
+
+    let paths = [temp_path("ours"), temp_path("base"), temp_path("theirs")];
+
+    let result = Iterator::zip(paths.iter(), [ours, base, theirs])
+        .try_for_each(|(path, content)| {
+            fs::write(path, content)
+                .with_context(|| format!("i/o error writing {}", path.display()))
+        })
+        .and_then(|()| {
+            let output = Command::new("git")
+                .args(["merge-file", "-p", "--diff3"])
+                .args(["-L", "ours", "-L", "base", "-L", "theirs"])
+                .args(&paths)
+                .stdin(Stdio::null())
+                .stderr(Stdio::inherit())
+                .output()
+                .context("failed to run git merge-file")?;
+
+            // The exit status is the number of conflicts, or negative (which
+            // becomes 255) if there was an error
+            match output.status.code() {
+                Some(0..=127) => String::from_utf8(output.stdout)
+                    .context("git merge-file produced a file that isn't UTF-8"),
+                _ => anyhow::bail!("git merge-file failed"),
+            }
+        });
+
+    for path in &paths {
+        let _ = fs::remove_file(path);
+    }
+
+    result
+}
+
+/// Find the imports that were in the common ancestor, and were deleted on
+/// one side but left unchanged on the other, so that they can be discarded
+/// from the merged file.
+fn deleted_imports(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    options: &MergeOptions,
+) -> anyhow::Result<Vec<Import>> {
+    let model = |content: &str, version: &str| -> anyhow::Result<ImportModel> {
+        let file = ConflictedFile::parse(content)
+            .with_context(|| format!("couldn't parse {version} version of the file"))?;
+        Ok(file.import_model(options))
+    };
+
+    let base = model(base, "the common ancestor's")?;
+    let ours = base.diff(&model(ours, "our")?, options.self_prefix);
+    let theirs = base.diff(&model(theirs, "their")?, options.self_prefix);
+
+    // Every import that a side touched in any way
+    let touched = |changes: &[ImportChange]| -> Vec<Import> {
+        changes
+            .iter()
+            .flat_map(|change| match change {
+                ImportChange::Added(import) | ImportChange::Removed(import) => {
+                    Vec::from([import.clone()])
+                }
+                ImportChange::Changed { old, new } => old.iter().chain(new).cloned().collect(),
+            })
+            .collect()
+    };
+
+    let removed = |changes: &[ImportChange], other: &[ImportChange]| -> Vec<Import> {
+        let touched = touched(other);
+
+        changes
+            .iter()
+            .filter_map(|change| match change {
+                ImportChange::Removed(import) if !touched.contains(import) => Some(import.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    Ok(Iterator::chain(removed(&ours, &theirs).into_iter(), removed(&theirs, &ours)).collect())
+}
+
+/// Split a path into the directory to run git in (so that it finds the right
+/// repository), and the file name
+fn split_path(path: &Path) -> anyhow::Result<(&Path, &OsStr)> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} isn't a file", path.display()))?;
+
+    Ok((dir, name))
+}
+
+/// Run a git command in a directory, and return its stdout
+fn git(dir: &Path, command: &mut Command) -> anyhow::Result<String> {
+    let output = command
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("failed to run git")?;
+
+    if !output.status.success() {
+        anyhow::bail!("git failed ({})", output.status);
+    }
+
+    String::from_utf8(output.stdout).context("git produced output that isn't UTF-8")
+}
@@ -4,7 +4,13 @@ converted into the left or right version of the conflict, along with line
 number mappings back to the original file.
  */
 
-use std::{collections::HashMap, iter, num::NonZeroUsize};
+use std::{
+    borrow::Cow,
+    fmt::{self, Display, Formatter},
+    iter,
+    num::NonZeroUsize,
+    ops::RangeInclusive,
+};
 
 use either::Either;
 use nom::{
@@ -12,7 +18,6 @@ use nom::{
     character::complete::space0,
     combinator::eof,
     error::{ErrorKind, ParseError},
-    sequence::pair,
     IResult, Parser,
 };
 use nom_supreme::{
@@ -34,10 +39,16 @@ impl LineNumber {
         NonZeroUsize::new(line).map(Self)
     }
 
-    /// Increment this value in place, then return the old value.
+    /// Get the one-indexed line number
+    pub fn get(self) -> usize {
+        self.0.get()
+    }
+
+    /// Increment this value in place, then return the old value. Saturates
+    /// at `usize::MAX`, rather than overflowing.
     pub fn get_incr(&mut self) -> Self {
         let value = *self;
-        self.0 = self.0.checked_add(1).expect("line number overflow");
+        self.0 = self.0.saturating_add(1);
         value
     }
 
@@ -66,15 +77,26 @@ impl<'a> Line<'a> {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// One of the two versions of a conflicted file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Side {
     Left,
     Right,
 }
 
+impl Display for Side {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Left => "left",
+            Side::Right => "right",
+        })
+    }
+}
+
 /// A parsed file containing git conflicts.
 #[derive(Debug)]
 pub struct GitFile<'a> {
+    source: &'a str,
     chunks: Vec<Chunk<'a, Line<'a>>>,
 }
 
@@ -100,25 +122,65 @@ impl<'a> GitFile<'a> {
         })
     }
 
-    pub fn build_derived_file(&self, side: Side) -> DerivedFile {
-        let mut content = String::new();
-        let mut line_mappings = HashMap::new();
+    /// Find the side of the file whose conflict markers are labeled with a
+    /// particular branch name (like `HEAD` in `<<<<<<< HEAD`)
+    pub fn side_labeled(&self, label: &str) -> Option<Side> {
+        self.chunks.iter().find_map(|chunk| match chunk {
+            Chunk::Line(_) => None,
+            Chunk::Conflict(conflict) if conflict.left.name == label => Some(Side::Left),
+            Chunk::Conflict(conflict) if conflict.right.name == label => Some(Side::Right),
+            Chunk::Conflict(_) => None,
+        })
+    }
+
+    /// Build one version of the file. If there aren't any conflicts, both
+    /// versions are the original file, so the content is borrowed from it
+    /// instead of being copied. Otherwise, each version is copied into a
+    /// single allocation of its own, because `syn` can only parse contiguous
+    /// text. The line mappings are stored as runs of consecutive lines, so
+    /// they take space for each conflict, rather than for each line.
+    pub fn build_derived_file(&self, side: Side) -> DerivedFile<'a> {
+        let mut runs: Vec<LineRun> = Vec::new();
+        let mut line_count = 0;
+
+        for line in self.get_lines(side) {
+            let continues = runs.last().is_some_and(|run| {
+                run.original_start.get() + (line_count - run.derived_start)
+                    == line.line_number.get()
+            });
+
+            if !continues {
+                runs.push(LineRun {
+                    derived_start: line_count,
+                    original_start: line.line_number,
+                });
+            }
 
-        for (line, line_number) in self.get_lines(side).zip(LineNumber::lines_iter()) {
-            line_mappings.insert(line_number, line.line_number);
-            content.push_str(line.content);
+            line_count += 1;
         }
 
+        let content = match self.contains_conflict() {
+            false => Cow::Borrowed(self.source),
+            true => {
+                let lines = || self.get_lines(side).map(|line| line.content);
+                let mut content = String::with_capacity(lines().map(str::len).sum());
+                lines().for_each(|line| content.push_str(line));
+                Cow::Owned(content)
+            }
+        };
+
         DerivedFile {
             content,
-            line_mappings,
+            runs,
+            line_count,
         }
     }
 
-    fn from_chunks(chunks: impl IntoIterator<Item = Chunk<'a, &'a str>>) -> Self {
+    fn from_chunks(source: &'a str, chunks: impl IntoIterator<Item = Chunk<'a, &'a str>>) -> Self {
         let mut line_number = LineNumber::ONE;
 
         Self {
+            source,
             chunks: chunks
                 .into_iter()
                 .map(|chunk| chunk.with_line_number(&mut line_number))
@@ -130,11 +192,52 @@ impl<'a> GitFile<'a> {
         &self.chunks
     }
 
+    /// The line ending used by the file: `\r\n` if its first line ends with
+    /// one, or `\n` otherwise
+    pub fn line_ending(&self) -> &'static str {
+        match self.source.split_inclusive('\n').next() {
+            Some(line) if line.ends_with("\r\n") => "\r\n",
+            _ => "\n",
+        }
+    }
+
     pub fn contains_conflict(&self) -> bool {
         self.chunks
             .iter()
             .any(|chunk| matches!(chunk, Chunk::Conflict(_)))
     }
+
+    /// Get the (one-indexed) lines spanned by each conflict, including the
+    /// conflict markers
+    pub fn conflict_lines(&self) -> impl Iterator<Item = RangeInclusive<usize>> + '_ {
+        let mut next_line = 1;
+
+        self.chunks.iter().filter_map(move |chunk| match chunk {
+            Chunk::Line(line) => {
+                next_line = line.line_number.get() + 1;
+                None
+            }
+            Chunk::Conflict(conflict) => {
+                let start = next_line;
+                next_line += conflict.line_count();
+
+                Some(start..=next_line - 1)
+            }
+        })
+    }
+
+    /// Get each conflict, along with the (one-indexed) lines it spans
+    /// (including the conflict markers)
+    pub fn conflicts(
+        &self,
+    ) -> impl Iterator<Item = (RangeInclusive<usize>, &Conflict<'a, Line<'a>>)> {
+        let conflicts = self.chunks.iter().filter_map(|chunk| match chunk {
+            Chunk::Conflict(conflict) => Some(conflict),
+            Chunk::Line(_) => None,
+        });
+
+        Iterator::zip(self.conflict_lines(), conflicts)
+    }
 }
 
 #[derive(Debug)]
@@ -155,18 +258,34 @@ impl<'a> Chunk<'a, &'a str> {
 #[derive(Debug)]
 pub struct Conflict<'a, L> {
     pub left: ConflictHalf<'a, L>,
+
+    /// The common ancestor of the two sides of the conflict, if the conflict
+    /// was written in diff3 style. This isn't part of either version of the
+    /// file, but we preserve it for any conflicts we leave unresolved.
+    pub base: Option<ConflictHalf<'a, L>>,
+
     pub right: ConflictHalf<'a, L>,
 }
 
+impl<L> Conflict<'_, L> {
+    /// The total number of lines in this conflict, including the markers
+    pub fn line_count(&self) -> usize {
+        let base = self.base.as_ref().map(|base| base.lines.len() + 1);
+
+        self.left.lines.len() + self.right.lines.len() + base.unwrap_or(0) + 3
+    }
+}
+
 impl<'a> Conflict<'a, &'a str> {
     pub fn with_line_number(self, line_number: &mut LineNumber) -> Conflict<'a, Line<'a>> {
         let left = self.left.with_line_number(line_number);
+        let base = self.base.map(|base| base.with_line_number(line_number));
         let right = self.right.with_line_number(line_number);
 
         // Skip the final line
         line_number.get_incr();
 
-        Conflict { left, right }
+        Conflict { left, base, right }
     }
 }
 
@@ -212,7 +331,7 @@ fn parse_file(input: &str) -> IResult<&str, GitFile<'_>, ErrorTree<&str>> {
         )),
         eof.value(()),
     )
-    .map(|(chunks, ())| GitFile::from_chunks(chunks))
+    .map(|(chunks, ())| GitFile::from_chunks(input, chunks))
     .parse(input)
 }
 
@@ -226,17 +345,53 @@ fn parse_file(input: &str) -> IResult<&str, GitFile<'_>, ErrorTree<&str>> {
 /// >>>>>>> branch-2
 /// ```
 ///
-/// Either or both sides of the conflict may be empty.
-fn parse_conflict(input: &str) -> IResult<&str, Conflict<&str>, ErrorTree<&str>> {
+/// Either or both sides of the conflict may be empty. The conflict may also
+/// be in diff3 style, with the common ancestor between the two sides:
+///
+/// ```text
+/// <<<<<<< branch-1
+/// content in branch-1
+/// ||||||| base
+/// content in the common ancestor
+/// =======
+/// content in branch-2
+/// >>>>>>> branch-2
+/// ```
+fn parse_conflict(input: &str) -> IResult<&str, Conflict<'_, &str>, ErrorTree<&str>> {
     let (input, left_name) = parse_conflict_header(input)?;
 
-    let (input, ((left_lines, ()), (right_lines, right_name))) = pair(
-        parse_lines_terminated(parse_any_line, parse_conflict_separator),
-        parse_lines_terminated(parse_any_line, parse_conflict_footer),
+    let (input, (left_lines, base_name)) = parse_lines_terminated(
+        parse_any_line,
+        alt((
+            parse_conflict_separator.value(None),
+            parse_conflict_base_header.map(Some),
+        )),
     )
     .cut()
     .parse(input)?;
 
+    let (input, base) = match base_name {
+        None => (input, None),
+        Some(base_name) => {
+            let (input, (base_lines, ())) =
+                parse_lines_terminated(parse_any_line, parse_conflict_separator)
+                    .cut()
+                    .parse(input)?;
+
+            let base = ConflictHalf {
+                name: base_name,
+                lines: base_lines,
+            };
+
+            (input, Some(base))
+        }
+    };
+
+    let (input, (right_lines, right_name)) =
+        parse_lines_terminated(parse_any_line, parse_conflict_footer)
+            .cut()
+            .parse(input)?;
+
     Ok((
         input,
         Conflict {
@@ -244,6 +399,7 @@ fn parse_conflict(input: &str) -> IResult<&str, Conflict<&str>, ErrorTree<&str>>
                 name: left_name,
                 lines: left_lines,
             },
+            base,
             right: ConflictHalf {
                 name: right_name,
                 lines: right_lines,
@@ -256,12 +412,18 @@ fn parse_conflict_header(input: &str) -> IResult<&str, &str, ErrorTree<&str>> {
     parse_conflict_part("<<<<<<<").parse(input)
 }
 
+fn parse_conflict_base_header(input: &str) -> IResult<&str, &str, ErrorTree<&str>> {
+    parse_conflict_part("|||||||").parse(input)
+}
+
 fn parse_conflict_footer(input: &str) -> IResult<&str, &str, ErrorTree<&str>> {
     parse_conflict_part(">>>>>>>").parse(input)
 }
 
 fn parse_conflict_separator(input: &str) -> IResult<&str, (), ErrorTree<&str>> {
-    tag("=======\n").value(()).parse(input)
+    alt((tag("=======\n"), tag("=======\r\n")))
+        .value(())
+        .parse(input)
 }
 
 /// Parse a conflict header or a conflict footer, which is a series of chevrons
@@ -328,24 +490,60 @@ where
     }
 }
 
+/// A run of consecutive lines in a derived file that are also consecutive in
+/// the original git conflicted file. A run continues until the next one
+/// starts (or the end of the file).
+#[derive(Debug, Clone, Copy)]
+struct LineRun {
+    /// The zero-indexed local line where this run starts
+    derived_start: usize,
+
+    /// The line in the original file where this run starts
+    original_start: LineNumber,
+}
+
 #[derive(Debug, Clone)]
-pub struct DerivedFile {
-    content: String,
+pub struct DerivedFile<'a> {
+    content: Cow<'a, str>,
 
     /// Mapping from local line numbers to line numbers in the original git
-    /// conflicted file. Technically this could be a `Vec`, since the local
-    /// line numbers are always going precisly resemble `1..n`, but it's just
-    /// a bit easier to do it this way.
-    line_mappings: HashMap<LineNumber, LineNumber>,
+    /// conflicted file, as runs of consecutive lines, sorted by
+    /// `derived_start`. The first run always starts at local line 1 (if
+    /// there are any lines at all).
+    runs: Vec<LineRun>,
+
+    /// The number of lines in this file. The local line numbers are always
+    /// precisely `1..=line_count`.
+    line_count: usize,
 }
 
-impl DerivedFile {
+impl DerivedFile<'_> {
     #[inline]
     pub fn content(&self) -> &str {
         &self.content
     }
 
     pub fn get_original_line(&self, derived_line: LineNumber) -> Option<LineNumber> {
-        self.line_mappings.get(&derived_line).copied()
+        let index = derived_line.get() - 1;
+
+        if index >= self.line_count {
+            return None;
+        }
+
+        let run = self.runs.partition_point(|run| run.derived_start <= index) - 1;
+        let run = self.runs[run];
+
+        LineNumber::from_one_indexed(run.original_start.get() + (index - run.derived_start))
+    }
+
+    /// Iterate over every line of this file, as pairs of local line numbers
+    /// and line numbers in the original git conflicted file.
+    pub fn line_mappings(&self) -> impl Iterator<Item = (LineNumber, LineNumber)> + '_ {
+        LineNumber::lines_iter().take(self.line_count).map(|line| {
+            (
+                line,
+                self.get_original_line(line).expect("line is in the file"),
+            )
+        })
     }
 }
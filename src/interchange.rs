@@ -0,0 +1,199 @@
+/*!
+The JSON form of an [`ImportModel`], used by `usefix extract` and `usefix
+apply`. The model is an object with a single `imports` field, which is a list
+of imports, each of which looks like this:
+
+```json
+{
+    "rooted": false,
+    "path": ["std", "io"],
+    "leaf": "Write",
+    "alias": "_",
+    "cfgs": ["unix"],
+    "visibility": "pub(crate)",
+    "docs": [" Some docs"],
+    "doc_attrs": ["hidden"],
+    "module": ["tests"],
+    "side": "both",
+    "lines": [3, 4]
+}
+```
+
+`leaf` is `"*"` for glob imports, and `alias` is `null` unless the import is
+renamed. `side` is `"left"`, `"right"`, or `"both"`, depending on which
+versions of the file the import appeared in (it's always `"both"` in a file
+without conflicts). `module` is the path to the inline module containing the
+import, and `lines` are the lines of the original file it came from. Each
+entry in `docs` is the text of a doc comment, or an object like `{"expr":
+"include_str!(\"../README.md\")"}` for docs produced by a macro call.
+
+When a model is applied, only `path` and `leaf` are required; everything else
+defaults to an unconditional, private, undocumented import at the top level.
+`side` and `lines` are ignored.
+ */
+
+use anyhow::{bail, Context};
+use usefix::{DocBlock, Import, ImportLeaf, ImportModel, Provenance};
+
+use crate::json::Json;
+
+pub fn import_model_to_json(model: &ImportModel) -> Json {
+    Json::object([(
+        "imports",
+        Json::Array(model.imports.iter().map(import_to_json).collect()),
+    )])
+}
+
+fn import_to_json(import: &Import) -> Json {
+    let (leaf, alias) = match import.leaf {
+        ImportLeaf::Name(ref name) => (name.as_str(), None),
+        ImportLeaf::Renamed {
+            ref name,
+            ref alias,
+        } => (name.as_str(), Some(alias.as_str())),
+        ImportLeaf::Wildcard => ("*", None),
+    };
+
+    let provenance = &import.provenance;
+
+    let side = match (provenance.left, provenance.right) {
+        (true, false) => "left",
+        (false, true) => "right",
+        _ => "both",
+    };
+
+    Json::object([
+        ("rooted", import.rooted.into()),
+        ("path", import.path.clone().into()),
+        ("leaf", leaf.into()),
+        ("alias", alias.into()),
+        ("cfgs", import.cfgs.clone().into()),
+        ("visibility", import.visibility.clone().into()),
+        (
+            "docs",
+            Json::Array(import.docs.iter().map(doc_to_json).collect()),
+        ),
+        ("doc_attrs", import.doc_attrs.clone().into()),
+        ("module", provenance.module.clone().into()),
+        ("side", side.into()),
+        ("lines", provenance.lines.clone().into()),
+    ])
+}
+
+pub fn import_model_from_json(json: &Json) -> anyhow::Result<ImportModel> {
+    let imports = json
+        .get("imports")
+        .and_then(Json::as_array)
+        .context("the import model must be an object with an 'imports' list")?;
+
+    let imports = imports
+        .iter()
+        .enumerate()
+        .map(|(index, import)| {
+            import_from_json(import).with_context(|| format!("invalid import at index {index}"))
+        })
+        .collect::<anyhow::Result<Vec<Import>>>()?;
+
+    Ok(ImportModel { imports })
+}
+
+fn import_from_json(json: &Json) -> anyhow::Result<Import> {
+    let leaf = json
+        .get("leaf")
+        .and_then(Json::as_str)
+        .context("missing 'leaf'")?;
+
+    let alias = optional_string(json, "alias")?;
+
+    let leaf = match (leaf, alias) {
+        ("*", None) => ImportLeaf::Wildcard,
+        ("*", Some(_)) => bail!("glob imports can't be renamed"),
+        (name, None) => ImportLeaf::Name(name.to_owned()),
+        (name, Some(alias)) => ImportLeaf::Renamed {
+            name: name.to_owned(),
+            alias,
+        },
+    };
+
+    let path = string_list(json, "path")?.context("missing 'path'")?;
+    if path.is_empty() && leaf == ImportLeaf::Wildcard {
+        bail!("glob imports need a path");
+    }
+
+    let (left, right) = match json.get("side").and_then(Json::as_str) {
+        Some("left") => (true, false),
+        Some("right") => (false, true),
+        _ => (true, true),
+    };
+
+    Ok(Import {
+        rooted: match json.get("rooted") {
+            None | Some(Json::Null) => false,
+            Some(&Json::Bool(rooted)) => rooted,
+            Some(_) => bail!("'rooted' must be a boolean"),
+        },
+        path,
+        leaf,
+        cfgs: string_list(json, "cfgs")?.unwrap_or_default(),
+        visibility: optional_string(json, "visibility")?,
+        docs: doc_list(json)?,
+        doc_attrs: string_list(json, "doc_attrs")?.unwrap_or_default(),
+        provenance: Provenance {
+            module: string_list(json, "module")?.unwrap_or_default(),
+            left,
+            right,
+            lines: Vec::new(),
+        },
+    })
+}
+
+/// A doc block is a string, or an object with an `expr` field for a macro
+/// call that produces docs, like `{"expr": "include_str!(\"README.md\")"}`
+fn doc_to_json(doc: &DocBlock) -> Json {
+    match doc {
+        DocBlock::Text(text) => text.as_str().into(),
+        DocBlock::Expr(expr) => Json::object([("expr", expr.as_str().into())]),
+    }
+}
+
+/// Get the optional list of doc blocks (see `doc_to_json`)
+fn doc_list(json: &Json) -> anyhow::Result<Vec<DocBlock>> {
+    match json.get("docs") {
+        None | Some(Json::Null) => Ok(Vec::new()),
+        Some(Json::Array(items)) => items
+            .iter()
+            .map(|item| match item {
+                Json::String(text) => Some(DocBlock::Text(text.clone())),
+                _ => item
+                    .get("expr")?
+                    .as_str()
+                    .map(|expr| DocBlock::Expr(expr.to_owned())),
+            })
+            .collect::<Option<Vec<DocBlock>>>()
+            .context("'docs' must be a list of strings and {\"expr\": ...} objects"),
+        Some(_) => bail!("'docs' must be a list"),
+    }
+}
+
+/// Get an optional string field, which may be missing or `null`
+fn optional_string(json: &Json, key: &str) -> anyhow::Result<Option<String>> {
+    match json.get(key) {
+        None | Some(Json::Null) => Ok(None),
+        Some(Json::String(value)) => Ok(Some(value.clone())),
+        Some(_) => bail!("'{key}' must be a string"),
+    }
+}
+
+/// Get an optional field that's a list of strings
+fn string_list(json: &Json, key: &str) -> anyhow::Result<Option<Vec<String>>> {
+    match json.get(key) {
+        None | Some(Json::Null) => Ok(None),
+        Some(Json::Array(items)) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_owned))
+            .collect::<Option<Vec<String>>>()
+            .with_context(|| format!("'{key}' must be a list of strings"))
+            .map(Some),
+        Some(_) => bail!("'{key}' must be a list of strings"),
+    }
+}
@@ -0,0 +1,281 @@
+/*!
+A minimal JSON value, parser, and serializer, for the parts of the command
+line tool that speak JSON (like `usefix lsp`). We only ever handle small
+documents, so this favors simplicity over speed: objects are lists of
+key-value pairs, and numbers are always `f64`.
+ */
+
+use std::fmt::{self, Display, Formatter, Write as _};
+
+use nom::{
+    branch::alt,
+    character::complete::{char, multispace0},
+    error::{ErrorKind, ParseError},
+    multi::separated_list0,
+    number::complete::recognize_float,
+    IResult, Parser,
+};
+use nom_supreme::{
+    error::ErrorTree,
+    final_parser::{final_parser, Location},
+    tag::complete::tag,
+    ParserExt,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(input: &str) -> Result<Self, ErrorTree<Location>> {
+        final_parser(parse_value.delimited_by(multispace0))(input)
+    }
+
+    /// Build an object from a list of fields
+    pub fn object<'k>(fields: impl IntoIterator<Item = (&'k str, Json)>) -> Self {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// Get a field of an object. Returns `None` if this isn't an object, or
+    /// if it doesn't have the field.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Get a non-negative whole number
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Json::Number(n) if n >= 0.0 && n.fract() == 0.0 => Some(n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Json::Null)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(items: Vec<T>) -> Self {
+        Json::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Serializes the value as compact JSON
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            // Whole numbers are written without a trailing `.0`
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) if n.is_finite() => write!(f, "{n}"),
+            Json::Number(_) => f.write_str("null"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+
+    f.write_char('"')
+}
+
+fn parse_value(input: &str) -> IResult<&str, Json, ErrorTree<&str>> {
+    alt((
+        tag("null").value(Json::Null),
+        tag("true").value(Json::Bool(true)),
+        tag("false").value(Json::Bool(false)),
+        recognize_float
+            .map_res(str::parse)
+            .map(Json::Number)
+            .context("number"),
+        parse_string.map(Json::String),
+        parse_array.map(Json::Array),
+        parse_object.map(Json::Object),
+    ))
+    .parse(input)
+}
+
+/// Parse a punctuation character, with optional whitespace around it
+fn punctuation<'a>(c: char) -> impl Parser<&'a str, char, ErrorTree<&'a str>> {
+    char(c).delimited_by(multispace0)
+}
+
+fn parse_array(input: &str) -> IResult<&str, Vec<Json>, ErrorTree<&str>> {
+    separated_list0(punctuation(','), parse_value)
+        .preceded_by(punctuation('['))
+        .terminated(punctuation(']'))
+        .context("array")
+        .parse(input)
+}
+
+fn parse_object(input: &str) -> IResult<&str, Vec<(String, Json)>, ErrorTree<&str>> {
+    let field = parse_string.terminated(punctuation(':')).and(parse_value);
+
+    separated_list0(punctuation(','), field)
+        .preceded_by(punctuation('{'))
+        .terminated(punctuation('}'))
+        .context("object")
+        .parse(input)
+}
+
+/// Parse a string literal, including its quotes, and unescape it.
+fn parse_string(input: &str) -> IResult<&str, String, ErrorTree<&str>> {
+    let error = |input| nom::Err::Error(ErrorTree::from_error_kind(input, ErrorKind::Char));
+
+    let mut chars = input
+        .strip_prefix('"')
+        .ok_or_else(|| error(input))?
+        .char_indices();
+
+    let body = &input[1..];
+    let mut output = String::new();
+
+    // Parse the 4 hex digits of a `\u` escape
+    let hex = |chars: &mut std::str::CharIndices<'_>| -> Option<u32> {
+        (0..4).try_fold(0, |value, _| {
+            let (_, c) = chars.next()?;
+            Some(value * 16 + c.to_digit(16)?)
+        })
+    };
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((&body[i + 1..], output)),
+            '\\' => {
+                let escaped = match chars.next().ok_or_else(|| error(&body[i..]))?.1 {
+                    '"' => '"',
+                    '\\' => '\\',
+                    '/' => '/',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'u' => {
+                        let high = hex(&mut chars).ok_or_else(|| error(&body[i..]))?;
+
+                        // Characters outside the basic multilingual plane are
+                        // written as a pair of UTF-16 surrogates
+                        let code = match high {
+                            0xD800..=0xDBFF => {
+                                let low = chars
+                                    .next()
+                                    .filter(|&(_, c)| c == '\\')
+                                    .and_then(|_| chars.next())
+                                    .filter(|&(_, c)| c == 'u')
+                                    .and_then(|_| hex(&mut chars))
+                                    .filter(|low| (0xDC00..=0xDFFF).contains(low))
+                                    .ok_or_else(|| error(&body[i..]))?;
+
+                                0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                            }
+                            code => code,
+                        };
+
+                        char::from_u32(code).ok_or_else(|| error(&body[i..]))?
+                    }
+                    _ => return Err(error(&body[i..])),
+                };
+
+                output.push(escaped);
+            }
+            c if c.is_control() => return Err(error(&body[i..])),
+            c => output.push(c),
+        }
+    }
+
+    Err(error(""))
+}
@@ -0,0 +1,2256 @@
+/*!
+`usefix` merges the use items in a rust source file containing git conflicts.
+Both versions of the file are parsed, their use items are merged together,
+and the merged use items are written back into the file, resolving any
+conflicts that only involved use items.
+
+The simplest entry point is [`fix_file`]. To inspect the intermediate steps,
+use [`ConflictedFile::parse`], [`ConflictedFile::merge_imports`], and
+[`MergedImports::render`].
+ */
+
+/*
+High level data model:
+
+We need to track a lot of things about imports. Specifically:
+
+    - The path: `a::b::c::d`
+    - The imported item, which can be either a regular item (`e`), a renamed item (`e as f`),
+      or a wildcard (`*`)
+    - Any `#[cfg(...)]` attributes attached to the item, which we call configs. Any
+      item without a config is called "unconditional"
+    - The visibility of the item (`pub`, `pub(crate)`, etc)
+    - Any docs attached to the item
+
+At various points in this algorithm we'll be grouping these imports in various
+ways to aid with normalization. At a very high level, the goal of usefix's
+merge algorithm is to compute a union of the imports of both forms of a
+conflicted file and use it as the conflict resolution.
+
+High level algorithm:
+
+- Load the file with git conflicts
+- Split into two files, based on conflicts. Include a mapping to the line numbers
+  of the original files.
+- Parse the files with syn
+- Extract all use items (at the top level, and inside of inline `mod` blocks)
+  from both files. Track which line numnbers they came from.
+  Module declarations without bodies (`mod foo;`) are extracted too; with
+  `--mods`, they're merged much like use items (`mods.rs`), and written in a
+  sorted block in front of the use items of their section. So are the
+  crate-level inner attributes (`#![...]`), which are merged as a section of
+  their own with `--crate-attrs` (`attrs.rs`).
+- Split the use items into sections (`sections.rs`): clusters of use items
+  separated by other items. Each inline module has its own sections, and each
+  section is merged and rewritten separately, so everything below (up to
+  inserting the use items into the file) happens once per section. With
+  `--hoist`, each inline module has a single section instead; modules are
+  told apart by where they start, not just by their names.
+- Convert the syn item into a local tree representation (`tree.rs`). The
+  representation include import paths (including wildcards and renames),
+  #[cfg] flags, visibility, and docs.
+- normalize configs: Flatten the tree into a list of paths, where each path
+  separately stores a mapping of config -> (visibility, docs). In any case
+  where a path appears in both unconditional and conditional forms, the
+  conditional forms are discarded, with their visibilities and docs merged into
+  the unconditional form. Otherwise, all distinct conditional forms are
+  retained; we don't make any effort to compute overlaps. If an import appears
+  more than once with the same config (for instance, because it appears on both
+  sides of a conflicted file), the visibilities and docs are merged.
+  Optionally, a leading `self::` is stripped from paths during flattening, so
+  that `self::foo::Bar` and `foo::Bar` are treated as the same import.
+- Normalize wildcards: group all of the items by (config -> (path -> (vis, docs))).
+  Within each config, if a path exists in wildcard form, all of the paths that
+  are subsumed by that wildcard are discarded and merged into the wildcard
+  form. Additionally, any anonymous imports (e.g. `a::Trait as _`) are subsumed
+  by a matching wildcard (`a::*`) or named import of the same path (`a::Trait`).
+- We now have a canonical set of imports (`printable.rs`). Convert them into a
+  series of use item trees. Much like `rust-analyzer`, we prefer to use a
+  single use item for each top level imported identifier:
+
+```
+// We prefer this
+use a::{b, c::d, e};
+use f::g;
+
+// Over this
+use {
+    a::{b, c::d, e},
+    f::g,
+}
+
+// Or this
+use a::b;
+use a::c::d;
+use a::e;
+use f::g;
+```
+
+  Note that we'll have to split these into multiple use items to account for
+  visibility, docs, and `#[cfg]` conditionals. In general we attempt to group
+  stuff up that share any of these attributes.
+- Put the use items in order, and into newline-separated groups. This section
+  is nominal, as we expect the specific order and groupings to evolve for a
+  while. In general:
+  - Prefer `std`/`alloc`/`core`, followed by dependencies, followed by `crate`,
+    `super`, and `self` imports
+  - Prefer unconditional imports before conditional imports
+  - The grouping and ordering are configurable; the criteria they're built
+    from are in `rules.rs`, and they're applied by the `PrintableKey` type, in
+    `printable.rs`
+- Render the use items. This is mostly handled by `Display` implementations in
+  `printable.rs`.
+- Prettify the rendered use items. Rather than try to compete with `rustfmt`,
+  we just use it directly. `rustfmt` can't be used as a library, so we offer
+  two options:
+  - Use `prettyplease`, a variant of `rustfmt` that is intended for use with
+    macros and other codegen tools. `prettyplease` doesn't respect grouping
+    of `use` items and the whitespace between them, so we have to call it
+    several times, once with each grouped set of use items.
+  - Call `rustfmt` as a subprocess. We expect in practice that this will be the
+    typical case, but it requires `rustfmt` to be installed, so we still ask
+    the user to ask for it.
+- Insert the prettified use items into the original file, and remove the
+  existing use items (`writefile.rs`). This is a fraught thing to try to do,
+  because the original file might include git conflicts. The basic rule is to
+  insert each section's use items at the point where the very first use item
+  in that section appears in the original file.
+  - If this point is a non-conflicted line, it's easy; we just put it there.
+  - If this point is a conflict, we split the conflict into two separate
+    conflicts, and insert the use items in between them.
+  - If there are no such points, it means that all the use items only appear
+    in half of the conflicts (that is, for each conflict, it appears ONLY on
+    the left or right side of the conflict). This is an awfully edge-casey
+    edge case, and we insert the use items twice: once at the first use item
+    in the left version of the file, and once at the first use item in the
+    right version of the file. Note again that we only do this if there's no
+    possible non-conflicted sites to insert these use items.
+  - We assume that, in the original rust file, no lines that include a use item
+    (or part of a use item) will include anything OTHER than that use item.
+    No sane rust developer would do otherwise, even if they don't use rustfmt
+    for some reason. Still, we check this assumption (other use items and
+    trailing comments are fine) and fail with an error if it's violated,
+    rather than deleting code.
+  - When writing conflicts, we check that the conflict is still a conflict: if
+    its remaining lines (after excluding the use items we processed) are
+    identical, we write them as a plain, non-conflicted lines. This will be
+    common in the case where a conflict appears in the middle of a larger set
+    of imports.
+  - One odd side effect of our algorithm is that spaces between groups of use
+    items in the original file are kept, "clump" together at the end of all the
+    use items. To handle this, each use item consumes the empty line after it,
+    and the blank lines around each inserted block are normalized as the file
+    is written: there's always exactly one blank line before the block, and
+    one (or `--trailing-blank-lines`) after the block, except at the start or
+    end of a `{ ... }` block, a conflict half, or the file, where there are
+    none. A `//` comment directly above the
+    first use item stays attached to it.
+
+
+Sub-algorithms:
+    Docs merge:
+        If either set of docs are a prefix or suffix of the other, use the
+        longer one. Otherwise, concatenate them. In a future version we could
+        detect if either docs are a complete subset of the other, but for now
+        this is fine. List-style doc attributes, like `#[doc(hidden)]`, are
+        kept apart from the docs, and unioned.
+    Visibility Merge
+        Always prefer the "more public" visibility
+    Rename conflicts
+        An item imported under several names keeps all of them.
+
+    Each of these (and wildcard subsumption) can be overridden by a
+    `MergePolicy` (`policy.rs`) in `MergeOptions`.
+ */
+
+mod attrs;
+mod cfg_rules;
+mod common;
+mod docprint;
+mod error;
+mod explain;
+mod flattened;
+mod fragment;
+mod gitfile;
+mod model;
+mod mods;
+mod options;
+mod origin;
+mod policy;
+mod pretty;
+mod printable;
+mod region;
+mod rules;
+mod sections;
+mod tree;
+mod write_file;
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+    ops::{Range, RangeInclusive},
+    panic, str,
+    sync::Arc,
+    thread,
+};
+
+use itertools::{EitherOrBoth, Itertools};
+use proc_macro2::Span;
+use syn::spanned::Spanned;
+
+use crate::{
+    attrs::{render_crate_attrs, CrateAttr},
+    common::Unraw,
+    flattened::{NormalizedUsedItems, SingleUsedItem, UsedItemPropertiesGroup},
+    gitfile::{Chunk, Conflict, ConflictHalf, DerivedFile, GitFile, Line, LineNumber},
+    mods::{render_mod_decls, ModDecl},
+    policy::PreferFirst,
+    pretty::{
+        indent, prettify_cfg_if_sections, prettify_with_prettyplease, prettify_with_subcommand,
+        validate_formatted_use_items, RustfmtSettings,
+    },
+    printable::{ConfigsOrder, PrintOptions, PrintableUseItems, RootOrder},
+    sections::{find_import_sections, hoisted_import_sections, ImportSection},
+    tree::{ConfigsList, UseItem},
+    write_file::FormattedSection,
+};
+
+pub use crate::{
+    cfg_rules::{CfgRules, InvalidCfgRulesError},
+    error::Error,
+    flattened::{Edition, MergeStrategy, SelfPrefixStyle},
+    gitfile::Side,
+    model::{Import, ImportChange, ImportLeaf, ImportModel, Provenance, VisibilityWidening},
+    options::{
+        Formatter, InvalidLineRangeError, InvalidOptionsError, LineRange, MergeOptions,
+        MergeOptionsBuilder, PreferredSide, WildcardPolicy,
+    },
+    origin::strip_origin_annotations,
+    policy::{Choice, MergePolicy, Policy, RenameConflict},
+    pretty::FormatterError,
+    printable::{CfgOrder, SelfImportStyle, TreeOrder, WildcardPosition},
+    rules::{CfgLayout, Criterion, InvalidRulesError, PubPlacement, StdLayout},
+    tree::DocBlock,
+};
+
+/// A rust source file, which may contain git conflicts, that has been parsed
+/// into its left and right versions, with the use items extracted from each.
+pub struct ConflictedFile<'a> {
+    source: &'a str,
+    git_file: GitFile<'a>,
+    left: Vec<AnnotatedUseItem>,
+    right: Vec<AnnotatedUseItem>,
+
+    /// The conflicts that are left alone, because their use items are
+    /// interleaved with other code (see `ConflictedFile::tangled_conflicts`)
+    tangled_conflicts: Vec<RangeInclusive<usize>>,
+}
+
+impl<'a> ConflictedFile<'a> {
+    /// Parse a rust source file. Fails if the git conflicts are malformed, if
+    /// either version of the file isn't valid rust, or if any use item
+    /// shares a line with some other code.
+    pub fn parse(source: &'a str) -> Result<Self, Error> {
+        Self::parse_with(source, extract_use_items)
+    }
+
+    /// Parse a fragment of a rust source file, such as a single conflicted
+    /// hunk with a few lines of context around it. The fragment doesn't need
+    /// to be valid rust as a whole: use items are found line by line, and
+    /// each one is parsed on its own. Anything that doesn't parse as a use
+    /// item is treated as some other code. This is less reliable than
+    /// `parse` (for instance, it can't see inline modules), so prefer that
+    /// whenever the whole file is available.
+    pub fn parse_fragment(source: &'a str) -> Result<Self, Error> {
+        Self::parse_with(source, extract_fragment_use_items)
+    }
+
+    fn parse_with(
+        source: &'a str,
+        extract: fn(&GitFile<'_>, Side) -> Result<Vec<AnnotatedUseItem>, Error>,
+    ) -> Result<Self, Error> {
+        let git_file =
+            GitFile::from_file(source).map_err(|err| Error::from_conflict_error(&err))?;
+
+        // Parse the two versions of the file in parallel. None of the `syn`
+        // types escape `extract`, so the results are `Send`. These parses
+        // can't also overlap with parsing the conflicts, since each version
+        // of the file is assembled from the parsed conflicts; that's fine,
+        // because parsing the conflicts takes a small fraction of the time
+        // `syn` does.
+        let (left, right) = thread::scope(|scope| {
+            let right = scope.spawn(|| extract(&git_file, Side::Right));
+            let left = extract(&git_file, Side::Left);
+
+            let right = right
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic));
+
+            (left, right)
+        });
+
+        let mut left = left?;
+        let mut right = right?;
+
+        // The use items in a tangled conflict are left where they are, as if
+        // they were any other code
+        let tangled_conflicts = find_tangled_conflicts(&git_file, &left, &right);
+        let untangled = |item: &AnnotatedUseItem| {
+            !item.touched_original_lines.iter().any(|line| {
+                tangled_conflicts
+                    .iter()
+                    .any(|lines| lines.contains(&line.get()))
+            })
+        };
+
+        left.retain(untangled);
+        right.retain(untangled);
+
+        label_conflicted_items(&git_file, Side::Left, &mut left);
+        label_conflicted_items(&git_file, Side::Right, &mut right);
+
+        Ok(Self {
+            source,
+            git_file,
+            left,
+            right,
+            tangled_conflicts,
+        })
+    }
+
+    /// True if the file contains any git conflicts
+    pub fn contains_conflict(&self) -> bool {
+        self.git_file.contains_conflict()
+    }
+
+    /// The (one-indexed, inclusive) lines of each conflict, including its
+    /// markers, in which the use items on one side are interleaved with
+    /// other code on the same side, like a conflict that contains a use item
+    /// in the middle of some functions. Discarding the use items from a
+    /// conflict like that could leave something that doesn't make sense, so
+    /// these conflicts are left completely alone, and their use items aren't
+    /// merged.
+    pub fn tangled_conflicts(&self) -> &[RangeInclusive<usize>] {
+        &self.tangled_conflicts
+    }
+
+    /// Merge the use items from both versions of the file, and format the
+    /// merged use items for each section of the file.
+    pub fn merge_imports(&self, options: &MergeOptions) -> Result<MergedImports<'_>, Error> {
+        options.validate()?;
+        let options = &*self.resolve_options(options)?;
+
+        let conflicts: Vec<RangeInclusive<usize>> = self
+            .git_file
+            .conflict_lines()
+            .filter(|lines| options.lines.is_none_or(|range| range.overlaps(lines)))
+            .collect();
+
+        if options.require_conflict && conflicts.is_empty() {
+            return Err(Error::NoConflicts);
+        }
+
+        // If there are conflicts, but none of them touch a use item, there's
+        // nothing for usefix to resolve, so the file is left as it is
+        if !conflicts.is_empty()
+            && !options.always_rewrite
+            && !self.touches_use_items(&conflicts, options)
+        {
+            return Ok(MergedImports {
+                file: self,
+                sections: Vec::new(),
+                trailing_blank_lines: options.trailing_blank_lines,
+                passthrough: true,
+                warnings: Vec::new(),
+            });
+        }
+
+        let print_options = options.print_options()?;
+        let import_sections = self.import_sections(options);
+
+        // With `--managed-region`, the use items outside of the regions are
+        // left alone, conflict markers and all. That's never what anyone
+        // wants, so it's an error instead.
+        if options.managed_region {
+            let managed_lines: HashSet<LineNumber> = import_sections
+                .iter()
+                .flat_map(|section| section.touched_original_lines())
+                .collect();
+
+            let unmanaged_options = MergeOptions {
+                managed_region: false,
+                ..options.clone()
+            };
+
+            let conflicted_line = self
+                .import_sections(&unmanaged_options)
+                .iter()
+                .flat_map(|section| section.touched_original_lines())
+                .filter(|line| !managed_lines.contains(line))
+                .map(LineNumber::get)
+                .filter(|line| conflicts.iter().any(|lines| lines.contains(line)))
+                .min();
+
+            if let Some(line) = conflicted_line {
+                return Err(Error::ConflictOutsideRegion { line });
+            }
+        }
+
+        if options.wildcards == WildcardPolicy::Deny {
+            let model = ImportModel::from_sections(options, &import_sections);
+
+            if let Some(import) = model.imports.iter().find(|import| {
+                import.leaf == ImportLeaf::Wildcard
+                    && !(import.provenance.left && import.provenance.right)
+            }) {
+                return Err(Error::DeniedWildcard {
+                    path: Iterator::chain(import.path.iter().map(String::as_str), ["*"]).join("::"),
+                    line: import.provenance.lines.first().copied().unwrap_or(1),
+                });
+            }
+        }
+
+        let rustfmt_settings = options.rustfmt_settings();
+        let mut warnings = Vec::new();
+
+        // For each section, compute the set of lines from the ORIGINAL file
+        // that need to be discarded; these are the lines in the original file
+        // that include any part of a use item. There's an important assumption
+        // here that no line that includes any part of a use item includes
+        // anything OTHER than that use item.
+        let mut sections = import_sections
+            .iter()
+            .map(|section| {
+                Ok(FormattedSection {
+                    discarded_lines: section.touched_original_lines(),
+                    preamble: section.preamble(),
+                    formatted_use_items: fix_import_section(
+                        options,
+                        rustfmt_settings.as_ref(),
+                        print_options,
+                        section,
+                        &mut warnings,
+                    )?,
+                })
+            })
+            .collect::<Result<Vec<FormattedSection>, Error>>()?;
+
+        // The first time a file is fixed with `--managed-region`, its first
+        // section of use items (which isn't the crate attributes) becomes the
+        // managed region
+        if options.managed_region && region::find_regions(self.source).is_empty() {
+            if let Some(index) = import_sections
+                .iter()
+                .position(|section| section.crate_attrs().next().is_none())
+            {
+                let formatted = &mut sections[index];
+                formatted.formatted_use_items = region::add_markers(
+                    &formatted.formatted_use_items,
+                    import_sections[index].indentation(),
+                );
+            }
+        }
+
+        // If there's nothing to resolve, and the use items are already
+        // written exactly the way usefix would write them, the file is left
+        // exactly as it is, rather than having the blank lines around its
+        // use items (or anything else) normalized
+        let passthrough = !self.contains_conflict()
+            && sections
+                .iter()
+                .all(|section| self.section_unchanged(section));
+
+        Ok(MergedImports {
+            file: self,
+            sections,
+            trailing_blank_lines: options.trailing_blank_lines,
+            passthrough,
+            warnings,
+        })
+    }
+
+    /// True if a section of formatted use items is identical to the lines
+    /// that it replaces, which must all be next to each other. Blank lines
+    /// before and after the use items don't count.
+    fn section_unchanged(&self, section: &FormattedSection) -> bool {
+        let (Some(&first), Some(&last)) = (
+            section.discarded_lines.iter().min(),
+            section.discarded_lines.iter().max(),
+        ) else {
+            return section.formatted_use_items.trim_ascii().is_empty();
+        };
+
+        if last.get() - first.get() + 1 != section.discarded_lines.len() {
+            return false;
+        }
+
+        let original: String = self
+            .git_file
+            .get_lines(Side::Left)
+            .filter(|line| section.discarded_lines.contains(&line.line_number))
+            .map(|line| line.content)
+            .collect();
+
+        let formatted = str::from_utf8(&section.formatted_use_items)
+            .expect("formatted use items are always valid UTF-8");
+
+        // The formatted use items always end their lines with `\n`, so the
+        // lines are compared without their line endings
+        fn lines(text: &str) -> str::Lines<'_> {
+            text.trim_start_matches(['\r', '\n']).trim_end().lines()
+        }
+
+        Iterator::eq(lines(&original), lines(formatted))
+    }
+
+    /// Find out how many of the conflicts in this file were resolved in a
+    /// fixed version of it (like the output of [`fix_file`], or of
+    /// [`MergedImports::render`]), by looking for the conflicts that remain
+    /// in the fixed file.
+    pub fn outcome(&self, fixed: &str, options: &MergeOptions) -> Outcome {
+        let conflicts: Vec<RangeInclusive<usize>> = self.git_file.conflict_lines().collect();
+
+        if conflicts.is_empty() {
+            return Outcome::NoConflicts;
+        }
+
+        // If the conflict markers in the fixed file are malformed, none of
+        // its conflicts can be trusted to be resolved
+        let remaining = match GitFile::from_file(fixed) {
+            Ok(fixed) => fixed.conflict_lines().count(),
+            Err(_) => conflicts.len(),
+        };
+
+        match remaining {
+            0 => Outcome::Resolved,
+            remaining if self.touches_use_items(&conflicts, options) => {
+                Outcome::UseItemsResolved { remaining }
+            }
+            remaining => Outcome::Unresolved { remaining },
+        }
+    }
+
+    /// Find the conflicts that remain in a fixed version of this file (like
+    /// the output of [`fix_file`]), along with the conflicts in this file
+    /// that they came from. The conflicts aren't tracked through fixing, so
+    /// they're matched up by their contents: a remaining conflict came from
+    /// the conflicts that have some of the same lines on the same side.
+    pub fn remaining_conflicts(&self, fixed: &str) -> Vec<RemainingConflict> {
+        let Ok(fixed_file) = GitFile::from_file(fixed) else {
+            return Vec::new();
+        };
+
+        let original: Vec<_> = self.git_file.conflicts().collect();
+
+        // Only lines with some content count; blank lines and braces are in
+        // nearly every conflict
+        let shares_lines = |a: &Conflict<'_, Line<'_>>, b: &Conflict<'_, Line<'_>>| {
+            let shares = |a: &ConflictHalf<'_, Line<'_>>, b: &ConflictHalf<'_, Line<'_>>| {
+                a.lines()
+                    .iter()
+                    .map(|line| line.content.trim())
+                    .filter(|line| line.len() > 1)
+                    .any(|line| b.lines().iter().any(|other| other.content.trim() == line))
+            };
+
+            shares(&a.left, &b.left) || shares(&a.right, &b.right)
+        };
+
+        // A conflict can be split into several, or several can be coalesced
+        // into one, but they always stay in the same order
+        let mut next = 0;
+
+        fixed_file
+            .conflicts()
+            .filter_map(|(fixed_lines, fixed_conflict)| {
+                let matching = |&(_, conflict): &(_, &Conflict<'_, Line<'_>>)| {
+                    shares_lines(conflict, fixed_conflict)
+                };
+
+                // If nothing matches, it's probably the next conflict
+                let start = original[next..]
+                    .iter()
+                    .position(matching)
+                    .map_or(next, |offset| next + offset);
+                let end = start
+                    + original[start..]
+                        .iter()
+                        .skip(1)
+                        .take_while(|conflict| matching(conflict))
+                        .count();
+
+                next = end;
+
+                let first = original.get(start)?;
+                let last = original.get(end)?;
+
+                Some(RemainingConflict {
+                    original: *first.0.start()..=*last.0.end(),
+                    fixed: fixed_lines,
+                })
+            })
+            .collect()
+    }
+
+    /// True if any of the items extracted from either version of the file
+    /// are on one of these lines. Function-local use items only count with
+    /// `--local-uses`.
+    fn touches_use_items(&self, lines: &[RangeInclusive<usize>], options: &MergeOptions) -> bool {
+        Iterator::chain(self.left.iter(), &self.right)
+            .filter(|item| options.local_uses || !matches!(item.item, ManagedItem::LocalUse(_)))
+            .flat_map(|item| &item.touched_original_lines)
+            .any(|line| lines.iter().any(|lines| lines.contains(&line.get())))
+    }
+
+    /// Merge the use items from both versions of the file into an
+    /// [`ImportModel`], without formatting them.
+    pub fn import_model(&self, options: &MergeOptions) -> ImportModel {
+        // An unknown branch in `--prefer-side` is just ignored here
+        let options = match self.resolve_options(options) {
+            Ok(options) => options,
+            Err(_) => Cow::Borrowed(options),
+        };
+
+        ImportModel::from_sections(&options, &self.import_sections(&options))
+    }
+
+    /// Find the merged imports whose visibility is wider than it was in one
+    /// version of the file, like an import that's private on one side and
+    /// `pub` on the other. Merging those makes the import more public than
+    /// at least one side intended, which could accidentally export it.
+    pub fn visibility_widenings(&self, options: &MergeOptions) -> Vec<VisibilityWidening> {
+        let options = match self.resolve_options(options) {
+            Ok(options) => options,
+            Err(_) => Cow::Borrowed(options),
+        };
+
+        self.import_sections(&options)
+            .iter()
+            .flat_map(|section| model::section_widenings(&options, section))
+            .collect()
+    }
+
+    /// Find the glob imports (like `use a::*;`) in the merged file. Each
+    /// one's provenance says whether it was on one side of a conflict, or
+    /// both.
+    pub fn wildcard_imports(&self, options: &MergeOptions) -> Vec<Import> {
+        let mut imports = self.import_model(options).imports;
+        imports.retain(|import| import.leaf == ImportLeaf::Wildcard);
+        imports
+    }
+
+    /// Replace the use items in the file with the imports in an
+    /// [`ImportModel`] (which might have come from [`Self::import_model`],
+    /// and then been edited). The imports for each module are written in
+    /// place of the first section of use items in that module, and any other
+    /// sections in that module are removed; it's an error for the model to
+    /// have imports in a module that doesn't have any use items. The
+    /// provenance of the imports, other than their modules, is ignored. All
+    /// of the use items are replaced, regardless of `MergeOptions::lines`,
+    /// and module declarations and crate attributes are left alone.
+    pub fn apply_model(
+        &self,
+        model: &ImportModel,
+        options: &MergeOptions,
+    ) -> Result<MergedImports<'_>, Error> {
+        let options = MergeOptions {
+            require_conflict: false,
+            fragment: false,
+            lines: None,
+            mods: false,
+            crate_attrs: false,
+            ..options.clone()
+        };
+
+        options.validate()?;
+
+        let import_sections = self.import_sections(&options);
+
+        if let Some(import) = model.imports.iter().find(|import| {
+            !import_sections
+                .iter()
+                .any(|section| section.module() == import.provenance.module)
+        }) {
+            return Err(Error::NoSection {
+                module: import.provenance.module.clone(),
+            });
+        }
+
+        let mut filled_modules: HashSet<&[String]> = HashSet::new();
+
+        let sections = import_sections
+            .iter()
+            .map(|section| {
+                let module = section.module();
+
+                let formatted_use_items = match filled_modules.insert(module) {
+                    false => Vec::new(),
+                    true => {
+                        let imports = ImportModel {
+                            imports: model
+                                .imports
+                                .iter()
+                                .filter(|import| import.provenance.module == module)
+                                .cloned()
+                                .collect(),
+                        };
+
+                        match imports.imports.is_empty() {
+                            true => Vec::new(),
+                            false => imports.to_source(&options)?.into_bytes(),
+                        }
+                    }
+                };
+
+                Ok(FormattedSection {
+                    discarded_lines: section.touched_original_lines(),
+                    preamble: section.preamble(),
+                    formatted_use_items: match section.indentation() {
+                        "" => formatted_use_items,
+                        indentation => indent(&formatted_use_items, indentation),
+                    },
+                })
+            })
+            .collect::<Result<Vec<FormattedSection>, Error>>()?;
+
+        Ok(MergedImports {
+            file: self,
+            sections,
+            trailing_blank_lines: options.trailing_blank_lines,
+            passthrough: false,
+            warnings: Vec::new(),
+        })
+    }
+
+    /// Describe every intermediate stage of merging each section of the file
+    /// (see `usefix explain`). This is meant for debugging, so the format
+    /// isn't stable.
+    pub fn explain(&self, options: &MergeOptions) -> Result<String, Error> {
+        options.validate()?;
+        let options = &*self.resolve_options(options)?;
+        explain::explain_sections(options, &self.import_sections(options))
+    }
+
+    /// Collect every intermediate artifact of fixing this file, for bug
+    /// reports (see `--debug-stages`): each derived version of the file with
+    /// its line mappings, the extracted use items, and the text of each
+    /// section before and after formatting. Returns a list of file names and
+    /// their contents; the names and formats aren't stable.
+    pub fn debug_stages(&self, options: &MergeOptions) -> Result<Vec<(String, String)>, Error> {
+        options.validate()?;
+        let options = &*self.resolve_options(options)?;
+
+        explain::debug_stages(
+            options,
+            &self.git_file,
+            [&self.left, &self.right],
+            &self.import_sections(options),
+        )
+    }
+
+    /// Explain why a path (like `serde::de::DeserializeOwned`, or `std::io::*`)
+    /// is imported by the merged file: which use items on each side imported
+    /// it, how they were merged, and whether a glob import subsumed it (see
+    /// `usefix why`). This is meant for debugging, so the format isn't
+    /// stable.
+    pub fn explain_path(&self, path: &str, options: &MergeOptions) -> Result<String, Error> {
+        options.validate()?;
+        let options = &*self.resolve_options(options)?;
+        explain::explain_path(options, &self.import_sections(options), path)
+    }
+
+    /// Resolve the options that depend on the file or its project:
+    ///
+    /// - If `MergeOptions::prefer_side` is a branch name, replace it with the
+    ///   side of the file whose conflict markers have that label. It's an
+    ///   error if there are conflicts, but none of them have that label.
+    /// - With `MergeOptions::normalize_roots` or
+    ///   `MergeOptions::modernize_extern_crate`, if there's no
+    ///   `MergeOptions::edition`, use the edition of the package containing
+    ///   the file.
+    fn resolve_options<'o>(
+        &self,
+        options: &'o MergeOptions,
+    ) -> Result<Cow<'o, MergeOptions>, Error> {
+        let mut options = Cow::Borrowed(options);
+
+        if let Some(PreferredSide::Label(ref label)) = options.prefer_side {
+            match self.git_file.side_labeled(label) {
+                Some(side) => options.to_mut().prefer_side = Some(PreferredSide::Side(side)),
+                None if self.contains_conflict() => {
+                    return Err(InvalidOptionsError::UnknownBranch(label.clone()).into())
+                }
+                None => {}
+            }
+        }
+
+        if (options.normalize_roots || options.modernize_extern_crate) && options.edition.is_none()
+        {
+            if let Some(edition) = options.package_edition() {
+                options.to_mut().edition = Some(edition);
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Check whether the use items in a file (which shouldn't contain any
+    /// conflicts) are already written the way usefix would write them, and
+    /// return a violation for each section of use items that isn't. If every
+    /// section matches, but the file still differs from the fixed version
+    /// (because of the blank lines around the use items, for instance), a
+    /// single violation is returned for the first line that differs.
+    pub fn check_style(&self, options: &MergeOptions) -> Result<Vec<StyleViolation>, Error> {
+        let options = MergeOptions {
+            require_conflict: false,
+            ..options.clone()
+        };
+
+        let merged = self.merge_imports(&options)?;
+        let source_lines: Vec<&str> = self.source.lines().collect();
+
+        // Blank lines are checked separately, by comparing the whole file
+        let significant_lines = |lines: &mut dyn Iterator<Item = &str>| {
+            lines
+                .map(str::trim_end)
+                .filter(|line| !line.is_empty())
+                .join("\n")
+        };
+
+        let mut violations: Vec<StyleViolation> = merged
+            .sections
+            .iter()
+            .filter_map(|section| {
+                let touched_lines: Vec<usize> = section
+                    .discarded_lines
+                    .iter()
+                    .map(|line| line.get())
+                    .sorted()
+                    .collect();
+
+                let lines = *touched_lines.first()?..=*touched_lines.last()?;
+
+                let actual = significant_lines(
+                    &mut touched_lines
+                        .iter()
+                        .filter_map(|&line| source_lines.get(line - 1).copied()),
+                );
+
+                let expected = str::from_utf8(&section.formatted_use_items)
+                    .expect("formatted use items are always valid UTF-8");
+
+                (actual != significant_lines(&mut expected.lines())).then(|| StyleViolation {
+                    lines,
+                    expected: expected.to_owned(),
+                })
+            })
+            .collect();
+
+        if violations.is_empty() {
+            let rendered = merged.render();
+
+            let first_difference = rendered
+                .lines()
+                .zip_longest(source_lines.iter().copied())
+                .position(|lines| {
+                    !matches!(lines, EitherOrBoth::Both(expected, actual) if expected == actual)
+                });
+
+            if let Some(index) = first_difference {
+                violations.push(StyleViolation {
+                    lines: index + 1..=index + 1,
+                    expected: rendered.lines().nth(index).unwrap_or_default().to_owned(),
+                });
+            }
+        }
+
+        Ok(violations)
+    }
+
+    /// Split the use items into sections, each of which is merged and
+    /// rewritten separately
+    fn import_sections(&self, options: &MergeOptions) -> Vec<ImportSection<'_>> {
+        // Module declarations, crate attributes, `extern crate` items, and
+        // function-local use items are always extracted, but they're only
+        // merged with `--mods`, `--crate-attrs`, `--modernize-extern-crate`,
+        // and `--local-uses`; otherwise they're left where they are. `extern
+        // crate` items are only replaced in editions where crates are in
+        // scope without them.
+        let managed = |item: &&AnnotatedUseItem| match item.item {
+            ManagedItem::Use(_) => true,
+            ManagedItem::Mod(_) => options.mods,
+            ManagedItem::CrateAttr(_) => false,
+            ManagedItem::ExternCrate(_) => {
+                options.modernize_extern_crate
+                    && options
+                        .edition
+                        .is_some_and(|edition| edition >= Edition::E2018)
+            }
+            ManagedItem::LocalUse(_) => options.local_uses,
+        };
+
+        let left = self.left.iter().filter(managed);
+        let right = self.right.iter().filter(managed);
+
+        let mut sections = match options.hoist {
+            true => hoisted_import_sections(left, right),
+            false => find_import_sections(left, right),
+        };
+
+        // Function-local use items are only rewritten where they're touched
+        // by a conflict
+        if options.local_uses {
+            let conflicts: Vec<RangeInclusive<usize>> = self.git_file.conflict_lines().collect();
+
+            sections.retain(|section| {
+                let is_local = Iterator::chain(section.left.iter(), &section.right)
+                    .all(|item| matches!(item.item, ManagedItem::LocalUse(_)));
+
+                !is_local
+                    || section
+                        .touched_original_lines()
+                        .iter()
+                        .any(|line| conflicts.iter().any(|lines| lines.contains(&line.get())))
+            });
+        }
+
+        // With `--managed-region`, only the sections inside of the marked
+        // regions are rewritten. If there aren't any regions yet, the first
+        // section becomes one (see `merge_imports`).
+        if options.managed_region {
+            match region::find_regions(self.source).as_slice() {
+                [] => sections.truncate(1),
+                regions => sections.retain(|section| region::section_in_regions(section, regions)),
+            }
+        }
+
+        // The crate attributes are always a section of their own, at the top
+        // of the file
+        if options.crate_attrs {
+            let is_crate_attr =
+                |item: &&AnnotatedUseItem| matches!(item.item, ManagedItem::CrateAttr(_));
+
+            let section = ImportSection {
+                left: self.left.iter().filter(is_crate_attr).collect(),
+                right: self.right.iter().filter(is_crate_attr).collect(),
+            };
+
+            if !section.left.is_empty() || !section.right.is_empty() {
+                sections.insert(0, section);
+            }
+        }
+
+        // With `--lines`, only the sections that touch one of the selected
+        // conflicts are rewritten; the rest of the file is left alone.
+        if let Some(range) = options.lines {
+            let selected: Vec<RangeInclusive<usize>> = self
+                .git_file
+                .conflict_lines()
+                .filter(|lines| range.overlaps(lines))
+                .collect();
+
+            sections.retain(|section| {
+                section.touched_original_lines().iter().any(|line| {
+                    selected
+                        .iter()
+                        .any(|conflict| conflict.contains(&line.get()))
+                })
+            });
+        }
+
+        sections
+    }
+}
+
+/// A section of use items that isn't written the way usefix would write it
+/// (see [`ConflictedFile::check_style`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyleViolation {
+    /// The (one-indexed) lines of the file spanned by the section
+    pub lines: RangeInclusive<usize>,
+
+    /// The section, as usefix would write it
+    pub expected: String,
+}
+
+/// A conflict that's left in a fixed file (see
+/// [`ConflictedFile::remaining_conflicts`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemainingConflict {
+    /// The (one-indexed, inclusive) lines of the conflict in the original
+    /// file, including its markers. If it was made from several conflicts,
+    /// this spans all of them.
+    pub original: RangeInclusive<usize>,
+
+    /// The (one-indexed, inclusive) lines of the conflict in the fixed file,
+    /// including its markers
+    pub fixed: RangeInclusive<usize>,
+}
+
+/// How many of the conflicts in a file were resolved (see
+/// [`ConflictedFile::outcome`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The file didn't contain any conflicts
+    NoConflicts,
+
+    /// Every conflict was resolved
+    Resolved,
+
+    /// The conflicts in use items were resolved, but some conflicts in other
+    /// code remain, and need to be resolved by hand
+    UseItemsResolved {
+        /// The number of conflicts left in the fixed file
+        remaining: usize,
+    },
+
+    /// None of the conflicts involved use items (or they were tangled up
+    /// with other code), so nothing could be resolved
+    Unresolved {
+        /// The number of conflicts left in the fixed file
+        remaining: usize,
+    },
+}
+
+/// The merged and formatted use items for a file, ready to be written back
+/// into it.
+pub struct MergedImports<'a> {
+    file: &'a ConflictedFile<'a>,
+    sections: Vec<FormattedSection>,
+    trailing_blank_lines: usize,
+
+    /// If true, there was nothing to fix, so the original file is written
+    /// byte for byte, instead of being rebuilt from its lines
+    passthrough: bool,
+
+    warnings: Vec<String>,
+}
+
+impl MergedImports<'_> {
+    /// The formatted use items for each section of the file, in the order
+    /// they appear in the file.
+    pub fn sections(&self) -> impl Iterator<Item = &str> + '_ {
+        self.sections.iter().map(|section| {
+            str::from_utf8(&section.formatted_use_items)
+                .expect("formatted use items are always valid UTF-8")
+        })
+    }
+
+    /// Problems that came up while merging the file, which usefix worked
+    /// around rather than failing: for instance, the external formatter
+    /// producing invalid output, which is replaced with prettyplease's.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Write the fixed version of the file, with the merged use items in
+    /// place of the original ones.
+    pub fn write_to(&self, mut dest: impl io::Write) -> io::Result<()> {
+        if self.passthrough {
+            return dest.write_all(self.file.source.as_bytes());
+        }
+
+        write_file::write_corrected_file(
+            &mut dest,
+            &self.file.git_file,
+            &self.sections,
+            self.trailing_blank_lines,
+        )
+    }
+
+    /// Render the fixed version of the file to a string.
+    pub fn render(&self) -> String {
+        // We assume that files fit neatly in memory, so we just put it all
+        // in a single buffer.
+        let mut output = Vec::with_capacity(self.file.source.len());
+        self.write_to(&mut output)
+            .expect("writing to a vector is infallible");
+
+        String::from_utf8(output).expect("fixed file is always valid UTF-8")
+    }
+}
+
+/// Parse a rust source file containing git conflicts (or a fragment of one,
+/// with `MergeOptions::fragment`), merge its use items, and render the fixed
+/// version of the file. Any warnings (see [`MergedImports::warnings`]) are
+/// discarded.
+pub fn fix_file(source: &str, options: &MergeOptions) -> Result<String, Error> {
+    let file = match options.fragment {
+        true => ConflictedFile::parse_fragment(source)?,
+        false => ConflictedFile::parse(source)?,
+    };
+
+    let merged = file.merge_imports(options)?;
+    Ok(merged.render())
+}
+
+/// Merge a set of use items (from the left and right sides of a single
+/// section), convert them into a form suitable for printing, and pass them
+/// to `render`.
+fn with_printable_items<T>(
+    options: &MergeOptions,
+    print_options: PrintOptions,
+    left: &[&AnnotatedUseItem],
+    right: &[&AnnotatedUseItem],
+    render: impl FnOnce(&PrintableUseItems<'_>) -> T,
+) -> Result<T, Error> {
+    with_merged_items(options, left, right, |grouped_flattened_items| {
+        printable_items(print_options, left, right, grouped_flattened_items)
+            .map(|printable_items| render(&printable_items))
+    })
+}
+
+/// Convert the merged imports of a section (see `with_merged_items`) into a
+/// form suitable for printing
+fn printable_items<'a>(
+    print_options: PrintOptions,
+    left: &[&'a AnnotatedUseItem],
+    right: &[&'a AnnotatedUseItem],
+    grouped_flattened_items: &'a ConfigToPathToProperties<'a>,
+) -> Result<PrintableUseItems<'a>, Error> {
+    let left_use_items = || left.iter().filter_map(|item| item.item.use_item());
+    let right_use_items = || right.iter().filter_map(|item| item.item.use_item());
+
+    let root_order = RootOrder::from_sides(
+        left_use_items()
+            .flat_map(|use_item| &use_item.roots)
+            .map(Unraw),
+        right_use_items()
+            .flat_map(|use_item| &use_item.roots)
+            .map(Unraw),
+    );
+
+    let configs_order = ConfigsOrder::from_sides(
+        left_use_items().map(|use_item| &use_item.configs),
+        right_use_items().map(|use_item| &use_item.configs),
+    );
+
+    PrintableUseItems::build_from_use_items(
+        print_options,
+        root_order,
+        configs_order,
+        grouped_flattened_items
+            .iter()
+            .flat_map(|(&configs, items)| {
+                items.iter().map(move |(&path, properties)| {
+                    let docs = &properties.docs;
+                    (
+                        docs,
+                        configs,
+                        &properties.doc_attrs,
+                        properties.visibility,
+                        path,
+                    )
+                })
+            }),
+    )
+}
+
+/// Merge a set of use items (from the left and right sides of a single
+/// section) into their canonical set of imports, grouped by config, and pass
+/// them to `f`.
+fn with_merged_items<T>(
+    options: &MergeOptions,
+    left: &[&AnnotatedUseItem],
+    right: &[&AnnotatedUseItem],
+    f: impl FnOnce(&ConfigToPathToProperties<'_>) -> T,
+) -> T {
+    let flattened_items = flatten_items(options, left, right);
+
+    // Group the list by config and normalize wildcard. Any time a path appears
+    // with a wildcard import, it subsumes all instances of that same path
+    // importing a non-renamed item, provided they share a config
+    let grouped_flattened_items =
+        group_flattened_items_normalize_wildcards(&flattened_items, &options.policy);
+
+    f(&grouped_flattened_items)
+}
+
+/// Flatten a set of use items (from the left and right sides of a single
+/// section) into a list of paths, each with all of its known properties.
+fn flatten_items<'a>(
+    options: &MergeOptions,
+    left: &[&'a AnnotatedUseItem],
+    right: &[&'a AnnotatedUseItem],
+) -> NormalizedUsedItems<'a> {
+    // Flatten the list into a list of paths, where each path stores all known
+    // properties variants. This step normalizes the configs (any time a path
+    // appears in unconditional form, it subsumes all instances of that path
+    // in conditional form), and optionally `self::` prefixes.
+    //
+    // With `--prefer-side`, the preferred side is flattened first, so that
+    // its version of each import is always the first one to be merged (see
+    // `PreferFirst`).
+    let (first, second, policy) = match options.prefer_side {
+        Some(PreferredSide::Side(side)) => {
+            let (preferred, other) = match side {
+                Side::Left => (left, right),
+                Side::Right => (right, left),
+            };
+
+            let preferred_imports =
+                flatten_side(options, &options.policy, preferred.iter().copied())
+                    .items
+                    .keys()
+                    .map(|path| {
+                        let configs = &ConfigsList::EMPTY;
+                        Import::from_flattened(path, configs, None, Vec::new(), Vec::new())
+                    })
+                    .collect();
+
+            let policy = Policy::new(PreferFirst {
+                inner: options.policy.clone(),
+                preferred: preferred_imports,
+            });
+
+            (preferred, other, policy)
+        }
+        _ => (left, right, options.policy.clone()),
+    };
+
+    let mut flattened_items = match options.strategy {
+        MergeStrategy::Union | MergeStrategy::Intersection => flatten_side(
+            options,
+            &policy,
+            Iterator::chain(first.iter(), second.iter()).copied(),
+        ),
+        MergeStrategy::Ours => flatten_side(options, &policy, left.iter().copied()),
+        MergeStrategy::Theirs => flatten_side(options, &policy, right.iter().copied()),
+    };
+
+    // An import is on a side if it appears there, or if something that
+    // subsumes it (like a glob import) does
+    if options.strategy == MergeStrategy::Intersection {
+        let left = flatten_side(options, &policy, left.iter().copied());
+        let right = flatten_side(options, &policy, right.iter().copied());
+
+        let is_on_side = |side: &NormalizedUsedItems<'_>, path: &SingleUsedItem<'_>| {
+            side.items.contains_key(path)
+                || path
+                    .possible_parents()
+                    .any(|parent| side.items.contains_key(&parent))
+        };
+
+        flattened_items
+            .items
+            .retain(|path, _| is_on_side(&left, path) && is_on_side(&right, path));
+    }
+
+    if !options.discard.is_empty() {
+        // All of the items in a section are in the same module
+        let module = Iterator::chain(left.iter(), right.iter())
+            .next()
+            .map(|item| item.module.as_slice())
+            .unwrap_or_default();
+
+        flattened_items
+            .items
+            .retain(|path, _| !is_discarded(options, module, path));
+    }
+
+    flattened_items.resolve_rename_conflicts();
+
+    if options.discard_shadowed_anonymous {
+        flattened_items.discard_shadowed_anonymous_imports();
+    }
+
+    flattened_items
+}
+
+/// Flatten the use items from one or both sides of a section, and normalize
+/// their `self::` prefixes (and their leading `::`, with
+/// `--normalize-roots`)
+fn flatten_side<'a>(
+    options: &MergeOptions,
+    policy: &Policy,
+    items: impl IntoIterator<Item = &'a AnnotatedUseItem>,
+) -> NormalizedUsedItems<'a> {
+    let items: Vec<&AnnotatedUseItem> = items.into_iter().collect();
+
+    let mut flattened_items = NormalizedUsedItems::new(options.self_prefix, policy.clone());
+    items
+        .iter()
+        .filter_map(|item| Some((item.item.use_item()?, item)))
+        .for_each(|(use_item, item)| {
+            let origin = item.origin.clone().filter(|_| options.mark_divergent_docs);
+            flattened_items.add_tree_from(use_item, origin)
+        });
+    flattened_items.apply_self_prefix_style();
+
+    if let Some(ref rules) = options.cfg_rules {
+        flattened_items.apply_cfg_rules(rules);
+    }
+
+    if options.strip_docs {
+        flattened_items.strip_docs();
+    }
+
+    if options.normalize_roots {
+        flattened_items.normalize_roots(
+            options.edition.unwrap_or(Edition::E2018),
+            items
+                .iter()
+                .filter_map(|item| item.item.mod_decl())
+                .map(|decl| &decl.name),
+        );
+    }
+
+    flattened_items
+}
+
+/// True if this path, in this module, is one of the imports in
+/// `MergeOptions::discard`
+fn is_discarded(options: &MergeOptions, module: &[String], path: &SingleUsedItem<'_>) -> bool {
+    let key = Import::from_flattened(path, &ConfigsList::EMPTY, None, Vec::new(), Vec::new());
+    let key = key.match_key(options.self_prefix);
+
+    options.discard.iter().any(|import| {
+        import.provenance.module == module && import.match_key(options.self_prefix) == key
+    })
+}
+
+/// The width that crate attributes are wrapped at, unless
+/// `MergeOptions::max_width` is set: rustfmt's default `max_width`
+const DEFAULT_MAX_WIDTH: usize = 100;
+
+/// Merge all of the use items in a single section, and render them into
+/// their final, prettified form. Any problems that are worked around are
+/// added to `warnings` (see `MergedImports::warnings`).
+fn fix_import_section(
+    options: &MergeOptions,
+    rustfmt_settings: Option<&RustfmtSettings>,
+    print_options: PrintOptions,
+    section: &ImportSection<'_>,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<u8>, Error> {
+    // Prettify rendered use items, adding indentation and newlines and so on
+    let mut prettify = |formatted_use_items: &str| -> Result<Vec<u8>, Error> {
+        match options.rustfmt.as_deref() {
+            None if options.no_format => Ok(formatted_use_items.as_bytes().to_vec()),
+            None => prettify_with_prettyplease(formatted_use_items, options.max_width),
+            Some(command) => {
+                let printable_command = command.display();
+
+                let prettified_use_items =
+                    prettify_with_subcommand(command, rustfmt_settings, formatted_use_items)
+                        .map_err(|source| Error::Formatter {
+                            command: command.to_path_buf(),
+                            source,
+                        })?;
+
+                // If the formatter produced garbage, we'd rather format the use
+                // items ourselves than splice the garbage into the file.
+                match validate_formatted_use_items(formatted_use_items, &prettified_use_items) {
+                    Ok(()) => Ok(prettified_use_items),
+                    Err(err) => {
+                        let warning = format!(
+                            "external subcommand '{printable_command}' produced invalid \
+                            output ({err}); using prettyplease instead"
+                        );
+
+                        // Every section is formatted separately, but the
+                        // warning only needs to be given once
+                        if !warnings.contains(&warning) {
+                            warnings.push(warning);
+                        }
+
+                        prettify_with_prettyplease(formatted_use_items, options.max_width)
+                    }
+                }
+            }
+        }
+    };
+
+    // Render the use items to a string, complete with sorting and grouping,
+    // then prettify them. In `cfg_if!` mode, each conditional group is
+    // rendered and prettified separately, then wrapped in its block. When
+    // preserving unchanged use items, each use item is handled separately.
+    let prettified_use_items = if options.preserve_unchanged {
+        let unchanged = unchanged_use_items(options, print_options, section)?;
+        let groups = with_printable_items(
+            options,
+            print_options,
+            &section.left,
+            &section.right,
+            |printable_items| printable_items.render_groups(),
+        )?;
+
+        prettify_preserving_unchanged(groups, &unchanged, prettify)?
+    } else {
+        with_printable_items(
+            options,
+            print_options,
+            &section.left,
+            &section.right,
+            |printable_items| match options.cfg_if {
+                true => {
+                    prettify_cfg_if_sections(printable_items.render_cfg_if_sections(), prettify)
+                }
+                false => prettify(&printable_items.to_string()),
+            },
+        )??
+    };
+
+    let prettified_use_items = match options.annotate_origin {
+        true => origin::annotate_origins(options, section, &prettified_use_items),
+        false => prettified_use_items,
+    };
+
+    // Crate attributes and module declarations go in blocks of their own,
+    // in front of the use items. They're simple enough that they never need
+    // to be prettified.
+    let crate_attrs = render_crate_attrs(
+        section.crate_attrs(),
+        options.max_width.unwrap_or(DEFAULT_MAX_WIDTH),
+    );
+    let mod_decls = render_mod_decls(&options.policy, section.mod_decls());
+
+    let formatted_items = [
+        crate_attrs.into_bytes(),
+        mod_decls.into_bytes(),
+        prettified_use_items,
+    ]
+    .into_iter()
+    .filter(|block| !block.is_empty())
+    .collect::<Vec<_>>()
+    .join(&b'\n');
+
+    Ok(match section.indentation() {
+        "" => formatted_items,
+        indentation => indent(&formatted_items, indentation),
+    })
+}
+
+/// Find all of the use items in a section that are already in their
+/// canonical form: each one, rendered on its own, produces a single use item
+/// that might appear unchanged in the output. Returns a map from the rendered
+/// form to the original text of the use item.
+fn unchanged_use_items<'a>(
+    options: &MergeOptions,
+    print_options: PrintOptions,
+    section: &ImportSection<'a>,
+) -> Result<HashMap<String, &'a str>, Error> {
+    // Each use item is rendered on its own, as if it were the left side of
+    // a conflict, so it has to be kept regardless of the strategy
+    let options = MergeOptions {
+        strategy: MergeStrategy::Union,
+        ..options.clone()
+    };
+
+    // With `--strip-docs`, a use item with docs is never unchanged. An
+    // `extern crate` item renders the same as the use item that replaces it,
+    // so it's never unchanged either; otherwise, the use item would be
+    // turned back into the `extern crate` item.
+    Iterator::chain(section.left.iter(), section.right.iter())
+        .filter(|item| match item.item {
+            ManagedItem::Use(ref use_item) | ManagedItem::LocalUse(ref use_item) => {
+                !(options.strip_docs && use_item.docs.is_not_empty())
+            }
+            _ => false,
+        })
+        .map(|&item| {
+            let groups =
+                with_printable_items(&options, print_options, &[item], &[], |printable_items| {
+                    printable_items.render_groups()
+                })?;
+
+            Ok(match <[_; 1]>::try_from(groups.concat()) {
+                Ok([rendered]) => Some((rendered, item.original_text.as_str())),
+                Err(_) => None,
+            })
+        })
+        .flatten_ok()
+        .collect()
+}
+
+/// Prettify a series of groups of use items, each rendered separately (see
+/// `PrintableUseItems::render_groups`). Any use item that appears in
+/// `unchanged` is replaced with its original text, rather than prettified,
+/// to avoid churn.
+fn prettify_preserving_unchanged<E>(
+    groups: Vec<Vec<String>>,
+    unchanged: &HashMap<String, &str>,
+    mut prettify: impl FnMut(&str) -> Result<Vec<u8>, E>,
+) -> Result<Vec<u8>, E> {
+    let mut output = Vec::new();
+
+    for group in groups {
+        for rendered in group {
+            match unchanged.get(&rendered) {
+                Some(original) => output.extend_from_slice(original.as_bytes()),
+                None => output.extend_from_slice(prettify(&rendered)?.trim_ascii_end()),
+            }
+
+            output.push(b'\n');
+        }
+
+        output.push(b'\n');
+    }
+
+    Ok(output)
+}
+
+/// Record the label of the conflict half that each item (from one version of
+/// the file) is in, if it's in a conflict
+fn label_conflicted_items(git_file: &GitFile<'_>, side: Side, items: &mut [AnnotatedUseItem]) {
+    let halves: Vec<(HashSet<LineNumber>, Arc<str>)> = git_file
+        .chunks()
+        .iter()
+        .filter_map(|chunk| match chunk {
+            Chunk::Conflict(conflict) => Some(match side {
+                Side::Left => &conflict.left,
+                Side::Right => &conflict.right,
+            }),
+            Chunk::Line(_) => None,
+        })
+        .map(|half| {
+            let lines = half.lines().iter().map(|line| line.line_number).collect();
+            (lines, Arc::from(half.name()))
+        })
+        .collect();
+
+    for item in items {
+        item.origin = halves
+            .iter()
+            .find(|(lines, _)| {
+                item.touched_original_lines
+                    .iter()
+                    .any(|line| lines.contains(line))
+            })
+            .map(|(_, name)| name.clone());
+    }
+}
+
+/// Find the conflicts in which the items extracted from either side are
+/// interleaved with other code on that side: that is, where some line that
+/// isn't blank, a comment, or part of an extracted item is between two lines
+/// that are part of extracted items (see `ConflictedFile::tangled_conflicts`).
+fn find_tangled_conflicts(
+    git_file: &GitFile<'_>,
+    left: &[AnnotatedUseItem],
+    right: &[AnnotatedUseItem],
+) -> Vec<RangeInclusive<usize>> {
+    // Function-local use items are always inside of some other code, so
+    // they're left out (see `ManagedItem::LocalUse`)
+    let touched_lines = |items: &[AnnotatedUseItem]| -> HashSet<LineNumber> {
+        items
+            .iter()
+            .filter(|item| !matches!(item.item, ManagedItem::LocalUse(_)))
+            .flat_map(|item| &item.touched_original_lines)
+            .copied()
+            .collect()
+    };
+
+    let left_lines = touched_lines(left);
+    let right_lines = touched_lines(right);
+
+    let is_tangled = |half: &ConflictHalf<'_, Line<'_>>, touched: &HashSet<LineNumber>| {
+        let mut seen_item = false;
+        let mut code_after_item = false;
+
+        for line in half.lines() {
+            let content = line.content.trim();
+
+            if touched.contains(&line.line_number) {
+                if code_after_item {
+                    return true;
+                }
+
+                seen_item = true;
+            } else if !content.is_empty() && !content.starts_with("//") {
+                code_after_item = seen_item;
+            }
+        }
+
+        false
+    };
+
+    let conflicts = git_file.chunks().iter().filter_map(|chunk| match chunk {
+        Chunk::Conflict(conflict) => Some(conflict),
+        Chunk::Line(_) => None,
+    });
+
+    Iterator::zip(conflicts, git_file.conflict_lines())
+        .filter(|(conflict, _)| {
+            is_tangled(&conflict.left, &left_lines) || is_tangled(&conflict.right, &right_lines)
+        })
+        .map(|(_, lines)| lines)
+        .collect()
+}
+
+/// Parse a GitFile with syn, and extract its use itmes (and their spans) into
+/// a list of Annotated Use Items.
+fn extract_use_items(file: &GitFile<'_>, side: Side) -> Result<Vec<AnnotatedUseItem>, Error> {
+    let derived_file = file.build_derived_file(side);
+    let derived_file_lines: Vec<&str> = derived_file.content().lines().collect();
+
+    let parsed_file = syn::parse_file(derived_file.content()).map_err(|err| {
+        let point = err.span().start();
+
+        Error::RustSyntax {
+            side: file.contains_conflict().then_some(side),
+            line: original_line_number(&derived_file, point.line),
+            column: point.column + 1,
+            source: err,
+        }
+    })?;
+
+    let preamble_end = find_preamble_end(&parsed_file.attrs);
+
+    // Crate attributes come before any items, so they're in a section of
+    // their own in any case (see `ConflictedFile::import_sections`)
+    let mut syn_use_items: Vec<SynUseItem> = parsed_file
+        .attrs
+        .into_iter()
+        .filter(|attr| matches!(attr.style, syn::AttrStyle::Inner(_)))
+        .filter(|attr| !attr.path().is_ident("doc"))
+        .map(|attr| SynUseItem {
+            item: SynItem::CrateAttr(attr),
+            module: Vec::new(),
+            module_start: None,
+            section: 0,
+            preamble_end: None,
+        })
+        .collect();
+
+    collect_syn_use_items(
+        parsed_file.items,
+        &[],
+        None,
+        preamble_end,
+        &mut SectionTracker::default(),
+        &mut syn_use_items,
+    );
+
+    annotate_use_items(&derived_file, &derived_file_lines, syn_use_items)
+}
+
+/// Like `extract_use_items`, but for a fragment of a file (see
+/// `ConflictedFile::parse_fragment`), which might not be valid rust as a
+/// whole.
+fn extract_fragment_use_items(
+    file: &GitFile<'_>,
+    side: Side,
+) -> Result<Vec<AnnotatedUseItem>, Error> {
+    let derived_file = file.build_derived_file(side);
+    let derived_file_lines: Vec<&str> = derived_file.content().lines().collect();
+    let syn_use_items = fragment::collect_fragment_use_items(&derived_file_lines);
+
+    annotate_use_items(&derived_file, &derived_file_lines, syn_use_items)
+}
+
+/// Convert the use items extracted from one version of a file into
+/// Annotated Use Items, with their line numbers mapped back to the original
+/// file.
+fn annotate_use_items(
+    derived_file: &DerivedFile<'_>,
+    derived_file_lines: &[&str],
+    syn_use_items: Vec<SynUseItem>,
+) -> Result<Vec<AnnotatedUseItem>, Error> {
+    // Items marked with `// usefix: skip` are left exactly where they are, as
+    // if they were any other kind of item, so they split their section in
+    // two.
+    let mut section = 0;
+    let mut previous_section = None;
+
+    let mut syn_use_items: Vec<SynUseItem> = syn_use_items
+        .into_iter()
+        .filter_map(|mut item| {
+            if is_skipped(item.item.span(), derived_file_lines) {
+                previous_section = None;
+                return None;
+            }
+
+            if previous_section != Some(item.section) {
+                section += 1;
+                previous_section = Some(item.section);
+            }
+
+            item.section = section;
+            Some(item)
+        })
+        .collect();
+
+    check_use_items_have_own_lines(&syn_use_items, derived_file, derived_file_lines)?;
+
+    // A module declaration or crate attribute that shares a line with
+    // anything else can't be moved without taking that along with it, so
+    // it's left unmanaged.
+    syn_use_items.retain(|item| match item.item {
+        SynItem::Use(_) => true,
+        SynItem::Mod(_)
+        | SynItem::CrateAttr(_)
+        | SynItem::ExternCrate(_)
+        | SynItem::LocalUse(_) => has_own_lines(item.item.span(), derived_file_lines),
+    });
+
+    let use_items = syn_use_items
+        .into_iter()
+        .filter_map(|item| {
+            let managed_item = match item.item {
+                SynItem::Use(use_item) => {
+                    ManagedItem::Use(UseItem::from_syn_use_item(use_item).ok()?)
+                }
+                SynItem::Mod(mod_item) => ManagedItem::Mod(ModDecl::from_syn_mod_item(mod_item)?),
+                SynItem::CrateAttr(attr) => {
+                    ManagedItem::CrateAttr(CrateAttr::from_syn_attribute(attr, derived_file_lines)?)
+                }
+                SynItem::ExternCrate(extern_crate) => {
+                    ManagedItem::ExternCrate(UseItem::from_syn_extern_crate(extern_crate)?)
+                }
+                SynItem::LocalUse(use_item) => {
+                    ManagedItem::LocalUse(UseItem::from_syn_use_item(use_item).ok()?)
+                }
+            };
+
+            Some((
+                managed_item,
+                item.module,
+                item.module_start,
+                item.section,
+                item.preamble_end,
+            ))
+        })
+        .map(|(item, module, module_start, section, preamble_end)| {
+            let start = *item.lines().start();
+            let end = *item.lines().end();
+
+            // Whenever a `use` item is followed by a newline, we include that
+            // newline in set of lines that are "touched" by it
+            //
+            // Note on indexing: syn line numbers are one-indexed and inclusive,
+            // but we want the line AFTER that end line, so it's end - 1 + 1
+            let end = match derived_file_lines.get(end) {
+                Some(line) if line.trim().is_empty() => end + 1,
+                _ => end,
+            }
+            // Add an extra +1 so we can use `..end` instead of `..=end`
+            + 1;
+
+            let touched_original_lines = (start..end)
+                .map(|derived_line| original_line(derived_file, derived_line))
+                .collect::<Result<_, Error>>()?;
+
+            // Use items in inline modules are indented; we assume that every
+            // use item in a module has the same indentation as the first one.
+            let indentation = start
+                .checked_sub(1)
+                .and_then(|index| derived_file_lines.get(index))
+                .map(|line| {
+                    let content = line.trim_start();
+                    line[..line.len() - content.len()].to_owned()
+                })
+                .unwrap_or_default();
+
+            let preamble_end = preamble_end
+                .map(|derived_line| original_line(derived_file, derived_line))
+                .transpose()?;
+            let module_start = module_start
+                .map(|derived_line| original_line(derived_file, derived_line))
+                .transpose()?;
+
+            // The original text of the use item, without its indentation
+            let original_text = derived_file_lines
+                .get(start.saturating_sub(1)..*item.lines().end())
+                .unwrap_or_default()
+                .iter()
+                .map(|line| line.strip_prefix(indentation.as_str()).unwrap_or(line))
+                .join("\n");
+
+            Ok(AnnotatedUseItem {
+                item,
+                touched_original_lines,
+                original_text,
+                section,
+                module,
+                module_start,
+                indentation,
+                preamble_end,
+                origin: None,
+            })
+        })
+        .collect();
+
+    use_items
+}
+
+/// Map a (one-indexed) line of a derived file to the line of the original
+/// file that it came from.
+fn original_line(derived_file: &DerivedFile<'_>, derived_line: usize) -> Result<LineNumber, Error> {
+    LineNumber::from_one_indexed(derived_line)
+        .and_then(|line| derived_file.get_original_line(line))
+        .ok_or(Error::Internal {
+            reason: "an item was found on a line that isn't in the file",
+        })
+}
+
+/// We assume that no line that includes any part of a use item includes
+/// anything OTHER than use items (and comments), because those lines are
+/// discarded wholesale. Check that assumption, so that we can fail cleanly
+/// instead of silently deleting code.
+fn check_use_items_have_own_lines(
+    use_items: &[SynUseItem],
+    derived_file: &DerivedFile<'_>,
+    derived_file_lines: &[&str],
+) -> Result<(), Error> {
+    // For each line, the ranges of columns (in chars, which is what
+    // proc-macro2 uses) that are covered by use items
+    let mut covered: BTreeMap<usize, Vec<Range<usize>>> = BTreeMap::new();
+
+    for item in use_items {
+        if let SynItem::Use(ref use_item) = item.item {
+            add_covered_columns(&mut covered, use_item.span());
+        }
+    }
+
+    for (line, ranges) in covered {
+        let Some(content) = derived_file_lines.get(line - 1) else {
+            continue;
+        };
+
+        if let Some(column) = first_uncovered_column(content, &ranges) {
+            return Err(Error::Unsupported {
+                line: original_line_number(derived_file, line),
+                column: column + 1,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// True if the line directly above this span is a `// usefix: skip` comment
+fn is_skipped(span: Span, derived_file_lines: &[&str]) -> bool {
+    span.start()
+        .line
+        .checked_sub(2)
+        .and_then(|line| derived_file_lines.get(line))
+        .and_then(|line| line.trim().strip_prefix("//"))
+        .and_then(|comment| comment.trim().strip_prefix("usefix:"))
+        .is_some_and(|directive| directive.trim() == "skip")
+}
+
+/// True if the lines containing this span contain nothing else (other than
+/// trailing comments)
+fn has_own_lines(span: Span, derived_file_lines: &[&str]) -> bool {
+    let mut covered: BTreeMap<usize, Vec<Range<usize>>> = BTreeMap::new();
+    add_covered_columns(&mut covered, span);
+
+    covered.iter().all(|(&line, ranges)| {
+        derived_file_lines
+            .get(line - 1)
+            .is_none_or(|content| first_uncovered_column(content, ranges).is_none())
+    })
+}
+
+/// Add the columns covered by a span, for each of its lines
+fn add_covered_columns(covered: &mut BTreeMap<usize, Vec<Range<usize>>>, span: Span) {
+    let start = span.start();
+    let end = span.end();
+
+    for line in start.line..=end.line {
+        let from = if line == start.line { start.column } else { 0 };
+        let to = if line == end.line {
+            end.column
+        } else {
+            usize::MAX
+        };
+
+        covered.entry(line).or_default().push(from..to);
+    }
+}
+
+/// Find the first (zero-indexed) column in a line, if any, that has code
+/// that isn't in any of the covered ranges
+fn first_uncovered_column(content: &str, ranges: &[Range<usize>]) -> Option<usize> {
+    let mut uncovered = content
+        .chars()
+        .enumerate()
+        .filter(|(column, _)| !ranges.iter().any(|range| range.contains(column)));
+
+    while let Some((column, c)) = uncovered.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        // A trailing comment is fine; it's discarded along with the line
+        if c == '/' && matches!(uncovered.next(), Some((_, '/'))) {
+            return None;
+        }
+
+        return Some(column);
+    }
+
+    None
+}
+
+/// Convert a one-indexed line number in a derived file to the corresponding
+/// line number in the original file. Lines past the end of the derived file
+/// (which syn sometimes reports for errors at EOF) are left as they are.
+fn original_line_number(derived_file: &DerivedFile<'_>, line: usize) -> usize {
+    LineNumber::from_one_indexed(line)
+        .and_then(|line| derived_file.get_original_line(line))
+        .map(|line| line.get())
+        .unwrap_or(line)
+}
+
+/// Tracks the division of use items into sections (see `sections.rs`) while
+/// walking the items in a file.
+#[derive(Debug, Default)]
+struct SectionTracker {
+    section: usize,
+    has_use_items: bool,
+}
+
+impl SectionTracker {
+    /// Add a use item to the current section, and get its index
+    fn add_use_item(&mut self) -> usize {
+        self.has_use_items = true;
+        self.section
+    }
+
+    /// End the current section, if it has any use items
+    fn end_section(&mut self) {
+        if self.has_use_items {
+            self.section += 1;
+            self.has_use_items = false;
+        }
+    }
+}
+
+/// A use item (or module declaration) that hasn't yet been converted into a
+/// `ManagedItem`, along with information about where it is in the file.
+struct SynUseItem {
+    item: SynItem,
+    module: Vec<String>,
+
+    /// The line where the inline module (or function) containing this use
+    /// item starts, if it isn't at the top level of the file
+    module_start: Option<usize>,
+
+    section: usize,
+
+    /// The last line of the inner attributes and docs (`#![...]` and `//!`)
+    /// of the module containing this use item, if it has any
+    preamble_end: Option<usize>,
+}
+
+/// The kinds of syn items that usefix extracts from a file
+enum SynItem {
+    Use(syn::ItemUse),
+    Mod(syn::ItemMod),
+    CrateAttr(syn::Attribute),
+    ExternCrate(syn::ItemExternCrate),
+
+    /// A use item at the top of a function body
+    LocalUse(syn::ItemUse),
+}
+
+impl SynItem {
+    fn span(&self) -> Span {
+        match self {
+            SynItem::Use(item) => item.span(),
+            SynItem::Mod(item) => item.span(),
+            SynItem::CrateAttr(attr) => attr.span(),
+            SynItem::ExternCrate(item) => item.span(),
+            SynItem::LocalUse(item) => item.span(),
+        }
+    }
+}
+
+/// Find the last line of a module's inner attributes and docs, given all of
+/// its attributes.
+fn find_preamble_end(attrs: &[syn::Attribute]) -> Option<usize> {
+    attrs
+        .iter()
+        .filter(|attr| matches!(attr.style, syn::AttrStyle::Inner(_)))
+        .map(|attr| attr.span().end().line)
+        .max()
+}
+
+/// Recursively collect all of the use items (and module declarations and
+/// `extern crate` items) from a list of items, including the use items in
+/// inline modules and at the top of function bodies, along with the path to
+/// the module that contains each one and its section.
+///
+/// Use items are split into sections by any other kind of item, except for
+/// `extern crate` items and module declarations, which are commonly
+/// interleaved with use items. Each inline module and function body gets its
+/// own sections; function bodies are named like `fn name`, and methods are
+/// in an `impl Type` (see `impl_name`).
+fn collect_syn_use_items(
+    items: Vec<syn::Item>,
+    module: &[String],
+    module_start: Option<usize>,
+    preamble_end: Option<usize>,
+    sections: &mut SectionTracker,
+    dest: &mut Vec<SynUseItem>,
+) {
+    for item in items {
+        match item {
+            syn::Item::Use(use_item) => dest.push(SynUseItem {
+                item: SynItem::Use(use_item),
+                module: module.to_vec(),
+                module_start,
+                section: sections.add_use_item(),
+                preamble_end,
+            }),
+            syn::Item::Mod(mod_item @ syn::ItemMod { content: None, .. }) => {
+                dest.push(SynUseItem {
+                    item: SynItem::Mod(mod_item),
+                    module: module.to_vec(),
+                    module_start,
+                    section: sections.add_use_item(),
+                    preamble_end,
+                })
+            }
+            syn::Item::ExternCrate(extern_crate) => dest.push(SynUseItem {
+                item: SynItem::ExternCrate(extern_crate),
+                module: module.to_vec(),
+                module_start,
+                section: sections.add_use_item(),
+                preamble_end,
+            }),
+            syn::Item::Fn(item_fn) => {
+                sections.end_section();
+
+                let start = item_fn.span().start().line;
+                let mut module = module.to_vec();
+                module.push(format!("fn {}", item_fn.sig.ident));
+                collect_local_use_items(
+                    *item_fn.block,
+                    &item_fn.attrs,
+                    &module,
+                    start,
+                    sections,
+                    dest,
+                );
+            }
+            syn::Item::Impl(item_impl) => {
+                sections.end_section();
+
+                let mut module = module.to_vec();
+                module.push(impl_name(&item_impl));
+
+                for impl_item in item_impl.items {
+                    if let syn::ImplItem::Fn(method) = impl_item {
+                        let start = method.span().start().line;
+                        let mut module = module.clone();
+                        module.push(format!("fn {}", method.sig.ident));
+                        collect_local_use_items(
+                            method.block,
+                            &method.attrs,
+                            &module,
+                            start,
+                            sections,
+                            dest,
+                        );
+                    }
+                }
+            }
+            syn::Item::Mod(mod_item) => {
+                sections.end_section();
+
+                let start = mod_item.span().start().line;
+                let preamble_end = find_preamble_end(&mod_item.attrs);
+                let mut module = module.to_vec();
+                module.push(mod_item.ident.to_string());
+
+                if let Some((_, items)) = mod_item.content {
+                    collect_syn_use_items(
+                        items,
+                        &module,
+                        Some(start),
+                        preamble_end,
+                        sections,
+                        dest,
+                    );
+                }
+
+                sections.end_section();
+            }
+            _ => sections.end_section(),
+        }
+    }
+}
+
+/// Collect the use items at the top of a function body (before any other
+/// statement), as function-local use items in a section of their own. The
+/// body is treated like an inline module, named after the function (see
+/// `collect_syn_use_items`).
+fn collect_local_use_items(
+    block: syn::Block,
+    attrs: &[syn::Attribute],
+    module: &[String],
+    start: usize,
+    sections: &mut SectionTracker,
+    dest: &mut Vec<SynUseItem>,
+) {
+    let preamble_end = find_preamble_end(attrs);
+
+    for stmt in block.stmts {
+        match stmt {
+            syn::Stmt::Item(syn::Item::Use(use_item)) => dest.push(SynUseItem {
+                item: SynItem::LocalUse(use_item),
+                module: module.to_vec(),
+                module_start: Some(start),
+                section: sections.add_use_item(),
+                preamble_end,
+            }),
+            _ => break,
+        }
+    }
+
+    sections.end_section();
+}
+
+/// The name of an impl block, for the module path of the use items in its
+/// methods: `impl Type`, or `impl Trait for Type`, with just the last
+/// segment of each path.
+fn impl_name(item_impl: &syn::ItemImpl) -> String {
+    let last_segment = |path: &syn::Path| {
+        path.segments
+            .last()
+            .map_or_else(String::new, |segment| segment.ident.to_string())
+    };
+
+    let self_ty = match *item_impl.self_ty {
+        syn::Type::Path(ref type_path) => last_segment(&type_path.path),
+        _ => String::from("_"),
+    };
+
+    match item_impl.trait_ {
+        Some((_, ref path, _)) => format!("impl {} for {self_ty}", last_segment(path)),
+        None => format!("impl {self_ty}"),
+    }
+}
+
+type ConfigToPathToProperties<'a> =
+    HashMap<&'a ConfigsList, BTreeMap<&'a SingleUsedItem<'a>, UsedItemPropertiesGroup<'a>>>;
+
+/// Group all of the flattened items by config (so that, for each unique `#[cfg]`
+/// among all the use items, all of the imports associated with that config are
+/// grouped together) and then normalize wildcards and
+fn group_flattened_items_normalize_wildcards<'a>(
+    flattened_items: &'a NormalizedUsedItems<'a>,
+    policy: &Policy,
+) -> ConfigToPathToProperties<'a> {
+    let mut grouped_flattened_items = ConfigToPathToProperties::new();
+
+    for (path, config_properties) in &flattened_items.items {
+        for (&config, properties) in config_properties {
+            let config_entries = grouped_flattened_items.entry(config).or_default();
+
+            // This works because `SingleUsedItem` is sorted such that any
+            // item comes *after* any other item that subsumes it.
+            let subsumed = path.possible_parents().any(|parent| {
+                config_entries
+                    .get_key_value(&parent)
+                    .is_some_and(|(&parent, parent_properties)| {
+                        parent_properties.docs == properties.docs
+                            && parent_properties.doc_attrs == properties.doc_attrs
+                            && parent_properties.visibility == properties.visibility
+                            && policy.allow_subsumption(
+                                (path, config, properties),
+                                (parent, config, parent_properties),
+                            )
+                    })
+            });
+
+            if !subsumed {
+                config_entries.insert(path, properties.clone());
+            }
+        }
+    }
+
+    grouped_flattened_items
+}
+
+/// A parsed `UseItem` (see `tree.rs`) or module declaration (see `mods.rs`),
+/// along with all of the line numbers from the original file are associated
+/// with this item.
+struct AnnotatedUseItem {
+    item: ManagedItem,
+    touched_original_lines: HashSet<LineNumber>,
+
+    /// The index of the section this item belongs to, in the version of the
+    /// file it came from (see `sections.rs`)
+    section: usize,
+
+    /// The path to the inline module containing this item, or empty if it's
+    /// at the top level of the file
+    module: Vec<String>,
+
+    /// The line in the original file where the inline module (or function)
+    /// containing this item starts. Unlike `module`, this tells apart
+    /// modules with the same name, like `#[cfg(unix)] mod imp` and
+    /// `#[cfg(windows)] mod imp`.
+    module_start: Option<LineNumber>,
+
+    /// The whitespace at the start of the first line of this item
+    indentation: String,
+
+    /// The text of this item in the original file (with its indentation
+    /// removed), in case it's preserved unchanged
+    original_text: String,
+
+    /// The line in the original file where the inner attributes and docs of
+    /// the module containing this item end, if it has any. Use items must
+    /// never be inserted above this line.
+    preamble_end: Option<LineNumber>,
+
+    /// The label of the conflict half (like `HEAD` or `main`) that this item
+    /// is in, if it's in a conflict at all
+    origin: Option<Arc<str>>,
+}
+
+/// The kinds of items that usefix merges
+enum ManagedItem {
+    Use(UseItem),
+    Mod(ModDecl),
+    CrateAttr(CrateAttr),
+
+    /// An `extern crate` item, as the use item that replaces it (see
+    /// `UseItem::from_syn_extern_crate`)
+    ExternCrate(UseItem),
+
+    /// A use item at the top of a function body
+    LocalUse(UseItem),
+}
+
+impl ManagedItem {
+    fn use_item(&self) -> Option<&UseItem> {
+        match self {
+            ManagedItem::Use(use_item)
+            | ManagedItem::ExternCrate(use_item)
+            | ManagedItem::LocalUse(use_item) => Some(use_item),
+            _ => None,
+        }
+    }
+
+    fn mod_decl(&self) -> Option<&ModDecl> {
+        match self {
+            ManagedItem::Mod(mod_decl) => Some(mod_decl),
+            _ => None,
+        }
+    }
+
+    fn crate_attr(&self) -> Option<&CrateAttr> {
+        match self {
+            ManagedItem::CrateAttr(crate_attr) => Some(crate_attr),
+            _ => None,
+        }
+    }
+
+    /// The (one-indexed) lines of this item
+    fn lines(&self) -> &RangeInclusive<usize> {
+        match self {
+            ManagedItem::Use(use_item)
+            | ManagedItem::ExternCrate(use_item)
+            | ManagedItem::LocalUse(use_item) => &use_item.lines,
+            ManagedItem::Mod(mod_decl) => &mod_decl.lines,
+            ManagedItem::CrateAttr(crate_attr) => &crate_attr.lines,
+        }
+    }
+}
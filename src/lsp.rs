@@ -0,0 +1,281 @@
+/*!
+`usefix lsp`: a minimal language server, spoken over stdin and stdout. It
+keeps track of open documents, and offers a single code action on any file
+containing git conflicts, which merges its use items (exactly as `usefix`
+would) and replaces the whole file with the result. Everything else in the
+protocol is either ignored or answered with an error.
+ */
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use anyhow::{bail, Context};
+use usefix::{ConflictedFile, MergeOptions};
+
+use crate::json::Json;
+
+const CODE_ACTION_TITLE: &str = "Resolve use-statement conflict";
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+struct Server<'a> {
+    options: &'a MergeOptions,
+
+    /// The content of each open document, by URI
+    documents: HashMap<String, String>,
+
+    /// True if we've received a `shutdown` request
+    shutdown: bool,
+}
+
+/// Run the language server until the client sends `exit`, or closes stdin.
+pub fn run(options: &MergeOptions) -> anyhow::Result<()> {
+    let mut server = Server {
+        options,
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+
+    while let Some(body) = read_message(&mut input).context("i/o error reading from stdin")? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(err) => {
+                let response = error_response(Json::Null, PARSE_ERROR, &err.to_string());
+                write_message(&mut output, &response).context("i/o error writing to stdout")?;
+                continue;
+            }
+        };
+
+        let method = message.get("method").and_then(Json::as_str);
+        let params = message.get("params").unwrap_or(&Json::Null);
+
+        match (method, message.get("id")) {
+            (Some("exit"), None) => match server.shutdown {
+                true => return Ok(()),
+                false => bail!("received 'exit' before 'shutdown'"),
+            },
+            (Some(method), None) => server.handle_notification(method, params),
+            (Some(method), Some(id)) => {
+                let response = match server.handle_request(method, params) {
+                    Ok(result) => Json::object([
+                        ("jsonrpc", "2.0".into()),
+                        ("id", id.clone()),
+                        ("result", result),
+                    ]),
+                    Err((code, message)) => error_response(id.clone(), code, message),
+                };
+
+                write_message(&mut output, &response).context("i/o error writing to stdout")?;
+            }
+            // A response to a request; we never send any, so ignore it
+            (None, Some(_)) => {}
+            (None, None) => {
+                let response = error_response(Json::Null, INVALID_REQUEST, "missing method");
+                write_message(&mut output, &response).context("i/o error writing to stdout")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Server<'_> {
+    fn handle_request(&mut self, method: &str, params: &Json) -> Result<Json, (i32, &'static str)> {
+        match method {
+            "initialize" => Ok(Json::object([
+                (
+                    "capabilities",
+                    Json::object([
+                        // Full document sync
+                        ("textDocumentSync", 1usize.into()),
+                        (
+                            "codeActionProvider",
+                            Json::object([("codeActionKinds", vec!["quickfix"].into())]),
+                        ),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object([
+                        ("name", "usefix".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/codeAction" => {
+                let uri = document_uri(params).ok_or((INVALID_PARAMS, "missing document URI"))?;
+                Ok(Json::Array(self.code_action(uri).into_iter().collect()))
+            }
+            _ => Err((METHOD_NOT_FOUND, "unsupported method")),
+        }
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Json) {
+        let Some(uri) = document_uri(params) else {
+            return;
+        };
+
+        match method {
+            "textDocument/didOpen" => {
+                if let Some(text) = params
+                    .get("textDocument")
+                    .and_then(|document| document.get("text"))
+                    .and_then(Json::as_str)
+                {
+                    self.documents.insert(uri.to_owned(), text.to_owned());
+                }
+            }
+            "textDocument/didChange" => {
+                // We only support full sync, so the last change is the whole
+                // document
+                if let Some(text) = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str)
+                {
+                    self.documents.insert(uri.to_owned(), text.to_owned());
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+            }
+            _ => {}
+        }
+    }
+
+    /// Get the code action for a document, if it has any git conflicts and
+    /// usefix can fix it.
+    fn code_action(&self, uri: &str) -> Option<Json> {
+        let text = self.documents.get(uri)?;
+
+        // Cheap check before we parse anything, since editors ask for code
+        // actions constantly
+        if !text.contains("<<<<<<<") {
+            return None;
+        }
+
+        let fixed = ConflictedFile::parse(text)
+            .and_then(|file| match file.contains_conflict() {
+                true => file
+                    .merge_imports(self.options)
+                    .map(|merged| Some(merged.render())),
+                false => Ok(None),
+            })
+            .unwrap_or_else(|err| {
+                // stderr is the server's log
+                eprintln!("usefix: {uri}: {}", err.full_message());
+                None
+            })?;
+
+        if fixed == *text {
+            return None;
+        }
+
+        let edit = Json::object([("range", document_range(text)), ("newText", fixed.into())]);
+
+        Some(Json::object([
+            ("title", CODE_ACTION_TITLE.into()),
+            ("kind", "quickfix".into()),
+            (
+                "edit",
+                Json::object([(
+                    "changes",
+                    Json::Object(vec![(uri.to_owned(), Json::Array(vec![edit]))]),
+                )]),
+            ),
+        ]))
+    }
+}
+
+fn document_uri(params: &Json) -> Option<&str> {
+    params
+        .get("textDocument")
+        .and_then(|document| document.get("uri"))
+        .and_then(Json::as_str)
+}
+
+/// The range covering an entire document. LSP positions are zero-indexed,
+/// and columns are counted in UTF-16 code units.
+fn document_range(text: &str) -> Json {
+    let position = |line: usize, character: usize| {
+        Json::object([("line", line.into()), ("character", character.into())])
+    };
+
+    let last_line = text.rsplit('\n').next().unwrap_or_default();
+    let end = position(text.matches('\n').count(), last_line.encode_utf16().count());
+
+    Json::object([("start", position(0, 0)), ("end", end)])
+}
+
+fn error_response(id: Json, code: i32, message: &str) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object([
+                ("code", Json::Number(code.into())),
+                ("message", message.into()),
+            ]),
+        ),
+    ])
+}
+
+/// Read a single message, returning `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut content_length = None;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let content_length = content_length.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length",
+        )
+    })?;
+
+    let mut body = vec![0; content_length];
+    input.read_exact(&mut body)?;
+
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
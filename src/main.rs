@@ -1,358 +1,932 @@
-/*
-High level data model:
-
-We need to track a lot of things about imports. Specifically:
-
-    - The path: `a::b::c::d`
-    - The imported item, which can be either a regular item (`e`), a renamed item (`e as f`),
-      or a wildcard (`*`)
-    - Any `#[cfg(...)]` attributes attached to the item, which we call configs. Any
-      item without a config is called "unconditional"
-    - The visibility of the item (`pub`, `pub(crate)`, etc)
-    - Any docs attached to the item
-
-At various points in this algorithm we'll be grouping these imports in various
-ways to aid with normalization. At a very high level, the goal of usefix's
-merge algorithm is to compute a union of the imports of both forms of a
-conflicted file and use it as the conflict resolution.
-
-High level algorithm:
-
-- Load the file with git conflicts
-- Split into two files, based on conflicts. Include a mapping to the line numbers
-  of the original files.
-- Parse the files with syn
-- Extract all top-level use items from both files. Track which line numnbers
-  they came from.
-- Convert the syn item into a local tree representation (`tree.rs`). The
-  representation include import paths (including wildcards and renames),
-  #[cfg] flags, visibility, and docs.
-- normalize configs: Flatten the tree into a list of paths, where each path
-  separately stores a mapping of config -> (visibility, docs). In any case
-  where a path appears in both unconditional and conditional forms, the
-  conditional forms are discarded, with their visibilities and docs merged into
-  the unconditional form. Otherwise, all distinct conditional forms are
-  retained; we don't make any effort to compute overlaps. If an import appears
-  more than once with the same config (for instance, because it appears on both
-  sides of a conflicted file), the visibilities and docs are merged.
-- Normalize wildcards: group all of the items by (config -> (path -> (vis, docs))).
-  Within each config, if a path exists in wildcard form, all of the paths that
-  are subsumed by that wildcard are discarded and merged into the wildcard
-  form. Additionally, any anonymous imports (e.g. `a::Trait as _`) are subsumed
-  by a matching wildcard (`a::*`) or named import of the same path (`a::Trait`).
-- We now have a canonical set of imports (`printable.rs`). Convert them into a
-  series of use item trees. Much like `rust-analyzer`, we prefer to use a
-  single use item for each top level imported identifier:
-
-```
-// We prefer this
-use a::{b, c::d, e};
-use f::g;
-
-// Over this
-use {
-    a::{b, c::d, e},
-    f::g,
-}
-
-// Or this
-use a::b;
-use a::c::d;
-use a::e;
-use f::g;
-```
-
-  Note that we'll have to split these into multiple use items to account for
-  visibility, docs, and `#[cfg]` conditionals. In general we attempt to group
-  stuff up that share any of these attributes.
-- Put the use items in order, and into newline-separated groups. This section
-  is nominal, as we expect the specific order and groupings to evolve for a
-  while. In general:
-  - Prefer `std`/`alloc`/`core`, followed by dependencies, followed by `crate`,
-    `super`, and `self` imports
-  - Prefer unconditional imports before conditional imports
-  - The complete set of rules for grouping and ordering is in the `PrintableKey`
-    type, in `printable.rs`
-- Render the use items. This is mostly handled by `Display` implementations in
-  `printable.rs`.
-- Prettify the rendered use items. Rather than try to compete with `rustfmt`,
-  we just use it directly. `rustfmt` can't be used as a library, so we offer
-  two options:
-  - Use `prettyplease`, a variant of `rustfmt` that is intended for use with
-    macros and other codegen tools. `prettyplease` doesn't respect grouping
-    of `use` items and the whitespace between them, so we have to call it
-    several times, once with each grouped set of use items.
-  - Call `rustfmt` as a subprocess. We expect in practice that this will be the
-    typical case, but it requires `rustfmt` to be installed, so we still ask
-    the user to ask for it.
-- Insert the prettified use items into the original file, and remove the
-  existing use items (`writefile.rs`). This is a fraught thing to try to do,
-  because the original file might include git conflicts. The basic rule is to
-  insert the use items at the point where the very first use item appears in
-  the original file.
-  - If this point is a non-conflicted line, it's easy; we just put it there.
-  - If this point is a conflict, we split the conflict into two separate
-    conflicts, and insert the use items in between them.
-  - If there are no such points, it means that all the use items only appear
-    in half of the conflicts (that is, for each conflict, it appears ONLY on
-    the left or right side of the conflict). This is an awfully edge-casey
-    edge case, and we insert the use items twice: once at the first use item
-    in the left version of the file, and once at the first use item in the
-    right version of the file. Note again that we only do this if there's no
-    possible non-conflicted sites to insert these use items.
-  - We assume that, in the original rust file, no lines that include a use item
-    (or part of a use item) will include anything OTHER than that use item.
-    No sane rust developer would do otherwise, even if they don't use rustfmt
-    for some reason.
-  - When writing conflicts, we check that the conflict is still a conflict: if
-    its remaining lines (after excluding the use items we processed) are
-    identical, we write them as a plain, non-conflicted lines. This will be
-    common in the case where a conflict appears in the middle of a larger set
-    of imports.
-  - One odd side effect of our algorithm is that spaces between groups of use
-    items in the original file are kept, "clump" together at the end of all the
-    use items. To handle this, we consume all but one empty when we insert
-    the formatted use items.
-
-
-Sub-algorithms:
-    Docs merge:
-        If either set of docs are a prefix or suffix of the other, use the
-        longer one. Otherwise, concatenate them. In a future version we could
-        detect if either docs are a complete subset of the other, but for now
-        this is fine.
-    Visibility Merge
-        Always prefer the "more public" visibility
- */
-
-mod common;
-mod docprint;
-mod flattened;
-mod gitfile;
-mod pretty;
-mod printable;
-mod tree;
-mod write_file;
+mod bug_report;
+mod compile;
+mod corpus;
+mod diagnostics;
+mod git;
+mod interchange;
+mod json;
+mod lsp;
+mod outcome;
+mod prune;
+mod resolutions;
+mod semantic;
+mod stats;
 
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    borrow::Cow,
+    fmt::Write as _,
+    fs,
     io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
 };
 
 use anyhow::Context;
 use clap::Parser;
-use pretty::prettify_with_prettyplease;
+use itertools::{EitherOrBoth, Itertools};
+use usefix::{
+    ConflictedFile, Import, ImportChange, ImportLeaf, MergeOptions, Policy, RemainingConflict,
+    WildcardPolicy,
+};
 
 use crate::{
-    flattened::{NormalizedUsedItems, SingleUsedItem, UsedItemPropertiesGroup},
-    gitfile::{GitFile, LineNumber, Side},
-    pretty::prettify_with_subcommand,
-    printable::PrintableUseItems,
-    tree::{ConfigsList, UseItem},
+    diagnostics::{Diagnostic, Level, MessageFormat},
+    json::Json,
+    outcome::OutcomeFormat,
+    semantic::{ExportIndex, SemanticPolicy},
 };
 
+/// Merge the use items in a rust source file containing git conflicts. The
+/// file is read from stdin, and the fixed file is written to stdout (or to
+/// `--output`).
 #[derive(clap::Parser)]
 struct Args {
-    /// By default, we use prettyplease to format the use items. This argument
-    /// specifies an external command (typically `rustfmt`) that will be used
-    /// instead (for instance, if you want `usefix` to respect your rustfmt
-    /// configuration).
-    ///
-    /// The given argument will be treated as a whole command; use a shell
-    /// script or something similar if you want to pass extra arguments to it.
-    /// The use items will be passed to the given command over stdin, and the
-    /// formatted use items will be read from stdout.
-    #[clap(long, short = 'c')]
-    rustfmt: Option<PathBuf>,
+    #[clap(flatten)]
+    options: MergeOptions,
+
+    /// Write the fixed file to this path, instead of to stdout (or, with
+    /// `--from-index`, instead of to the worktree file). `-` means stdout.
+    #[clap(long, short = 'o', value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// After merging, drop any imports that `cargo check` reports as unused
+    /// (in both normal and `cfg(test)` builds). Requires `--path`; the
+    /// merged file is temporarily written there while cargo runs. If the
+    /// merged file doesn't compile, nothing is pruned, with a warning.
+    #[clap(long)]
+    prune_unused: bool,
+
+    /// The rustdoc JSON for a crate (the first one is this crate), used to
+    /// check whether glob imports export the names they appear to subsume,
+    /// and to warn about glob imports that collide after merging. Can be
+    /// given more than once.
+    #[clap(long, value_name = "FILE")]
+    rustdoc_json: Vec<PathBuf>,
+
+    /// Record how the conflicts in each file were merged in this directory,
+    /// and replay the manual adjustments recorded with `usefix remember`
+    /// when the same conflicts come up again (like `git rerere`). Requires
+    /// `--path`, which identifies the file.
+    #[clap(long, value_name = "DIR")]
+    resolution_cache: Option<PathBuf>,
+
+    /// Merge an unmerged path from its stages in the git index (common
+    /// ancestor, ours, and theirs) instead of reading a conflicted file
+    /// from stdin, and write the result to the worktree file. Imports
+    /// deleted on one side are left out of the merge. If the file itself was
+    /// deleted on one side, nothing is written, and usefix exits with status
+    /// 3.
+    #[clap(long, value_name = "PATH", conflicts_with = "resolution_cache")]
+    from_index: Option<PathBuf>,
+
+    /// Find the imports that were deleted on one side of the merge (or
+    /// rebase, or cherry-pick) in progress, by reading the common ancestor
+    /// and both versions of the file at `--path` from git, and leave them
+    /// out of the merge instead of bringing them back. Requires `--path`.
+    #[clap(long, requires = "path")]
+    merge_base: bool,
+
+    /// Check the fixed file before writing it: fixing it again must not
+    /// change anything, and if it doesn't contain any conflicts, it must be
+    /// valid rust. If either check fails, nothing is written, and usefix
+    /// exits with an error.
+    #[clap(long)]
+    verify: bool,
+
+    /// After merging, check that the merged file compiles with `cargo
+    /// check` (in the package containing it). If it doesn't, or if cargo
+    /// fails without reporting any errors in the file (for instance,
+    /// because of errors in other files), report the errors, and keep the
+    /// original file: the input is written to the output unchanged (or
+    /// nothing is written, with `--from-index`), and usefix exits with an
+    /// error. Requires `--path`, unless
+    /// `--from-index` is given; the merged file is temporarily written there
+    /// while cargo runs.
+    #[clap(long)]
+    ensure_compiles: bool,
+
+    /// Write every intermediate stage of fixing the file to this directory,
+    /// to attach to a bug report: the input and output, each derived version
+    /// of the file with its line mappings, the extracted use items, and the
+    /// text of each section before and after formatting.
+    #[clap(long, value_name = "DIR")]
+    debug_stages: Option<PathBuf>,
+
+    /// Write a bug report to this file, to attach to an issue: the input,
+    /// minimized as far as possible while usefix still fails on it (with an
+    /// error, or output that fails the `--verify` checks), along with the
+    /// output and every intermediate stage of fixing it. The input is then
+    /// fixed as usual.
+    #[clap(long, value_name = "FILE", conflicts_with = "from_index")]
+    report_bug: Option<PathBuf>,
+
+    /// Replace the identifiers in the input of a `--report-bug` report with
+    /// meaningless ones, so that it doesn't reveal any code
+    #[clap(long, requires = "report_bug")]
+    anonymize: bool,
+
+    /// After fixing the file, print a note for each conflict that's left in
+    /// it (because it isn't just in use items), with its lines in both the
+    /// original file and the fixed file, so that it can be found and
+    /// resolved by hand.
+    #[clap(long)]
+    list_conflicts: bool,
+
+    /// After fixing the file, report whether all of its conflicts were
+    /// resolved, only the conflicts in use items were, or none could be, to
+    /// stderr: `human` prints a sentence, and `json` prints a JSON object.
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    report_outcome: Option<OutcomeFormat>,
+
+    /// Remove the comments added by `--annotate-origin` from the input before
+    /// fixing it.
+    #[clap(long)]
+    strip_origin: bool,
+
+    /// How to print warnings and errors. `short` prints one
+    /// `file:line:column: level: message` line for each of them (including
+    /// each finding of `check-style`), in a format that won't change.
+    #[clap(long, value_enum, default_value_t)]
+    message_format: MessageFormat,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Run a minimal language server over stdin and stdout, which offers a
+    /// code action to resolve the use item conflicts in a file. Options
+    /// must be given before `lsp`, and apply to every file.
+    Lsp,
+
+    /// Print every intermediate stage of merging the use items in a file:
+    /// the parsed use items from each side, the flattened list of paths,
+    /// the paths grouped by configs after wildcards are normalized, and the
+    /// final use items along with their sort keys. Options must be given
+    /// before `explain`. Meant for debugging surprising merges; the output
+    /// format isn't stable.
+    Explain {
+        /// The file to explain
+        file: PathBuf,
+    },
+
+    /// Explain why a path (like `serde::de::DeserializeOwned`) is imported
+    /// by the merged version of a file: which use items on each side
+    /// imported it, how their configs, visibilities, and docs were merged,
+    /// and whether a glob import subsumed it. Options must be given before
+    /// `why`.
+    Why {
+        /// The imported path
+        path: String,
+
+        /// The file to explain
+        file: PathBuf,
+    },
+
+    /// Print the merged imports of a file (which may contain conflicts) as
+    /// JSON, with the path, leaf, cfgs, visibility, docs, doc attributes,
+    /// and origin of each imported name. Options must be given before
+    /// `extract`.
+    Extract {
+        /// The file to extract imports from
+        file: PathBuf,
+    },
+
+    /// Replace the use items in a file with the imports in a JSON import
+    /// model (in the format written by `extract`), and print the rewritten
+    /// file. The imports for each module replace the first group of use
+    /// items in that module. Options must be given before `apply`.
+    Apply {
+        /// The file to rewrite
+        file: PathBuf,
+
+        /// The JSON import model. If omitted, it's read from stdin.
+        #[clap(long, value_name = "FILE")]
+        model: Option<PathBuf>,
+    },
+
+    /// Compare the imports of two files (or two versions of one file), and
+    /// print the imports that were added, removed, or changed (in their
+    /// cfgs, visibility, or renames). Files that contain conflicts are
+    /// merged first. Options must be given before `diff-imports`.
+    DiffImports {
+        /// The old version of the file
+        old: PathBuf,
+
+        /// The new version of the file
+        new: PathBuf,
+    },
+
+    /// Report statistics about the imports of every rust file in a
+    /// directory: the most imported crates, imports duplicated across
+    /// modules, and the number of glob and cfg-gated imports. Options must
+    /// be given before `stats`.
+    Stats {
+        /// The directory to search for rust files
+        #[clap(default_value = ".")]
+        dir: PathBuf,
+
+        /// How many entries to show in each list
+        #[clap(long, default_value_t = 10)]
+        top: usize,
+
+        /// Include files and directories that git ignores (with
+        /// `.gitignore` or `.git/info/exclude`)
+        #[clap(long)]
+        no_ignore: bool,
+    },
+
+    /// Record the use items in a file, after it was merged with
+    /// `--resolution-cache` and adjusted by hand, as the resolution of the
+    /// conflicts that were recorded for it, so that the same adjustments
+    /// are made the next time those conflicts are merged. Options
+    /// (including `--resolution-cache`) must be given before `remember`.
+    Remember {
+        /// The resolved file, which must not contain conflicts
+        file: PathBuf,
+    },
+
+    /// Check that the use items in some files (which must not contain any
+    /// conflicts) are already written the way usefix would write them,
+    /// without rewriting anything. Each section of use items that isn't is
+    /// printed, along with how it should be written, and the exit status is
+    /// 1 if there were any. Options must be given before `check-style`.
+    CheckStyle {
+        /// The files to check
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Check usefix against a corpus: fix every `NAME.input.rs` file in a
+    /// directory, and compare it with `NAME.expected.rs`. With
+    /// `--revisions`, the corpus is every rust file in the directory that
+    /// conflicts when two revisions of its git repository are merged, and
+    /// each one is checked as if with `--verify` instead. Every mismatch is
+    /// printed, and the exit status is 1 if there were any. Options must be
+    /// given before `test-corpus`.
+    TestCorpus {
+        /// The directory containing the corpus
+        dir: PathBuf,
+
+        /// Generate the corpus by merging these two revisions
+        #[clap(long, num_args = 2, value_names = ["OURS", "THEIRS"])]
+        revisions: Option<Vec<String>>,
+
+        /// Include files and directories that git ignores (with
+        /// `.gitignore` or `.git/info/exclude`)
+        #[clap(long, conflicts_with = "revisions")]
+        no_ignore: bool,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
+    let format = args.message_format;
+    let file = input_path(&args).map(Path::to_path_buf);
+
+    match (run(args), format) {
+        (Err(err), MessageFormat::Short) => {
+            let diagnostic = Diagnostic::new(Level::Error, file.as_deref(), format!("{err:#}"));
+
+            match err
+                .downcast_ref::<usefix::Error>()
+                .and_then(usefix::Error::location)
+            {
+                Some((line, column)) => diagnostic.at(line, column),
+                None => diagnostic,
+            }
+            .emit(format);
 
-    let file =
-        io::read_to_string(io::stdin().lock()).context("i/o error reading file from stdin")?;
-    let parsed_file = GitFile::from_file(&file).context("error parsing git conflicts in file")?;
-
-    // TODO: do these in separate threads. `proc-macro2`` stuff isn't Send,
-    // unfortunately. Only way to resolve this for now is to NOT use `syn`
-    // types in `tree.rs``
-    let left_use_items = extract_use_items(&parsed_file, Side::Left).context(
-        if parsed_file.contains_conflict() {
-            "failed to get `use` items from the left side of the conflicted file"
-        } else {
-            "failed to get `use` items"
-        },
-    )?;
-
-    let right_use_items = extract_use_items(&parsed_file, Side::Right)
-        .context("failed to get use items from the right side of the conflicted file")?;
-
-    // Flatten the list into a list of paths, where each path stores all known
-    // properties variants. This step normalizes the configs (any time a path
-    // appears in unconditional form, it subsumes all instances of that path
-    // in conditional form)
-    let mut flattened_items = NormalizedUsedItems::default();
-    Iterator::chain(left_use_items.iter(), right_use_items.iter())
-        .for_each(|item| flattened_items.add_tree(&item.use_item));
-
-    // Group the list by config and normalize wildcard. Any time a path appears
-    // with a wildcard import, it subsumes all instances of that same path
-    // importing a non-renamed item, provided they share a config
-    let grouped_flattened_items = group_flattened_items_normalize_wildcards(&flattened_items);
-
-    // We now have the final set of imports we wish to use. Convert them into
-    // a form suitable for printing.
-    let printable_items =
-        PrintableUseItems::build_from_use_items(grouped_flattened_items.iter().flat_map(
-            |(&configs, items)| {
-                items.iter().map(move |(&path, properties)| {
-                    (&properties.docs, configs, properties.visibility, path)
-                })
-            },
-        ));
-
-    // Render the use items to a string, complete with sorting and grouping
-    let formatted_use_items = printable_items.to_string();
-
-    // Then prettify them, adding indentation and newlines and so on
-    let prettified_use_items = match args.rustfmt {
-        None => prettify_with_prettyplease(&formatted_use_items),
-        Some(command) => {
-            let printable_command = command.display();
-
-            prettify_with_subcommand(&command, &formatted_use_items).with_context(|| {
-                format!("error formatting with external subcommand '{printable_command}'")
-            })?
+            process::exit(match err.is::<git::DeleteModifyConflict>() {
+                true => git::DeleteModifyConflict::STATUS,
+                false => 1,
+            })
         }
+        (Err(err), _) if err.is::<git::DeleteModifyConflict>() => {
+            eprintln!("Error: {err:?}");
+            process::exit(git::DeleteModifyConflict::STATUS)
+        }
+        (result, _) => result,
+    }
+}
+
+/// The file that a command operates on, for diagnostics that aren't about
+/// any particular finding
+fn input_path(args: &Args) -> Option<&Path> {
+    match args.command {
+        Some(Command::Explain { ref file })
+        | Some(Command::Why { ref file, .. })
+        | Some(Command::Extract { ref file })
+        | Some(Command::Apply { ref file, .. })
+        | Some(Command::Remember { ref file }) => Some(file),
+        None if args.from_index.is_some() => args.from_index.as_deref(),
+        _ => args.options.path.as_deref(),
+    }
+}
+
+fn run(mut args: Args) -> anyhow::Result<()> {
+    let format = args.message_format;
+
+    let index = match args.rustdoc_json.is_empty() {
+        true => None,
+        false => Some(Arc::new(ExportIndex::load(&args.rustdoc_json)?)),
     };
 
-    // Compute the set of lines from the ORIGINAL file that need to be
-    // discarded; these are the lines in the original file that include any
-    // part of a use item. There's an important assumption here that no line
-    // that includes any part of a use item includes anything OTHER than that
-    // use item.
-    let discarded_lines = Iterator::chain(left_use_items.iter(), right_use_items.iter())
-        .flat_map(|item| &item.touched_original_lines)
-        .copied()
-        .collect();
-
-    // Create the final, fixed version of the file. We assume that files fit
-    // neatly in memory, so to save on system calls, we just put it all in a
-    // single buffer and write it at the end.
-    let mut output_file: Vec<u8> = Vec::with_capacity(file.len());
-    write_file::write_corrected_file(
-        &mut output_file,
-        &parsed_file,
-        &discarded_lines,
-        &prettified_use_items,
-    )
-    .expect("writing to a vector is infallible");
+    if let Some(ref index) = index {
+        args.options.policy = Policy::new(SemanticPolicy {
+            index: index.clone(),
+        });
+    }
+
+    match args.command {
+        None => match args.from_index {
+            Some(ref path) => {
+                let merge = git::fix_from_index(path, &args.options)?;
+
+                if args.verify {
+                    verify_fixed(&merge.fixed, &args.options)?;
+                }
+
+                if args.ensure_compiles {
+                    compile::ensure_compiles(path, &merge.fixed, format)?;
+                }
+
+                write_output(Some(args.output.as_deref().unwrap_or(path)), &merge.fixed)?;
 
+                if args.list_conflicts {
+                    report_remaining_conflicts(Some(path), &merge.remaining_conflicts, format);
+                }
+
+                if let Some(report) = args.report_outcome {
+                    outcome::report_outcome(Some(path), merge.outcome, report);
+                }
+
+                Ok(())
+            }
+            None => fix_stdin(&args, index.as_deref()),
+        },
+        Some(Command::Lsp) => lsp::run(&args.options),
+        Some(Command::Explain { ref file }) => explain(file, &args.options),
+        Some(Command::Why { ref path, ref file }) => why(path, file, &args.options),
+        Some(Command::Extract { ref file }) => extract(file, &args.options),
+        Some(Command::Apply {
+            ref file,
+            ref model,
+        }) => apply(file, model.as_deref(), &args.options),
+        Some(Command::DiffImports { ref old, ref new }) => diff_imports(old, new, &args.options),
+        Some(Command::Stats {
+            ref dir,
+            top,
+            no_ignore,
+        }) => write_stdout(&stats::repository_stats(
+            dir,
+            top,
+            no_ignore,
+            &args.options,
+            format,
+        )?),
+        Some(Command::CheckStyle { ref files }) => check_style(files, &args.options, format),
+        Some(Command::TestCorpus {
+            ref dir,
+            ref revisions,
+            no_ignore,
+        }) => test_corpus(dir, revisions.as_deref(), no_ignore, &args.options),
+        Some(Command::Remember { ref file }) => match args.resolution_cache {
+            Some(ref cache) => resolutions::remember(cache, file, &args.options, format),
+            None => anyhow::bail!("remember requires --resolution-cache"),
+        },
+    }
+}
+
+fn read_file(path: &Path) -> anyhow::Result<String> {
+    fs::read_to_string(path).with_context(|| format!("i/o error reading {}", path.display()))
+}
+
+fn parse_file<'a>(content: &'a str, options: &MergeOptions) -> anyhow::Result<ConflictedFile<'a>> {
+    Ok(match options.fragment {
+        true => ConflictedFile::parse_fragment(content)?,
+        false => ConflictedFile::parse(content)?,
+    })
+}
+
+fn write_stdout(content: &str) -> anyhow::Result<()> {
     io::stdout()
         .lock()
-        .write_all(&output_file)
-        .context("i/o error writing to stdout")?;
+        .write_all(content.as_bytes())
+        .context("i/o error writing to stdout")
+}
 
-    Ok(())
+/// Open the destination for the fixed file (see `--output`): a file, or
+/// stdout if the path is missing or `-`
+fn output_writer(output: Option<&Path>) -> anyhow::Result<Box<dyn Write>> {
+    match output {
+        Some(path) if path != Path::new("-") => {
+            Ok(Box::new(fs::File::create(path).with_context(|| {
+                format!("i/o error creating {}", path.display())
+            })?))
+        }
+        _ => Ok(Box::new(io::stdout().lock())),
+    }
 }
 
-/// Parse a GitFile with syn, and extract its use itmes (and their spans) into
-/// a list of Annotated Use Items.
-fn extract_use_items(file: &GitFile<'_>, side: Side) -> anyhow::Result<Vec<AnnotatedUseItem>> {
-    let derived_file = file.build_derived_file(side);
-    let derived_file_lines: Vec<&str> = derived_file.content().lines().collect();
-
-    let parsed_file = syn::parse_file(&derived_file.content()).map_err(|err| {
-        let span = err.span();
-        let point = span.start();
-        let line = point.line;
-        let column = point.column;
-
-        let context = format!("Error parsing rust syntax at line {line}, column {column}");
-        anyhow::Error::new(err).context(context)
-    })?;
-
-    let use_items = parsed_file
-        .items
-        .into_iter()
-        .filter_map(|item| match item {
-            syn::Item::Use(use_item) => Some(use_item),
-            _ => None,
-        })
-        .filter_map(|use_item| UseItem::from_syn_use_item(use_item).ok())
-        .map(|use_item| {
-            let start = use_item.span.start().line;
-            let end = use_item.span.end().line;
-
-            // Whenever a `use` item is followed by a newline, we include that
-            // newline in set of lines that are "touched" by it
-            //
-            // Note on indexing: syn line numbers are one-indexed and inclusive,
-            // but we want the line AFTER that end line, so it's end - 1 + 1
-            let end = match derived_file_lines.get(end) {
-                Some(line) if line.trim().is_empty() => end + 1,
-                _ => end,
+/// Write the fixed file to its destination (see `output_writer`)
+fn write_output(output: Option<&Path>, content: &str) -> anyhow::Result<()> {
+    match output {
+        Some(path) if path != Path::new("-") => fs::write(path, content)
+            .with_context(|| format!("i/o error writing {}", path.display())),
+        _ => write_stdout(content),
+    }
+}
+
+fn explain(path: &Path, options: &MergeOptions) -> anyhow::Result<()> {
+    let content = read_file(path)?;
+    let file = parse_file(&content, options)?;
+
+    write_stdout(&file.explain(options)?)
+}
+
+fn why(query: &str, path: &Path, options: &MergeOptions) -> anyhow::Result<()> {
+    let content = read_file(path)?;
+    let file = parse_file(&content, options)?;
+
+    write_stdout(&file.explain_path(query, options)?)
+}
+
+fn extract(path: &Path, options: &MergeOptions) -> anyhow::Result<()> {
+    options.validate()?;
+
+    let content = read_file(path)?;
+    let file = parse_file(&content, options)?;
+    let model = file.import_model(options);
+
+    write_stdout(&format!("{}\n", interchange::import_model_to_json(&model)))
+}
+
+fn apply(path: &Path, model: Option<&Path>, options: &MergeOptions) -> anyhow::Result<()> {
+    let model = match model {
+        Some(model) => read_file(model)?,
+        None => io::read_to_string(io::stdin().lock())
+            .context("i/o error reading import model from stdin")?,
+    };
+
+    let model = Json::parse(&model)
+        .map_err(|err| anyhow::anyhow!("{err}"))
+        .context("the import model isn't valid JSON")?;
+    let model = interchange::import_model_from_json(&model)?;
+
+    let content = read_file(path)?;
+    let file = ConflictedFile::parse(&content)?;
+
+    write_stdout(&file.apply_model(&model, options)?.render())
+}
+
+fn diff_imports(old: &Path, new: &Path, options: &MergeOptions) -> anyhow::Result<()> {
+    options.validate()?;
+
+    let old_content = read_file(old)?;
+    let new_content = read_file(new)?;
+
+    let old_model = parse_file(&old_content, options)?.import_model(options);
+    let new_model = parse_file(&new_content, options)?.import_model(options);
+
+    // Each import is written on a single line, followed by its module
+    let describe = |import: &Import| {
+        let line = import.to_string().lines().join(" ");
+
+        match import.provenance.module.as_slice() {
+            [] => line,
+            module => format!("{line} (in module {})", module.join("::")),
+        }
+    };
+
+    let mut output = String::new();
+
+    for change in old_model.diff(&new_model, options.self_prefix) {
+        let lines = match change {
+            ImportChange::Added(import) => vec![("added:  ", import)],
+            ImportChange::Removed(import) => vec![("removed:", import)],
+            ImportChange::Changed { old, new } => Iterator::chain(
+                old.into_iter().map(|import| ("changed:", import)),
+                new.into_iter().map(|import| ("     to:", import)),
+            )
+            .collect(),
+        };
+
+        for (label, import) in lines {
+            writeln!(output, "{label} {}", describe(&import))
+                .expect("writing to a string is infallible");
+        }
+    }
+
+    write_stdout(&output)
+}
+
+fn check_style(
+    paths: &[PathBuf],
+    options: &MergeOptions,
+    format: MessageFormat,
+) -> anyhow::Result<()> {
+    let mut output = String::new();
+    let mut violations = 0;
+
+    for path in paths {
+        let content = read_file(path)?;
+        let file = ConflictedFile::parse(&content)?;
+
+        if file.contains_conflict() {
+            anyhow::bail!("{} contains conflicts", path.display());
+        }
+
+        for violation in file.check_style(options)? {
+            violations += 1;
+
+            if format == MessageFormat::Short {
+                let diagnostic = Diagnostic::new(
+                    Level::Warning,
+                    Some(path),
+                    "use items don't match the configured style",
+                )
+                .at(*violation.lines.start(), 1);
+
+                writeln!(output, "{}", diagnostic.render(format))
+                    .expect("writing to a string is infallible");
+                continue;
             }
-            // Add an extra +1 so we can use `..end` instead of `..=end`
-            + 1;
-
-            let touched_original_lines = (start..end)
-                .map(|derived_line| {
-                    LineNumber::from_one_indexed(derived_line).expect("line number was 0")
-                })
-                .map(|derived_line| {
-                    derived_file
-                        .get_original_line(derived_line)
-                        .expect("derived line didn't exist")
-                })
-                .collect();
-
-            AnnotatedUseItem {
-                use_item,
-                touched_original_lines,
+
+            writeln!(
+                output,
+                "{}:{}-{}: use items don't match the configured style; expected:",
+                path.display(),
+                violation.lines.start(),
+                violation.lines.end(),
+            )
+            .expect("writing to a string is infallible");
+
+            match violation.expected.trim_end() {
+                "" => output.push_str("    (a blank line)\n"),
+                expected => expected.lines().for_each(|line| match line {
+                    "" => output.push('\n'),
+                    line => {
+                        writeln!(output, "    {line}").expect("writing to a string is infallible")
+                    }
+                }),
             }
-        })
-        .collect();
+        }
+    }
+
+    write_stdout(&output)?;
 
-    Ok(use_items)
+    if violations > 0 {
+        process::exit(1);
+    }
+
+    Ok(())
 }
 
-type ConfigToPathToProperties<'a> =
-    HashMap<&'a ConfigsList, BTreeMap<&'a SingleUsedItem<'a>, UsedItemPropertiesGroup<'a>>>;
-
-/// Group all of the flattened items by config (so that, for each unique `#[cfg]`
-/// among all the use items, all of the imports associated with that config are
-/// grouped together) and then normalize wildcards and
-fn group_flattened_items_normalize_wildcards<'a>(
-    flattened_items: &'a NormalizedUsedItems<'a>,
-) -> ConfigToPathToProperties<'a> {
-    let mut grouped_flattened_items = ConfigToPathToProperties::new();
-
-    for (path, config_properties) in &flattened_items.items {
-        for (&config, properties) in config_properties {
-            let config_entries = grouped_flattened_items.entry(config).or_default();
-
-            // This works because `SingleUsedItem` is sorted such that any
-            // item comes *after* any other item that subsumes it.
-            match config_entries.last_entry() {
-                Some(entry)
-                    if path.is_subsumed_by(entry.key())
-                        && entry.get().docs == properties.docs
-                        && entry.get().visibility == properties.visibility => {}
-                _ => {
-                    config_entries.insert(path, properties.clone());
-                }
-            }
+fn test_corpus(
+    dir: &Path,
+    revisions: Option<&[String]>,
+    no_ignore: bool,
+    options: &MergeOptions,
+) -> anyhow::Result<()> {
+    options.validate()?;
+
+    let report = match revisions {
+        Some([ours, theirs]) => corpus::test_revisions(dir, ours, theirs, options)?,
+        Some(_) => anyhow::bail!("--revisions takes exactly two revisions"),
+        None => corpus::test_pairs(dir, no_ignore, options)?,
+    };
+
+    write_stdout(&report.render())?;
+
+    if report.failed() {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn fix_stdin(args: &Args, index: Option<&ExportIndex>) -> anyhow::Result<()> {
+    let options = &match (args.merge_base, args.options.path.as_deref()) {
+        (true, Some(path)) => MergeOptions {
+            discard: Iterator::chain(
+                args.options.discard.iter().cloned(),
+                git::merge_base_deleted_imports(path, &args.options)?,
+            )
+            .collect(),
+            ..args.options.clone()
+        },
+        _ => args.options.clone(),
+    };
+    let file =
+        io::read_to_string(io::stdin().lock()).context("i/o error reading file from stdin")?;
+    let file = match args.strip_origin {
+        true => usefix::strip_origin_annotations(&file).into_owned(),
+        false => file,
+    };
+
+    if let Some(ref report) = args.report_bug {
+        bug_report::write_bug_report(report, &file, options, args.anonymize)?;
+    }
+
+    // Unless the fixed file has to be inspected or changed before it's
+    // written, it's streamed straight to the output, rather than being
+    // rendered into a buffer first, so that a large file isn't in memory
+    // twice.
+    let in_memory = args.resolution_cache.is_some()
+        || args.prune_unused
+        || args.debug_stages.is_some()
+        || args.verify
+        || args.ensure_compiles
+        || args.report_outcome.is_some()
+        || args.list_conflicts;
+
+    let parsed = parse_file(&file, options)?;
+    report_tangled_conflicts(&parsed, options, args.message_format);
+    report_visibility_widenings(&parsed, options, args.message_format);
+    report_wildcards(&parsed, options, args.message_format);
+
+    if !in_memory {
+        let merged = parsed.merge_imports(options)?;
+        diagnostics::emit_merge_warnings(&merged, options.path.as_deref(), args.message_format);
+
+        if let Some(index) = index {
+            semantic::glob_collisions(&parsed, options, index)
+                .iter()
+                .for_each(|warning| warning.emit(args.message_format));
+        }
+
+        let mut dest = io::BufWriter::new(output_writer(args.output.as_deref())?);
+
+        return merged
+            .write_to(&mut dest)
+            .and_then(|()| dest.flush())
+            .context("i/o error writing the fixed file");
+    }
+
+    let fixed = match args.resolution_cache {
+        Some(ref cache) => resolutions::fix_file(cache, &file, options, args.message_format)?,
+        None => {
+            let merged = parsed.merge_imports(options)?;
+            diagnostics::emit_merge_warnings(&merged, options.path.as_deref(), args.message_format);
+            merged.render()
+        }
+    };
+    let fixed = match args.prune_unused {
+        true => prune::prune_unused_imports(&fixed, options, args.message_format)?,
+        false => fixed,
+    };
+
+    if let Some(ref dir) = args.debug_stages {
+        write_debug_stages(dir, &file, &fixed, options)?;
+    }
+
+    if args.verify {
+        verify_fixed(&fixed, options)?;
+    }
+
+    if args.ensure_compiles {
+        let path = options
+            .path
+            .as_deref()
+            .context("--ensure-compiles requires --path")?;
+
+        if let Err(err) = compile::ensure_compiles(path, &fixed, args.message_format) {
+            write_output(args.output.as_deref(), &file)?;
+            return Err(err);
+        }
+    }
+
+    if let Some(index) = index {
+        semantic::glob_collisions(&parsed, options, index)
+            .iter()
+            .for_each(|warning| warning.emit(args.message_format));
+    }
+
+    write_output(args.output.as_deref(), &fixed)?;
+
+    if args.list_conflicts {
+        let conflicts = parsed.remaining_conflicts(&fixed);
+        report_remaining_conflicts(options.path.as_deref(), &conflicts, args.message_format);
+    }
+
+    if let Some(report) = args.report_outcome {
+        let outcome = parsed.outcome(&fixed, options);
+        outcome::report_outcome(options.path.as_deref(), outcome, report);
+    }
+
+    Ok(())
+}
+
+/// Warn about each conflict that usefix leaves alone, because its use items
+/// are interleaved with other code (see `ConflictedFile::tangled_conflicts`)
+fn report_tangled_conflicts(
+    file: &ConflictedFile<'_>,
+    options: &MergeOptions,
+    format: MessageFormat,
+) {
+    for lines in file.tangled_conflicts() {
+        Diagnostic::new(
+            Level::Warning,
+            options.path.as_deref(),
+            format!(
+                "the conflict at lines {}-{} mixes use items with other code, so its use items \
+                 were left alone",
+                lines.start(),
+                lines.end()
+            ),
+        )
+        .at(*lines.start(), 1)
+        .emit(format);
+    }
+}
+
+/// Print a note with the location of each conflict left in a fixed file (see
+/// `--list-conflicts`)
+fn report_remaining_conflicts(
+    path: Option<&Path>,
+    conflicts: &[RemainingConflict],
+    format: MessageFormat,
+) {
+    for conflict in conflicts {
+        Diagnostic::new(
+            Level::Note,
+            path,
+            format!(
+                "the conflict at lines {}-{} of the original file is unresolved; it's at lines \
+                 {}-{} of the fixed file",
+                conflict.original.start(),
+                conflict.original.end(),
+                conflict.fixed.start(),
+                conflict.fixed.end(),
+            ),
+        )
+        .at(*conflict.fixed.start(), 1)
+        .emit(format);
+    }
+}
+
+/// Warn about each import that the merge made more public than it was on one
+/// side (see `ConflictedFile::visibility_widenings`), since that might export
+/// something by accident
+fn report_visibility_widenings(
+    file: &ConflictedFile<'_>,
+    options: &MergeOptions,
+    format: MessageFormat,
+) {
+    let describe = |vis: &Option<String>| match vis {
+        Some(vis) => format!("`{vis}`"),
+        None => "private".to_owned(),
+    };
+
+    for widening in file.visibility_widenings(options) {
+        let import = &widening.import;
+        let leaf = match import.leaf {
+            ImportLeaf::Name(ref name) => name.clone(),
+            ImportLeaf::Renamed {
+                ref name,
+                ref alias,
+            } => format!("{name} as {alias}"),
+            ImportLeaf::Wildcard => "*".to_owned(),
+        };
+        let path = Iterator::chain(import.path.iter().cloned(), [leaf]).join("::");
+        let location = match import.provenance.module.as_slice() {
+            [] => String::new(),
+            module => format!(" (in module {})", module.join("::")),
+        };
+
+        Diagnostic::new(
+            Level::Warning,
+            options.path.as_deref(),
+            format!(
+                "`{}{path}`{location} was {} on the left side and {} on the right side, so the \
+                 merged import is {}",
+                if import.rooted { "::" } else { "" },
+                describe(&widening.left),
+                describe(&widening.right),
+                describe(&import.visibility),
+            ),
+        )
+        .at(import.provenance.lines.first().copied().unwrap_or(1), 1)
+        .emit(format);
+    }
+}
+
+/// Warn about the glob imports in the merged file (see `--wildcards`). With
+/// `deny`, glob imports from only one side are an error instead, so only the
+/// ones that were on both sides are reported.
+fn report_wildcards(file: &ConflictedFile<'_>, options: &MergeOptions, format: MessageFormat) {
+    let existing_only = match options.wildcards {
+        WildcardPolicy::Allow => return,
+        WildcardPolicy::Warn => false,
+        WildcardPolicy::Deny => true,
+    };
+
+    for import in file.wildcard_imports(options) {
+        let existing = import.provenance.left && import.provenance.right;
+
+        if existing_only && !existing {
+            continue;
         }
+
+        let path = Iterator::chain(import.path.iter().map(String::as_str), ["*"]).join("::");
+        let location = match import.provenance.module.as_slice() {
+            [] => String::new(),
+            module => format!(" (in module {})", module.join("::")),
+        };
+
+        Diagnostic::new(
+            Level::Warning,
+            options.path.as_deref(),
+            format!(
+                "`{}{path}`{location} is a glob import",
+                if import.rooted { "::" } else { "" },
+            ),
+        )
+        .at(import.provenance.lines.first().copied().unwrap_or(1), 1)
+        .emit(format);
     }
+}
 
-    grouped_flattened_items
+/// Write the intermediate stages of fixing a file to a directory (see
+/// `--debug-stages`)
+fn write_debug_stages(
+    dir: &Path,
+    input: &str,
+    output: &str,
+    options: &MergeOptions,
+) -> anyhow::Result<()> {
+    let stages = parse_file(input, options)?.debug_stages(options)?;
+
+    fs::create_dir_all(dir).with_context(|| format!("i/o error creating {}", dir.display()))?;
+
+    let artifacts = Iterator::chain(
+        [("input.rs", input), ("output.rs", output)].into_iter(),
+        stages
+            .iter()
+            .map(|(name, content)| (name.as_str(), content.as_str())),
+    );
+
+    for (name, content) in artifacts {
+        let path = dir.join(name);
+        fs::write(&path, content)
+            .with_context(|| format!("i/o error writing {}", path.display()))?;
+    }
+
+    Ok(())
 }
 
-/// A parsed `UseItem` (see `tree.rs`) along with all of the line numbers from
-/// the original file are associated with this item.
-struct AnnotatedUseItem {
-    use_item: UseItem,
-    touched_original_lines: HashSet<LineNumber>,
+/// Check that a fixed file is a fixpoint (fixing it again doesn't change it),
+/// and that it's valid rust, if it doesn't contain any conflicts (see
+/// `--verify`).
+fn verify_fixed(fixed: &str, options: &MergeOptions) -> anyhow::Result<()> {
+    // The annotations are only added where there were conflicts, so fixing
+    // the output again would remove them
+    let fixed = &*match options.annotate_origin {
+        true => usefix::strip_origin_annotations(fixed),
+        false => Cow::Borrowed(fixed),
+    };
+
+    let file = parse_file(fixed, options)
+        .context("--verify: usefix produced a file that it can't parse")?;
+
+    if !file.contains_conflict() && !options.fragment {
+        if let Err(err) = syn::parse_file(fixed) {
+            let start = err.span().start();
+
+            anyhow::bail!(
+                "--verify: usefix produced a file that isn't valid rust, at line {}, column {}: {err}",
+                start.line,
+                start.column + 1
+            );
+        }
+    }
+
+    let options = MergeOptions {
+        require_conflict: false,
+        ..options.clone()
+    };
+
+    let refixed = usefix::fix_file(fixed, &options)
+        .context("--verify: usefix failed to fix its own output")?;
+
+    if let Some((line, _)) = Iterator::zip(1.., fixed.lines().zip_longest(refixed.lines()))
+        .find(|(_, lines)| !matches!(lines, EitherOrBoth::Both(a, b) if a == b))
+    {
+        anyhow::bail!(
+            "--verify: fixing the output again changed it, starting at line {line}, so \
+            usefix isn't idempotent on this file"
+        );
+    }
+
+    if fixed != refixed {
+        anyhow::bail!(
+            "--verify: fixing the output again changed its line endings, so usefix isn't \
+            idempotent on this file"
+        );
+    }
+
+    Ok(())
 }
@@ -0,0 +1,597 @@
+/*!
+A public, owned model of the merged imports in a file. The rest of usefix
+works with types that borrow from (and contain) `syn` types, which is
+convenient for the merge algorithm but awkward for anything else. An
+[`ImportModel`] is a flat list of imports, one for each imported name, that
+can be inspected and modified freely and then converted back into use items.
+ */
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+};
+
+use crate::{
+    common::{NameUse, Rooted},
+    flattened::{
+        wider_visibility, NormalizedUsedItems, SelfPrefixStyle, SingleUsedItem, UsedItemLeaf,
+    },
+    gitfile::Side,
+    sections::ImportSection,
+    tree::{ConfigsList, DocBlock, UseItem, Visibility},
+    with_merged_items, AnnotatedUseItem, Error, MergeOptions,
+};
+
+/// The merged set of imports of a file, after normalization.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportModel {
+    /// Every import, ordered by module and then by path
+    pub imports: Vec<Import>,
+}
+
+/// A single imported name, like `std::io::Write as _`, along with its
+/// attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// If true, the path has a leading `::`
+    pub rooted: bool,
+
+    /// The path segments preceding the leaf, as written (so raw identifiers
+    /// keep their `r#`)
+    pub path: Vec<String>,
+
+    /// The imported item
+    pub leaf: ImportLeaf,
+
+    /// The predicate inside each `#[cfg(...)]` attached to this import. If
+    /// there are several, all of them must hold. Empty if the import is
+    /// unconditional.
+    pub cfgs: Vec<String>,
+
+    /// The visibility of this import (`pub`, `pub(crate)`, etc), or `None`
+    /// if it's private
+    pub visibility: Option<String>,
+
+    /// The docs attached to this import. Each entry is the content of a
+    /// single `///` or `/** ... */` comment, or `#[doc = ...]` attribute.
+    pub docs: Vec<DocBlock>,
+
+    /// The contents of each list-style doc attribute attached to this
+    /// import, like the `hidden` in `#[doc(hidden)]`
+    pub doc_attrs: Vec<String>,
+
+    /// Where this import came from in the original file
+    pub provenance: Provenance,
+}
+
+/// The very last part of an import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportLeaf {
+    /// `name`
+    Name(String),
+
+    /// `name as alias`
+    Renamed { name: String, alias: String },
+
+    /// `*`
+    Wildcard,
+}
+
+/// Where an import came from in the original file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// The path to the inline module containing this import, or empty if
+    /// it's at the top level of the file
+    pub module: Vec<String>,
+
+    /// True if this import appeared in the left version of the file
+    pub left: bool,
+
+    /// True if this import appeared in the right version of the file
+    pub right: bool,
+
+    /// The (one-indexed) lines of the original file that were occupied by
+    /// the use items that included this import (including the blank line
+    /// after each one, if any). Imports that were only present because
+    /// they were subsumed by another import (like `a::b`, subsumed by
+    /// `a::*`) are attributed to the use items with the subsuming import.
+    pub lines: Vec<usize>,
+}
+
+/// A merged import whose visibility is wider than it was on one side of the
+/// conflict, like a `use` on one side and a `pub use` of the same name on the
+/// other, which were merged into a `pub use` (see
+/// [`ConflictedFile::visibility_widenings`](crate::ConflictedFile::visibility_widenings)).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisibilityWidening {
+    /// The merged import, with its merged visibility
+    pub import: Import,
+
+    /// The visibility of the import in the left version of the file, or
+    /// `None` if it was private
+    pub left: Option<String>,
+
+    /// The visibility of the import in the right version of the file, or
+    /// `None` if it was private
+    pub right: Option<String>,
+}
+
+/// A difference between the imports of two models (see
+/// [`ImportModel::diff`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportChange {
+    /// An import that's only in the new model
+    Added(Import),
+
+    /// An import that's only in the old model
+    Removed(Import),
+
+    /// A name that's imported from the same path, in the same module, in
+    /// both models, but with different cfgs, visibility, or renames. Each
+    /// side has every import of that name.
+    Changed { old: Vec<Import>, new: Vec<Import> },
+}
+
+impl ImportModel {
+    /// Build the model from the import sections of a file.
+    pub(crate) fn from_sections(options: &MergeOptions, sections: &[ImportSection<'_>]) -> Self {
+        let mut imports: Vec<Import> = sections
+            .iter()
+            .flat_map(|section| section_imports(options, section))
+            .collect();
+
+        sort_imports(&mut imports, options.self_prefix);
+
+        Self { imports }
+    }
+
+    /// Compare the imports in this model with the imports in a newer one.
+    /// Imports are matched by their module, path, and imported name (so
+    /// `a::b` and `a::b as c` are a change, rather than an addition and a
+    /// removal), and compared by their renames, cfgs, and visibility. Docs
+    /// and provenance are ignored. Changes are in the same order as the
+    /// models.
+    pub fn diff(&self, new: &ImportModel, self_prefix: SelfPrefixStyle) -> Vec<ImportChange> {
+        let old_groups = diff_groups(self, self_prefix);
+        let new_groups = diff_groups(new, self_prefix);
+
+        let keys: BTreeSet<&DiffKey<'_>> = old_groups.keys().chain(new_groups.keys()).collect();
+
+        let owned = |imports: &[&Import]| -> Vec<Import> {
+            imports.iter().map(|&import| import.clone()).collect()
+        };
+
+        keys.into_iter()
+            .flat_map(|key| {
+                let old = old_groups.get(key).map(Vec::as_slice).unwrap_or_default();
+                let new = new_groups.get(key).map(Vec::as_slice).unwrap_or_default();
+
+                match (old, new) {
+                    ([], new) => owned(new).into_iter().map(ImportChange::Added).collect(),
+                    (old, []) => owned(old).into_iter().map(ImportChange::Removed).collect(),
+                    (old, new)
+                        if diff_variants(old, self_prefix) == diff_variants(new, self_prefix) =>
+                    {
+                        Vec::new()
+                    }
+                    (old, new) => Vec::from([ImportChange::Changed {
+                        old: owned(old),
+                        new: owned(new),
+                    }]),
+                }
+            })
+            .collect()
+    }
+
+    /// Apply a list of changes (from [`ImportModel::diff`]) to this model.
+    /// Removed imports are removed if they're present (matched in the same
+    /// way as `diff` compares them), added imports are added if they aren't
+    /// already present, and changed names are replaced entirely with their
+    /// new imports.
+    pub fn apply_changes(&mut self, changes: &[ImportChange], self_prefix: SelfPrefixStyle) {
+        let variant = |import: &Import| {
+            let (rooted, path, leaf) = import.match_key(self_prefix);
+            (
+                import.provenance.module.clone(),
+                rooted,
+                path.into_iter().map(str::to_owned).collect::<Vec<_>>(),
+                leaf.map(|(name, alias)| (name.to_owned(), alias.to_owned())),
+                import.cfgs.clone(),
+                import.visibility.clone(),
+            )
+        };
+
+        for change in changes {
+            match change {
+                ImportChange::Removed(removed) => {
+                    let removed = variant(removed);
+                    self.imports.retain(|import| variant(import) != removed);
+                }
+                ImportChange::Added(added) => {
+                    let key = variant(added);
+                    if !self.imports.iter().any(|import| variant(import) == key) {
+                        self.imports.push(added.clone());
+                    }
+                }
+                ImportChange::Changed { old, new } => {
+                    let names: Vec<_> = old
+                        .iter()
+                        .chain(new)
+                        .map(|import| {
+                            let (module, rooted, path, leaf, _, _) = variant(import);
+                            (module, rooted, path, leaf.map(|(name, _)| name))
+                        })
+                        .collect();
+
+                    self.imports.retain(|import| {
+                        let (module, rooted, path, leaf, _, _) = variant(import);
+                        !names.contains(&(module, rooted, path, leaf.map(|(name, _)| name)))
+                    });
+
+                    self.imports.extend(new.iter().cloned());
+                }
+            }
+        }
+
+        sort_imports(&mut self.imports, self_prefix);
+    }
+
+    /// Render all of these imports as use items, merged and formatted in the
+    /// same way as the imports in a file. The imports are all rendered
+    /// together, regardless of their modules, so filter them first if you
+    /// only want the imports from a single module.
+    pub fn to_source(&self, options: &MergeOptions) -> Result<String, Error> {
+        let source: String = self
+            .imports
+            .iter()
+            .map(|import| import.to_string())
+            .collect();
+
+        // The rendered imports never contain any conflicts
+        let options = MergeOptions {
+            require_conflict: false,
+            ..options.clone()
+        };
+
+        crate::fix_file(&source, &options)
+    }
+}
+
+/// Sort imports by module, and then by path
+fn sort_imports(imports: &mut [Import], self_prefix: SelfPrefixStyle) {
+    imports.sort_by(|a, b| {
+        Ord::cmp(&a.provenance.module, &b.provenance.module)
+            .then_with(|| Ord::cmp(&a.match_key(self_prefix), &b.match_key(self_prefix)))
+            .then_with(|| Ord::cmp(&a.cfgs, &b.cfgs))
+    });
+}
+
+/// Identifies a group of imports being compared by `ImportModel::diff`: the
+/// module, followed by the match key of the imports, without the alias.
+type DiffKey<'a> = (&'a [String], bool, Vec<&'a str>, Option<&'a str>);
+
+fn diff_groups(
+    model: &ImportModel,
+    self_prefix: SelfPrefixStyle,
+) -> BTreeMap<DiffKey<'_>, Vec<&Import>> {
+    let mut groups: BTreeMap<DiffKey<'_>, Vec<&Import>> = BTreeMap::new();
+
+    for import in &model.imports {
+        let (rooted, path, leaf) = import.match_key(self_prefix);
+        let key = (
+            import.provenance.module.as_slice(),
+            rooted,
+            path,
+            leaf.map(|(name, _)| name),
+        );
+
+        groups.entry(key).or_default().push(import);
+    }
+
+    groups
+}
+
+/// The properties of an import that are compared by `ImportModel::diff`:
+/// its leaf (with its alias), cfgs, and visibility
+type DiffVariant<'a> = (Option<(&'a str, &'a str)>, &'a [String], Option<&'a str>);
+
+fn diff_variants<'a>(
+    imports: &[&'a Import],
+    self_prefix: SelfPrefixStyle,
+) -> BTreeSet<DiffVariant<'a>> {
+    imports
+        .iter()
+        .map(|import| {
+            let (_, _, leaf) = import.match_key(self_prefix);
+            (leaf, import.cfgs.as_slice(), import.visibility.as_deref())
+        })
+        .collect()
+}
+
+impl Import {
+    pub(crate) fn from_flattened(
+        path: &SingleUsedItem<'_>,
+        configs: &ConfigsList,
+        visibility: Option<String>,
+        docs: Vec<DocBlock>,
+        doc_attrs: Vec<String>,
+    ) -> Self {
+        Self {
+            rooted: path.rooted == Rooted::Rooted,
+            path: path.path.iter().map(|ident| ident.to_string()).collect(),
+            leaf: match path.leaf {
+                UsedItemLeaf::Wildcard => ImportLeaf::Wildcard,
+                UsedItemLeaf::Plain(name, NameUse::Used) => ImportLeaf::Name(name.to_string()),
+                UsedItemLeaf::Plain(name, NameUse::Renamed(alias)) => ImportLeaf::Renamed {
+                    name: name.to_string(),
+                    alias: alias.to_string(),
+                },
+            },
+            cfgs: configs
+                .configs()
+                .map(|config| config.as_str().to_owned())
+                .collect(),
+            visibility,
+            docs,
+            doc_attrs,
+            provenance: Provenance::default(),
+        }
+    }
+
+    /// A key identifying the imported path, used to match imports with the
+    /// use items they came from. Identifiers are compared by their unescaped
+    /// value, and `self::` prefixes are ignored unless they're distinct.
+    pub(crate) fn match_key(
+        &self,
+        self_prefix: SelfPrefixStyle,
+    ) -> (bool, Vec<&str>, Option<(&str, &str)>) {
+        fn unraw(ident: &str) -> &str {
+            ident.strip_prefix("r#").unwrap_or(ident)
+        }
+
+        let mut path: Vec<&str> = self.path.iter().map(|segment| unraw(segment)).collect();
+        if self_prefix != SelfPrefixStyle::Distinct && !self.rooted && path.first() == Some(&"self")
+        {
+            path.remove(0);
+        }
+
+        let leaf = match self.leaf {
+            ImportLeaf::Wildcard => None,
+            ImportLeaf::Name(ref name) => Some((unraw(name), "")),
+            ImportLeaf::Renamed {
+                ref name,
+                ref alias,
+            } => Some((unraw(name), unraw(alias))),
+        };
+
+        (self.rooted, path, leaf)
+    }
+}
+
+/// Renders the import as a single, complete use item
+impl Display for Import {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.docs.iter().try_for_each(|doc| match doc {
+            DocBlock::Text(text) => writeln!(f, "#[doc = {text:?}]"),
+            DocBlock::Expr(expr) => writeln!(f, "#[doc = {expr}]"),
+        })?;
+
+        self.cfgs
+            .iter()
+            .try_for_each(|cfg| writeln!(f, "#[cfg({cfg})]"))?;
+
+        self.doc_attrs
+            .iter()
+            .try_for_each(|attr| writeln!(f, "#[doc({attr})]"))?;
+
+        if let Some(ref visibility) = self.visibility {
+            write!(f, "{visibility} ")?;
+        }
+
+        f.write_str("use ")?;
+
+        if self.rooted {
+            f.write_str("::")?;
+        }
+
+        self.path
+            .iter()
+            .try_for_each(|segment| write!(f, "{segment}::"))?;
+
+        match self.leaf {
+            ImportLeaf::Name(ref name) => writeln!(f, "{name};"),
+            ImportLeaf::Renamed {
+                ref name,
+                ref alias,
+            } => writeln!(f, "{name} as {alias};"),
+            ImportLeaf::Wildcard => writeln!(f, "*;"),
+        }
+    }
+}
+
+/// Get the merged imports from a single section, along with their
+/// provenance.
+fn section_imports<'a>(options: &MergeOptions, section: &ImportSection<'a>) -> Vec<Import> {
+    let mut imports: Vec<Import> =
+        with_merged_items(options, &section.left, &section.right, |grouped| {
+            grouped
+                .iter()
+                .flat_map(|(&configs, items)| {
+                    items.iter().map(move |(&path, properties)| {
+                        Import::from_flattened(
+                            path,
+                            configs,
+                            properties.visibility.map(|vis| vis.to_string()),
+                            properties.docs.blocks().to_vec(),
+                            properties.doc_attrs.to_vec(),
+                        )
+                    })
+                })
+                .collect()
+        });
+
+    let module = section.module().to_vec();
+
+    let left = item_imports(options, &section.left);
+    let right = item_imports(options, &section.right);
+
+    for import in &mut imports {
+        let key = import.match_key(options.self_prefix);
+        let matching_items = |items: &[(&'a AnnotatedUseItem, Vec<Import>)]| {
+            items
+                .iter()
+                .filter(|(_, imports)| {
+                    imports
+                        .iter()
+                        .any(|other| other.match_key(options.self_prefix) == key)
+                })
+                .map(|&(item, _)| item)
+                .collect::<Vec<&AnnotatedUseItem>>()
+        };
+
+        let left_items = matching_items(&left);
+        let right_items = matching_items(&right);
+
+        let mut lines: Vec<usize> = Iterator::chain(left_items.iter(), right_items.iter())
+            .flat_map(|item| &item.touched_original_lines)
+            .map(|line| line.get())
+            .collect();
+
+        lines.sort_unstable();
+        lines.dedup();
+
+        import.provenance = Provenance {
+            module: module.clone(),
+            left: !left_items.is_empty(),
+            right: !right_items.is_empty(),
+            lines,
+        };
+    }
+
+    imports
+}
+
+/// Find the merged imports in a single section whose visibility is wider than
+/// it was on one side. If a side imports the same name several times, its
+/// widest visibility is the one that counts.
+pub(crate) fn section_widenings<'a>(
+    options: &MergeOptions,
+    section: &ImportSection<'a>,
+) -> Vec<VisibilityWidening> {
+    let left = item_imports(options, &section.left);
+    let right = item_imports(options, &section.right);
+
+    section_imports(options, section)
+        .into_iter()
+        .filter_map(|import| {
+            let key = import.match_key(options.self_prefix);
+
+            // `None` if the side doesn't import this name at all
+            let side_visibility = |items: &[(&'a AnnotatedUseItem, Vec<Import>)]| {
+                items
+                    .iter()
+                    .filter(|(_, imports)| {
+                        imports
+                            .iter()
+                            .any(|other| other.match_key(options.self_prefix) == key)
+                    })
+                    .filter_map(|&(item, _)| item.item.use_item())
+                    .map(|use_item| use_item.visibility.as_ref())
+                    .reduce(wider_visibility)
+            };
+
+            let left = side_visibility(&left)?;
+            let right = side_visibility(&right)?;
+
+            // The policy might have picked the narrower visibility instead
+            let widened = left != right
+                && import.visibility == wider_visibility(left, right).map(Visibility::to_string);
+
+            let to_string = |vis: Option<&Visibility>| vis.map(Visibility::to_string);
+            let (left, right) = (to_string(left), to_string(right));
+
+            widened.then_some(VisibilityWidening {
+                import,
+                left,
+                right,
+            })
+        })
+        .collect()
+}
+
+/// Find the merged imports in a single section that were only on one side
+/// of a conflict, along with the label of the conflict half they came from
+/// (or the name of the side, if the conflict markers aren't labeled).
+pub(crate) fn section_origins(
+    options: &MergeOptions,
+    section: &ImportSection<'_>,
+) -> Vec<(Import, Arc<str>)> {
+    let left = item_imports(options, &section.left);
+    let right = item_imports(options, &section.right);
+
+    section_imports(options, section)
+        .into_iter()
+        .filter_map(|import| {
+            let (items, side) = match (import.provenance.left, import.provenance.right) {
+                (true, false) => (&left, Side::Left),
+                (false, true) => (&right, Side::Right),
+                _ => return None,
+            };
+
+            let key = import.match_key(options.self_prefix);
+            let label = items
+                .iter()
+                .find(|(_, imports)| {
+                    imports
+                        .iter()
+                        .any(|other| other.match_key(options.self_prefix) == key)
+                })
+                .and_then(|(item, _)| item.origin.clone())
+                .filter(|label| !label.is_empty())
+                .unwrap_or_else(|| Arc::from(side.to_string()));
+
+            Some((import, label))
+        })
+        .collect()
+}
+
+/// Flatten a single use item into its imports, with its configs (but
+/// without its visibility or docs)
+pub(crate) fn use_item_imports(options: &MergeOptions, use_item: &UseItem) -> Vec<Import> {
+    let mut flattened = NormalizedUsedItems::new(options.self_prefix, options.policy.clone());
+    flattened.add_tree(use_item);
+
+    flattened
+        .items
+        .keys()
+        .map(|path| Import::from_flattened(path, &use_item.configs, None, Vec::new(), Vec::new()))
+        .collect()
+}
+
+/// Flatten each use item on its own, so that merged imports can be matched
+/// with the use items they came from.
+fn item_imports<'a>(
+    options: &MergeOptions,
+    items: &[&'a AnnotatedUseItem],
+) -> Vec<(&'a AnnotatedUseItem, Vec<Import>)> {
+    items
+        .iter()
+        .filter_map(|&item| {
+            let use_item = item.item.use_item()?;
+            let mut flattened =
+                NormalizedUsedItems::new(options.self_prefix, options.policy.clone());
+            flattened.add_tree(use_item);
+
+            let imports = flattened
+                .items
+                .keys()
+                .map(|path| {
+                    Import::from_flattened(path, &ConfigsList::EMPTY, None, Vec::new(), Vec::new())
+                })
+                .collect();
+
+            Some((item, imports))
+        })
+        .collect()
+}
@@ -0,0 +1,139 @@
+/*!
+Module declarations without bodies (`mod foo;`). Lists of these at the top of
+a `lib.rs` conflict for exactly the same reasons that use items do, so with
+`--mods` they're merged as a second kind of managed item: they're extracted
+from both versions of the file alongside the use items, belong to the same
+sections, and are written back as a sorted block in front of the merged use
+items of their section.
+
+Declarations are merged by name and configs, following the same rules as
+imports: visibilities and docs are merged, and an unconditional declaration
+subsumes any conditional declarations of the same module. Declarations with
+any attributes other than `cfg` and docs (like `#[path = "..."]` or
+`#[macro_use]`) aren't managed at all, and are left where they are.
+ */
+
+use std::{collections::BTreeMap, fmt, ops::RangeInclusive};
+
+use syn::spanned::Spanned;
+
+use crate::{
+    common::{Ident, Unraw},
+    flattened::{add_properties, UsedItemPropertiesGroup},
+    policy::Policy,
+    tree::{parse_attributes, ConfigsList, DocAttrsList, DocsList, Visibility},
+};
+
+/// A single `mod foo;` declaration
+#[derive(Debug)]
+pub struct ModDecl {
+    pub name: Ident,
+    pub visibility: Option<Visibility>,
+    pub configs: ConfigsList,
+    pub docs: DocsList,
+    pub doc_attrs: DocAttrsList,
+
+    /// The (one-indexed) lines of the syn item from which this was generated
+    pub lines: RangeInclusive<usize>,
+}
+
+impl ModDecl {
+    /// Convert a syn module item, if it's a declaration that we can manage.
+    pub fn from_syn_mod_item(item: syn::ItemMod) -> Option<Self> {
+        if item.content.is_some() || item.unsafety.is_some() {
+            return None;
+        }
+
+        let span = item.span();
+        let lines = span.start().line..=span.end().line;
+
+        let (docs, configs, doc_attrs) = parse_attributes(item.attrs).ok()?;
+        let visibility = Visibility::from_syn_vis(item.vis).ok()?;
+
+        Some(Self {
+            name: item.ident.into(),
+            visibility,
+            configs,
+            docs,
+            doc_attrs,
+            lines,
+        })
+    }
+}
+
+/// Merge a set of module declarations (from both sides of a single section),
+/// and render them, unconditional declarations first, then sorted by configs
+/// and name.
+pub fn render_mod_decls<'a>(
+    policy: &Policy,
+    decls: impl IntoIterator<Item = &'a ModDecl>,
+) -> String {
+    let mut merged: BTreeMap<Unraw<'a>, BTreeMap<&'a ConfigsList, UsedItemPropertiesGroup<'a>>> =
+        BTreeMap::new();
+
+    for decl in decls {
+        // If this module was already declared with a different spelling
+        // (`r#try` vs `try`), keep whichever spelling is raw.
+        let (name, mut properties) = match merged.remove_entry(&Unraw(&decl.name)) {
+            Some((existing, properties)) if existing.is_raw() => (existing, properties),
+            Some((_, properties)) => (Unraw(&decl.name), properties),
+            None => (Unraw(&decl.name), BTreeMap::new()),
+        };
+
+        add_properties(
+            &mut properties,
+            &decl.configs,
+            UsedItemPropertiesGroup {
+                visibility: decl.visibility.as_ref(),
+                docs: decl.docs.clone(),
+                docs_breadth: 1,
+                doc_attrs: decl.doc_attrs.clone(),
+            },
+            policy,
+        );
+
+        merged.insert(name, properties);
+    }
+
+    let mut decls: Vec<_> = merged
+        .iter()
+        .flat_map(|(&name, properties)| {
+            properties
+                .iter()
+                .map(move |(&configs, properties)| (configs, name, properties))
+        })
+        .collect();
+
+    decls.sort_by_key(|&(configs, name, _)| (configs, name));
+
+    let mut rendered = String::new();
+
+    for (configs, Unraw(name), properties) in decls {
+        format_mod_decl(&mut rendered, name, configs, properties)
+            .expect("writing to a string is infallible");
+    }
+
+    rendered
+}
+
+fn format_mod_decl(
+    dest: &mut impl fmt::Write,
+    name: &Ident,
+    configs: &ConfigsList,
+    properties: &UsedItemPropertiesGroup<'_>,
+) -> fmt::Result {
+    write!(dest, "{}", properties.docs)?;
+
+    for config in configs.configs() {
+        writeln!(dest, "{config}")?;
+    }
+
+    for attr in properties.doc_attrs.attrs() {
+        writeln!(dest, "{attr}")?;
+    }
+
+    match properties.visibility {
+        Some(visibility) => writeln!(dest, "{visibility} mod {name};"),
+        None => writeln!(dest, "mod {name};"),
+    }
+}
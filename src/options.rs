@@ -0,0 +1,909 @@
+/*!
+Options controlling the behavior of usefix. [`MergeOptions`] is shared by the
+command line tool (which builds it from its arguments) and the library API
+(where it can be built with [`MergeOptionsBuilder`]), so that the two can't
+drift apart.
+ */
+
+use std::{
+    convert::Infallible,
+    env,
+    ffi::OsString,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::Parser;
+
+use crate::{
+    cfg_rules::CfgRules,
+    flattened::{Edition, MergeStrategy, SelfPrefixStyle},
+    gitfile::Side,
+    model::Import,
+    policy::{MergePolicy, Policy},
+    pretty::RustfmtSettings,
+    printable::{CfgOrder, PrintOptions, SelfImportStyle, TreeOrder, WildcardPosition},
+    rules::{CfgLayout, Criterion, InvalidRulesError, PubPlacement, SortRules, StdLayout},
+};
+
+/// Options controlling how use items are merged, ordered, and formatted.
+/// These are the same options accepted by the `usefix` command line tool;
+/// each field is documented in terms of its command line flag.
+#[derive(Debug, Clone, clap::Args)]
+pub struct MergeOptions {
+    /// By default, we use prettyplease to format the use items. This argument
+    /// specifies an external command (typically `rustfmt`) that will be used
+    /// instead (for instance, if you want `usefix` to respect your rustfmt
+    /// configuration).
+    ///
+    /// The given argument will be treated as a whole command; use a shell
+    /// script or something similar if you want to pass extra arguments to it.
+    /// The use items will be passed to the given command over stdin, and the
+    /// formatted use items will be read from stdout. If the command is named
+    /// `rustfmt`, we also pass it `--config-path` and `--edition` (see
+    /// `--path`).
+    #[clap(long, short = 'c')]
+    pub rustfmt: Option<PathBuf>,
+
+    /// The path to the file being fixed (or a directory containing it). When
+    /// formatting with `rustfmt`, this is used to find the nearest
+    /// `rustfmt.toml` and the package's edition, which are passed to
+    /// `rustfmt` explicitly so that it formats the use items the same way
+    /// that `cargo fmt` would. Defaults to the current directory.
+    #[clap(long)]
+    pub path: Option<PathBuf>,
+
+    /// Don't format the use items at all; just emit them as they're rendered,
+    /// one per line. Useful if you're going to run `cargo fmt` afterwards
+    /// anyway.
+    #[clap(long, conflicts_with = "rustfmt")]
+    pub no_format: bool,
+
+    /// When formatting with prettyplease, wrap use items that are longer than
+    /// this many columns. prettyplease always wraps use items longer than 100
+    /// columns, so this only has an effect if it's smaller than that.
+    #[clap(long, conflicts_with_all = ["rustfmt", "no_format"])]
+    pub max_width: Option<usize>,
+
+    /// Keep the braces around a single item at the end of a path, like
+    /// `use a::{b};`, rather than removing them. Note that this applies to
+    /// every use item, even if it didn't have braces originally. prettyplease
+    /// removes these braces itself, so this only has an effect with
+    /// `--rustfmt` or `--no-format`.
+    #[clap(long)]
+    pub keep_single_item_braces: bool,
+
+    /// The number of blank lines to put after each block of use items,
+    /// unless it's at the end of a block or the file.
+    #[clap(long, default_value_t = 1)]
+    pub trailing_blank_lines: usize,
+
+    /// How to treat imports that begin with `self::`. By default, `self::foo`
+    /// and `foo` are treated as distinct imports. With `explicit` or
+    /// `implicit`, they're treated as the same import, and rendered with or
+    /// without the `self::`, respectively.
+    #[clap(long, value_enum, default_value_t)]
+    pub self_prefix: SelfPrefixStyle,
+
+    /// Write paths with a leading `::` (`::serde::Deserialize`) without it
+    /// (`serde::Deserialize`), the way they're usually written since the
+    /// 2018 edition, so that the merged use items are consistent even if the
+    /// two sides used different styles. In the 2015 edition, the two are
+    /// always equivalent; in later editions, the `::` is kept if the first
+    /// segment of the path is also imported or declared as a module in the
+    /// same section, since it's what tells them apart.
+    #[clap(long)]
+    pub normalize_roots: bool,
+
+    /// The edition of the file, for `--normalize-roots` and
+    /// `--modernize-extern-crate`. Defaults to the edition of the package
+    /// containing `--path` (or the current directory). If that can't be
+    /// found, `--normalize-roots` uses the rules for the 2018 and later
+    /// editions, since they're more conservative, and
+    /// `--modernize-extern-crate` does nothing.
+    #[clap(long, value_enum)]
+    pub edition: Option<Edition>,
+
+    /// Replace `extern crate` items with the use items that mean the same
+    /// thing in the 2018 and later editions, as part of the use items around
+    /// them: `extern crate foo;` is removed, and `extern crate foo as bar;`
+    /// becomes `use foo as bar;`. Items with `#[macro_use]`, and the crates
+    /// that still need `extern crate` (like `alloc`), are left alone.
+    #[clap(long)]
+    pub modernize_extern_crate: bool,
+
+    /// Collect all anonymous imports (`use std::io::Write as _;`) into a
+    /// single group at the end of the use items.
+    #[clap(long)]
+    pub trailing_anonymous_group: bool,
+
+    /// Collect all test-only imports (those under `#[cfg(test)]`, or a
+    /// `#[cfg(all(...))]` that requires `test`) into a group of their own,
+    /// after every other group of conditional imports.
+    #[clap(long)]
+    pub trailing_test_group: bool,
+
+    /// Instead of attaching `#[cfg(...)]` attributes to each conditional use
+    /// item, wrap each group of conditional use items in a
+    /// `cfg_if::cfg_if! { ... }` block. Requires the `cfg_if` crate.
+    #[clap(long)]
+    pub cfg_if: bool,
+
+    /// Collect all of the use items in the file (or in each inline module)
+    /// into a single section, at the point where the first use item
+    /// appears. By default, each cluster of use items (separated by other
+    /// items, like functions or macro definitions) is merged and rewritten
+    /// separately, in place. Inline modules with the same name (like
+    /// `#[cfg(unix)] mod imp` and `#[cfg(windows)] mod imp`) are kept
+    /// separate.
+    #[clap(long)]
+    pub hoist: bool,
+
+    /// Only rewrite the use items between `// usefix:begin` and `// usefix:end`
+    /// comments, and leave every other use item alone. If the file doesn't
+    /// have these markers yet, only the first section of use items is
+    /// rewritten, and the markers are added around it. It's an error for a
+    /// conflict to touch use items outside of the region, since they'd be
+    /// left unresolved.
+    #[clap(long)]
+    pub managed_region: bool,
+
+    /// Keep the original text of any use item that's unchanged by the merge
+    /// (that is, whenever the merged use item is semantically identical to a
+    /// use item that was already in the file), and only format the use items
+    /// that actually changed. This reduces noise in diffs. Use items are still
+    /// put in order and grouped.
+    #[clap(long, conflicts_with = "cfg_if")]
+    pub preserve_unchanged: bool,
+
+    /// Instead of sorting the use items alphabetically by crate, keep the
+    /// crates in the order they first appeared in the original file. Crates
+    /// are still grouped into `std`, dependencies, `crate`, etc.
+    #[clap(long)]
+    pub preserve_root_order: bool,
+
+    /// How to order groups of conditional use items relative to each other:
+    /// alphabetically, from simplest to most complex `#[cfg(...)]`, or by
+    /// their first appearance in the original file.
+    #[clap(long, value_enum, default_value_t)]
+    pub cfg_order: CfgOrder,
+
+    /// Where to put the `*` wildcard inside of braces: before or after the
+    /// other items in the braces. `self` always comes first.
+    #[clap(long, value_enum, default_value_t)]
+    pub wildcard_position: WildcardPosition,
+
+    /// How to order the items inside of braces: by name, or with shallower
+    /// paths before deeper ones (`use a::{z, b::c};`).
+    #[clap(long, value_enum, default_value_t)]
+    pub tree_order: TreeOrder,
+
+    /// Sort renamed items inside of braces by their alias, rather than by
+    /// the name they're renamed from, so that `use a::{Beta, Zeta as Alpha};`
+    /// becomes `use a::{Zeta as Alpha, Beta};`.
+    /// Anonymous imports (`Trait as _`) are still sorted by name.
+    #[clap(long)]
+    pub sort_by_alias: bool,
+
+    /// How to import a module along with some of its contents: as `self`,
+    /// inside of the braces with them (`use a::b::{self, c};`), or in a use
+    /// item of its own (`use a::b;` and `use a::b::c;`).
+    #[clap(long, value_enum, default_value_t)]
+    pub self_import: SelfImportStyle,
+
+    /// The maximum depth of nested braces in a single use item. Use items
+    /// nested more deeply than this are split into several use items. For
+    /// instance, with `--max-nesting 1`, `use a::{b::{c, d}, e};` becomes
+    /// `use a::b::{c, d};` and `use a::e;`.
+    #[clap(long)]
+    pub max_nesting: Option<usize>,
+
+    /// The maximum number of paths in a single use item. Use items with more
+    /// paths than this are split into several use items, keeping the paths
+    /// from the same module together where they fit. For instance, with
+    /// `--max-paths 2`, `use a::{b::{c, d}, e};` becomes `use a::b::{c, d};`
+    /// and `use a::e;`.
+    #[clap(long, value_name = "N")]
+    pub max_paths: Option<usize>,
+
+    /// Discard private anonymous imports (`use a::Trait as _;`) whenever the
+    /// same item is also imported by name or by a wildcard, even under a
+    /// different `#[cfg(...)]`. By default, this only happens when both
+    /// imports have the same configs.
+    #[clap(long)]
+    pub discard_shadowed_anonymous: bool,
+
+    /// A file of project-specific relations between cfg predicates, like
+    /// `feature = "full" implies feature = "tokio"` (see `CfgRules`). An
+    /// import under some configs is merged into the same import under
+    /// configs that they imply, since it's redundant.
+    #[clap(long, value_name = "FILE", value_parser = CfgRules::from_file)]
+    pub cfg_rules: Option<CfgRules>,
+
+    /// Where to put conditional use items. By default, they're collected into
+    /// their own groups after the unconditional use items; with `adjacent`,
+    /// each is placed immediately after the unconditional use item for the
+    /// same crate. This is a preset for `--sort-order` and `--group-by`.
+    #[clap(long, value_enum, default_value_t)]
+    pub cfg_layout: CfgLayout,
+
+    /// A comma-separated list of criteria by which use items are sorted, in
+    /// order of priority. Defaults to `locality,configs,docs,root` (or
+    /// `locality,docs,root,configs` with `--cfg-layout adjacent`).
+    #[clap(long, value_enum, value_delimiter = ',')]
+    pub sort_order: Option<Vec<Criterion>>,
+
+    /// A comma-separated list of criteria by which use items are split into
+    /// groups; a blank line is inserted between adjacent use items that
+    /// differ in any of these criteria. Use items must also be sorted by
+    /// each of these (except `docs`) before anything else. Defaults to
+    /// `locality,cfg,docs` (or `locality,docs` with `--cfg-layout
+    /// adjacent`).
+    #[clap(long, value_enum, value_delimiter = ',')]
+    pub group_by: Option<Vec<Criterion>>,
+
+    /// Where to put `pub use` items within each group: interleaved with the
+    /// private use items (sorted by crate), before them, or after them.
+    #[clap(long, value_enum, default_value_t)]
+    pub pub_placement: PubPlacement,
+
+    /// How to order and group the standard library crates: together, sorted
+    /// by name (the default); together, as `core`, `alloc`, then `std`; or
+    /// split into a group for each crate, in that order.
+    #[clap(long, value_enum, default_value_t)]
+    pub std_layout: StdLayout,
+
+    /// Fail if the file doesn't contain any git conflicts, rather than just
+    /// merging and reformatting its use items.
+    #[clap(long)]
+    pub require_conflict: bool,
+
+    /// Merge and reformat the use items even if none of the file's
+    /// conflicts touch them. By default, a file whose conflicts are all in
+    /// other code is left exactly as it is.
+    #[clap(long)]
+    pub always_rewrite: bool,
+
+    /// Only resolve the conflicts that overlap this (one-indexed, inclusive)
+    /// range of lines, like `12:30`. Use items that aren't in the same
+    /// section as one of those conflicts are left exactly as they are, as is
+    /// every other conflict. With `--require-conflict`, fail if there are no
+    /// conflicts in the range.
+    #[clap(long, value_name = "N:M")]
+    pub lines: Option<LineRange>,
+
+    /// The input is only a fragment of a file, such as a single conflicted
+    /// hunk with a few lines of context, rather than a whole file. The
+    /// fragment doesn't need to be valid rust; each use item in it is
+    /// parsed on its own. Inline modules aren't recognized in this mode.
+    #[clap(long)]
+    pub fragment: bool,
+
+    /// Also merge the module declarations without bodies (`mod foo;`) that
+    /// appear among the use items. They're sorted and written as a block in
+    /// front of the use items in their section.
+    #[clap(long)]
+    pub mods: bool,
+
+    /// Also merge the crate-level inner attributes (`#![...]`) at the top of
+    /// the file. `#![feature(...)]` lists are unioned, and identical
+    /// attributes are deduplicated.
+    #[clap(long)]
+    pub crate_attrs: bool,
+
+    /// Also merge the use items at the top of function bodies (including
+    /// methods in impl blocks), wherever they're touched by a conflict. Each
+    /// function's use items are merged on their own, and stay in its body.
+    #[clap(long)]
+    pub local_uses: bool,
+
+    /// Which imports to keep from each side of a conflict: all of them
+    /// (`union`), only the ones on both sides (`intersection`), or all of
+    /// the ones from one side (`ours` or `theirs`). Module declarations and
+    /// crate attributes are always unioned.
+    #[clap(long, value_enum, default_value_t)]
+    pub strategy: MergeStrategy,
+
+    /// When the two sides of a conflict disagree about an import (its
+    /// visibility, its docs, or what it's renamed to), use the version from
+    /// this side: `left`, `right`, or the branch name from one of the
+    /// conflict markers (like `main`). By default, the more public
+    /// visibility wins, docs are combined, and every rename is kept.
+    #[clap(long, value_name = "SIDE")]
+    pub prefer_side: Option<PreferredSide>,
+
+    /// When an import's docs on the two sides of a conflict genuinely
+    /// differ, keep both, each preceded by a marker naming the branch it came
+    /// from (like `/// --- from branch 'main' ---`), so that whoever cleans
+    /// up after the merge can choose between them. By default, they're
+    /// concatenated without any markers.
+    #[clap(long)]
+    pub mark_divergent_docs: bool,
+
+    /// Drop the docs from every merged use item, rather than merging them.
+    /// The imports themselves are still merged as usual.
+    #[clap(long)]
+    pub strip_docs: bool,
+
+    /// What to do about glob imports (`use a::*;`), for projects that ban
+    /// them. With `warn`, there's a warning for each glob import in the
+    /// merged file. With `deny`, it's an error for the merged file to have a
+    /// glob import that was only on one side of a conflict (including one
+    /// that subsumed imports from the other side), and there's a warning for
+    /// each glob import that was already on both sides.
+    #[clap(long, value_enum, default_value_t)]
+    pub wildcards: WildcardPolicy,
+
+    /// Add a trailing comment to each use item with imports that were only
+    /// on one side of a conflict, naming the branch they came from, like
+    /// `use a::b; // from: feature/foo`, so that the new imports stand out
+    /// when reviewing the merge. `--strip-origin` removes these comments.
+    #[clap(long, conflicts_with = "cfg_if")]
+    pub annotate_origin: bool,
+
+    /// Hooks for overriding individual merge decisions (see `MergePolicy`).
+    /// Only available in the library.
+    #[clap(skip)]
+    pub policy: Policy,
+
+    /// Imports to drop from the merged output, such as imports that the
+    /// compiler reported as unused. Each one is matched by its module (in
+    /// `provenance.module`) and its path; everything else is ignored. Only
+    /// available in the library.
+    #[clap(skip)]
+    pub discard: Vec<Import>,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        Self {
+            rustfmt: None,
+            path: None,
+            no_format: false,
+            max_width: None,
+            keep_single_item_braces: false,
+            trailing_blank_lines: 1,
+            self_prefix: SelfPrefixStyle::default(),
+            normalize_roots: false,
+            edition: None,
+            modernize_extern_crate: false,
+            trailing_anonymous_group: false,
+            trailing_test_group: false,
+            cfg_if: false,
+            hoist: false,
+            managed_region: false,
+            preserve_unchanged: false,
+            preserve_root_order: false,
+            cfg_order: CfgOrder::default(),
+            wildcard_position: WildcardPosition::default(),
+            tree_order: TreeOrder::default(),
+            sort_by_alias: false,
+            self_import: SelfImportStyle::default(),
+            max_nesting: None,
+            max_paths: None,
+            discard_shadowed_anonymous: false,
+            cfg_rules: None,
+            cfg_layout: CfgLayout::default(),
+            sort_order: None,
+            group_by: None,
+            pub_placement: PubPlacement::default(),
+            std_layout: StdLayout::default(),
+            require_conflict: false,
+            always_rewrite: false,
+            lines: None,
+            fragment: false,
+            mods: false,
+            crate_attrs: false,
+            local_uses: false,
+            strategy: MergeStrategy::default(),
+            prefer_side: None,
+            mark_divergent_docs: false,
+            strip_docs: false,
+            wildcards: WildcardPolicy::default(),
+            annotate_origin: false,
+            policy: Policy::default(),
+            discard: Vec::new(),
+        }
+    }
+}
+
+/// Wrapper for parsing `MergeOptions` on its own, without a binary name
+#[derive(clap::Parser)]
+#[clap(no_binary_name = true)]
+struct OptionsArgs {
+    #[clap(flatten)]
+    options: MergeOptions,
+}
+
+impl MergeOptions {
+    /// Parse options from a list of command line arguments (like
+    /// `["--hoist", "--cfg-order", "complexity"]`, without the binary name),
+    /// exactly like the `usefix` command line tool does.
+    pub fn from_args<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        OptionsArgs::try_parse_from(args).map(|args| args.options)
+    }
+
+    /// Create a builder for `MergeOptions`, starting from the defaults.
+    pub fn builder() -> MergeOptionsBuilder {
+        MergeOptionsBuilder::default()
+    }
+
+    /// How the merged use items will be formatted
+    pub fn formatter(&self) -> Formatter {
+        match self.rustfmt {
+            Some(ref command) => Formatter::External(command.clone()),
+            None if self.no_format => Formatter::Unformatted,
+            None => Formatter::Prettyplease {
+                max_width: self.max_width,
+            },
+        }
+    }
+
+    /// Check that these options are consistent with each other. The command
+    /// line tool rejects these combinations while parsing its arguments; this
+    /// performs the same checks for options built in code.
+    pub fn validate(&self) -> Result<(), InvalidOptionsError> {
+        if self.rustfmt.is_some() && self.no_format {
+            return Err(InvalidOptionsError::Conflict("--no-format", "--rustfmt"));
+        }
+
+        if self.max_width.is_some() && self.rustfmt.is_some() {
+            return Err(InvalidOptionsError::Conflict("--max-width", "--rustfmt"));
+        }
+
+        if self.max_width.is_some() && self.no_format {
+            return Err(InvalidOptionsError::Conflict("--max-width", "--no-format"));
+        }
+
+        if self.preserve_unchanged && self.cfg_if {
+            return Err(InvalidOptionsError::Conflict(
+                "--preserve-unchanged",
+                "--cfg-if",
+            ));
+        }
+
+        if self.annotate_origin && self.cfg_if {
+            return Err(InvalidOptionsError::Conflict(
+                "--annotate-origin",
+                "--cfg-if",
+            ));
+        }
+
+        self.print_options().map(drop)
+    }
+
+    /// Build the options for converting the final set of imports in each
+    /// section into a form suitable for printing.
+    pub(crate) fn print_options(&self) -> Result<PrintOptions, InvalidOptionsError> {
+        let sort_rules = SortRules::new(
+            self.sort_order
+                .as_deref()
+                .unwrap_or(self.cfg_layout.sort_order()),
+            self.group_by
+                .as_deref()
+                .unwrap_or(self.cfg_layout.group_by()),
+        )
+        .map_err(InvalidOptionsError::Rules)?
+        .with_pub_placement(self.pub_placement)
+        .with_std_layout(self.std_layout);
+
+        Ok(PrintOptions {
+            trailing_anonymous_group: self.trailing_anonymous_group,
+            trailing_test_group: self.trailing_test_group,
+            preserve_root_order: self.preserve_root_order,
+            wildcard_position: self.wildcard_position,
+            tree_order: self.tree_order,
+            sort_by_alias: self.sort_by_alias,
+            self_import: self.self_import,
+            max_nesting: self.max_nesting,
+            max_paths: self.max_paths,
+            sort_rules,
+            cfg_order: self.cfg_order,
+            collapse_single_item_braces: !self.keep_single_item_braces,
+        })
+    }
+
+    /// We only pass extra arguments to the external formatter if it's
+    /// actually rustfmt, since it could be anything
+    pub(crate) fn rustfmt_settings(&self) -> Option<RustfmtSettings> {
+        match self.rustfmt.as_deref() {
+            Some(command) if command.file_stem().is_some_and(|stem| stem == "rustfmt") => self
+                .project_dir()
+                .map(|start| RustfmtSettings::discover(&start)),
+            _ => None,
+        }
+    }
+
+    /// The edition of the package containing the file being fixed, if it
+    /// can be found
+    pub(crate) fn package_edition(&self) -> Option<Edition> {
+        let start = self.project_dir()?;
+        let edition = RustfmtSettings::discover(&start).edition?;
+
+        Edition::from_manifest(&edition)
+    }
+
+    /// The directory to start searching for project settings from (see
+    /// `--path`)
+    fn project_dir(&self) -> Option<PathBuf> {
+        match self.path.as_deref() {
+            Some(path) if path.is_file() => path.parent().map(Path::to_path_buf),
+            Some(path) => Some(path.to_path_buf()),
+            None => env::current_dir().ok(),
+        }
+    }
+}
+
+/// How the merged use items are formatted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Formatter {
+    /// Format with prettyplease, optionally wrapping use items that are
+    /// longer than `max_width` columns
+    Prettyplease { max_width: Option<usize> },
+
+    /// Format with an external command, typically `rustfmt` (see
+    /// `MergeOptions::rustfmt`)
+    External(PathBuf),
+
+    /// Don't format the use items at all; emit them one per line
+    Unformatted,
+}
+
+/// An inclusive range of one-indexed lines in a file, written `N:M`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl LineRange {
+    /// True if this range overlaps another (inclusive) range of lines
+    pub fn overlaps(&self, lines: &RangeInclusive<usize>) -> bool {
+        self.start <= *lines.end() && *lines.start() <= self.end
+    }
+}
+
+impl FromStr for LineRange {
+    type Err = InvalidLineRangeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once(':').ok_or(InvalidLineRangeError)?;
+        let start: usize = start.trim().parse().map_err(|_| InvalidLineRangeError)?;
+        let end: usize = end.trim().parse().map_err(|_| InvalidLineRangeError)?;
+
+        match 0 < start && start <= end {
+            true => Ok(Self { start, end }),
+            false => Err(InvalidLineRangeError),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy)]
+#[error("expected a range of lines like `12:30`, where 0 < N <= M")]
+pub struct InvalidLineRangeError;
+
+/// The side of a conflict to prefer when the two sides disagree (see
+/// `MergeOptions::prefer_side`), written `left`, `right`, or as a branch name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreferredSide {
+    Side(Side),
+
+    /// The side whose conflict markers are labeled with this branch name.
+    /// If the file doesn't contain any conflicts, this is ignored.
+    Label(String),
+}
+
+impl FromStr for PreferredSide {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "left" => Self::Side(Side::Left),
+            "right" => Self::Side(Side::Right),
+            label => Self::Label(label.to_owned()),
+        })
+    }
+}
+
+/// What to do about glob imports in the merged file (see
+/// `MergeOptions::wildcards`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum WildcardPolicy {
+    /// Glob imports are merged like any other import
+    #[default]
+    Allow,
+
+    /// Warn about every glob import
+    Warn,
+
+    /// Refuse to add glob imports, and warn about existing ones
+    Deny,
+}
+
+/// A builder for [`MergeOptions`]. Every option starts with the same default
+/// as the corresponding command line flag.
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptionsBuilder {
+    options: MergeOptions,
+}
+
+impl MergeOptionsBuilder {
+    /// How to format the merged use items
+    pub fn formatter(mut self, formatter: Formatter) -> Self {
+        let (rustfmt, no_format, max_width) = match formatter {
+            Formatter::Prettyplease { max_width } => (None, false, max_width),
+            Formatter::External(command) => (Some(command), false, None),
+            Formatter::Unformatted => (None, true, None),
+        };
+
+        self.options.rustfmt = rustfmt;
+        self.options.no_format = no_format;
+        self.options.max_width = max_width;
+        self
+    }
+
+    /// The path to the file being fixed, used to find its `rustfmt.toml` and
+    /// edition when formatting with `rustfmt`
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.path = Some(path.into());
+        self
+    }
+
+    pub fn keep_single_item_braces(mut self, keep: bool) -> Self {
+        self.options.keep_single_item_braces = keep;
+        self
+    }
+
+    pub fn trailing_blank_lines(mut self, count: usize) -> Self {
+        self.options.trailing_blank_lines = count;
+        self
+    }
+
+    pub fn self_prefix(mut self, style: SelfPrefixStyle) -> Self {
+        self.options.self_prefix = style;
+        self
+    }
+
+    pub fn normalize_roots(mut self, enabled: bool) -> Self {
+        self.options.normalize_roots = enabled;
+        self
+    }
+
+    pub fn edition(mut self, edition: Edition) -> Self {
+        self.options.edition = Some(edition);
+        self
+    }
+
+    pub fn modernize_extern_crate(mut self, enabled: bool) -> Self {
+        self.options.modernize_extern_crate = enabled;
+        self
+    }
+
+    pub fn trailing_anonymous_group(mut self, enabled: bool) -> Self {
+        self.options.trailing_anonymous_group = enabled;
+        self
+    }
+
+    pub fn trailing_test_group(mut self, enabled: bool) -> Self {
+        self.options.trailing_test_group = enabled;
+        self
+    }
+
+    pub fn cfg_if(mut self, enabled: bool) -> Self {
+        self.options.cfg_if = enabled;
+        self
+    }
+
+    pub fn hoist(mut self, enabled: bool) -> Self {
+        self.options.hoist = enabled;
+        self
+    }
+
+    pub fn managed_region(mut self, enabled: bool) -> Self {
+        self.options.managed_region = enabled;
+        self
+    }
+
+    pub fn preserve_unchanged(mut self, enabled: bool) -> Self {
+        self.options.preserve_unchanged = enabled;
+        self
+    }
+
+    pub fn preserve_root_order(mut self, enabled: bool) -> Self {
+        self.options.preserve_root_order = enabled;
+        self
+    }
+
+    pub fn cfg_order(mut self, order: CfgOrder) -> Self {
+        self.options.cfg_order = order;
+        self
+    }
+
+    pub fn wildcard_position(mut self, position: WildcardPosition) -> Self {
+        self.options.wildcard_position = position;
+        self
+    }
+
+    pub fn tree_order(mut self, order: TreeOrder) -> Self {
+        self.options.tree_order = order;
+        self
+    }
+
+    pub fn sort_by_alias(mut self, enabled: bool) -> Self {
+        self.options.sort_by_alias = enabled;
+        self
+    }
+
+    pub fn self_import(mut self, style: SelfImportStyle) -> Self {
+        self.options.self_import = style;
+        self
+    }
+
+    pub fn max_nesting(mut self, max_nesting: Option<usize>) -> Self {
+        self.options.max_nesting = max_nesting;
+        self
+    }
+
+    pub fn max_paths(mut self, max_paths: Option<usize>) -> Self {
+        self.options.max_paths = max_paths;
+        self
+    }
+
+    pub fn discard_shadowed_anonymous(mut self, enabled: bool) -> Self {
+        self.options.discard_shadowed_anonymous = enabled;
+        self
+    }
+
+    /// Relations between cfg predicates, used to merge redundant
+    /// conditional imports
+    pub fn cfg_rules(mut self, rules: CfgRules) -> Self {
+        self.options.cfg_rules = Some(rules);
+        self
+    }
+
+    pub fn cfg_layout(mut self, layout: CfgLayout) -> Self {
+        self.options.cfg_layout = layout;
+        self
+    }
+
+    /// The criteria by which use items are sorted, in order of priority.
+    /// Overrides the preset from `cfg_layout`.
+    pub fn sort_order(mut self, criteria: impl IntoIterator<Item = Criterion>) -> Self {
+        self.options.sort_order = Some(criteria.into_iter().collect());
+        self
+    }
+
+    /// The criteria by which use items are split into groups. Overrides the
+    /// preset from `cfg_layout`.
+    pub fn group_by(mut self, criteria: impl IntoIterator<Item = Criterion>) -> Self {
+        self.options.group_by = Some(criteria.into_iter().collect());
+        self
+    }
+
+    pub fn pub_placement(mut self, placement: PubPlacement) -> Self {
+        self.options.pub_placement = placement;
+        self
+    }
+
+    pub fn std_layout(mut self, layout: StdLayout) -> Self {
+        self.options.std_layout = layout;
+        self
+    }
+
+    /// Fail with `Error::NoConflicts` if the file doesn't contain any git
+    /// conflicts
+    pub fn require_conflict(mut self, require: bool) -> Self {
+        self.options.require_conflict = require;
+        self
+    }
+
+    /// Merge and reformat the use items even if none of the file's conflicts
+    /// touch them
+    pub fn always_rewrite(mut self, enabled: bool) -> Self {
+        self.options.always_rewrite = enabled;
+        self
+    }
+
+    /// Only resolve the conflicts that overlap a range of lines
+    pub fn lines(mut self, range: LineRange) -> Self {
+        self.options.lines = Some(range);
+        self
+    }
+
+    /// The input is only a fragment of a file (see
+    /// `ConflictedFile::parse_fragment`)
+    pub fn fragment(mut self, fragment: bool) -> Self {
+        self.options.fragment = fragment;
+        self
+    }
+
+    /// Also merge module declarations (`mod foo;`)
+    pub fn mods(mut self, mods: bool) -> Self {
+        self.options.mods = mods;
+        self
+    }
+
+    /// Also merge crate-level inner attributes (`#![...]`)
+    pub fn crate_attrs(mut self, crate_attrs: bool) -> Self {
+        self.options.crate_attrs = crate_attrs;
+        self
+    }
+
+    /// Also merge the use items at the top of function bodies, where
+    /// they're touched by a conflict
+    pub fn local_uses(mut self, local_uses: bool) -> Self {
+        self.options.local_uses = local_uses;
+        self
+    }
+
+    /// Override some of usefix's merge decisions (see `MergePolicy`)
+    pub fn policy(mut self, policy: impl MergePolicy + 'static) -> Self {
+        self.options.policy = Policy::new(policy);
+        self
+    }
+
+    /// Which imports to keep from each side of a conflict
+    pub fn strategy(mut self, strategy: MergeStrategy) -> Self {
+        self.options.strategy = strategy;
+        self
+    }
+
+    /// Which side wins when the two sides of a conflict disagree
+    pub fn prefer_side(mut self, side: PreferredSide) -> Self {
+        self.options.prefer_side = Some(side);
+        self
+    }
+
+    /// Mark each side's docs when they diverge, instead of concatenating them
+    pub fn mark_divergent_docs(mut self, mark_divergent_docs: bool) -> Self {
+        self.options.mark_divergent_docs = mark_divergent_docs;
+        self
+    }
+
+    /// Drop the docs from every merged use item
+    pub fn strip_docs(mut self, strip_docs: bool) -> Self {
+        self.options.strip_docs = strip_docs;
+        self
+    }
+
+    pub fn wildcards(mut self, policy: WildcardPolicy) -> Self {
+        self.options.wildcards = policy;
+        self
+    }
+
+    pub fn annotate_origin(mut self, enabled: bool) -> Self {
+        self.options.annotate_origin = enabled;
+        self
+    }
+
+    /// Drop some imports from the merged output
+    pub fn discard(mut self, imports: impl IntoIterator<Item = Import>) -> Self {
+        self.options.discard.extend(imports);
+        self
+    }
+
+    /// Finish building the options, checking that they're consistent with
+    /// each other (see `MergeOptions::validate`).
+    pub fn build(self) -> Result<MergeOptions, InvalidOptionsError> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
+/// An inconsistent set of options (see `MergeOptions::validate`)
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum InvalidOptionsError {
+    #[error("{0} can't be used with {1}")]
+    Conflict(&'static str, &'static str),
+
+    #[error("invalid --sort-order or --group-by")]
+    Rules(#[source] InvalidRulesError),
+
+    #[error("--prefer-side {0}: none of the conflicts are labeled with that branch")]
+    UnknownBranch(String),
+}
@@ -0,0 +1,169 @@
+/*!
+`MergeOptions::annotate_origin`: mark each merged import that was only on one
+side of a conflict with a trailing comment naming the branch it came from, so
+that whoever reviews the merge can see at a glance which imports are new to
+the other side:
+
+```text
+use serde::Deserialize; // from: feature/foo
+use std::{fmt, io}; // from: main (io)
+```
+
+If all of a use item's imports came from the same side, the comment just
+names its branch (the label on the conflict markers, or `left` or `right` if
+they aren't labeled). Otherwise, the imports from each branch are listed after
+it, and branches are separated by `;`.
+
+Formatters drop comments, so the annotations are added to the formatted use
+items, on the line with each one's semicolon. Since they're an artifact of a
+particular merge, [`strip_origin_annotations`] removes them again.
+ */
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    str,
+    sync::Arc,
+};
+
+use crate::{
+    model::{self, ImportLeaf},
+    sections::ImportSection,
+    tree::UseItem,
+    ConflictedFile, MergeOptions,
+};
+
+/// The start of every annotation
+const PREFIX: &str = "// from: ";
+
+/// Add an annotation to each use item in some formatted use items that has
+/// imports from only one side of a conflict. If the use items can't be
+/// parsed, they're returned unchanged.
+pub(crate) fn annotate_origins(
+    options: &MergeOptions,
+    section: &ImportSection<'_>,
+    use_items: &[u8],
+) -> Vec<u8> {
+    let origins = model::section_origins(options, section);
+
+    if origins.is_empty() {
+        return use_items.to_vec();
+    }
+
+    let Some(file) = str::from_utf8(use_items)
+        .ok()
+        .and_then(|text| syn::parse_file(text).ok())
+    else {
+        return use_items.to_vec();
+    };
+
+    // The annotation for each (one-indexed) line
+    let mut annotations: HashMap<usize, String> = HashMap::new();
+
+    for item in file.items {
+        let syn::Item::Use(item) = item else {
+            continue;
+        };
+
+        let line = item.semi_token.span.start().line;
+        let Ok(use_item) = UseItem::from_syn_use_item(item) else {
+            continue;
+        };
+
+        let imports = model::use_item_imports(options, &use_item);
+
+        // The names from each branch, in order of the branches' first
+        // appearance
+        let mut branches: Vec<(&Arc<str>, Vec<String>)> = Vec::new();
+
+        for import in &imports {
+            let key = import.match_key(options.self_prefix);
+            let Some((_, label)) = origins.iter().find(|(origin, _)| {
+                origin.match_key(options.self_prefix) == key && origin.cfgs == import.cfgs
+            }) else {
+                continue;
+            };
+
+            let name = match import.leaf {
+                ImportLeaf::Name(ref name) => name.clone(),
+                ImportLeaf::Renamed {
+                    ref name,
+                    ref alias,
+                } => format!("{name} as {alias}"),
+                ImportLeaf::Wildcard => "*".to_owned(),
+            };
+
+            match branches.iter_mut().find(|(branch, _)| *branch == label) {
+                Some((_, names)) => names.push(name),
+                None => branches.push((label, Vec::from([name]))),
+            }
+        }
+
+        let annotation = match branches.as_slice() {
+            [] => continue,
+            [(label, names)] if names.len() == imports.len() => format!("{PREFIX}{label}"),
+            branches => {
+                let branches: Vec<String> = branches
+                    .iter()
+                    .map(|(label, names)| format!("{label} ({})", names.join(", ")))
+                    .collect();
+
+                format!("{PREFIX}{}", branches.join("; "))
+            }
+        };
+
+        annotations.insert(line, annotation);
+    }
+
+    Iterator::zip(1.., use_items.split_inclusive(|&byte| byte == b'\n'))
+        .flat_map(|(number, line)| match annotations.get(&number) {
+            None => line.to_vec(),
+            Some(annotation) => {
+                let content = line.strip_suffix(b"\n").unwrap_or(line);
+                let newline = &line[content.len()..];
+
+                [content, b" ", annotation.as_bytes(), newline].concat()
+            }
+        })
+        .collect()
+}
+
+/// Remove the annotations added by `MergeOptions::annotate_origin` from a
+/// file: every trailing `// from: ...` comment after the semicolon of a use
+/// item. Comments like that anywhere else are left alone, and so is the
+/// whole file if it can't be parsed.
+pub fn strip_origin_annotations(source: &str) -> Cow<'_, str> {
+    if !source.contains(PREFIX) {
+        return Cow::Borrowed(source);
+    }
+
+    let Ok(file) = ConflictedFile::parse(source) else {
+        return Cow::Borrowed(source);
+    };
+
+    // The (one-indexed) lines of the original file with part of a use item
+    let use_item_lines: HashSet<usize> = Iterator::chain(file.left.iter(), file.right.iter())
+        .filter(|item| item.item.use_item().is_some())
+        .flat_map(|item| &item.touched_original_lines)
+        .map(|line| line.get())
+        .collect();
+
+    Cow::Owned(
+        Iterator::zip(1.., source.split_inclusive('\n'))
+            .map(|(number, line)| {
+                let content = line.trim_end_matches(['\r', '\n']);
+                let ending = &line[content.len()..];
+
+                match content.find(PREFIX) {
+                    Some(index)
+                        if use_item_lines.contains(&number)
+                            && content[..index].trim_end().ends_with(';') =>
+                    {
+                        Cow::Owned(format!("{}{ending}", content[..index].trim_end()))
+                    }
+                    _ => Cow::Borrowed(line),
+                }
+            })
+            .collect(),
+    )
+}
@@ -0,0 +1,80 @@
+/*!
+`--report-outcome`: after fixing a file, report how many of its conflicts
+were resolved, so that a script fixing a batch of files can summarize which
+of them still need attention. The report is written to stderr, as a single
+line for each file. In the `human` format, it looks like this:
+
+```text
+src/lib.rs: conflicts in use items were resolved; 2 other conflict(s) remain
+```
+
+In the `json` format, it's a JSON object, with the `file` (or `null` if it's
+unknown), the `outcome` (`no-conflicts`, `resolved`, `use-items-resolved`,
+or `unresolved`), and the number of `remaining_conflicts`:
+
+```text
+{"file":"src/lib.rs","outcome":"use-items-resolved","remaining_conflicts":2}
+```
+ */
+
+use std::path::Path;
+
+use usefix::Outcome;
+
+use crate::json::Json;
+
+/// How the outcome of fixing a file is reported (see the module docs)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutcomeFormat {
+    /// A human-readable sentence
+    Human,
+
+    /// A JSON object
+    Json,
+}
+
+/// Write the outcome of fixing a file to stderr
+pub fn report_outcome(file: Option<&Path>, outcome: Outcome, format: OutcomeFormat) {
+    let remaining = match outcome {
+        Outcome::NoConflicts | Outcome::Resolved => 0,
+        Outcome::UseItemsResolved { remaining } | Outcome::Unresolved { remaining } => remaining,
+    };
+
+    match format {
+        OutcomeFormat::Human => {
+            let file = match file {
+                Some(file) => file.display().to_string(),
+                None => "<stdin>".to_owned(),
+            };
+
+            let message = match outcome {
+                Outcome::NoConflicts => "no conflicts".to_owned(),
+                Outcome::Resolved => "all conflicts were resolved".to_owned(),
+                Outcome::UseItemsResolved { .. } => format!(
+                    "conflicts in use items were resolved; {remaining} other conflict(s) remain"
+                ),
+                Outcome::Unresolved { .. } => {
+                    format!("no conflicts could be resolved; {remaining} conflict(s) remain")
+                }
+            };
+
+            eprintln!("{file}: {message}");
+        }
+        OutcomeFormat::Json => {
+            let outcome = match outcome {
+                Outcome::NoConflicts => "no-conflicts",
+                Outcome::Resolved => "resolved",
+                Outcome::UseItemsResolved { .. } => "use-items-resolved",
+                Outcome::Unresolved { .. } => "unresolved",
+            };
+
+            let report = Json::object([
+                ("file", file.map(|file| file.display().to_string()).into()),
+                ("outcome", outcome.into()),
+                ("remaining_conflicts", remaining.into()),
+            ]);
+
+            eprintln!("{report}");
+        }
+    }
+}
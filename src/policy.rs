@@ -0,0 +1,247 @@
+/*!
+Hooks for overriding individual merge decisions. usefix makes a handful of
+judgement calls when merging imports (which visibility wins, how docs are
+combined, whether a wildcard subsumes a named import); a [`MergePolicy`] can
+override any of them without replacing the rest of the merge algorithm.
+
+Each hook has a default implementation that defers to usefix's built-in
+behavior, so a policy only needs to implement the hooks it cares about.
+ */
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    sync::Arc,
+};
+
+use itertools::Itertools;
+
+use crate::{
+    flattened::{SingleUsedItem, UsedItemPropertiesGroup},
+    model::{Import, ImportLeaf},
+    tree::{ConfigsList, DocBlock, DocsList, Visibility},
+};
+
+/// Which of two alternatives to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    First,
+    Second,
+}
+
+/// The same item, imported under more than one name (like `use a::b as c`
+/// and `use a::b as d`). Anonymous imports (`use a::b as _`) never conflict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameConflict {
+    /// If true, the path has a leading `::`
+    pub rooted: bool,
+
+    /// The path segments preceding the imported name
+    pub path: Vec<String>,
+
+    /// The imported name
+    pub name: String,
+
+    /// Each name the item is imported under. `None` is the item's own name.
+    pub aliases: Vec<Option<String>>,
+}
+
+/// Hooks invoked at each of usefix's merge decisions. Every hook returns
+/// `None` (or `true`) by default, which means to use the built-in behavior.
+pub trait MergePolicy: Send + Sync {
+    /// Choose between two different visibilities (`None` is private) for the
+    /// same import. By default, the more public visibility wins.
+    fn merge_visibility(&self, first: Option<&str>, second: Option<&str>) -> Option<Choice> {
+        let _ = (first, second);
+        None
+    }
+
+    /// Combine two different sets of docs for the same import. Each entry
+    /// is a single doc comment, or a macro call that produces docs. By
+    /// default, if either is a prefix or suffix of the other, the longer one
+    /// is kept; otherwise they're concatenated.
+    fn merge_docs(&self, first: &[DocBlock], second: &[DocBlock]) -> Option<Vec<DocBlock>> {
+        let _ = (first, second);
+        None
+    }
+
+    /// Resolve an item imported under more than one name, by returning the
+    /// index (into `conflict.aliases`) of the only name to keep. By default,
+    /// every name is kept.
+    fn resolve_rename_conflict(&self, conflict: &RenameConflict) -> Option<usize> {
+        let _ = conflict;
+        None
+    }
+
+    /// Decide whether `import` may be discarded because it's subsumed by
+    /// `parent`: either a wildcard import of the same path, or a named import
+    /// that subsumes an anonymous one. This is only called when usefix would
+    /// otherwise discard `import`; returning `false` keeps it.
+    fn allow_subsumption(&self, import: &Import, parent: &Import) -> bool {
+        let _ = (import, parent);
+        true
+    }
+}
+
+/// A shared handle to a [`MergePolicy`], stored in
+/// [`MergeOptions`](crate::MergeOptions). The default handle uses usefix's
+/// built-in behavior for everything.
+#[derive(Clone, Default)]
+pub struct Policy(Option<Arc<dyn MergePolicy>>);
+
+impl Policy {
+    pub fn new(policy: impl MergePolicy + 'static) -> Self {
+        Self(Some(Arc::new(policy)))
+    }
+
+    /// True if this is the default policy
+    pub fn is_default(&self) -> bool {
+        self.0.is_none()
+    }
+
+    pub(crate) fn merge_visibility(
+        &self,
+        first: Option<&Visibility>,
+        second: Option<&Visibility>,
+    ) -> Option<Choice> {
+        let policy = self.0.as_ref()?;
+
+        let first = first.map(|vis| vis.to_string());
+        let second = second.map(|vis| vis.to_string());
+
+        policy.merge_visibility(first.as_deref(), second.as_deref())
+    }
+
+    pub(crate) fn merge_docs(&self, first: &DocsList, second: &DocsList) -> Option<DocsList> {
+        self.0
+            .as_ref()?
+            .merge_docs(first.blocks(), second.blocks())
+            .map(DocsList::new)
+    }
+
+    pub(crate) fn resolve_rename_conflict(&self, conflict: &RenameConflict) -> Option<usize> {
+        self.0
+            .as_ref()?
+            .resolve_rename_conflict(conflict)
+            .filter(|&index| index < conflict.aliases.len())
+    }
+
+    pub(crate) fn allow_subsumption(
+        &self,
+        item: FlattenedImport<'_, '_>,
+        parent: FlattenedImport<'_, '_>,
+    ) -> bool {
+        let Some(ref policy) = self.0 else {
+            return true;
+        };
+
+        let to_import = |(path, configs, properties): FlattenedImport<'_, '_>| {
+            Import::from_flattened(
+                path,
+                configs,
+                properties.visibility.map(|vis| vis.to_string()),
+                properties.docs.blocks().to_vec(),
+                properties.doc_attrs.to_vec(),
+            )
+        };
+
+        policy.allow_subsumption(&to_import(item), &to_import(parent))
+    }
+}
+
+/// The policy used for `--prefer-side`. The imports from the preferred side
+/// are always flattened first, so whenever the two sides disagree, the
+/// version from the preferred side is the first one. Any decision made by
+/// the inner policy takes priority.
+pub(crate) struct PreferFirst {
+    pub inner: Policy,
+
+    /// The imports from the preferred side, used to resolve rename conflicts
+    pub preferred: Vec<Import>,
+}
+
+impl MergePolicy for PreferFirst {
+    fn merge_visibility(&self, first: Option<&str>, second: Option<&str>) -> Option<Choice> {
+        self.inner
+            .0
+            .as_ref()
+            .and_then(|inner| inner.merge_visibility(first, second))
+            .or(Some(Choice::First))
+    }
+
+    fn merge_docs(&self, first: &[DocBlock], second: &[DocBlock]) -> Option<Vec<DocBlock>> {
+        self.inner
+            .0
+            .as_ref()
+            .and_then(|inner| inner.merge_docs(first, second))
+            .or_else(|| Some(first.to_vec()))
+    }
+
+    fn resolve_rename_conflict(&self, conflict: &RenameConflict) -> Option<usize> {
+        if let Some(index) = self
+            .inner
+            .0
+            .as_ref()
+            .and_then(|inner| inner.resolve_rename_conflict(conflict))
+        {
+            return Some(index);
+        }
+
+        // Only resolve the conflict if the preferred side has exactly one of
+        // the names
+        conflict
+            .aliases
+            .iter()
+            .positions(|alias| {
+                self.preferred
+                    .iter()
+                    .any(|import| is_same_rename(import, conflict, alias.as_deref()))
+            })
+            .exactly_one()
+            .ok()
+    }
+
+    fn allow_subsumption(&self, import: &Import, parent: &Import) -> bool {
+        self.inner
+            .0
+            .as_ref()
+            .is_none_or(|inner| inner.allow_subsumption(import, parent))
+    }
+}
+
+/// True if an import is one of the names in a rename conflict
+fn is_same_rename(import: &Import, conflict: &RenameConflict, alias: Option<&str>) -> bool {
+    fn unraw(ident: &str) -> &str {
+        ident.strip_prefix("r#").unwrap_or(ident)
+    }
+
+    let leaf_matches = match (&import.leaf, alias) {
+        (ImportLeaf::Name(name), None) => unraw(name) == unraw(&conflict.name),
+        (ImportLeaf::Renamed { name, alias: other }, Some(alias)) => {
+            unraw(name) == unraw(&conflict.name) && unraw(other) == unraw(alias)
+        }
+        _ => false,
+    };
+
+    leaf_matches
+        && import.rooted == conflict.rooted
+        && Iterator::eq(
+            import.path.iter().map(|segment| unraw(segment)),
+            conflict.path.iter().map(|segment| unraw(segment)),
+        )
+}
+
+/// A single flattened import, with its configs and properties
+pub(crate) type FlattenedImport<'s, 'a> = (
+    &'s SingleUsedItem<'a>,
+    &'s ConfigsList,
+    &'s UsedItemPropertiesGroup<'a>,
+);
+
+impl Debug for Policy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            None => f.write_str("Policy(default)"),
+            Some(_) => f.write_str("Policy(custom)"),
+        }
+    }
+}
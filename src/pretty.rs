@@ -1,15 +1,25 @@
 use std::{
-    io::{Read, Write},
+    ffi::OsStr,
+    fs,
+    io::{self, Read, Write},
     panic,
-    path::Path,
-    process::{Command, Stdio},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    str::Utf8Error,
     thread,
 };
 
-use anyhow::Context;
 use itertools::Itertools;
 
-pub fn prettify_with_prettyplease(formatted_use_items: &str) -> Vec<u8> {
+use crate::{printable::RenderedSection, Error};
+
+/// Prettify use items with `prettyplease`. `prettyplease` always wraps lines
+/// at 100 columns; if `max_width` is smaller than that, any use item that it
+/// leaves on a single line that's too long is wrapped by us afterwards.
+pub fn prettify_with_prettyplease(
+    formatted_use_items: &str,
+    max_width: Option<usize>,
+) -> Result<Vec<u8>, Error> {
     // We use prettyplease, a variant of rustfmt intended for use with macros
     // and other codegen tools. For use items, it's hopefully identical to
     // rustfmt (though it probably doesn't respect your rustfmt config)
@@ -33,15 +43,22 @@ pub fn prettify_with_prettyplease(formatted_use_items: &str) -> Vec<u8> {
             .split("\n\n")
             .map(|chunk| {
                 scope.spawn(move || {
-                    let parsed_chunk = syn::parse_file(chunk)
-                        .expect("usefix shouldn't produce syntatically invalid rust");
+                    // usefix shouldn't ever produce syntactically invalid
+                    // rust, but if it does, the file is left untouched
+                    let parsed_chunk = syn::parse_file(chunk).map_err(|_| Error::Internal {
+                        reason: "usefix rendered use items that aren't valid rust",
+                    })?;
                     let mut prettified_chunk = prettyplease::unparse(&parsed_chunk);
 
+                    if let Some(max_width) = max_width {
+                        prettified_chunk = wrap_long_use_items(&prettified_chunk, max_width);
+                    }
+
                     let len_without_trailing_space = prettified_chunk.trim_end().len();
                     prettified_chunk.truncate(len_without_trailing_space);
                     prettified_chunk.push_str("\n\n");
 
-                    prettified_chunk
+                    Ok(prettified_chunk)
                 })
             })
             // This collect_vec is very important; it ensures that all of this
@@ -54,25 +71,319 @@ pub fn prettify_with_prettyplease(formatted_use_items: &str) -> Vec<u8> {
                     .join()
                     .unwrap_or_else(|panic| panic::resume_unwind(panic))
             })
-            .reduce(|mut left, right| {
-                left.push_str(&right);
-                left
-            })
-            .unwrap_or_default()
-            .into()
+            .collect::<Result<String, Error>>()
+            .map(String::into_bytes)
     })
 }
 
+/// Wrap any single-line use item longer than `max_width` so that each of the
+/// items in its outermost braces is on its own line, the same way rustfmt
+/// wraps use items:
+///
+/// ```text
+/// use a::{
+///     b::{c, d},
+///     e,
+/// };
+/// ```
+fn wrap_long_use_items(prettified: &str, max_width: usize) -> String {
+    let mut output = String::with_capacity(prettified.len());
+
+    for line in prettified.lines() {
+        let content = line.trim_start();
+        let indentation = &line[..line.len() - content.len()];
+
+        let braced = match line.chars().count() > max_width {
+            true => content
+                .strip_suffix("};")
+                .and_then(|content| content.split_once('{')),
+            false => None,
+        };
+
+        let Some((prefix, items)) = braced else {
+            output.push_str(line);
+            output.push('\n');
+            continue;
+        };
+
+        output.push_str(indentation);
+        output.push_str(prefix);
+        output.push_str("{\n");
+
+        // Split the items on the commas that aren't inside nested braces
+        let mut depth = 0;
+        let mut item_start = 0;
+
+        for (index, c) in items.char_indices().chain([(items.len(), ',')]) {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    let item = items[item_start..index].trim();
+                    item_start = index + 1;
+
+                    if !item.is_empty() {
+                        output.push_str(indentation);
+                        output.push_str("    ");
+                        output.push_str(item);
+                        output.push_str(",\n");
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        output.push_str(indentation);
+        output.push_str("};\n");
+    }
+
+    output
+}
+
+/// Settings discovered from the project containing the file being fixed.
+/// rustfmt reads its configuration relative to the working directory, but
+/// usefix pipes the use items to it via stdin, possibly from somewhere else
+/// entirely, so we find the settings ourselves and pass them explicitly.
+/// This way the use items are formatted the same way `cargo fmt` would.
+#[derive(Debug, Clone, Default)]
+pub struct RustfmtSettings {
+    /// The nearest `rustfmt.toml` or `.rustfmt.toml`
+    pub config_path: Option<PathBuf>,
+
+    /// The edition of the nearest package
+    pub edition: Option<String>,
+}
+
+impl RustfmtSettings {
+    /// Search `start` and its ancestors for a rustfmt config file and a
+    /// package manifest.
+    pub fn discover(start: &Path) -> Self {
+        let mut settings = Self::default();
+
+        // If the package inherits its edition from the workspace, we keep
+        // searching for the workspace's manifest
+        let mut needs_workspace_edition = false;
+
+        for dir in start.ancestors() {
+            if settings.config_path.is_none() {
+                settings.config_path = ["rustfmt.toml", ".rustfmt.toml"]
+                    .iter()
+                    .map(|name| dir.join(name))
+                    .find(|path| path.is_file());
+            }
+
+            if settings.edition.is_none() {
+                if let Ok(manifest) = fs::read_to_string(dir.join("Cargo.toml")) {
+                    let section = match needs_workspace_edition {
+                        false => "[package]",
+                        true => "[workspace.package]",
+                    };
+
+                    match find_manifest_edition(&manifest, section) {
+                        Some(ManifestEdition::Explicit(edition)) => {
+                            settings.edition = Some(edition.to_owned())
+                        }
+                        Some(ManifestEdition::Workspace) => needs_workspace_edition = true,
+                        None => {}
+                    }
+                }
+            }
+
+            if settings.config_path.is_some() && settings.edition.is_some() {
+                break;
+            }
+        }
+
+        settings
+    }
+
+    fn args(&self) -> Vec<&OsStr> {
+        let mut args = Vec::new();
+
+        if let Some(config_path) = &self.config_path {
+            args.extend([OsStr::new("--config-path"), config_path.as_os_str()]);
+        }
+
+        if let Some(edition) = &self.edition {
+            args.extend([OsStr::new("--edition"), OsStr::new(edition)]);
+        }
+
+        args
+    }
+}
+
+enum ManifestEdition<'a> {
+    Explicit(&'a str),
+    Workspace,
+}
+
+/// Find the `edition` key in a particular section of a `Cargo.toml`. This is
+/// a very rough line-based search, rather than a real TOML parser, but it
+/// handles every manifest that `cargo new` would create, along with trailing
+/// comments and `edition = { workspace = true }`. Anything other than one of
+/// the editions that rustfmt knows about is ignored, so that it's never
+/// passed along to `--edition`.
+fn find_manifest_edition<'a>(manifest: &'a str, section: &str) -> Option<ManifestEdition<'a>> {
+    let mut in_section = false;
+
+    // None of the lines we're interested in have a `#` in a string
+    let lines = manifest
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(line, _comment)| line))
+        .map(str::trim);
+
+    for line in lines {
+        if line.starts_with('[') {
+            in_section = line == section;
+        } else if in_section {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            let value = value.trim();
+            let inherited = match key.trim() {
+                "edition" => value
+                    .strip_prefix('{')
+                    .and_then(|table| table.strip_suffix('}'))
+                    .and_then(|table| table.split_once('='))
+                    .is_some_and(|(key, value)| {
+                        key.trim() == "workspace" && value.trim() == "true"
+                    }),
+                "edition.workspace" => value == "true",
+                _ => continue,
+            };
+
+            if inherited {
+                return Some(ManifestEdition::Workspace);
+            }
+
+            let edition = value.trim_matches(['"', '\'']);
+
+            return ["2015", "2018", "2021", "2024"]
+                .contains(&edition)
+                .then_some(ManifestEdition::Explicit(edition));
+        }
+    }
+
+    None
+}
+
+/// Create the `Command` for an external formatter. On Windows, we resolve
+/// the command the way a shell would: searching the `PATH` with each of the
+/// extensions in `PATHEXT`, and running `.bat` and `.cmd` scripts via
+/// `cmd /C`, since they can't be executed directly.
+fn formatter_command(command_name: &Path) -> Command {
+    #[cfg(windows)]
+    {
+        let resolved = resolve_windows_command(
+            command_name,
+            std::env::var("PATHEXT").ok().as_deref(),
+            std::env::var_os("PATH").as_deref(),
+        );
+
+        let is_script = resolved.extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("bat") || extension.eq_ignore_ascii_case("cmd")
+        });
+
+        if is_script {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(resolved);
+            return command;
+        }
+
+        Command::new(resolved)
+    }
+
+    #[cfg(not(windows))]
+    Command::new(command_name)
+}
+
+/// Find the file that a command name refers to on Windows, given the values
+/// of the `PATHEXT` and `PATH` environment variables. If it can't be found,
+/// the command name is returned as-is, and we let `Command` try. This doesn't
+/// depend on anything Windows-specific, so it's tested on every platform.
+#[cfg(any(windows, test))]
+fn resolve_windows_command(
+    command_name: &Path,
+    extensions: Option<&str>,
+    search_path: Option<&OsStr>,
+) -> PathBuf {
+    let extensions = extensions.unwrap_or(".COM;.EXE;.BAT;.CMD");
+
+    let find_in = |dir: &Path| -> Option<PathBuf> {
+        let base = dir.join(command_name);
+
+        if command_name.extension().is_some() && base.is_file() {
+            return Some(base);
+        }
+
+        extensions
+            .split(';')
+            .filter(|extension| !extension.is_empty())
+            .map(|extension| {
+                let mut path = base.clone().into_os_string();
+                path.push(extension);
+                PathBuf::from(path)
+            })
+            .find(|path| path.is_file())
+    };
+
+    // Commands with a directory component are never searched for in the
+    // `PATH`, just like in a shell
+    let is_bare_name = command_name.components().count() == 1 && !command_name.is_absolute();
+
+    let found = match is_bare_name {
+        false => find_in(Path::new("")),
+        true => std::iter::once(PathBuf::from("."))
+            .chain(
+                search_path
+                    .map(|paths| std::env::split_paths(paths).collect::<Vec<_>>())
+                    .unwrap_or_default(),
+            )
+            .find_map(|dir| find_in(&dir)),
+    };
+
+    found.unwrap_or_else(|| command_name.to_path_buf())
+}
+
+/// An error from running an external formatter
+#[derive(thiserror::Error, Debug)]
+pub enum FormatterError {
+    #[error("failed to launch formatting subcommand")]
+    Launch(#[source] io::Error),
+
+    #[error("i/o error while writing to stdin of formatting subcommand")]
+    Stdin(#[source] io::Error),
+
+    #[error("i/o error while reading from stdout of formatting subcommand")]
+    Stdout(#[source] io::Error),
+
+    #[error("i/o error while reading from stderr of formatting subcommand")]
+    Stderr(#[source] io::Error),
+
+    #[error("i/o error while waiting for formatting subcommand")]
+    Wait(#[source] io::Error),
+
+    #[error("command failed: {status}{}", match stderr.as_str() {
+        "" => String::new(),
+        stderr => format!("\n{stderr}"),
+    })]
+    Failed { status: ExitStatus, stderr: String },
+}
+
 /// Sometimes you just gotta use rustfmt
 pub fn prettify_with_subcommand(
     command_name: &Path,
+    settings: Option<&RustfmtSettings>,
     formatted_use_items: &str,
-) -> anyhow::Result<Vec<u8>> {
-    let mut command = Command::new(command_name)
+) -> Result<Vec<u8>, FormatterError> {
+    let mut command = formatter_command(command_name)
+        .args(settings.map(RustfmtSettings::args).unwrap_or_default())
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .spawn()
-        .with_context(|| format!("failed to launch formatting subcommand"))?;
+        .map_err(FormatterError::Launch)?;
 
     let mut stdin = command
         .stdin
@@ -84,6 +395,11 @@ pub fn prettify_with_subcommand(
         .take()
         .expect("stdout was piped, it shouldn't be None");
 
+    let mut stderr = command
+        .stderr
+        .take()
+        .expect("stderr was piped, it shouldn't be None");
+
     // Prevent deadlocks: use some threads to handle reading and writing in
     // parallel.
 
@@ -95,33 +411,264 @@ pub fn prettify_with_subcommand(
         let stdout_thread = scope.spawn(move || {
             let mut output = Vec::with_capacity(formatted_use_items.len());
             stdout.read_to_end(&mut output).map(move |_| {
+                // Some formatters (like rustfmt on Windows, by default) write
+                // `\r\n` line endings. Everything is `\n` until it's written
+                // into the file, where it gets the file's line endings.
+                let mut output = lf_line_endings(&output);
+
                 // Always add an extra newline at the end
                 output.push(b'\n');
                 output
             })
         });
 
+        // stderr thread. We only care about stderr if the command fails, in
+        // which case it probably explains why.
+        let stderr_thread = scope.spawn(move || {
+            let mut output = String::new();
+            stderr.read_to_string(&mut output).map(move |_| output)
+        });
+
         // Await the command, then join the threads.
-        let status = command.wait().expect("commands can always be joined");
+        let status = command.wait().map_err(FormatterError::Wait)?;
+
+        let stderr = stderr_thread
+            .join()
+            .unwrap_or_else(|panic| panic::resume_unwind(panic))
+            .map_err(FormatterError::Stderr)?;
 
         if !status.success() {
-            anyhow::bail!("command failed: {status}");
+            return Err(FormatterError::Failed {
+                status,
+                stderr: stderr.trim().to_owned(),
+            });
         }
 
         stdin_thread
             .join()
             .unwrap_or_else(|panic| panic::resume_unwind(panic))
-            .with_context(|| {
-                format!("i/o error while writing to stdin of formatting subcommand")
-            })?;
+            .map_err(FormatterError::Stdin)?;
 
         // The stdout thread will directly return the output, so just propagate
         // it directly
         stdout_thread
             .join()
             .unwrap_or_else(|panic| panic::resume_unwind(panic))
-            .with_context(|| {
-                format!("i/o error while reading from stdout of formatting subcommand")
-            })
+            .map_err(FormatterError::Stdout)
     })
 }
+
+/// Replace every `\r\n` line ending with `\n`
+fn lf_line_endings(text: &[u8]) -> Vec<u8> {
+    text.split_inclusive(|&b| b == b'\n')
+        .flat_map(|line| match line.strip_suffix(b"\r\n") {
+            Some(line) => [line, b"\n"],
+            None => [line, b""],
+        })
+        .flatten()
+        .copied()
+        .collect()
+}
+
+/// Check that the output of an external formatter is plausible: it should
+/// be valid rust, and it shouldn't have lost all of the use items. We can't
+/// check much more than that, since formatters are allowed to reorder and
+/// even merge use items.
+pub fn validate_formatted_use_items(
+    formatted_use_items: &str,
+    prettified_use_items: &[u8],
+) -> Result<(), InvalidOutputError> {
+    let prettified_use_items =
+        str::from_utf8(prettified_use_items).map_err(InvalidOutputError::Utf8)?;
+
+    let parsed = syn::parse_file(prettified_use_items).map_err(InvalidOutputError::Rust)?;
+
+    if parsed.items.is_empty() && !formatted_use_items.trim().is_empty() {
+        return Err(InvalidOutputError::Empty);
+    }
+
+    Ok(())
+}
+
+/// The reason that the output of an external formatter was rejected (see
+/// `validate_formatted_use_items`)
+#[derive(thiserror::Error, Debug)]
+pub enum InvalidOutputError {
+    #[error("formatter output wasn't valid UTF-8")]
+    Utf8(#[source] Utf8Error),
+
+    #[error("formatter output wasn't valid rust")]
+    Rust(#[source] syn::Error),
+
+    #[error("formatter output didn't contain any use items")]
+    Empty,
+}
+
+/// Prettify a series of sections rendered for `cfg_if!` mode (see
+/// `PrintableUseItems::render_cfg_if_sections`). Each section is prettified
+/// on its own with `prettify`; conditional sections are then indented and
+/// wrapped in a `cfg_if!` block. Formatters won't touch the interior of a
+/// macro invocation, which is why we can't just prettify the whole thing at
+/// once.
+pub fn prettify_cfg_if_sections<E>(
+    sections: impl IntoIterator<Item = RenderedSection>,
+    mut prettify: impl FnMut(&str) -> Result<Vec<u8>, E>,
+) -> Result<Vec<u8>, E> {
+    let mut output = Vec::new();
+
+    for section in sections {
+        match section {
+            RenderedSection::Plain(body) => {
+                let prettified = prettify(&body)?;
+                output.extend_from_slice(prettified.trim_ascii_end());
+                output.push(b'\n');
+            }
+            RenderedSection::CfgIf { predicate, body } => {
+                let prettified = prettify(&body)?;
+
+                output.extend_from_slice(b"cfg_if::cfg_if! {\n");
+                output.extend_from_slice(format!("    if #[cfg({predicate})] {{\n").as_bytes());
+
+                output.extend(indent(prettified.trim_ascii_end(), "        "));
+                output.push(b'\n');
+
+                output.extend_from_slice(b"    }\n");
+                output.extend_from_slice(b"}\n");
+            }
+        }
+
+        output.push(b'\n');
+    }
+
+    Ok(output)
+}
+
+/// Add indentation to the start of every non-empty line in some prettified
+/// content. Used for use items that go inside of a block, like a `cfg_if!` or
+/// an inline module.
+pub fn indent(prettified: &[u8], indentation: &str) -> Vec<u8> {
+    let mut output = Vec::with_capacity(prettified.len());
+
+    for line in prettified.split_inclusive(|&b| b == b'\n') {
+        if !line.trim_ascii().is_empty() {
+            output.extend_from_slice(indentation.as_bytes());
+        }
+        output.extend_from_slice(line);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for a test, containing the given (empty) files.
+    /// The extensions of the files match the case of the ones in `PATHEXT`,
+    /// so that the tests pass on case-sensitive file systems.
+    fn test_dir(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("usefix-{}-{name}", std::process::id()));
+
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        files
+            .iter()
+            .for_each(|file| fs::write(dir.join(file), b"").unwrap());
+
+        dir
+    }
+
+    #[test]
+    fn windows_command_uses_pathext() {
+        let dir = test_dir("pathext", &["rustfmt.EXE", "fmt.BAT"]);
+        let search = dir.as_os_str();
+
+        assert_eq!(
+            resolve_windows_command(Path::new("rustfmt"), Some(".COM;.EXE"), Some(search)),
+            dir.join("rustfmt.EXE"),
+        );
+
+        assert_eq!(
+            resolve_windows_command(Path::new("fmt"), None, Some(search)),
+            dir.join("fmt.BAT"),
+        );
+
+        // An extension that isn't in PATHEXT isn't tried
+        assert_eq!(
+            resolve_windows_command(Path::new("fmt"), Some(".EXE"), Some(search)),
+            PathBuf::from("fmt"),
+        );
+    }
+
+    #[test]
+    fn windows_command_searches_path_in_order() {
+        let first = test_dir("path-first", &["other.exe"]);
+        let second = test_dir("path-second", &["rustfmt.EXE"]);
+        let third = test_dir("path-third", &["rustfmt.EXE"]);
+        let search = std::env::join_paths([&first, &second, &third]).unwrap();
+
+        assert_eq!(
+            resolve_windows_command(Path::new("rustfmt"), Some(".EXE"), Some(&search)),
+            second.join("rustfmt.EXE"),
+        );
+    }
+
+    #[test]
+    fn windows_command_with_extension() {
+        let dir = test_dir("extension", &["rustfmt.exe", "rustfmt.exe.CMD"]);
+
+        assert_eq!(
+            resolve_windows_command(
+                Path::new("rustfmt.exe"),
+                Some(".CMD"),
+                Some(dir.as_os_str())
+            ),
+            dir.join("rustfmt.exe"),
+        );
+    }
+
+    #[test]
+    fn windows_command_with_directory() {
+        let dir = test_dir("directory", &["rustfmt.EXE"]);
+        let other = test_dir("directory-other", &["rustfmt.EXE"]);
+
+        // A path to a command is resolved on its own, and never searched for
+        // in the PATH
+        assert_eq!(
+            resolve_windows_command(&dir.join("rustfmt"), Some(".EXE"), Some(other.as_os_str())),
+            dir.join("rustfmt.EXE"),
+        );
+
+        assert_eq!(
+            resolve_windows_command(&dir.join("missing"), Some(".EXE"), Some(other.as_os_str())),
+            dir.join("missing"),
+        );
+    }
+
+    #[test]
+    fn windows_command_not_found() {
+        let dir = test_dir("not-found", &[]);
+
+        assert_eq!(
+            resolve_windows_command(Path::new("rustfmt"), None, Some(dir.as_os_str())),
+            PathBuf::from("rustfmt"),
+        );
+        assert_eq!(
+            resolve_windows_command(Path::new("rustfmt"), None, None),
+            PathBuf::from("rustfmt"),
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_scripts_run_with_cmd() {
+        let dir = test_dir("scripts", &["fmt.CMD"]);
+        let command = formatter_command(&dir.join("fmt"));
+
+        assert!(command.get_program().eq_ignore_ascii_case("cmd"));
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [OsStr::new("/C"), dir.join("fmt.CMD").as_os_str()],
+        );
+    }
+}
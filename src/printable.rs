@@ -1,19 +1,21 @@
 use std::{
-    cmp::Ord,
-    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    cmp::{Ord, Ordering},
+    collections::{btree_map::Entry, BTreeMap},
     fmt::{self, Display, Formatter},
 };
 
 use itertools::Itertools;
-use syn::Ident;
 
 use crate::{
-    common::{NameUse, Rooted},
+    common::{Ident, NameUse, Rooted, Unraw, UsageOrder},
     flattened::{SingleUsedItem, UsedItemLeaf},
-    tree::{ConfigsList, DocsList, Visibility},
+    rules::{Criterion, PubPlacement, SortRules, StdLayout},
+    tree::{ConfigsList, DocAttrsList, DocsList, Visibility},
+    Error,
 };
 
 /// The list of things that can happen at path `a::b`
+#[derive(Clone)]
 enum PrintableChild<'a> {
     /// Just `a::b` or `a::b as c`
     Plain(NameUse<&'a Ident>),
@@ -35,7 +37,7 @@ impl<'a> PrintableChild<'a> {
         };
 
         *self = PrintableChild::Subtree(PrintableTree {
-            this_usage: BTreeSet::from([usage]),
+            this_usage: BTreeMap::from([(usage.order(), usage)]),
             wildcard: false,
             children: BTreeMap::new(),
         });
@@ -51,23 +53,33 @@ impl<'a> PrintableChild<'a> {
     /// the usage is added to it as a self usage.
     pub fn add_self_useage(&mut self, usage: NameUse<&'a Ident>) {
         if let Self::Plain(current_usage) = *self {
-            if current_usage == usage {
+            if current_usage.order() == usage.order() {
                 return;
             }
         }
 
         let tree = self.become_subtree();
-        tree.this_usage.insert(usage);
+        tree.this_usage.entry(usage.order()).or_insert(usage);
+    }
+
+    /// The number of `::` separators in the shortest path in this child
+    fn min_depth(&self) -> usize {
+        match *self {
+            PrintableChild::Plain(_) => 0,
+            PrintableChild::Subtree(ref tree) => tree.min_depth(),
+        }
     }
 }
 
 /// A printable tree is a collection of things that can appear inside {} in
 /// an import path. When printed, it will automatically include or omit the {},
 /// depending on if it contains one item or more than one item.
+#[derive(Clone)]
 pub struct PrintableTree<'a> {
     // Whether this tree contains a field called `self` or any fields
-    // called `self as rename`
-    this_usage: BTreeSet<NameUse<&'a Ident>>,
+    // called `self as rename`. Keyed by `UsageOrder` so that these are always
+    // printed in a consistent order.
+    this_usage: BTreeMap<UsageOrder<'a>, NameUse<&'a Ident>>,
 
     // Whether this tree contains a field called `*`
     wildcard: bool,
@@ -82,7 +94,7 @@ impl<'a> PrintableTree<'a> {
     // take care to `.add_path()` to it immediately after creation.
     fn new() -> Self {
         Self {
-            this_usage: BTreeSet::new(),
+            this_usage: BTreeMap::new(),
             wildcard: false,
             children: BTreeMap::new(),
         }
@@ -127,13 +139,294 @@ impl<'a> PrintableTree<'a> {
         }
     }
 
+    /// Create a new tree containing a single child subtree
+    fn new_from_child(ident: &'a Ident, child: PrintableChild<'a>) -> Self {
+        let mut this = Self::new();
+        this.children.insert(ident, child);
+        this
+    }
+
+    /// The identifiers along the first path in this tree, in the order that
+    /// paths are sorted inside of braces. `self` and `*` come before any of
+    /// the children, so they end the path.
+    fn first_path(&self) -> Vec<&'a Ident> {
+        let mut path = Vec::new();
+        let mut tree = self;
+
+        while tree.this_usage.is_empty() && !tree.wildcard {
+            let Some((&ident, child)) = tree.children.first_key_value() else {
+                break;
+            };
+
+            path.push(ident);
+
+            match *child {
+                PrintableChild::Plain(_) => break,
+                PrintableChild::Subtree(ref subtree) => tree = subtree,
+            }
+        }
+
+        path
+    }
+
+    /// The number of items in this tree. If there's more than one, the tree
+    /// is wrapped in braces when printed.
+    fn len(&self) -> usize {
+        self.this_usage.len() + usize::from(self.wildcard) + self.children.len()
+    }
+
+    /// The depth of nested braces that will be printed for this tree.
+    fn nesting(&self) -> usize {
+        let child_nesting = self
+            .children
+            .values()
+            .map(|child| match *child {
+                PrintableChild::Plain(_) => 0,
+                PrintableChild::Subtree(ref tree) => tree.nesting(),
+            })
+            .max()
+            .unwrap_or(0);
+
+        match self.len() > 1 {
+            true => child_nesting + 1,
+            false => child_nesting,
+        }
+    }
+
+    /// Split this tree into a list of trees, none of which are nested more
+    /// than `max_nesting` braces deep, which together contain all the same
+    /// paths as this tree. Each of the trees is intended to be printed as its
+    /// own use item. Subtrees that are too deep are detached from their
+    /// parent and become separate trees; if even that isn't enough (because
+    /// `max_nesting` is 0), the tree is split into each of its items.
+    fn limit_nesting(&self, max_nesting: usize) -> Vec<Self> {
+        if self.nesting() <= max_nesting {
+            return Vec::from([self.clone()]);
+        }
+
+        // Split the tree into each of its items. Each item is alone in its own
+        // tree, so it doesn't need braces.
+        if self.len() <= 1 || max_nesting == 0 {
+            let this_usages = self
+                .this_usage
+                .iter()
+                .map(|(&order, &usage)| PrintableTree {
+                    this_usage: BTreeMap::from([(order, usage)]),
+                    wildcard: false,
+                    children: BTreeMap::new(),
+                });
+
+            let wildcard = self.wildcard.then(|| PrintableTree {
+                this_usage: BTreeMap::new(),
+                wildcard: true,
+                children: BTreeMap::new(),
+            });
+
+            let children = self
+                .children
+                .iter()
+                .flat_map(|(&ident, child)| match *child {
+                    PrintableChild::Plain(usage) => {
+                        Vec::from([Self::new_from_child(ident, PrintableChild::Plain(usage))])
+                    }
+                    PrintableChild::Subtree(ref tree) => tree
+                        .limit_nesting(max_nesting)
+                        .into_iter()
+                        .map(|part| Self::new_from_child(ident, PrintableChild::Subtree(part)))
+                        .collect(),
+                });
+
+            return this_usages.chain(wildcard).chain(children).collect();
+        }
+
+        // Keep everything that fits inside of our own braces, and detach the
+        // subtrees that don't.
+        let mut kept = PrintableTree {
+            this_usage: self.this_usage.clone(),
+            wildcard: self.wildcard,
+            children: BTreeMap::new(),
+        };
+
+        let mut detached = Vec::new();
+
+        for (&ident, child) in &self.children {
+            match *child {
+                PrintableChild::Subtree(ref tree) if tree.nesting() >= max_nesting => detached
+                    .extend(Self::new_from_child(ident, child.clone()).limit_nesting(max_nesting)),
+                _ => {
+                    kept.children.insert(ident, child.clone());
+                }
+            }
+        }
+
+        let kept = match kept.len() {
+            0 => None,
+            _ => Some(kept),
+        };
+
+        kept.into_iter().chain(detached).collect()
+    }
+
+    /// Split the `self` usages out of this tree, and out of each of its
+    /// subtrees, which together contain all the same paths as this tree. Each
+    /// of the results is intended to be printed as its own use item, and
+    /// none of them contain `self`: a `self` usage of this tree becomes a
+    /// plain usage of this path, and a `self` usage of a subtree becomes a
+    /// plain child, like `a::{b::{self, c}, d}` becoming `a::{b, d}` and
+    /// `a::b::c`.
+    fn separate_self(&self) -> Vec<PrintableChild<'a>> {
+        let mut rest = PrintableTree {
+            this_usage: BTreeMap::new(),
+            wildcard: self.wildcard,
+            children: BTreeMap::new(),
+        };
+
+        let mut detached = Vec::new();
+
+        for (&ident, child) in &self.children {
+            let parts = match *child {
+                PrintableChild::Plain(_) => Vec::from([child.clone()]),
+                PrintableChild::Subtree(ref tree) => tree.separate_self(),
+            };
+
+            // The first part (a plain usage, if there are any) stays in our
+            // braces, and the rest become use items of their own
+            let mut parts = parts.into_iter();
+
+            if let Some(part) = parts.next() {
+                rest.children.insert(ident, part);
+            }
+
+            detached.extend(
+                parts.map(|part| PrintableChild::Subtree(Self::new_from_child(ident, part))),
+            );
+        }
+
+        let this_usages = self
+            .this_usage
+            .values()
+            .map(|&usage| PrintableChild::Plain(usage));
+
+        let rest = match rest.len() {
+            0 => None,
+            _ => Some(PrintableChild::Subtree(rest)),
+        };
+
+        this_usages.chain(rest).chain(detached).collect()
+    }
+
+    /// The number of paths in this tree: each `self`, `*`, and plain item,
+    /// at any depth.
+    fn paths(&self) -> usize {
+        let child_paths: usize = self
+            .children
+            .values()
+            .map(|child| match *child {
+                PrintableChild::Plain(_) => 1,
+                PrintableChild::Subtree(ref tree) => tree.paths(),
+            })
+            .sum();
+
+        self.this_usage.len() + usize::from(self.wildcard) + child_paths
+    }
+
+    /// Split this tree into a list of trees, none of which contain more than
+    /// `max_paths` paths (or a single path, if it's 0), which together contain
+    /// all the same paths as this tree. Each of the trees is intended to be
+    /// printed as its own use item. Items are packed into each tree in
+    /// order, until the next one doesn't fit; a subtree that's too big on its
+    /// own is split the same way, and each of its parts becomes a separate
+    /// tree.
+    fn limit_paths(&self, max_paths: usize) -> Vec<Self> {
+        let max_paths = max_paths.max(1);
+
+        if self.paths() <= max_paths {
+            return Vec::from([self.clone()]);
+        }
+
+        let mut parts = Vec::new();
+        let mut current = Self::new();
+
+        let add = |parts: &mut Vec<Self>, current: &mut Self, item: Self| {
+            if current.paths() + item.paths() > max_paths && current.len() > 0 {
+                parts.push(std::mem::replace(current, Self::new()));
+            }
+
+            current.this_usage.extend(item.this_usage);
+            current.wildcard |= item.wildcard;
+            current.children.extend(item.children);
+        };
+
+        for (&order, &usage) in &self.this_usage {
+            let item = PrintableTree {
+                this_usage: BTreeMap::from([(order, usage)]),
+                wildcard: false,
+                children: BTreeMap::new(),
+            };
+
+            add(&mut parts, &mut current, item);
+        }
+
+        if self.wildcard {
+            let item = PrintableTree {
+                this_usage: BTreeMap::new(),
+                wildcard: true,
+                children: BTreeMap::new(),
+            };
+
+            add(&mut parts, &mut current, item);
+        }
+
+        for (&ident, child) in &self.children {
+            match *child {
+                PrintableChild::Subtree(ref tree) if tree.paths() > max_paths => {
+                    if current.len() > 0 {
+                        parts.push(std::mem::replace(&mut current, Self::new()));
+                    }
+
+                    parts.extend(
+                        tree.limit_paths(max_paths)
+                            .into_iter()
+                            .map(|part| Self::new_from_child(ident, PrintableChild::Subtree(part))),
+                    );
+                }
+                _ => add(
+                    &mut parts,
+                    &mut current,
+                    Self::new_from_child(ident, child.clone()),
+                ),
+            }
+        }
+
+        if current.len() > 0 {
+            parts.push(current);
+        }
+
+        parts
+    }
+
+    /// The number of `::` separators in the shortest path in this tree
+    fn min_depth(&self) -> usize {
+        if !self.this_usage.is_empty() {
+            0
+        } else if self.wildcard {
+            1
+        } else {
+            self.children
+                .values()
+                .map(|child| child.min_depth() + 1)
+                .min()
+                .unwrap_or(0)
+        }
+    }
+
     /// Iterate over all of the items in the tree. Used during formatting.
     /// Essentially serves to unify the 3 kinds of item in the tree: regular
     /// items, the `self` item (and its renames), and the `*` item.
-    fn items(&self) -> impl Iterator<Item = PrintableItem<'_>> + '_ {
+    fn items(&self, options: &PrintOptions) -> impl Iterator<Item = PrintableItem<'_>> + '_ {
         let this_usages = self
             .this_usage
-            .iter()
+            .values()
             .map(|&this_usage| PrintableItem::Plain(BasicName::This, this_usage));
 
         let wildcard = if self.wildcard {
@@ -142,33 +435,75 @@ impl<'a> PrintableTree<'a> {
             None
         };
 
-        let children = self.children.iter().map(|(&ident, child)| match *child {
+        let (leading_wildcard, trailing_wildcard) = match options.wildcard_position {
+            WildcardPosition::First => (wildcard, None),
+            WildcardPosition::Last => (None, wildcard),
+        };
+
+        let mut children = self.children.iter().collect_vec();
+
+        if options.sort_by_alias {
+            // This is a stable sort, and the children are already sorted by
+            // name, so only the renamed children move
+            children.sort_by_key(|&(ident, child)| match *child {
+                PrintableChild::Plain(usage @ NameUse::Renamed(alias)) if !usage.is_anonymous() => {
+                    alias
+                }
+                _ => *ident,
+            });
+        }
+
+        if options.tree_order == TreeOrder::Depth {
+            // This is a stable sort, so children with the same depth are
+            // still sorted by name
+            children.sort_by_key(|(_, child)| child.min_depth());
+        }
+
+        let children = children.into_iter().map(|(&ident, child)| match *child {
             PrintableChild::Plain(usage) => PrintableItem::Plain(BasicName::Ident(ident), usage),
             PrintableChild::Subtree(ref tree) => PrintableItem::Tree { root: ident, tree },
         });
 
-        this_usages.chain(wildcard).chain(children)
+        this_usages
+            .chain(leading_wildcard)
+            .chain(children)
+            .chain(trailing_wildcard)
     }
-}
 
-impl Display for PrintableTree<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let items = self.items();
+    /// Create a printable version of this tree, rendered according to the
+    /// given options.
+    fn display<'s>(&'s self, options: &'s PrintOptions) -> impl Display + 's {
+        lazy_format::make_lazy_format!(|f| {
+            let items = self.items(options);
 
-        // God bless `itertools`
-        match items.exactly_one() {
-            Ok(item) => item.fmt(f),
-            Err(mut items) => {
-                f.write_str("{")?;
+            // God bless `itertools`. `a::{self}` can't be written as
+            // `a::self`, so it always keeps its braces.
+            match items.exactly_one() {
+                Ok(item)
+                    if options.collapse_single_item_braces
+                        && !matches!(item, PrintableItem::Plain(BasicName::This, _)) =>
+                {
+                    item.display(options).fmt(f)
+                }
+                Ok(item) => {
+                    let item = item.display(options);
+                    write!(f, "{{{item}}}")
+                }
+                Err(items) => {
+                    f.write_str("{")?;
 
-                items.try_for_each(|item| {
-                    item.fmt(f)?;
-                    f.write_str(",")
-                })?;
+                    items.enumerate().try_for_each(|(index, item)| {
+                        if index > 0 {
+                            f.write_str(",")?;
+                        }
 
-                f.write_str("}")
+                        item.display(options).fmt(f)
+                    })?;
+
+                    f.write_str("}")
+                }
             }
-        }
+        })
     }
 }
 
@@ -197,28 +532,239 @@ enum PrintableItem<'a> {
     },
 }
 
-impl Display for PrintableItem<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self {
+impl PrintableItem<'_> {
+    /// Create a printable version of this item, rendered according to the
+    /// given options.
+    fn display<'s>(&'s self, options: &'s PrintOptions) -> impl Display + 's {
+        lazy_format::make_lazy_format!(|f| match self {
             PrintableItem::Wildcard => f.write_str("*"),
             PrintableItem::Plain(name, NameUse::Used) => name.fmt(f),
             PrintableItem::Plain(name, NameUse::Renamed(renamed)) => {
                 write!(f, "{name} as {renamed}")
             }
-            PrintableItem::Tree { root, tree } => write!(f, "{root}::{tree}"),
+            PrintableItem::Tree { root, tree } => {
+                let tree = tree.display(options);
+                write!(f, "{root}::{tree}")
+            }
+        })
+    }
+}
+
+/// Where the `*` wildcard is placed among its siblings inside `{...}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum WildcardPosition {
+    /// Put the wildcard before the other items (but after `self`), like
+    /// `a::{self, *, b, c}`
+    #[default]
+    First,
+
+    /// Put the wildcard after all of the other items, like
+    /// `a::{self, b, c, *}`
+    Last,
+}
+
+/// How the items inside of `{...}` are ordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TreeOrder {
+    /// Items are sorted by name, like `a::{b::c, d, e::f}`
+    #[default]
+    Lexicographic,
+
+    /// Items with shorter paths come before items with deeper paths, like
+    /// `a::{d, b::c, e::f}`. Items with the same depth are sorted by name.
+    /// Subtrees are sorted by their shortest path.
+    Depth,
+}
+
+/// How an import of a module is written alongside imports of its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SelfImportStyle {
+    /// Fold the module into the braces with its contents, as `self`, like
+    /// `use a::b::{self, c};`
+    #[default]
+    Braces,
+
+    /// Import the module in a use item of its own, like `use a::b;` and
+    /// `use a::b::c;`
+    Separate,
+}
+
+/// Options controlling how use items are grouped, ordered, and rendered.
+#[derive(Debug, Clone, Copy)]
+pub struct PrintOptions {
+    /// If true, all anonymous imports (`use std::io::Write as _;`) are
+    /// collected into a single group at the end of the use items, rather than
+    /// being grouped with the other imports from the same crate.
+    pub trailing_anonymous_group: bool,
+
+    /// If true, all test-only imports (see `ConfigsList::requires_test`) are
+    /// collected into a single group after the other conditional imports,
+    /// rather than being ordered with them.
+    pub trailing_test_group: bool,
+
+    /// If true, root identifiers (`std`, `serde`, etc) are ordered by their
+    /// first appearance in the original file (see `RootOrder`), rather than
+    /// alphabetically. Locality groups are still respected.
+    pub preserve_root_order: bool,
+
+    /// Where to put the `*` wildcard inside of `{...}`.
+    pub wildcard_position: WildcardPosition,
+
+    /// How to order the items inside of `{...}`.
+    pub tree_order: TreeOrder,
+
+    /// If true, renamed items inside of `{...}` are sorted by their alias,
+    /// rather than by the name they're renamed from.
+    pub sort_by_alias: bool,
+
+    /// Whether a module is imported as `self` inside of the braces with its
+    /// contents, or on its own.
+    pub self_import: SelfImportStyle,
+
+    /// If given, use items are split into several use items such that none
+    /// of them contain braces nested more deeply than this.
+    pub max_nesting: Option<usize>,
+
+    /// If given, use items are split into several use items such that none
+    /// of them contain more paths than this.
+    pub max_paths: Option<usize>,
+
+    /// The rules for ordering use items and splitting them into groups
+    pub sort_rules: SortRules,
+
+    /// How to order sets of configs relative to each other
+    pub cfg_order: CfgOrder,
+
+    /// If true (the default), braces containing a single item are removed,
+    /// so that `a::{b}` is rendered as `a::b`.
+    pub collapse_single_item_braces: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            trailing_anonymous_group: false,
+            trailing_test_group: false,
+            preserve_root_order: false,
+            wildcard_position: WildcardPosition::default(),
+            tree_order: TreeOrder::default(),
+            sort_by_alias: false,
+            self_import: SelfImportStyle::default(),
+            max_nesting: None,
+            max_paths: None,
+            sort_rules: SortRules::default(),
+            cfg_order: CfgOrder::default(),
+            collapse_single_item_braces: true,
         }
     }
 }
 
+/// The relative order in which things (root identifiers, configs) first
+/// appeared in the original file. When a file is conflicted, the orders from
+/// both sides are union-merged: things that appear only on the right side are
+/// placed immediately after whichever thing preceded them on the right side.
+#[derive(Debug)]
+pub struct AppearanceOrder<K> {
+    ranks: BTreeMap<K, usize>,
+}
+
+/// The order in which root identifiers first appeared in the original file
+pub type RootOrder<'a> = AppearanceOrder<Unraw<'a>>;
+
+/// The order in which sets of configs first appeared in the original file
+pub type ConfigsOrder<'a> = AppearanceOrder<&'a ConfigsList>;
+
+impl<K: Ord + Copy> AppearanceOrder<K> {
+    /// Build an order from the sequences of things, in the order they appear
+    /// in the left and right versions of the file. Duplicates are fine; only
+    /// the first appearance counts.
+    pub fn from_sides(
+        left: impl IntoIterator<Item = K>,
+        right: impl IntoIterator<Item = K>,
+    ) -> Self {
+        let mut merged: Vec<K> = Vec::new();
+
+        for key in left {
+            if !merged.contains(&key) {
+                merged.push(key);
+            }
+        }
+
+        // The position in `merged` after which the next right-only key
+        // should be inserted
+        let mut insert_at = 0;
+
+        for key in right {
+            match merged.iter().position(|&existing| existing == key) {
+                Some(position) => insert_at = position + 1,
+                None => {
+                    merged.insert(insert_at, key);
+                    insert_at += 1;
+                }
+            }
+        }
+
+        Self {
+            ranks: merged
+                .into_iter()
+                .enumerate()
+                .map(|(rank, key)| (key, rank))
+                .collect(),
+        }
+    }
+
+    /// Get the rank of a key. Keys that never appeared in the original file
+    /// (which can happen thanks to normalization) sort last.
+    fn rank(&self, key: K) -> usize {
+        self.ranks.get(&key).copied().unwrap_or(usize::MAX)
+    }
+}
+
+/// How sets of configs (and therefore groups of conditional use items) are
+/// ordered relative to each other. Unconditional use items always come
+/// before conditional ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CfgOrder {
+    /// Alphabetically, by the text of the configs
+    #[default]
+    Alphabetical,
+
+    /// Simpler configs first, by the number of predicates they contain (so
+    /// `unix` comes before `all(unix, feature = "x")`), then alphabetically
+    Complexity,
+
+    /// By first appearance in the original file
+    Appearance,
+}
+
 /// A printable key associates a series of use paths that are grouped under
 /// a single `use` item
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PrintableKey<'a> {
+    /// If true, this use item contains only anonymous (`as _`) imports, which
+    /// are being collected into their own trailing group.
+    anonymous: bool,
+
+    /// If true, this use item contains only test-only imports, which are
+    /// being collected into their own group after the other conditional
+    /// imports.
+    test_only: bool,
     configs: &'a ConfigsList,
     rooted: Rooted,
     root_ident: &'a Ident,
+
+    /// The position of `root_ident` in the `RootOrder`, if the original order
+    /// is being preserved; otherwise, always 0.
+    root_rank: usize,
+
+    /// The position of `configs` according to the `CfgOrder`, if it isn't
+    /// alphabetical; otherwise, always 0. Always 0 for unconditional use
+    /// items.
+    configs_rank: usize,
     visibility: Option<&'a Visibility>,
     docs: &'a DocsList,
+    doc_attrs: &'a DocAttrsList,
+    sort_rules: SortRules,
 }
 
 impl PrintableKey<'_> {
@@ -238,24 +784,94 @@ impl PrintableKey<'_> {
             CrateLocalityKey::Dependency
         };
 
+        // With a `StdLayout` other than `Together`, the standard library
+        // crates are ranked within their locality
+        let std_rank = match (locality, self.sort_rules.std_layout) {
+            (CrateLocalityKey::StandardLib, StdLayout::Ordered | StdLayout::Split) => {
+                if self.root_ident == "core" {
+                    0
+                } else if self.root_ident == "alloc" {
+                    1
+                } else {
+                    2
+                }
+            }
+            _ => 0,
+        };
+
         UseItemSortKey {
+            anonymous: self.anonymous,
+            test_only: self.test_only,
             locality,
+            std_rank,
             configs: self.configs,
+            configs_rank: self.configs_rank,
+            docs: self.docs,
+            doc_attrs: self.doc_attrs,
+            visibility: self.visibility,
+            root_rank: self.root_rank,
             rooted: self.rooted,
             ident: self.root_ident,
-            docs: self.docs,
+            rules: &self.sort_rules,
         }
     }
+
+    /// Describe the properties of this key, for `usefix explain`
+    fn describe(&self) -> impl Display + '_ {
+        lazy_format::make_lazy_format!(|f| {
+            let rooted = match self.rooted {
+                Rooted::Rooted => "::",
+                Rooted::Unrooted => "",
+            };
+
+            write!(
+                f,
+                "root `{rooted}{}` ({:?})",
+                self.root_ident,
+                self.sort_key().locality
+            )?;
+
+            if !self.configs.is_empty() {
+                write!(f, ", cfg({})", self.configs.predicate())?;
+            }
+
+            if let Some(visibility) = self.visibility {
+                write!(f, ", {visibility}")?;
+            }
+
+            if !self.docs.blocks().is_empty() {
+                write!(f, ", {} doc blocks", self.docs.blocks().len())?;
+            }
+
+            for attr in self.doc_attrs.attrs() {
+                write!(f, ", doc({})", attr.as_str())?;
+            }
+
+            if self.test_only {
+                f.write_str(", test-only")?;
+            }
+
+            if self.anonymous {
+                f.write_str(", anonymous")?;
+            }
+
+            write!(
+                f,
+                ", root rank {}, configs rank {}",
+                self.root_rank, self.configs_rank
+            )
+        })
+    }
 }
 
 impl Ord for PrintableKey<'_> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    fn cmp(&self, other: &Self) -> Ordering {
         Ord::cmp(&self.sort_key(), &other.sort_key())
     }
 }
 
 impl PartialOrd for PrintableKey<'_> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
@@ -278,30 +894,122 @@ enum CrateLocalityKey {
     This,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-// Note that this is used as a sort key, so the order of these fields is
-// very important.
+/// The sort key for a use item. The order of use items, and how they're
+/// split into groups, is determined by the `SortRules`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct UseItemSortKey<'a> {
+    anonymous: bool,
+    test_only: bool,
     locality: CrateLocalityKey,
+
+    /// The position of a standard library crate according to the
+    /// `StdLayout`; always 0 for every other crate.
+    std_rank: u8,
     configs: &'a ConfigsList,
+    configs_rank: usize,
     docs: &'a DocsList,
+    doc_attrs: &'a DocAttrsList,
+    visibility: Option<&'a Visibility>,
+    root_rank: usize,
     rooted: Rooted,
     ident: &'a Ident,
+    rules: &'a SortRules,
 }
 
 impl UseItemSortKey<'_> {
+    /// Compare two use items by a single criterion
+    fn cmp_by(&self, other: &Self, criterion: Criterion) -> Ordering {
+        match criterion {
+            Criterion::Locality => Ord::cmp(
+                &(self.locality, self.std_rank),
+                &(other.locality, other.std_rank),
+            ),
+            // Unconditional imports come first
+            Criterion::Cfg => Ord::cmp(&other.configs.is_empty(), &self.configs.is_empty()),
+            Criterion::Configs => Ord::cmp(
+                &(self.configs_rank, self.configs),
+                &(other.configs_rank, other.configs),
+            ),
+            Criterion::Docs => {
+                Ord::cmp(&(self.docs, self.doc_attrs), &(other.docs, other.doc_attrs))
+            }
+            // Private imports come last, unless `pub` imports are placed last
+            Criterion::Visibility => {
+                let ordering = match (self.visibility, other.visibility) {
+                    (None, None) => Ordering::Equal,
+                    (None, Some(_)) => Ordering::Greater,
+                    (Some(_), None) => Ordering::Less,
+                    (Some(vis1), Some(vis2)) => Ord::cmp(vis1, vis2),
+                };
+
+                match self.rules.pub_placement {
+                    PubPlacement::Last => ordering.reverse(),
+                    PubPlacement::First | PubPlacement::Interleaved => ordering,
+                }
+            }
+            Criterion::Root => Ord::cmp(
+                &(self.root_rank, self.rooted, self.ident),
+                &(other.root_rank, other.rooted, other.ident),
+            ),
+        }
+    }
+
+    /// Check if two adjacent use items belong in different groups according
+    /// to a single criterion
+    fn is_grouped_apart_by(&self, previous: &Self, criterion: Criterion) -> bool {
+        match criterion {
+            // Anonymous imports all share a single group, regardless of
+            // locality.
+            Criterion::Locality => {
+                let std_split =
+                    self.rules.std_layout == StdLayout::Split && self.std_rank != previous.std_rank;
+
+                (self.locality != previous.locality || std_split) && !self.anonymous
+            }
+            Criterion::Cfg => self.configs.is_empty() != previous.configs.is_empty(),
+            Criterion::Configs => self.configs != previous.configs,
+            Criterion::Docs => self.docs.is_not_empty() || previous.docs.is_not_empty(),
+            Criterion::Visibility => self.visibility != previous.visibility,
+            Criterion::Root => (self.rooted, self.ident) != (previous.rooted, previous.ident),
+        }
+    }
+
     /// Determine if two use items should have a space inserted between them`
     fn is_spaced_from(&self, previous: &Self) -> bool {
-        // I'm expecting to mess with this a lot during testing.
-        if self.locality != previous.locality {
-            true
-        } else if self.configs.is_empty() != previous.configs.is_empty() {
-            true
-        } else if self.docs.is_not_empty() || previous.docs.is_not_empty() {
-            true
-        } else {
-            false
-        }
+        self.anonymous != previous.anonymous
+            || self.test_only != previous.test_only
+            || self
+                .rules
+                .group_by
+                .iter()
+                .any(|criterion| self.is_grouped_apart_by(previous, criterion))
+    }
+}
+
+impl Ord for UseItemSortKey<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Anonymous imports, if they're being collected, always come last,
+        // and test-only imports, if they're being collected, come just before
+        // them. After the configured criteria, we use every criterion as a
+        // tie breaker, so that distinct use items never compare equal.
+        let criteria = Iterator::chain(self.rules.sort_order.iter(), Criterion::ALL);
+
+        Ord::cmp(
+            &(self.anonymous, self.test_only),
+            &(other.anonymous, other.test_only),
+        )
+        .then_with(|| {
+            criteria
+                .map(|criterion| self.cmp_by(other, criterion))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+    }
+}
+
+impl PartialOrd for UseItemSortKey<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -310,17 +1018,89 @@ impl UseItemSortKey<'_> {
 /// newlines as appropriate between all of the attributes, but does not
 /// include indentation or interior newlines (we rely on a separate rustfmt
 /// pass to correctly indent everything).
+///
+/// If `include_configs` is false, the `#[cfg(...)]` attributes are omitted;
+/// this is used when the configs are instead being expressed by an enclosing
+/// `cfg_if!` block.
+///
+/// With `SelfImportStyle::Separate`, the `self` usages in the tree are split
+/// into use items of their own. If the tree is nested more deeply than
+/// `options.max_nesting`, or contains more paths than `options.max_paths`,
+/// it's split into several use items. Either way, all of them share the same
+/// attributes.
 fn format_use_item(
     dest: &mut impl fmt::Write,
+    options: &PrintOptions,
     key: &PrintableKey<'_>,
     tree: &PrintableChild<'_>,
+    include_configs: bool,
+) -> fmt::Result {
+    let parts = match *tree {
+        PrintableChild::Subtree(ref tree) if options.self_import == SelfImportStyle::Separate => {
+            tree.separate_self()
+        }
+        _ => Vec::from([tree.clone()]),
+    };
+
+    parts.iter().try_for_each(|part| match *part {
+        PrintableChild::Subtree(ref tree) => {
+            split_tree(options, tree).into_iter().try_for_each(|part| {
+                let part = PrintableChild::Subtree(part);
+                format_single_use_item(dest, options, key, &part, include_configs)
+            })
+        }
+        PrintableChild::Plain(_) => {
+            format_single_use_item(dest, options, key, part, include_configs)
+        }
+    })
+}
+
+/// Split a tree into several trees, to satisfy `options.max_nesting` and
+/// `options.max_paths` (see `format_use_item`).
+fn split_tree<'a>(options: &PrintOptions, tree: &PrintableTree<'a>) -> Vec<PrintableTree<'a>> {
+    let mut parts = match options.max_nesting {
+        Some(max_nesting) => tree.limit_nesting(max_nesting),
+        None => Vec::from([tree.clone()]),
+    };
+
+    // `limit_nesting` puts the subtrees it detaches after everything else.
+    // The parts all share a sort key, so they're put back in order by path,
+    // unless they're being ordered by depth anyway.
+    if options.tree_order != TreeOrder::Depth {
+        parts.sort_by_cached_key(|part| part.first_path());
+    }
+
+    match options.max_paths {
+        Some(max_paths) => parts
+            .iter()
+            .flat_map(|part| part.limit_paths(max_paths))
+            .collect(),
+        None => parts,
+    }
+}
+
+/// Write a single use item, without regard for `options.self_import`,
+/// `options.max_nesting`, or `options.max_paths`. See `format_use_item` for
+/// details.
+fn format_single_use_item(
+    dest: &mut impl fmt::Write,
+    options: &PrintOptions,
+    key: &PrintableKey<'_>,
+    tree: &PrintableChild<'_>,
+    include_configs: bool,
 ) -> fmt::Result {
     let docs = key.docs;
     write!(dest, "{docs}")?;
 
-    key.configs
-        .configs()
-        .try_for_each(|config| writeln!(dest, "{config}"))?;
+    if include_configs {
+        key.configs
+            .configs()
+            .try_for_each(|config| writeln!(dest, "{config}"))?;
+    }
+
+    key.doc_attrs
+        .attrs()
+        .try_for_each(|attr| writeln!(dest, "{attr}"))?;
 
     if let Some(visibility) = key.visibility {
         write!(dest, "{visibility} ")?;
@@ -341,11 +1121,60 @@ fn format_use_item(
         },
     };
 
+    let item = item.display(options);
     writeln!(dest, "{item};")
 }
 
+/// Write a series of use items (see `format_use_item`), adding blank lines
+/// between them as appropriate.
+fn format_use_items<'k, 'a: 'k>(
+    dest: &mut impl fmt::Write,
+    options: &PrintOptions,
+    items: impl IntoIterator<Item = (&'k PrintableKey<'a>, &'k PrintableChild<'a>)>,
+    include_configs: bool,
+) -> fmt::Result {
+    let mut items = items.into_iter();
+
+    let Some((first_key, first_child)) = items.next() else {
+        return Ok(());
+    };
+
+    // We use the sort key to determine when we should add additional
+    // newlines
+    let mut last_sort_key = first_key.sort_key();
+
+    format_use_item(dest, options, first_key, first_child, include_configs)?;
+
+    items.try_for_each(|(key, child)| {
+        let sort_key = key.sort_key();
+
+        if sort_key.is_spaced_from(&last_sort_key) {
+            writeln!(dest)?;
+        }
+
+        last_sort_key = sort_key;
+
+        format_use_item(dest, options, key, child, include_configs)
+    })
+}
+
+/// A section of rendered use items, produced by the `cfg_if!` rendering mode.
+/// Each section is formatted separately, since formatters generally don't
+/// touch the interior of macro invocations.
+pub enum RenderedSection {
+    /// Unconditional use items, which are emitted as-is
+    Plain(String),
+
+    /// Use items that all share the same configs. They should be wrapped in a
+    /// `cfg_if! { if #[cfg(predicate)] { ... } }` block.
+    CfgIf { predicate: String, body: String },
+}
+
 pub struct PrintableUseItems<'a> {
     items: BTreeMap<PrintableKey<'a>, PrintableChild<'a>>,
+    options: PrintOptions,
+    root_order: RootOrder<'a>,
+    configs_order: ConfigsOrder<'a>,
 }
 
 impl<'a> PrintableUseItems<'a> {
@@ -354,19 +1183,43 @@ impl<'a> PrintableUseItems<'a> {
         &mut self,
         docs: &'a DocsList,
         configs: &'a ConfigsList,
+        doc_attrs: &'a DocAttrsList,
         visibility: Option<&'a Visibility>,
         item: &'a SingleUsedItem<'a>,
-    ) {
+    ) -> Result<(), Error> {
         let mut path = item.path.iter().copied();
 
+        let anonymous = self.options.trailing_anonymous_group
+            && matches!(item.leaf, UsedItemLeaf::Plain(_, usage) if usage.is_anonymous());
+
+        let test_only = self.options.trailing_test_group && configs.requires_test();
+
+        let configs_rank = match self.options.cfg_order {
+            _ if configs.is_empty() => 0,
+            CfgOrder::Alphabetical => 0,
+            CfgOrder::Complexity => configs.complexity(),
+            CfgOrder::Appearance => self.configs_order.rank(configs),
+        };
+
+        let make_key = |root_ident| PrintableKey {
+            anonymous,
+            test_only,
+            configs,
+            docs,
+            doc_attrs,
+            visibility,
+            rooted: item.rooted,
+            root_ident,
+            root_rank: match self.options.preserve_root_order {
+                true => self.root_order.rank(Unraw(root_ident)),
+                false => 0,
+            },
+            configs_rank,
+            sort_rules: self.options.sort_rules,
+        };
+
         match path.next() {
-            Some(ident) => match self.items.entry(PrintableKey {
-                configs,
-                docs,
-                visibility,
-                rooted: item.rooted,
-                root_ident: ident,
-            }) {
+            Some(ident) => match self.items.entry(make_key(ident)) {
                 Entry::Vacant(entry) => {
                     entry.insert(PrintableChild::Subtree(PrintableTree::new_from_path(
                         path, &item.leaf,
@@ -378,18 +1231,13 @@ impl<'a> PrintableUseItems<'a> {
                 }
             },
             None => match item.leaf {
+                // Root wildcard imports are rejected at the parse step
                 UsedItemLeaf::Wildcard => {
-                    // Panic is okay here because we already rejected root
-                    // wildcard imports at the parse step
-                    panic!("can't add a wildcard import at the root level")
+                    return Err(Error::Internal {
+                        reason: "a glob import at the root of a path reached the printer",
+                    })
                 }
-                UsedItemLeaf::Plain(ident, usage) => match self.items.entry(PrintableKey {
-                    configs,
-                    docs,
-                    visibility,
-                    rooted: item.rooted,
-                    root_ident: ident,
-                }) {
+                UsedItemLeaf::Plain(ident, usage) => match self.items.entry(make_key(ident)) {
                     Entry::Vacant(entry) => {
                         entry.insert(PrintableChild::Plain(usage));
                     }
@@ -397,59 +1245,135 @@ impl<'a> PrintableUseItems<'a> {
                 },
             },
         }
+
+        Ok(())
     }
 
     /// We take a generic iterator here in order to account for the different
     /// normalization steps that we might perform in `usefix`. We don't want
     /// to couple `PrintableUseItems` to any particular normalized form.
     pub fn build_from_use_items(
+        options: PrintOptions,
+        root_order: RootOrder<'a>,
+        configs_order: ConfigsOrder<'a>,
         items: impl Iterator<
             Item = (
                 &'a DocsList,
                 &'a ConfigsList,
+                &'a DocAttrsList,
                 Option<&'a Visibility>,
                 &'a SingleUsedItem<'a>,
             ),
         >,
-    ) -> Self {
+    ) -> Result<Self, Error> {
         let mut this = Self {
             items: BTreeMap::new(),
+            options,
+            root_order,
+            configs_order,
         };
 
         items
             .into_iter()
-            .for_each(|(docs, configs, visibility, item)| {
-                this.add_single_used_item(docs, configs, visibility, item)
-            });
+            .try_for_each(|(docs, configs, doc_attrs, visibility, item)| {
+                this.add_single_used_item(docs, configs, doc_attrs, visibility, item)
+            })?;
 
-        this
+        Ok(this)
     }
-}
 
-impl Display for PrintableUseItems<'_> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let mut items = self.items.iter();
+    /// Render the use items as a series of sections, one for each distinct
+    /// set of configs, in the order that each set first appears. The use
+    /// items in each section with (non-empty) configs are rendered without
+    /// their `#[cfg(...)]` attributes, so that they can be wrapped in a
+    /// single `cfg_if!` block instead, even if they're sorted into several
+    /// places (for instance, by locality). Unconditional anonymous imports
+    /// being collected into a trailing group get a section of their own,
+    /// after all of the others.
+    pub fn render_cfg_if_sections(&self) -> Vec<RenderedSection> {
+        let mut sections: Vec<(&ConfigsList, bool, Vec<_>)> = Vec::new();
 
-        let Some((first_key, first_child)) = items.next() else {
-            return Ok(());
-        };
+        for (key, child) in &self.items {
+            let trailing = key.anonymous && key.configs.is_empty();
 
-        // We use the sort key to determine when we should add additional
-        // newlines
-        let mut last_sort_key = first_key.sort_key();
+            match sections.iter_mut().find(|(configs, is_trailing, _)| {
+                *configs == key.configs && *is_trailing == trailing
+            }) {
+                Some((_, _, items)) => items.push((key, child)),
+                None => sections.push((key.configs, trailing, Vec::from([(key, child)]))),
+            }
+        }
+
+        // This is a stable sort, so the other sections stay in order
+        sections.sort_by_key(|&(_, trailing, _)| trailing);
+
+        sections
+            .into_iter()
+            .map(|(configs, _, items)| {
+                let mut body = String::new();
 
-        format_use_item(f, first_key, first_child)?;
+                format_use_items(&mut body, &self.options, items, false)
+                    .expect("writing to a string is infallible");
 
-        items.try_for_each(|(key, child)| {
+                if configs.is_empty() {
+                    RenderedSection::Plain(body)
+                } else {
+                    RenderedSection::CfgIf {
+                        predicate: configs.predicate().to_string(),
+                        body,
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Render each use item separately, split into the same groups that are
+    /// separated by blank lines when everything is rendered at once.
+    pub fn render_groups(&self) -> Vec<Vec<String>> {
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        let mut last_sort_key = None;
+
+        for (key, child) in &self.items {
             let sort_key = key.sort_key();
 
-            if sort_key.is_spaced_from(&last_sort_key) {
-                writeln!(f)?;
+            match last_sort_key {
+                Some(ref last_sort_key) if !sort_key.is_spaced_from(last_sort_key) => {}
+                _ => groups.push(Vec::new()),
             }
 
-            last_sort_key = sort_key;
+            let mut rendered = String::new();
+            format_use_item(&mut rendered, &self.options, key, child, true)
+                .expect("writing to a string is infallible");
 
-            format_use_item(f, key, child)
-        })
+            groups
+                .last_mut()
+                .expect("there's always at least one group")
+                .push(rendered);
+
+            last_sort_key = Some(sort_key);
+        }
+
+        groups
+    }
+
+    /// Describe each use item's key, in order, along with the rendered use
+    /// item (see `usefix explain`)
+    pub fn explain_keys(&self) -> Vec<(String, String)> {
+        self.items
+            .iter()
+            .map(|(key, child)| {
+                let mut rendered = String::new();
+                format_use_item(&mut rendered, &self.options, key, child, true)
+                    .expect("writing to a string is infallible");
+
+                (key.describe().to_string(), rendered)
+            })
+            .collect()
+    }
+}
+
+impl Display for PrintableUseItems<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        format_use_items(f, &self.options, &self.items, true)
     }
 }
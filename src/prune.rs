@@ -0,0 +1,374 @@
+/*!
+`--prune-unused`: after merging, drop the imports that the compiler reports as
+unused. The union of both sides of a conflict often includes imports that are
+no longer needed, because the other side removed the code that used them.
+
+The merged file is temporarily written in place of the file at `--path`, and
+checked with `cargo check --message-format=json`; the original file is
+restored as soon as cargo is done. The file is checked twice, once normally
+and once with `cfg(test)` enabled (`--profile=test`), and only imports that
+are unused in both builds are dropped, so that imports used only by tests are
+kept. The `unused_imports` diagnostics are matched up with the imports in the
+merged file by their spans, and the file is merged again, with those imports
+discarded (see `MergeOptions::discard`). If cargo can't be run, or the merged
+file doesn't compile, a warning is printed and nothing is pruned.
+ */
+
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::Context;
+use proc_macro2::LineColumn;
+use syn::spanned::Spanned;
+use usefix::{ConflictedFile, Import, ImportLeaf, MergeOptions, Provenance};
+
+use crate::{
+    diagnostics::{Diagnostic, Level, MessageFormat},
+    json::Json,
+};
+
+/// The location of an unused import in the merged file, from a compiler
+/// diagnostic. Columns are zero-indexed, like `LineColumn`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct UnusedSpan {
+    start: LineColumn,
+    end: LineColumn,
+}
+
+/// Drop the unused imports from a merged file (see the module docs). If the
+/// merged file still contains conflicts, it can't be compiled, so it's
+/// returned unchanged.
+pub fn prune_unused_imports(
+    fixed: &str,
+    options: &MergeOptions,
+    format: MessageFormat,
+) -> anyhow::Result<String> {
+    let path = options
+        .path
+        .as_deref()
+        .context("--prune-unused requires --path")?;
+
+    let file = ConflictedFile::parse(fixed)?;
+    if file.contains_conflict() {
+        Diagnostic::new(
+            Level::Warning,
+            Some(path),
+            "the merged file still has conflicts; not pruning unused imports",
+        )
+        .emit(format);
+        return Ok(fixed.to_owned());
+    }
+
+    let replaced = ReplacedFile::write(path, fixed)?;
+    let unused = check_unused_imports(path);
+    replaced.restore()?;
+
+    let unused = match unused {
+        Ok(unused) => unused,
+        Err(err) => {
+            Diagnostic::new(
+                Level::Warning,
+                Some(path),
+                format!("{err:#}; not pruning unused imports"),
+            )
+            .emit(format);
+            return Ok(fixed.to_owned());
+        }
+    };
+
+    if unused.is_empty() {
+        return Ok(fixed.to_owned());
+    }
+
+    let parsed = syn::parse_file(fixed).context("merged file isn't valid rust")?;
+    let mut discard = Vec::new();
+    collect_unused_imports(&parsed.items, &[], &unused, &mut discard);
+
+    // The merged file doesn't contain any conflicts, so the second merge
+    // only drops the discarded imports
+    let options = MergeOptions {
+        require_conflict: false,
+        always_rewrite: true,
+        lines: None,
+        discard,
+        ..options.clone()
+    };
+
+    Ok(file.merge_imports(&options)?.render())
+}
+
+/// A file that has been temporarily overwritten, which is restored to its
+/// original content when this is dropped, even if that's because of an early
+/// return or a panic. Call `restore` to find out whether restoring it worked.
+pub struct ReplacedFile<'a> {
+    path: &'a Path,
+    original: Option<Vec<u8>>,
+}
+
+impl<'a> ReplacedFile<'a> {
+    /// Overwrite the file at `path` with `content`, keeping its original
+    /// content to restore later
+    pub fn write(path: &'a Path, content: &str) -> anyhow::Result<Self> {
+        let original = fs::read(path)
+            .with_context(|| format!("i/o error reading original file {}", path.display()))?;
+
+        // The guard exists before the write, in case the write fails partway
+        let replaced = Self {
+            path,
+            original: Some(original),
+        };
+
+        fs::write(path, content)
+            .with_context(|| format!("i/o error writing merged file to {}", path.display()))?;
+
+        Ok(replaced)
+    }
+
+    /// Restore the original content of the file
+    pub fn restore(mut self) -> anyhow::Result<()> {
+        self.restore_original()
+    }
+
+    fn restore_original(&mut self) -> anyhow::Result<()> {
+        match self.original.take() {
+            None => Ok(()),
+            Some(original) => fs::write(self.path, original).with_context(|| {
+                format!("i/o error restoring original file {}", self.path.display())
+            }),
+        }
+    }
+}
+
+impl Drop for ReplacedFile<'_> {
+    fn drop(&mut self) {
+        // There's nowhere to report an error to from here
+        let _ = self.restore_original();
+    }
+}
+
+/// Check the file at `path` (which has been replaced by the merged file)
+/// with cargo. Returns the spans of the imports that are unused in both the
+/// normal and the test build.
+fn check_unused_imports(path: &Path) -> anyhow::Result<Vec<UnusedSpan>> {
+    let normal = run_cargo_check(path, &[])?;
+    let test = run_cargo_check(path, &["--profile=test"])?;
+
+    Ok(normal
+        .into_iter()
+        .filter(|span| test.contains(span))
+        .collect())
+}
+
+/// The result of running `cargo check` on the package containing a file
+pub struct CargoCheck {
+    pub success: bool,
+
+    /// Every compiler diagnostic (the `message` of each `compiler-message`)
+    pub diagnostics: Vec<Json>,
+
+    /// The canonical path of the file
+    pub path: PathBuf,
+}
+
+impl CargoCheck {
+    /// The spans of a diagnostic that are in the checked file
+    pub fn spans_in_file<'a>(&'a self, diagnostic: &'a Json) -> impl Iterator<Item = &'a Json> {
+        diagnostic
+            .get("spans")
+            .and_then(Json::as_array)
+            .unwrap_or_default()
+            .iter()
+            .filter(|span| {
+                // File names are relative to the workspace root
+                span.get("file_name")
+                    .and_then(Json::as_str)
+                    .is_some_and(|file_name| self.path.ends_with(file_name))
+            })
+    }
+}
+
+/// Run `cargo check --message-format=json` in the directory containing
+/// `path`, which checks the package containing it, and collect the compiler
+/// diagnostics.
+pub fn cargo_check(path: &Path, args: &[&str]) -> anyhow::Result<CargoCheck> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let output = Command::new(&cargo)
+        .args(["check", "--message-format=json"])
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .with_context(|| format!("failed to run {}", cargo.to_string_lossy()))?;
+
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("couldn't find {}", path.display()))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    Ok(CargoCheck {
+        success: output.status.success(),
+        diagnostics: stdout
+            .lines()
+            .filter_map(|line| Json::parse(line).ok())
+            .filter(|message| {
+                message.get("reason").and_then(Json::as_str) == Some("compiler-message")
+            })
+            .filter_map(|message| message.get("message").cloned())
+            .collect(),
+        path,
+    })
+}
+
+/// Run `cargo check` in the directory containing `path`, and collect the
+/// spans of all of the unused imports in that file.
+fn run_cargo_check(path: &Path, args: &[&str]) -> anyhow::Result<Vec<UnusedSpan>> {
+    let check = cargo_check(path, args)?;
+
+    if !check.success {
+        anyhow::bail!("cargo check failed on the merged file");
+    }
+
+    Ok(check
+        .diagnostics
+        .iter()
+        .filter(|diagnostic| {
+            diagnostic
+                .get("code")
+                .and_then(|code| code.get("code"))
+                .and_then(Json::as_str)
+                == Some("unused_imports")
+        })
+        .flat_map(|diagnostic| check.spans_in_file(diagnostic))
+        .filter_map(|span| {
+            let position = |line: &str, column: &str| {
+                Some(LineColumn {
+                    line: span.get(line)?.as_usize()?,
+                    column: span.get(column)?.as_usize()?.checked_sub(1)?,
+                })
+            };
+
+            Some(UnusedSpan {
+                start: position("line_start", "column_start")?,
+                end: position("line_end", "column_end")?,
+            })
+        })
+        .collect())
+}
+
+/// Find all of the imports in a list of items (including the items in
+/// inline modules) that are entirely covered by one of the unused spans.
+fn collect_unused_imports(
+    items: &[syn::Item],
+    module: &[String],
+    unused: &[UnusedSpan],
+    dest: &mut Vec<Import>,
+) {
+    for item in items {
+        match item {
+            syn::Item::Use(use_item) => collect_unused_leaves(
+                &use_item.tree,
+                use_item.leading_colon.is_some(),
+                &mut Vec::new(),
+                module,
+                unused,
+                dest,
+            ),
+            syn::Item::Mod(syn::ItemMod {
+                ident,
+                content: Some((_, items)),
+                ..
+            }) => {
+                let mut module = module.to_vec();
+                module.push(ident.to_string());
+                collect_unused_imports(items, &module, unused, dest);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_unused_leaves(
+    tree: &syn::UseTree,
+    rooted: bool,
+    prefix: &mut Vec<String>,
+    module: &[String],
+    unused: &[UnusedSpan],
+    dest: &mut Vec<Import>,
+) {
+    let (leaf, span) = match tree {
+        syn::UseTree::Path(path) => {
+            prefix.push(path.ident.to_string());
+            collect_unused_leaves(&path.tree, rooted, prefix, module, unused, dest);
+            prefix.pop();
+            return;
+        }
+        syn::UseTree::Group(group) => {
+            group
+                .items
+                .iter()
+                .for_each(|tree| collect_unused_leaves(tree, rooted, prefix, module, unused, dest));
+            return;
+        }
+        syn::UseTree::Name(name) => (ImportLeaf::Name(name.ident.to_string()), name.span()),
+        syn::UseTree::Rename(rename) => (
+            ImportLeaf::Renamed {
+                name: rename.ident.to_string(),
+                alias: rename.rename.to_string(),
+            },
+            rename.span(),
+        ),
+        syn::UseTree::Glob(glob) => (ImportLeaf::Wildcard, glob.span()),
+    };
+
+    let is_unused = unused
+        .iter()
+        .any(|unused| unused.start <= span.start() && span.end() <= unused.end);
+
+    if !is_unused {
+        return;
+    }
+
+    // `a::{self}` and `a::{self as b}` import `a` itself
+    let mut path = prefix.clone();
+    let mut leaf_name = |name: String| match name == "self" {
+        true => path.pop(),
+        false => Some(name),
+    };
+
+    let leaf = match leaf {
+        ImportLeaf::Name(name) => match leaf_name(name) {
+            Some(name) => ImportLeaf::Name(name),
+            None => return,
+        },
+        ImportLeaf::Renamed { name, alias } => match leaf_name(name) {
+            Some(name) => ImportLeaf::Renamed { name, alias },
+            None => return,
+        },
+        ImportLeaf::Wildcard => ImportLeaf::Wildcard,
+    };
+
+    dest.push(Import {
+        rooted,
+        path,
+        leaf,
+        cfgs: Vec::new(),
+        visibility: None,
+        docs: Vec::new(),
+        doc_attrs: Vec::new(),
+        provenance: Provenance {
+            module: module.to_vec(),
+            ..Provenance::default()
+        },
+    });
+}
@@ -0,0 +1,55 @@
+/*!
+Managed import regions (see `MergeOptions::managed_region`). A region is the
+lines between a `// usefix:begin` comment and the next `// usefix:end`
+comment, each on a line of its own. With managed regions, usefix owns the use
+items inside of them, and promises to leave every other use item alone.
+ */
+
+use std::ops::RangeInclusive;
+
+use crate::sections::ImportSection;
+
+pub const REGION_BEGIN: &str = "// usefix:begin";
+pub const REGION_END: &str = "// usefix:end";
+
+/// Find the (one-indexed) lines of the original file inside of each managed
+/// region, not including the markers themselves. A `begin` marker without a
+/// matching `end` marker is ignored.
+pub fn find_regions(source: &str) -> Vec<RangeInclusive<usize>> {
+    let mut regions = Vec::new();
+    let mut begin = None;
+
+    for (line, content) in Iterator::zip(1.., source.lines()) {
+        match content.trim() {
+            REGION_BEGIN => begin = Some(line),
+            REGION_END => {
+                if let Some(begin) = begin.take() {
+                    regions.push(begin + 1..=line - 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    regions
+}
+
+/// True if every use item in a section is inside of one of the regions
+pub fn section_in_regions(section: &ImportSection<'_>, regions: &[RangeInclusive<usize>]) -> bool {
+    section
+        .touched_original_lines()
+        .iter()
+        .all(|line| regions.iter().any(|region| region.contains(&line.get())))
+}
+
+/// Surround a block of formatted use items with the region markers.
+pub fn add_markers(formatted_use_items: &[u8], indentation: &str) -> Vec<u8> {
+    let formatted_use_items = formatted_use_items.trim_ascii_end();
+
+    format!("{indentation}{REGION_BEGIN}\n")
+        .into_bytes()
+        .into_iter()
+        .chain(formatted_use_items.iter().copied())
+        .chain(format!("\n{indentation}{REGION_END}\n").into_bytes())
+        .collect()
+}
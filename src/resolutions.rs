@@ -0,0 +1,300 @@
+/*!
+`--resolution-cache`: remember how conflicts were resolved, and replay those
+resolutions when the same conflicts come up again (like `git rerere`). This
+is mostly useful when a long-lived branch is rebased over and over, and the
+same import conflicts have to be resolved every time.
+
+Each time a file with conflicts is merged, its conflicts are identified by a
+fingerprint: a hash of the imports that appear on only one side of the
+conflict (along with their modules and sides, but not their lines, which
+change with every rebase). The first time a fingerprint is seen, the merged
+import model is recorded in the cache directory, as `<fingerprint>.json`.
+
+After the merged file has been adjusted by hand, `usefix remember <file>`
+records its imports as the resolution of every pending conflict recorded for
+that file (by its `--path`). The next time the same conflicts are merged,
+the adjustments between the recorded merge and the recorded resolution (see
+`ImportModel::diff`) are applied to the merged imports, and the imports of
+the file are replaced with the result (see `ConflictedFile::apply_model`).
+ */
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use usefix::{ConflictedFile, ImportModel, MergeOptions, MergeStrategy};
+
+use crate::{
+    diagnostics::{self, Diagnostic, Level, MessageFormat},
+    interchange,
+    json::Json,
+};
+
+/// A conflict recorded in the cache
+struct Entry {
+    /// The `--path` of the file the conflict was in
+    path: PathBuf,
+
+    /// The imports that usefix merged for the conflict
+    merged: ImportModel,
+
+    /// The imports that the conflict was eventually resolved to, if they've
+    /// been recorded with `usefix remember`
+    resolved: Option<ImportModel>,
+}
+
+impl Entry {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("path", self.path.to_string_lossy().into_owned().into()),
+            ("merged", interchange::import_model_to_json(&self.merged)),
+            (
+                "resolved",
+                match self.resolved {
+                    Some(ref resolved) => interchange::import_model_to_json(resolved),
+                    None => Json::Null,
+                },
+            ),
+        ])
+    }
+
+    fn from_json(json: &Json) -> anyhow::Result<Self> {
+        let path = json
+            .get("path")
+            .and_then(Json::as_str)
+            .context("missing 'path'")?;
+
+        let merged = json.get("merged").context("missing 'merged'")?;
+
+        Ok(Self {
+            path: PathBuf::from(path),
+            merged: interchange::import_model_from_json(merged)?,
+            resolved: match json.get("resolved") {
+                None | Some(Json::Null) => None,
+                Some(resolved) => Some(interchange::import_model_from_json(resolved)?),
+            },
+        })
+    }
+
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("i/o error reading {}", path.display()))?;
+
+        let json = Json::parse(&content)
+            .map_err(|err| anyhow::anyhow!("{err}"))
+            .with_context(|| format!("{} isn't valid JSON", path.display()))?;
+
+        Self::from_json(&json)
+            .with_context(|| format!("{} isn't a recorded conflict", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, format!("{}\n", self.to_json()))
+            .with_context(|| format!("i/o error writing {}", path.display()))
+    }
+}
+
+/// Merge a file, replaying the recorded resolution of its conflicts if
+/// there is one, or recording the conflicts if there isn't.
+pub fn fix_file(
+    cache: &Path,
+    content: &str,
+    options: &MergeOptions,
+    format: MessageFormat,
+) -> anyhow::Result<String> {
+    let file = match options.fragment {
+        true => ConflictedFile::parse_fragment(content)?,
+        false => ConflictedFile::parse(content)?,
+    };
+
+    if !file.contains_conflict() {
+        return merge(&file, options, format);
+    }
+
+    let path = options
+        .path
+        .as_deref()
+        .context("--resolution-cache requires --path")?;
+
+    options.validate()?;
+
+    let entry_path = cache.join(format!("{:016x}.json", fingerprint(&file, options)));
+    let merged = file.import_model(options);
+
+    if entry_path.exists() {
+        let entry = Entry::load(&entry_path)?;
+
+        if let Some(ref resolved) = entry.resolved {
+            let adjustments = entry.merged.diff(resolved, options.self_prefix);
+
+            Diagnostic::new(
+                Level::Note,
+                Some(path),
+                format!(
+                    "replaying the recorded resolution of {} ({} adjustments)",
+                    path.display(),
+                    adjustments.len()
+                ),
+            )
+            .emit(format);
+
+            let mut model = merged;
+            model.apply_changes(&adjustments, options.self_prefix);
+
+            return Ok(file.apply_model(&model, options)?.render());
+        }
+    }
+
+    fs::create_dir_all(cache).with_context(|| format!("i/o error creating {}", cache.display()))?;
+
+    Entry {
+        path: path.to_owned(),
+        merged,
+        resolved: None,
+    }
+    .save(&entry_path)?;
+
+    merge(&file, options, format)
+}
+
+/// Merge a file normally, printing any warnings
+fn merge(
+    file: &ConflictedFile<'_>,
+    options: &MergeOptions,
+    format: MessageFormat,
+) -> anyhow::Result<String> {
+    let merged = file.merge_imports(options)?;
+    diagnostics::emit_merge_warnings(&merged, options.path.as_deref(), format);
+
+    Ok(merged.render())
+}
+
+/// Record the imports of a (resolved) file as the resolution of every
+/// pending conflict recorded for it.
+pub fn remember(
+    cache: &Path,
+    path: &Path,
+    options: &MergeOptions,
+    format: MessageFormat,
+) -> anyhow::Result<()> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("i/o error reading {}", path.display()))?;
+
+    let file = match options.fragment {
+        true => ConflictedFile::parse_fragment(&content)?,
+        false => ConflictedFile::parse(&content)?,
+    };
+
+    if file.contains_conflict() {
+        anyhow::bail!("{} still contains conflicts", path.display());
+    }
+
+    options.validate()?;
+    let resolved = file.import_model(options);
+
+    let entries =
+        fs::read_dir(cache).with_context(|| format!("i/o error reading {}", cache.display()))?;
+
+    let mut remembered = 0;
+
+    for entry in entries {
+        let entry_path = entry
+            .with_context(|| format!("i/o error reading {}", cache.display()))?
+            .path();
+
+        if entry_path
+            .extension()
+            .is_none_or(|extension| extension != "json")
+        {
+            continue;
+        }
+
+        let mut entry = Entry::load(&entry_path)?;
+
+        if entry.resolved.is_some() || !same_file(&entry.path, path) {
+            continue;
+        }
+
+        entry.resolved = Some(resolved.clone());
+        entry.save(&entry_path)?;
+        remembered += 1;
+    }
+
+    if remembered == 0 {
+        anyhow::bail!("no pending conflicts were recorded for {}", path.display());
+    }
+
+    Diagnostic::new(
+        Level::Note,
+        Some(path),
+        format!(
+            "remembered the resolution of {remembered} conflict(s) in {}",
+            path.display()
+        ),
+    )
+    .emit(format);
+
+    Ok(())
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Identify the conflicts in a file, by the imports that appear on only one
+/// side. The imports are always merged as a union, so that every import from
+/// both sides is included.
+fn fingerprint(file: &ConflictedFile<'_>, options: &MergeOptions) -> u64 {
+    let options = MergeOptions {
+        strategy: MergeStrategy::Union,
+        prefer_side: None,
+        discard: Vec::new(),
+        ..options.clone()
+    };
+
+    let mut hash = Fnv::default();
+
+    for import in file
+        .import_model(&options)
+        .imports
+        .iter()
+        .filter(|import| !(import.provenance.left && import.provenance.right))
+    {
+        let side = match import.provenance.left {
+            true => "left",
+            false => "right",
+        };
+
+        hash.write(import.provenance.module.join("::").as_bytes());
+        hash.write(side.as_bytes());
+        hash.write(import.to_string().as_bytes());
+    }
+
+    hash.0
+}
+
+/// A 64-bit FNV-1a hash. Fingerprints are stored on disk, so they can't use
+/// `DefaultHasher`, which isn't stable across rust versions.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    /// Hash some bytes, followed by a terminator, so that consecutive writes
+    /// can't run together
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter().chain([&0xff]) {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
@@ -0,0 +1,305 @@
+/*!
+Declarative rules for ordering use items and splitting them into groups. A
+rule set is a pair of lists of criteria: one list describes the order in
+which use items are sorted, and the other describes which differences between
+adjacent use items cause them to be separated by a blank line.
+ */
+
+use std::fmt::{self, Display, Formatter};
+
+use clap::ValueEnum;
+
+/// A single property of a use item that can be used to order or group it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Criterion {
+    /// Where the imported crate comes from: the standard library, a
+    /// dependency, `crate`, `super`, or `self`, in that order.
+    Locality,
+
+    /// Whether the use item has any `#[cfg(...)]` attributes. Unconditional
+    /// use items come first.
+    Cfg,
+
+    /// The complete set of `#[cfg(...)]` attributes on the use item. When
+    /// grouping, every distinct set of configs forms its own group.
+    Configs,
+
+    /// The docs on the use item. When grouping, documented use items are
+    /// always separated from their neighbors.
+    Docs,
+
+    /// The visibility of the use item, from most public to least public.
+    Visibility,
+
+    /// The root identifier of the use item (`std`, `serde`, etc).
+    Root,
+}
+
+impl Criterion {
+    pub const ALL: [Criterion; 6] = [
+        Criterion::Locality,
+        Criterion::Cfg,
+        Criterion::Configs,
+        Criterion::Docs,
+        Criterion::Visibility,
+        Criterion::Root,
+    ];
+
+    /// Check if use items that are sorted by `self` will be clustered such
+    /// that grouping by `group` won't result in the same group appearing more
+    /// than once.
+    fn clusters(self, group: Criterion) -> bool {
+        self == group || (self == Criterion::Configs && group == Criterion::Cfg)
+    }
+}
+
+impl Display for Criterion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => f.write_str(value.get_name()),
+            None => write!(f, "{self:?}"),
+        }
+    }
+}
+
+/// An ordered list of distinct criteria. This is stored inline, rather than
+/// in a `Vec`, so that it can be freely copied along with the rest of the
+/// print options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CriteriaList {
+    criteria: [Criterion; Criterion::ALL.len()],
+    len: usize,
+}
+
+impl CriteriaList {
+    /// Create a new list of criteria. Fails if the same criterion appears
+    /// more than once.
+    fn new(criteria: &[Criterion]) -> Result<Self, InvalidRulesError> {
+        let mut list = Self {
+            criteria: Criterion::ALL,
+            len: 0,
+        };
+
+        for &criterion in criteria {
+            if list.contains(criterion) {
+                return Err(InvalidRulesError::Duplicate(criterion));
+            }
+
+            list.criteria[list.len] = criterion;
+            list.len += 1;
+        }
+
+        Ok(list)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Criterion> + '_ {
+        self.criteria[..self.len].iter().copied()
+    }
+
+    pub fn contains(&self, criterion: Criterion) -> bool {
+        self.iter().any(|existing| existing == criterion)
+    }
+
+    /// Insert a criterion immediately before another one, or at the end if
+    /// the other one isn't present. Does nothing if the criterion is already
+    /// present.
+    fn insert_before(&mut self, criterion: Criterion, before: Criterion) {
+        if self.contains(criterion) {
+            return;
+        }
+
+        let index = self
+            .iter()
+            .position(|existing| existing == before)
+            .unwrap_or(self.len);
+
+        self.criteria.copy_within(index..self.len, index + 1);
+        self.criteria[index] = criterion;
+        self.len += 1;
+    }
+}
+
+/// The complete set of rules for ordering and grouping use items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortRules {
+    /// The criteria by which use items are sorted, in order of priority. Use
+    /// items are always totally ordered: after these criteria, every other
+    /// criterion is used as a tie breaker.
+    pub sort_order: CriteriaList,
+
+    /// A blank line is inserted between adjacent use items that differ in
+    /// any of these criteria.
+    pub group_by: CriteriaList,
+
+    /// The direction in which use items are sorted by visibility.
+    pub pub_placement: PubPlacement,
+
+    /// How the standard library crates are ordered and grouped, within
+    /// their locality.
+    pub std_layout: StdLayout,
+}
+
+impl SortRules {
+    /// Create a new set of rules. Fails if either list contains duplicates,
+    /// or if use items are grouped by a criterion that they aren't sorted by
+    /// before every criterion they aren't grouped by (either of which would
+    /// cause the same group to appear in several places).
+    pub fn new(
+        sort_order: &[Criterion],
+        group_by: &[Criterion],
+    ) -> Result<Self, InvalidRulesError> {
+        let sort_order = CriteriaList::new(sort_order)?;
+        let group_by = CriteriaList::new(group_by)?;
+
+        // Docs groups are always separated from their neighbors, so they
+        // don't need to be clustered, and sorting by docs doesn't split any
+        // other groups.
+        let sorts: Vec<Criterion> = sort_order
+            .iter()
+            .filter(|&criterion| criterion != Criterion::Docs)
+            .collect();
+        let prefix_len = sorts
+            .iter()
+            .position(|sort| !group_by.iter().any(|group| sort.clusters(group)))
+            .unwrap_or(sorts.len());
+        let (prefix, rest) = sorts.split_at(prefix_len);
+
+        if let Some(group) = group_by
+            .iter()
+            .filter(|&criterion| criterion != Criterion::Docs)
+            .find(|&group| !prefix.iter().any(|sort| sort.clusters(group)))
+        {
+            return Err(match rest.first() {
+                Some(&sort) if rest.iter().any(|sort| sort.clusters(group)) => {
+                    InvalidRulesError::UngroupedSort { group, sort }
+                }
+                _ => InvalidRulesError::UnsortedGroup(group),
+            });
+        }
+
+        Ok(Self {
+            sort_order,
+            group_by,
+            pub_placement: PubPlacement::default(),
+            std_layout: StdLayout::default(),
+        })
+    }
+
+    /// Set where `pub use` items are placed relative to private use items.
+    /// Unless they're interleaved, this adds visibility to the sort order
+    /// immediately before the root identifier, so that it applies within
+    /// each group.
+    pub fn with_pub_placement(mut self, pub_placement: PubPlacement) -> Self {
+        if pub_placement != PubPlacement::Interleaved {
+            self.sort_order
+                .insert_before(Criterion::Visibility, Criterion::Root);
+        }
+
+        self.pub_placement = pub_placement;
+        self
+    }
+
+    /// Set how the standard library crates are ordered and grouped.
+    pub fn with_std_layout(mut self, std_layout: StdLayout) -> Self {
+        self.std_layout = std_layout;
+        self
+    }
+}
+
+/// Where `pub use` items are placed, relative to private use items, within
+/// a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum PubPlacement {
+    /// Public and private use items are sorted together, by their root
+    /// identifiers. If visibility is part of the sort order anyway, more
+    /// public use items come first.
+    #[default]
+    Interleaved,
+
+    /// More public use items come before less public ones
+    First,
+
+    /// More public use items come after less public ones
+    Last,
+}
+
+/// How the standard library crates (`core`, `alloc`, and `std`) are ordered
+/// and grouped relative to each other. They always share a locality, so this
+/// only applies when use items are sorted or grouped by locality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum StdLayout {
+    /// The standard library crates are sorted by name, in a single group
+    #[default]
+    Together,
+
+    /// The standard library crates are in a single group, ordered from the
+    /// most fundamental: `core`, then `alloc`, then `std`
+    Ordered,
+
+    /// Each standard library crate is in a group of its own, ordered like
+    /// `ordered`
+    Split,
+}
+
+impl Default for SortRules {
+    fn default() -> Self {
+        CfgLayout::default().rules()
+    }
+}
+
+/// Where conditional use items are placed relative to unconditional ones.
+/// Each layout is a preset set of `SortRules`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CfgLayout {
+    /// Conditional use items are collected into their own groups, after all
+    /// of the unconditional use items with the same locality
+    #[default]
+    Separate,
+
+    /// Conditional use items are placed immediately after the unconditional
+    /// use items for the same crate, in the same group
+    Adjacent,
+}
+
+impl CfgLayout {
+    pub fn sort_order(self) -> &'static [Criterion] {
+        use Criterion::*;
+
+        match self {
+            CfgLayout::Separate => &[Locality, Configs, Docs, Root],
+            CfgLayout::Adjacent => &[Locality, Docs, Root, Configs],
+        }
+    }
+
+    pub fn group_by(self) -> &'static [Criterion] {
+        use Criterion::*;
+
+        match self {
+            CfgLayout::Separate => &[Locality, Cfg, Docs],
+            CfgLayout::Adjacent => &[Locality, Docs],
+        }
+    }
+
+    pub fn rules(self) -> SortRules {
+        SortRules::new(self.sort_order(), self.group_by()).expect("preset rules are valid")
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum InvalidRulesError {
+    #[error("the criterion `{0}` appears more than once")]
+    Duplicate(Criterion),
+
+    #[error(
+        "use items are grouped by `{0}`, but they aren't sorted by it, so the \
+        same group would appear in several places; add `{0}` to the sort order"
+    )]
+    UnsortedGroup(Criterion),
+
+    #[error(
+        "use items are grouped by `{group}`, but they're sorted by `{sort}` (which \
+        they aren't grouped by) first, so the same group would appear in several \
+        places; move `{group}` before `{sort}` in the sort order"
+    )]
+    UngroupedSort { group: Criterion, sort: Criterion },
+}
@@ -0,0 +1,277 @@
+/*!
+Detection of import sections. Files often have more than one cluster of use
+items: a block at the top of the file, and another after some macro
+definitions, for instance. Each of these sections is merged and rewritten
+separately, in place, rather than hoisting everything to a single point.
+
+Within a single version of the file, a section is a run of use items that
+isn't interrupted by any other kind of item (except for `extern crate` items
+and `mod` declarations without bodies). The use items in each inline `mod`
+block form their own sections. Conflicted files have two versions,
+each with its own sections, so the sections from the two sides have to be
+matched up with each other:
+
+- Sections that share any lines of the original file (because they include
+  some of the same non-conflicted use items) are the same section.
+- Any remaining sections (which were entirely inside of conflicts) are paired
+  up in the order they appear, provided they're in the same inline module.
+ */
+
+use std::collections::HashSet;
+
+use crate::{
+    attrs::CrateAttr, gitfile::LineNumber, mods::ModDecl, write_file::Preamble, AnnotatedUseItem,
+};
+
+/// A set of use items (and, with `--mods`, module declarations), from both
+/// sides of the file, that are merged together and rewritten at a single
+/// point.
+pub struct ImportSection<'a> {
+    pub left: Vec<&'a AnnotatedUseItem>,
+    pub right: Vec<&'a AnnotatedUseItem>,
+}
+
+impl<'a> ImportSection<'a> {
+    /// All of the lines from the original file that include any part of a
+    /// use item in this section.
+    pub fn touched_original_lines(&self) -> HashSet<LineNumber> {
+        Iterator::chain(self.left.iter(), self.right.iter())
+            .flat_map(|item| &item.touched_original_lines)
+            .copied()
+            .collect()
+    }
+
+    /// The end of the inner attributes and docs of the module containing
+    /// this section, in each version of the file
+    pub fn preamble(&self) -> Preamble {
+        Preamble {
+            left: self.left.iter().filter_map(|item| item.preamble_end).max(),
+            right: self.right.iter().filter_map(|item| item.preamble_end).max(),
+        }
+    }
+
+    /// All of the module declarations in this section (see `mods.rs`)
+    pub fn mod_decls(&self) -> impl Iterator<Item = &'a ModDecl> + '_ {
+        Iterator::chain(self.left.iter(), self.right.iter()).filter_map(|item| item.item.mod_decl())
+    }
+
+    /// All of the crate attributes in this section (see `attrs.rs`)
+    pub fn crate_attrs(&self) -> impl Iterator<Item = &'a CrateAttr> + '_ {
+        Iterator::chain(self.left.iter(), self.right.iter())
+            .filter_map(|item| item.item.crate_attr())
+    }
+
+    /// The path to the inline module containing this section
+    pub fn module(&self) -> &[String] {
+        Iterator::chain(self.left.iter(), self.right.iter())
+            .next()
+            .map(|item| item.module.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The inline module containing this section, identified by its path and
+    /// the line where it starts (see `AnnotatedUseItem::module_start`)
+    fn module_key(&self) -> Option<ModuleKey<'a>> {
+        Iterator::chain(self.left.iter(), self.right.iter())
+            .next()
+            .map(|item| module_key(item))
+    }
+
+    /// The indentation of the use items in this section, based on the first
+    /// one
+    pub fn indentation(&self) -> &str {
+        Iterator::chain(self.left.iter(), self.right.iter())
+            .next()
+            .map(|item| item.indentation.as_str())
+            .unwrap_or_default()
+    }
+
+    fn first_line(&self) -> Option<LineNumber> {
+        Iterator::chain(self.left.iter(), self.right.iter())
+            .flat_map(|item| &item.touched_original_lines)
+            .copied()
+            .min()
+    }
+
+    fn overlaps(&self, lines: &HashSet<LineNumber>) -> bool {
+        self.left
+            .iter()
+            .flat_map(|item| &item.touched_original_lines)
+            .any(|line| lines.contains(line))
+    }
+}
+
+/// An inline module, identified by its path and the line in the original file
+/// where it starts, so that modules with the same name (under different
+/// `#[cfg(...)]` attributes) are kept apart
+type ModuleKey<'a> = (&'a [String], Option<LineNumber>);
+
+fn module_key(item: &AnnotatedUseItem) -> ModuleKey<'_> {
+    (item.module.as_slice(), item.module_start)
+}
+
+/// Split the use items from one side of the file into that side's sections.
+fn split_sections<'a>(
+    items: impl IntoIterator<Item = &'a AnnotatedUseItem>,
+) -> Vec<Vec<&'a AnnotatedUseItem>> {
+    let mut sections: Vec<Vec<&AnnotatedUseItem>> = Vec::new();
+
+    for item in items {
+        match sections.last_mut() {
+            Some(section) if section[0].section == item.section => section.push(item),
+            _ => sections.push(vec![item]),
+        }
+    }
+
+    sections
+}
+
+/// Find all of the sections in the file, matching up the sections from the
+/// left and right sides. The sections are returned in the order they appear
+/// in the original file.
+pub fn find_import_sections<'a>(
+    left: impl IntoIterator<Item = &'a AnnotatedUseItem>,
+    right: impl IntoIterator<Item = &'a AnnotatedUseItem>,
+) -> Vec<ImportSection<'a>> {
+    let mut sections: Vec<ImportSection<'a>> = split_sections(left)
+        .into_iter()
+        .map(|left| ImportSection {
+            left,
+            right: Vec::new(),
+        })
+        .collect();
+
+    let mut unmatched_right = Vec::new();
+
+    for right in split_sections(right) {
+        let lines: HashSet<LineNumber> = right
+            .iter()
+            .flat_map(|item| &item.touched_original_lines)
+            .copied()
+            .collect();
+
+        let overlapping: Vec<usize> = sections
+            .iter()
+            .enumerate()
+            .filter(|(_, section)| section.overlaps(&lines))
+            .map(|(index, _)| index)
+            .collect();
+
+        // A single right section might overlap several left sections (for
+        // instance, if the right side deleted whatever separated them), in
+        // which case they all become one section.
+        match overlapping.split_first() {
+            None => unmatched_right.push(right),
+            Some((&first, rest)) => {
+                let merged: Vec<ImportSection<'a>> = rest
+                    .iter()
+                    .rev()
+                    .map(|&index| sections.remove(index))
+                    .collect();
+
+                for merged in merged.into_iter().rev() {
+                    sections[first].left.extend(merged.left);
+                    sections[first].right.extend(merged.right);
+                }
+
+                sections[first].right.extend(right);
+            }
+        }
+    }
+
+    // Sections in the same module are paired up first, and then, in case the
+    // module itself starts inside of a conflict (so that it starts on a
+    // different line on each side), sections in a module with the same path
+    for right in unmatched_right {
+        let key = module_key(right[0]);
+
+        let index = sections
+            .iter()
+            .position(|section| section.right.is_empty() && section.module_key() == Some(key))
+            .or_else(|| {
+                sections
+                    .iter()
+                    .position(|section| section.right.is_empty() && section.module() == key.0)
+            });
+
+        match index.map(|index| &mut sections[index]) {
+            Some(section) => section.right = right,
+            None => sections.push(ImportSection {
+                left: Vec::new(),
+                right,
+            }),
+        }
+    }
+
+    sections.sort_by_key(|section| section.first_line());
+    sections
+}
+
+/// Create a single section for each inline module (including the top level
+/// of the file), containing every use item from both sides in that module.
+/// Modules are identified by where they start, as well as their paths (see
+/// `ModuleKey`). A module that starts inside of a conflict starts on a
+/// different line on each side, so use items from the right side whose
+/// module doesn't match any module on the left side go to a module with the
+/// same path instead.
+pub fn hoisted_import_sections<'a>(
+    left: impl IntoIterator<Item = &'a AnnotatedUseItem>,
+    right: impl IntoIterator<Item = &'a AnnotatedUseItem>,
+) -> Vec<ImportSection<'a>> {
+    let mut sections: Vec<ImportSection<'a>> = Vec::new();
+    let mut keys: Vec<ModuleKey<'a>> = Vec::new();
+
+    for item in left {
+        let key = module_key(item);
+
+        match keys.iter().position(|&existing| existing == key) {
+            Some(index) => sections[index].left.push(item),
+            None => {
+                keys.push(key);
+                sections.push(ImportSection {
+                    left: Vec::from([item]),
+                    right: Vec::new(),
+                });
+            }
+        }
+    }
+
+    // The sections that right-side modules that don't match any left-side
+    // module have been assigned to
+    let mut reassigned: Vec<(ModuleKey<'a>, usize)> = Vec::new();
+
+    for item in right {
+        let key = module_key(item);
+
+        let index = keys
+            .iter()
+            .position(|&existing| existing == key)
+            .or_else(|| {
+                reassigned
+                    .iter()
+                    .find(|&&(existing, _)| existing == key)
+                    .map(|&(_, index)| index)
+            })
+            .or_else(|| {
+                let index = Iterator::zip(keys.iter(), sections.iter()).position(
+                    |(&(module, _), section)| module == key.0 && section.right.is_empty(),
+                )?;
+                reassigned.push((key, index));
+                Some(index)
+            });
+
+        match index {
+            Some(index) => sections[index].right.push(item),
+            None => {
+                keys.push(key);
+                sections.push(ImportSection {
+                    left: Vec::new(),
+                    right: Vec::from([item]),
+                });
+            }
+        }
+    }
+
+    sections.sort_by_key(|section| section.first_line());
+    sections
+}
@@ -0,0 +1,298 @@
+/*!
+`--rustdoc-json`: semantic checks, using the JSON output of rustdoc
+(`cargo +nightly rustdoc -- -Z unstable-options --output-format json`). The
+merge algorithm is purely syntactic, so it has to guess about two things that
+rustdoc can answer:
+
+- Whether `a::*` actually exports `Foo`. Usually it does, and `use a::Foo` is
+  redundant next to `use a::*`, so it's discarded. If `a` is known not to
+  export `Foo` (because it's private, for instance), it's kept instead. This
+  is done with a `MergePolicy`.
+- Whether two glob imports export the same name, which makes any use of that
+  name ambiguous. The merged file is checked for pairs of globs that collide
+  like this, where at least one of them was only on one side of the conflict
+  (so that the collision was introduced by the merge), and a warning is
+  reported for each collision.
+
+Only paths starting with a crate name, or with `crate` (which is the crate of
+the first JSON file), can be resolved; for everything else, usefix behaves
+exactly as it would without `--rustdoc-json`.
+ */
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context};
+use usefix::{ConflictedFile, Import, ImportLeaf, MergeOptions, MergePolicy};
+
+use crate::{
+    diagnostics::{Diagnostic, Level},
+    json::Json,
+};
+
+/// The names exported by each module of a set of crates
+#[derive(Debug, Default)]
+pub struct ExportIndex {
+    /// The name of the crate that `crate::` refers to
+    local_crate: Option<String>,
+
+    /// The exports of each crate, by its name
+    crates: HashMap<String, ModuleExports>,
+}
+
+/// The names exported by each module of a crate, by their paths within the
+/// crate
+type ModuleExports = HashMap<Vec<String>, HashSet<String>>;
+
+impl ExportIndex {
+    /// Load a set of rustdoc JSON files. The first one is the local crate.
+    pub fn load(paths: &[impl AsRef<Path>]) -> anyhow::Result<Self> {
+        let mut index = Self::default();
+
+        for path in paths {
+            let path = path.as_ref();
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("i/o error reading {}", path.display()))?;
+
+            let json = Json::parse(&content)
+                .map_err(|err| anyhow::anyhow!("{err}"))
+                .with_context(|| format!("{} isn't valid JSON", path.display()))?;
+
+            let (name, modules) = crate_exports(&json)
+                .with_context(|| format!("{} isn't rustdoc JSON", path.display()))?;
+
+            index.local_crate.get_or_insert_with(|| name.clone());
+            index.crates.insert(name, modules);
+        }
+
+        Ok(index)
+    }
+
+    /// The names exported by the module at a path, if it's known
+    fn exports(&self, path: &[String]) -> Option<&HashSet<String>> {
+        let (root, path) = path.split_first()?;
+
+        let root = match unraw(root) {
+            "crate" => self.local_crate.as_deref()?,
+            root => root,
+        };
+
+        let path: Vec<String> = path
+            .iter()
+            .map(|segment| unraw(segment).to_owned())
+            .collect();
+        self.crates.get(root)?.get(&path)
+    }
+}
+
+fn unraw(ident: &str) -> &str {
+    ident.strip_prefix("r#").unwrap_or(ident)
+}
+
+/// A rustdoc item ID. Older versions of the format use strings, and newer
+/// ones use numbers.
+fn item_id(id: &Json) -> Option<String> {
+    match id {
+        Json::String(id) => Some(id.clone()),
+        Json::Number(_) => id.as_usize().map(|id| id.to_string()),
+        _ => None,
+    }
+}
+
+/// Get the name of a crate from its rustdoc JSON, along with the names
+/// exported by each of its modules.
+fn crate_exports(json: &Json) -> anyhow::Result<(String, ModuleExports)> {
+    let Some(index) = json.get("index") else {
+        bail!("missing 'index'");
+    };
+
+    let item = |id: &str| index.get(id);
+
+    let root = json
+        .get("root")
+        .and_then(item_id)
+        .and_then(|root| item(&root))
+        .context("missing root module")?;
+
+    let name = root
+        .get("name")
+        .and_then(Json::as_str)
+        .context("root module has no name")?
+        .to_owned();
+
+    let mut modules = HashMap::new();
+    let mut pending = vec![(Vec::new(), root)];
+
+    while let Some((path, module)) = pending.pop() {
+        // `None` if the module's exports aren't known
+        let mut exports = Some(HashSet::new());
+
+        for child in module_children(module).filter_map(|id| item(&id)) {
+            // Private items can't be glob imported from outside of their
+            // module
+            if child.get("visibility").and_then(Json::as_str) == Some("default") {
+                continue;
+            }
+
+            let inner = child.get("inner");
+
+            // Re-exports are `use` in newer versions of the format, and
+            // `import` in older ones
+            match inner.and_then(|inner| inner.get("use").or_else(|| inner.get("import"))) {
+                // We'd have to resolve glob re-exports, so we give up on
+                // the module's exports entirely
+                Some(reexport) if reexport.get("is_glob") == Some(&Json::Bool(true)) => {
+                    exports = None;
+                }
+                Some(reexport) => {
+                    if let (Some(exports), Some(name)) =
+                        (&mut exports, reexport.get("name").and_then(Json::as_str))
+                    {
+                        exports.insert(name.to_owned());
+                    }
+                }
+                None => {
+                    let Some(name) = child.get("name").and_then(Json::as_str) else {
+                        continue;
+                    };
+
+                    if let Some(ref mut exports) = exports {
+                        exports.insert(name.to_owned());
+                    }
+
+                    if inner.and_then(|inner| inner.get("module")).is_some() {
+                        let mut path = path.clone();
+                        path.push(name.to_owned());
+                        pending.push((path, child));
+                    }
+                }
+            }
+        }
+
+        if let Some(exports) = exports {
+            modules.insert(path, exports);
+        }
+    }
+
+    Ok((name, modules))
+}
+
+/// The IDs of the items in a module
+fn module_children(module: &Json) -> impl Iterator<Item = String> + '_ {
+    module
+        .get("inner")
+        .and_then(|inner| inner.get("module"))
+        .and_then(|module| module.get("items"))
+        .and_then(Json::as_array)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(item_id)
+}
+
+/// A `MergePolicy` that only lets a glob import subsume a named import if
+/// the glob is known to export that name (or if nothing is known about it)
+pub struct SemanticPolicy {
+    pub index: Arc<ExportIndex>,
+}
+
+impl MergePolicy for SemanticPolicy {
+    fn allow_subsumption(&self, import: &Import, parent: &Import) -> bool {
+        let name = match (&parent.leaf, &import.leaf) {
+            (ImportLeaf::Wildcard, ImportLeaf::Name(name))
+            | (ImportLeaf::Wildcard, ImportLeaf::Renamed { name, .. }) => name,
+            _ => return true,
+        };
+
+        self.index
+            .exports(&import.path)
+            .is_none_or(|exports| exports.contains(unraw(name)))
+    }
+}
+
+/// Create a warning for each pair of glob imports in the merged file that
+/// export the same name, if the collision was introduced by the merge.
+pub fn glob_collisions(
+    file: &ConflictedFile<'_>,
+    options: &MergeOptions,
+    index: &ExportIndex,
+) -> Vec<Diagnostic> {
+    let model = file.import_model(options);
+    let mut warnings = Vec::new();
+
+    // Names that are imported explicitly shadow any glob imports, so they
+    // aren't ambiguous
+    let named: HashSet<(&[String], &str)> = model
+        .imports
+        .iter()
+        .filter_map(|import| match import.leaf {
+            ImportLeaf::Name(ref name) => Some((import.provenance.module.as_slice(), unraw(name))),
+            ImportLeaf::Renamed { ref alias, .. } => {
+                Some((import.provenance.module.as_slice(), unraw(alias)))
+            }
+            ImportLeaf::Wildcard => None,
+        })
+        .collect();
+
+    let globs: Vec<&Import> = model
+        .imports
+        .iter()
+        .filter(|import| import.leaf == ImportLeaf::Wildcard)
+        .collect();
+
+    for (i, first) in globs.iter().enumerate() {
+        for second in &globs[i + 1..] {
+            let module = first.provenance.module.as_slice();
+
+            let from_merge = |import: &Import| !(import.provenance.left && import.provenance.right);
+
+            if module != second.provenance.module
+                || first.cfgs != second.cfgs
+                || !(from_merge(first) || from_merge(second))
+            {
+                continue;
+            }
+
+            let (Some(first_exports), Some(second_exports)) =
+                (index.exports(&first.path), index.exports(&second.path))
+            else {
+                continue;
+            };
+
+            let collisions: BTreeSet<&str> = first_exports
+                .intersection(second_exports)
+                .map(String::as_str)
+                .filter(|&name| !named.contains(&(module, name)))
+                .collect();
+
+            for name in collisions {
+                let location = match module {
+                    [] => String::new(),
+                    module => format!(" (in module {})", module.join("::")),
+                };
+
+                let message = format!(
+                    "`{}::*` and `{}::*` both export `{name}`{location}, so uses of it are ambiguous",
+                    first.path.join("::"),
+                    second.path.join("::"),
+                );
+
+                // Point at the glob that came from the merge
+                let line = [first, second]
+                    .into_iter()
+                    .filter(|import| from_merge(import))
+                    .find_map(|import| import.provenance.lines.first().copied())
+                    .unwrap_or(1);
+
+                warnings.push(
+                    Diagnostic::new(Level::Warning, options.path.as_deref(), message).at(line, 1),
+                );
+            }
+        }
+    }
+
+    warnings
+}
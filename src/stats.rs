@@ -0,0 +1,238 @@
+/*!
+`usefix stats`: aggregate statistics about the imports of every rust file in a
+directory tree, to help decide on import policies. Each file is parsed (and
+merged, if it contains conflicts) into an import model, exactly like `usefix
+extract`; files that can't be parsed are skipped, with a warning. Hidden
+directories and `target` directories are skipped entirely, and so are files
+and directories that git ignores, unless `--no-ignore` is given.
+ */
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use itertools::Itertools;
+use usefix::{ConflictedFile, Import, ImportLeaf, MergeOptions};
+
+use crate::{
+    diagnostics::{Diagnostic, Level, MessageFormat},
+    git,
+};
+
+#[derive(Debug, Default)]
+struct Stats {
+    files: usize,
+    skipped_files: usize,
+    imports: usize,
+    glob_imports: usize,
+    cfg_imports: usize,
+
+    /// The number of imports from each crate (by the first segment of the
+    /// path)
+    crates: HashMap<String, usize>,
+
+    /// For each imported path (as it would be written in a single use
+    /// item), the modules that import it
+    modules: HashMap<String, Vec<String>>,
+}
+
+/// Collect statistics for every rust file under `root`, and render a report
+/// with the `top` most common entries of each list.
+pub fn repository_stats(
+    root: &Path,
+    top: usize,
+    no_ignore: bool,
+    options: &MergeOptions,
+    format: MessageFormat,
+) -> anyhow::Result<String> {
+    let paths = rust_files(root, no_ignore)?;
+
+    let mut stats = Stats::default();
+
+    for path in &paths {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("i/o error reading {}", path.display()))?;
+
+        let file = match ConflictedFile::parse(&content) {
+            Ok(file) => file,
+            Err(err) => {
+                let diagnostic = Diagnostic::new(
+                    Level::Warning,
+                    Some(path),
+                    format!("skipping {}: {err}", path.display()),
+                );
+
+                match err.location() {
+                    Some((line, column)) => diagnostic.at(line, column),
+                    None => diagnostic,
+                }
+                .emit(format);
+                stats.skipped_files += 1;
+                continue;
+            }
+        };
+
+        stats.files += 1;
+        let relative = path.strip_prefix(root).unwrap_or(path);
+
+        for import in file.import_model(options).imports {
+            stats.imports += 1;
+
+            if import.leaf == ImportLeaf::Wildcard {
+                stats.glob_imports += 1;
+            }
+
+            if !import.cfgs.is_empty() {
+                stats.cfg_imports += 1;
+            }
+
+            let root = import
+                .path
+                .first()
+                .map(String::as_str)
+                .or(match import.leaf {
+                    ImportLeaf::Name(ref name) | ImportLeaf::Renamed { ref name, .. } => Some(name),
+                    ImportLeaf::Wildcard => None,
+                });
+
+            if let Some(root) = root.filter(|root| !matches!(*root, "crate" | "self" | "super")) {
+                *stats.crates.entry(root.to_owned()).or_default() += 1;
+            }
+
+            let module = match import.provenance.module.as_slice() {
+                [] => relative.display().to_string(),
+                module => format!("{}::{}", relative.display(), module.join("::")),
+            };
+
+            // Docs, cfgs, and visibility don't matter here; we only care about
+            // where the same path is imported
+            let import = Import {
+                docs: Vec::new(),
+                doc_attrs: Vec::new(),
+                cfgs: Vec::new(),
+                visibility: None,
+                ..import
+            };
+
+            let path = import.to_string().trim_end().to_owned();
+            let modules = stats.modules.entry(path).or_default();
+
+            if !modules.contains(&module) {
+                modules.push(module);
+            }
+        }
+    }
+
+    Ok(render_stats(&stats, top))
+}
+
+/// Find all of the `.rs` files under a directory, in order, skipping hidden
+/// and `target` directories, and (unless `no_ignore` is set) anything that
+/// git ignores
+pub fn rust_files(dir: &Path, no_ignore: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let ignored = match no_ignore {
+        true => HashSet::new(),
+        false => git::ignored_paths(dir)?,
+    };
+
+    let mut paths = Vec::new();
+    collect_rust_files(dir, &ignored, &mut paths)?;
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn collect_rust_files(
+    dir: &Path,
+    ignored: &HashSet<PathBuf>,
+    dest: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let entries =
+        fs::read_dir(dir).with_context(|| format!("i/o error reading {}", dir.display()))?;
+
+    for entry in entries {
+        let entry = entry.with_context(|| format!("i/o error reading {}", dir.display()))?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("i/o error reading {}", path.display()))?;
+
+        if ignored.contains(&path) {
+            continue;
+        }
+
+        if file_type.is_dir() {
+            if !name.starts_with('.') && name != "target" {
+                collect_rust_files(&path, ignored, dest)?;
+            }
+        } else if file_type.is_file() && name.ends_with(".rs") {
+            dest.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn render_stats(stats: &Stats, top: usize) -> String {
+    let mut report = String::new();
+    write_stats(&mut report, stats, top).expect("writing to a string is infallible");
+    report
+}
+
+fn write_stats(dest: &mut impl fmt::Write, stats: &Stats, top: usize) -> fmt::Result {
+    let percent = |count: usize| match stats.imports {
+        0 => 0.0,
+        total => count as f64 * 100.0 / total as f64,
+    };
+
+    writeln!(dest, "files: {}", stats.files)?;
+    if stats.skipped_files > 0 {
+        writeln!(dest, "skipped files: {}", stats.skipped_files)?;
+    }
+    writeln!(dest, "imports: {}", stats.imports)?;
+    writeln!(
+        dest,
+        "glob imports: {} ({:.1}%)",
+        stats.glob_imports,
+        percent(stats.glob_imports)
+    )?;
+    writeln!(
+        dest,
+        "cfg-gated imports: {} ({:.1}%)",
+        stats.cfg_imports,
+        percent(stats.cfg_imports)
+    )?;
+
+    writeln!(dest, "\nmost imported crates:")?;
+    stats
+        .crates
+        .iter()
+        .sorted_by(|(a_name, a_count), (b_name, b_count)| {
+            Ord::cmp(b_count, a_count).then_with(|| Ord::cmp(a_name, b_name))
+        })
+        .take(top)
+        .try_for_each(|(name, count)| writeln!(dest, "{count:>8}  {name}"))?;
+
+    // Only imports that appear in more than one module are interesting
+    writeln!(dest, "\nimports duplicated across modules:")?;
+    stats
+        .modules
+        .iter()
+        .filter(|(_, modules)| modules.len() > 1)
+        .sorted_by(|(a_path, a_modules), (b_path, b_modules)| {
+            Ord::cmp(&b_modules.len(), &a_modules.len()).then_with(|| Ord::cmp(a_path, b_path))
+        })
+        .take(top)
+        .try_for_each(|(path, modules)| {
+            writeln!(dest, "{:>8}  {path}", modules.len())?;
+            modules
+                .iter()
+                .try_for_each(|module| writeln!(dest, "          {module}"))
+        })
+}
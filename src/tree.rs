@@ -6,17 +6,25 @@ and `use abc::def::self`.)
 */
 
 use std::{
+    cmp::Ordering,
     collections::{BTreeSet, HashMap, HashSet},
     fmt::{self, Display},
-    hash::Hash,
+    hash::{Hash, Hasher},
+    ops::RangeInclusive,
+    sync::Arc,
 };
 
+use itertools::Itertools;
 use joinery::JoinableIterator;
-use proc_macro2::Span;
+use proc_macro2::TokenStream;
 use syn::spanned::Spanned;
-use syn::{AttrStyle, Expr, ExprLit, Ident, Lit, Meta, Path, UseName, UseRename, UseTree};
+use syn::{punctuated::Punctuated, Token};
+use syn::{
+    AttrStyle, Expr, ExprLit, ExprMacro, Lit, Macro, MacroDelimiter, Meta, UseName, UseRename,
+    UseTree,
+};
 
-use crate::common::{NameUse, Rooted};
+use crate::common::{Ident, NameUse, Rooted};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Visibility {
@@ -26,51 +34,81 @@ pub enum Visibility {
     /// `pub(crate)`
     Crate,
 
-    /// `pub(self)`
-    This,
-
     /// `pub(super)`
     Super,
 
-    /// `pub(in PATH)`
-    In(Path),
+    /// `pub(in PATH)`. The path is stored as its segments; a leading `::`
+    /// isn't allowed here in any edition that usefix supports.
+    In(Vec<Ident>),
 }
 
 impl Visibility {
+    /// Parse a visibility into its shortest form, so that different
+    /// spellings of the same visibility are merged together: `pub(self)` and
+    /// `pub(in self)` are the same as private, so they're parsed as no
+    /// visibility at all, and `pub(in crate)` and `pub(in super)` are parsed
+    /// as `pub(crate)` and `pub(super)`.
     pub fn from_syn_vis(vis: syn::Visibility) -> Result<Option<Self>, CreateUseItemError> {
         match vis {
             syn::Visibility::Public(_) => Ok(Some(Visibility::Public)),
             syn::Visibility::Restricted(vis) => match vis.in_token {
-                Some(_) => Ok(Some(Visibility::In(*vis.path))),
-                None if vis.path.is_ident("crate") => Ok(Some(Visibility::Crate)),
-                None if vis.path.is_ident("self") => Ok(Some(Visibility::This)),
-                None if vis.path.is_ident("super") => Ok(Some(Visibility::Super)),
+                _ if vis.path.is_ident("crate") => Ok(Some(Visibility::Crate)),
+                _ if vis.path.is_ident("self") => Ok(None),
+                _ if vis.path.is_ident("super") => Ok(Some(Visibility::Super)),
+                Some(_) if vis.path.leading_colon.is_some() => {
+                    Err(CreateUseItemError::MalformedVisibility)
+                }
+
+                // We know, from the syn parser, that the path here doesn't
+                // have any fucky nonsense going on, so we can just keep the
+                // idents (for context, check out the `PathSegment` type for
+                // the fucky nonsense we're ignoring).
+                Some(_) => Ok(Some(Visibility::In(
+                    vis.path
+                        .segments
+                        .into_iter()
+                        .map(|segment| segment.ident.into())
+                        .collect(),
+                ))),
                 None => Err(CreateUseItemError::MalformedVisibility),
             },
             syn::Visibility::Inherited => Ok(None),
         }
     }
-}
 
-/// Create a printable version of a `Path`
-fn fmt_path(path: &Path) -> impl Display + '_ {
-    lazy_format::make_lazy_format!(|f| {
-        if path.leading_colon.is_some() {
-            write!(f, "::")?;
+    /// The rank of this visibility, from most public to least public
+    fn rank(&self) -> u8 {
+        match self {
+            Visibility::Public => 0,
+            Visibility::Crate => 1,
+            Visibility::In(_) => 2,
+            Visibility::Super => 3,
         }
+    }
+}
 
-        // We know, from the syn parser, that the path here doesn't have any
-        // fucky nonsense going on, so we can just write the idents (for
-        // context, check out the `PathSegment` type for the fucky nonsense
-        // we're ignoring).
-        let joined_segments = path
-            .segments
-            .iter()
-            .map(|segment| &segment.ident)
-            .join_with("::");
+/// Visibilities are ordered from most public to least public. `pub(in PATH)`
+/// visibilities are ordered by their paths.
+impl Ord for Visibility {
+    fn cmp(&self, other: &Self) -> Ordering {
+        Ord::cmp(&self.rank(), &other.rank()).then_with(|| match (self, other) {
+            (Visibility::In(path1), Visibility::In(path2)) => {
+                Ord::cmp(&fmt_path(path1).to_string(), &fmt_path(path2).to_string())
+            }
+            _ => Ordering::Equal,
+        })
+    }
+}
+
+impl PartialOrd for Visibility {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-        write!(f, "{joined_segments}")
-    })
+/// Create a printable version of the path of a `pub(in PATH)` visibility
+fn fmt_path(path: &[Ident]) -> impl Display + '_ {
+    path.iter().join_with("::")
 }
 
 impl Display for Visibility {
@@ -78,7 +116,6 @@ impl Display for Visibility {
         match self {
             Visibility::Public => write!(f, "pub"),
             Visibility::Crate => write!(f, "pub(crate)"),
-            Visibility::This => write!(f, "pub(self)"),
             Visibility::Super => write!(f, "pub(super)"),
             Visibility::In(path) => {
                 let path = fmt_path(path);
@@ -121,13 +158,51 @@ impl Branches {
             self.children.entry(location).or_default()
         }
     }
+
+    /// The number of imports in this subtree, including wildcards
+    fn import_count(&self) -> usize {
+        self.used.len()
+            + usize::from(self.wildcard)
+            + self
+                .children
+                .values()
+                .map(Branches::import_count)
+                .sum::<usize>()
+    }
 }
 
 /// The contents of a single `#[cfg(...)]`. Ideally this would contain a
 /// TokenStream, but we need to be able to use it as a key in a map sometimes.
-#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Config(String);
 
+impl Config {
+    /// Build a config from the tokens inside of a `#[cfg(...)]`, in its
+    /// canonical form (see `canonical_predicate`), so that configs that
+    /// only differ in their formatting are the same config. If the tokens
+    /// aren't a predicate we understand, they're kept as they are.
+    pub fn from_tokens(tokens: &TokenStream) -> Self {
+        let canonical = syn::parse2::<Meta>(tokens.clone())
+            .ok()
+            .and_then(|meta| canonical_predicate(&meta));
+
+        Self(canonical.unwrap_or_else(|| tokens.to_string()))
+    }
+
+    /// Parse a cfg predicate from its source, like `feature = "std"`, into
+    /// its canonical form. Unlike `from_tokens`, this fails if it isn't a
+    /// predicate we understand.
+    pub fn parse(source: &str) -> Option<Self> {
+        let meta = syn::parse_str::<Meta>(source).ok()?;
+        canonical_predicate(&meta).map(Self)
+    }
+
+    /// The predicate inside of the `#[cfg(...)]`
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 impl Display for Config {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let config = self.0.as_str();
@@ -151,6 +226,180 @@ impl ConfigsList {
     pub fn configs(&self) -> impl Iterator<Item = &Config> + '_ {
         self.0.iter()
     }
+
+    /// The total number of predicates in these configs. For instance,
+    /// `unix` has 1 and `all(unix, not(feature = "x"))` has 2.
+    pub fn complexity(&self) -> usize {
+        self.0
+            .iter()
+            .map(|config| match syn::parse_str(&config.0) {
+                Ok(meta) => meta_complexity(&meta),
+                Err(_) => 1,
+            })
+            .sum()
+    }
+
+    /// True if these configs only hold in test builds: if one of them is
+    /// `test`, or an `all(...)` that requires `test`.
+    pub fn requires_test(&self) -> bool {
+        self.0.iter().any(|config| match syn::parse_str(&config.0) {
+            Ok(meta) => meta_requires_test(&meta),
+            Err(_) => false,
+        })
+    }
+
+    /// Render these configs as a single `cfg` predicate: the predicate itself,
+    /// if there's only one, or `all(...)` if there are several.
+    pub fn predicate(&self) -> impl Display + '_ {
+        lazy_format::make_lazy_format!(|f| match self.0.iter().exactly_one() {
+            Ok(config) => f.write_str(&config.0),
+            Err(configs) => {
+                let joined = configs.map(|config| &config.0).join_with(", ");
+                write!(f, "all({joined})")
+            }
+        })
+    }
+}
+
+/// The contents of a single list-style doc attribute, like the `hidden` in
+/// `#[doc(hidden)]` or the `inline` in `#[doc(inline)]`. These are kept apart
+/// from the docs themselves, since they aren't documentation.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DocAttr(String);
+
+impl DocAttr {
+    /// The tokens inside of the `#[doc(...)]`
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for DocAttr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let attr = self.0.as_str();
+        write!(f, "#[doc({attr})]")
+    }
+}
+
+/// All of the list-style doc attributes attached to an item (see `DocAttr`)
+#[derive(Debug, Clone, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DocAttrsList(BTreeSet<DocAttr>);
+
+impl DocAttrsList {
+    pub fn new(attrs: impl IntoIterator<Item = String>) -> Self {
+        Self(attrs.into_iter().map(DocAttr).collect())
+    }
+
+    pub fn attrs(&self) -> impl Iterator<Item = &DocAttr> + '_ {
+        self.0.iter()
+    }
+
+    /// Get the contents of each attribute
+    pub fn to_vec(&self) -> Vec<String> {
+        self.0.iter().map(|attr| attr.0.clone()).collect()
+    }
+
+    /// Merge another set of doc attributes into this one. Doc attributes
+    /// are unioned, since each one is a separate instruction to rustdoc,
+    /// except that `inline` and `no_inline` can't be combined; if the sides
+    /// disagree about inlining, neither is kept, and rustdoc's default
+    /// applies.
+    pub fn union(&mut self, other: &Self) {
+        self.0.extend(other.0.iter().cloned());
+
+        let inline = DocAttr("inline".to_owned());
+        let no_inline = DocAttr("no_inline".to_owned());
+
+        if self.0.contains(&inline) && self.0.contains(&no_inline) {
+            self.0.remove(&inline);
+            self.0.remove(&no_inline);
+        }
+    }
+}
+
+/// Render a cfg predicate in its canonical form: with consistent spacing,
+/// without trailing commas, and with the operands of each `all(...)` and
+/// `any(...)` sorted and deduplicated. The operands of `not(...)` and any
+/// other list are left in their original order.
+fn canonical_predicate(meta: &Meta) -> Option<String> {
+    match meta {
+        Meta::Path(path) => path.get_ident().map(|ident| ident.to_string()),
+        Meta::NameValue(pair) => match pair.value {
+            Expr::Lit(ExprLit {
+                ref attrs,
+                lit: Lit::Str(ref value),
+            }) if attrs.is_empty() => {
+                let name = pair.path.get_ident()?;
+                Some(format!("{name} = {}", value.token()))
+            }
+            _ => None,
+        },
+        Meta::List(list) => {
+            let name = list.path.get_ident()?;
+
+            let mut operands: Vec<String> = list
+                .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+                .ok()?
+                .iter()
+                .map(canonical_predicate)
+                .collect::<Option<_>>()?;
+
+            if name == "all" || name == "any" {
+                operands.sort_unstable();
+                operands.dedup();
+            }
+
+            let operands = operands.join(", ");
+            Some(format!("{name}({operands})"))
+        }
+    }
+}
+
+/// Count the predicates in a single cfg predicate (see
+/// `ConfigsList::complexity`)
+fn meta_complexity(meta: &Meta) -> usize {
+    match meta {
+        Meta::Path(_) | Meta::NameValue(_) => 1,
+        Meta::List(list) => list
+            .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .map(|nested| nested.iter().map(meta_complexity).sum())
+            .unwrap_or(1),
+    }
+}
+
+/// Check if a single cfg predicate requires `test` (see
+/// `ConfigsList::requires_test`)
+fn meta_requires_test(meta: &Meta) -> bool {
+    match meta {
+        Meta::Path(path) => path.is_ident("test"),
+        Meta::List(list) if list.path.is_ident("all") => list
+            .parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)
+            .is_ok_and(|nested| nested.iter().any(meta_requires_test)),
+        Meta::List(_) | Meta::NameValue(_) => false,
+    }
+}
+
+/// A single block of docs.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DocBlock {
+    /// The content of a single `///` or `/** ... */` comment, or
+    /// `#[doc = "..."]` attribute
+    Text(String),
+
+    /// A macro call that produces the docs, like the
+    /// `include_str!("../README.md")` in
+    /// `#[doc = include_str!("../README.md")]`. It's kept exactly as it was
+    /// written (modulo spacing), without being expanded.
+    Expr(String),
+}
+
+impl DocBlock {
+    /// The text of this block, or the source of its expression
+    pub fn as_str(&self) -> &str {
+        match self {
+            DocBlock::Text(text) | DocBlock::Expr(text) => text,
+        }
+    }
 }
 
 /// The complete set of docs for an item.
@@ -158,71 +407,174 @@ impl ConfigsList {
 /// When parsing rust code, `///` and `/** ... */` comments are converted into
 /// `#[doc = "..."]` attributes. Each element in this list is a single one of
 /// these attributes.
-#[derive(Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Default, Clone)]
-pub struct DocsList(Vec<String>);
+///
+/// The same docs are attached to every import flattened from a use item, and
+/// compared every time two of those imports are merged, so the blocks are
+/// shared rather than copied, and their total length is computed up front.
+#[derive(Debug, Default, Clone)]
+pub struct DocsList {
+    blocks: Arc<Vec<DocBlock>>,
+
+    /// The total length of the blocks, in bytes
+    len: usize,
+
+    /// The label of the conflict half (like `HEAD` or `main`) that these
+    /// docs came from, when they're being tracked (see
+    /// `MergeOptions::mark_divergent_docs`). This doesn't participate in
+    /// comparisons.
+    origin: Option<Arc<str>>,
+}
 
 impl DocsList {
+    pub fn new(blocks: Vec<DocBlock>) -> Self {
+        Self {
+            len: blocks.iter().map(|block| block.as_str().len()).sum(),
+            blocks: Arc::new(blocks),
+            origin: None,
+        }
+    }
+
+    /// Attach the label of the conflict half these docs came from, so that
+    /// they're marked with it if they're combined with different docs from
+    /// somewhere else.
+    pub fn with_origin(self, origin: Option<Arc<str>>) -> Self {
+        Self { origin, ..self }
+    }
+
     /// Get the blocks for these docs. Each block is associated with a single
     /// `///` or `/** ... */` comment.
-    pub fn blocks(&self) -> &[String] {
-        &self.0
+    pub fn blocks(&self) -> &[DocBlock] {
+        &self.blocks
     }
 
-    /// Get a sequential iterator of all of the content of these docs, as
-    /// bytes.
-    fn bytes(&self) -> impl DoubleEndedIterator<Item = u8> + '_ {
-        self.0.iter().flat_map(|s| s.bytes())
+    /// Get the content of each block, as bytes
+    fn chunks(&self) -> impl DoubleEndedIterator<Item = &[u8]> + '_ {
+        self.blocks.iter().map(|block| block.as_str().as_bytes())
     }
 
     pub fn is_not_empty(&self) -> bool {
-        self.0.iter().any(|s| !s.is_empty())
-    }
-
-    /// The total length of these docs, in bytes
-    fn len(&self) -> usize {
-        self.0.iter().map(|s| s.len()).sum()
+        self.len != 0
     }
 
     /// Returns true if either `self` or `other` is a prefix of the other.
     fn either_prefix(&self, other: &Self) -> bool {
-        let mut self_bytes = self.bytes();
-        let mut other_bytes = other.bytes();
-
-        loop {
-            match (self_bytes.next(), other_bytes.next()) {
-                (Some(a), Some(b)) if a == b => {}
-                (None, _) => return true,
-                (_, None) => return true,
-                _ => return false,
-            }
-        }
+        chunks_agree(self.chunks(), other.chunks(), |chunk, len| {
+            chunk.split_at(len)
+        })
     }
 
     /// Returns true if either `self` or `other` is a suffix of the other.
     fn either_suffix(&self, other: &Self) -> bool {
-        let mut self_bytes = self.bytes().rev();
-        let mut other_bytes = other.bytes().rev();
-
-        loop {
-            match (self_bytes.next(), other_bytes.next()) {
-                (Some(a), Some(b)) if a == b => {}
-                (None, _) => return true,
-                (_, None) => return true,
-                _ => return false,
-            }
-        }
+        chunks_agree(self.chunks().rev(), other.chunks().rev(), |chunk, len| {
+            let (rest, tail) = chunk.split_at(chunk.len() - len);
+            (tail, rest)
+        })
     }
 
     /// Combine two docs. The algorithm here is pretty dumb: if either is a
     /// prefix or suffix of the other, we take the longer one. Otherwise, we
-    /// just concatenate them.
+    /// just concatenate them, and if they came from different conflict
+    /// halves, each one is preceded by a marker naming its half.
     pub fn combine(&mut self, other: &Self) {
-        if self.either_prefix(other) || self.either_suffix(other) {
-            if self.len() < other.len() {
+        let same_origin = self.origin == other.origin;
+
+        if self == other || self.either_prefix(other) || self.either_suffix(other) {
+            if self.len < other.len {
                 *self = other.clone()
             }
+        } else if same_origin {
+            Arc::make_mut(&mut self.blocks).extend(other.blocks.iter().cloned());
+            self.len += other.len;
+        } else {
+            let marker = |docs: &Self| {
+                docs.origin
+                    .as_ref()
+                    .map(|origin| DocBlock::Text(format!(" --- from branch '{origin}' ---")))
+            };
+
+            *self = Self::new(
+                Iterator::chain(
+                    marker(self).into_iter().chain(self.blocks.iter().cloned()),
+                    marker(other)
+                        .into_iter()
+                        .chain(other.blocks.iter().cloned()),
+                )
+                .collect(),
+            );
+        }
+
+        if !same_origin {
+            self.origin = None;
+        }
+    }
+}
+
+impl PartialEq for DocsList {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && (Arc::ptr_eq(&self.blocks, &other.blocks) || self.blocks == other.blocks)
+    }
+}
+
+impl Eq for DocsList {}
+
+impl PartialOrd for DocsList {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DocsList {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match Arc::ptr_eq(&self.blocks, &other.blocks) {
+            true => Ordering::Equal,
+            false => Ord::cmp(&self.blocks, &other.blocks),
+        }
+    }
+}
+
+impl Hash for DocsList {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.blocks.hash(state)
+    }
+}
+
+/// Compare two sequences of chunks of bytes, as if each sequence were
+/// concatenated, up to the end of the shorter one; returns true if they're
+/// equal up to that point. `split(chunk, len)` splits `len` bytes off of the
+/// chunk, returning them and the rest of the chunk: splitting off of the
+/// front compares prefixes, and splitting off of the back (with the chunks
+/// reversed) compares suffixes.
+fn chunks_agree<'a>(
+    mut a: impl Iterator<Item = &'a [u8]>,
+    mut b: impl Iterator<Item = &'a [u8]>,
+    split: impl Fn(&'a [u8], usize) -> (&'a [u8], &'a [u8]),
+) -> bool {
+    let mut a_chunk: &[u8] = &[];
+    let mut b_chunk: &[u8] = &[];
+
+    loop {
+        if a_chunk.is_empty() {
+            match a.next() {
+                Some(chunk) => a_chunk = chunk,
+                None => return true,
+            }
+        } else if b_chunk.is_empty() {
+            match b.next() {
+                Some(chunk) => b_chunk = chunk,
+                None => return true,
+            }
         } else {
-            self.0.extend(other.0.iter().cloned());
+            let len = usize::min(a_chunk.len(), b_chunk.len());
+            let (a_head, a_rest) = split(a_chunk, len);
+            let (b_head, b_rest) = split(b_chunk, len);
+
+            if a_head != b_head {
+                return false;
+            }
+
+            a_chunk = a_rest;
+            b_chunk = b_rest;
         }
     }
 }
@@ -240,65 +592,40 @@ pub struct UseItem {
     /// contain the stuff inside the parenthesis, for each #[cfg(THIS_STUFF)]
     pub configs: ConfigsList,
 
+    /// All of the list-style doc attributes, like `#[doc(hidden)]`, attached
+    /// to this `use`
+    pub doc_attrs: DocAttrsList,
+
     /// Any `pub`, `pub(crate)`, etc associated with this use
     pub visibility: Option<Visibility>,
 
     /// The tree of imports in the use item.
     pub children: HashMap<TreeRoot, Branches>,
 
-    /// The span of the syn Use Item from which this was generated
-    pub span: Span,
+    /// The root identifiers of the tree, in the order they first appear in
+    /// the source code
+    pub roots: Vec<Ident>,
+
+    /// The number of imports in the tree. Docs on a use item with a single
+    /// import are about that import in particular, rather than about a
+    /// whole group of them.
+    pub import_count: usize,
+
+    /// The (one-indexed) lines of the syn Use Item from which this was
+    /// generated
+    pub lines: RangeInclusive<usize>,
 }
 
 impl UseItem {
     pub fn from_syn_use_item(item: syn::ItemUse) -> Result<UseItem, CreateUseItemError> {
         let span = item.span();
+        let lines = span.start().line..=span.end().line;
 
-        let mut docs = Vec::new();
-        let mut configs = BTreeSet::new();
-
-        // Handle all attributes. Collect doc and cfg attributes, and reject
-        // items that have other attributes.
-        for attr in item.attrs {
-            if matches!(attr.style, AttrStyle::Inner(_)) {
-                return Err(CreateUseItemError::InnerAttributes);
-            }
-
-            match attr.meta {
-                Meta::List(attr) => {
-                    if !matches!(attr.delimiter, syn::MacroDelimiter::Paren(_)) {
-                        return Err(CreateUseItemError::UnrecognizedAttribute);
-                    }
-
-                    if attr.path.is_ident("cfg") {
-                        configs.insert(Config(attr.tokens.to_string()));
-                    } else {
-                        return Err(CreateUseItemError::UnrecognizedAttribute);
-                    }
-                }
-                Meta::NameValue(attr) => {
-                    if attr.path.is_ident("doc") {
-                        // Doc attributes should contain precisely a single string
-                        match attr.value {
-                            Expr::Lit(ExprLit {
-                                attrs,
-                                lit: Lit::Str(content),
-                            }) if attrs.is_empty() => {
-                                docs.push(content.value());
-                            }
-                            _ => return Err(CreateUseItemError::MalformedDocAttribute),
-                        }
-                    } else {
-                        return Err(CreateUseItemError::UnrecognizedAttribute);
-                    }
-                }
-                Meta::Path(_) => return Err(CreateUseItemError::UnrecognizedAttribute),
-            }
-        }
-
+        let (docs, configs, doc_attrs) = parse_attributes(item.attrs)?;
         let visibility = Visibility::from_syn_vis(item.vis)?;
 
         let mut children = HashMap::new();
+        let mut roots = Vec::new();
         build_use_item_children_root(
             item.tree,
             match item.leading_colon {
@@ -306,56 +633,195 @@ impl UseItem {
                 None => Rooted::Unrooted,
             },
             &mut children,
+            &mut roots,
         )?;
 
         Ok(Self {
-            docs: DocsList(docs),
-            configs: ConfigsList(configs),
+            docs,
+            configs,
+            doc_attrs,
+            visibility,
+            import_count: children.values().map(Branches::import_count).sum(),
+            children,
+            roots,
+            lines,
+        })
+    }
+
+    /// Convert an `extern crate` item into the use item that replaces it in
+    /// the 2018 and later editions (see `MergeOptions::modernize_extern_crate`).
+    /// Crates are in scope everywhere without `extern crate`, so a plain
+    /// `extern crate foo;` becomes a use item with no imports at all, and is
+    /// dropped; a renamed or public one (`extern crate foo as bar;`) becomes
+    /// `use foo as bar;`.
+    ///
+    /// Returns `None` for the declarations that still mean something: those
+    /// with `#[macro_use]` (or any other attribute usefix doesn't recognize),
+    /// those for crates that aren't always in scope (`std`, `alloc`, `test`,
+    /// and `proc_macro`), `extern crate self`, and `extern crate foo as _;`,
+    /// which only exists to link the crate.
+    pub fn from_syn_extern_crate(item: syn::ItemExternCrate) -> Option<UseItem> {
+        const PRESERVED_CRATES: &[&str] = &["self", "std", "alloc", "test", "proc_macro"];
+
+        if PRESERVED_CRATES.iter().any(|&name| item.ident == name) {
+            return None;
+        }
+
+        let span = item.span();
+        let lines = span.start().line..=span.end().line;
+
+        let (docs, configs, doc_attrs) = parse_attributes(item.attrs).ok()?;
+        let visibility = Visibility::from_syn_vis(item.vis).ok()?;
+
+        let tree = match item.rename {
+            Some((_, rename)) if rename == "_" => return None,
+            Some((as_token, rename)) => Some(UseTree::Rename(UseRename {
+                ident: item.ident,
+                as_token,
+                rename,
+            })),
+            None if visibility.is_some() => Some(UseTree::Name(UseName { ident: item.ident })),
+            None => None,
+        };
+
+        let mut children = HashMap::new();
+        let mut roots = Vec::new();
+
+        if let Some(tree) = tree {
+            build_use_item_children_root(tree, Rooted::Unrooted, &mut children, &mut roots).ok()?;
+        }
+
+        Some(Self {
+            docs,
+            configs,
+            doc_attrs,
             visibility,
+            import_count: children.values().map(Branches::import_count).sum(),
             children,
-            span,
+            roots,
+            lines,
         })
     }
 }
 
+/// Collect the docs, configs, and list-style doc attributes from the
+/// attributes of an item, rejecting any other attributes.
+pub fn parse_attributes(
+    attrs: Vec<syn::Attribute>,
+) -> Result<(DocsList, ConfigsList, DocAttrsList), CreateUseItemError> {
+    let mut docs = Vec::new();
+    let mut configs = BTreeSet::new();
+    let mut doc_attrs = Vec::new();
+
+    // Handle all attributes. Collect doc and cfg attributes, and reject
+    // items that have other attributes.
+    for attr in attrs {
+        if matches!(attr.style, AttrStyle::Inner(_)) {
+            return Err(CreateUseItemError::InnerAttributes);
+        }
+
+        match attr.meta {
+            Meta::List(attr) => {
+                if !matches!(attr.delimiter, MacroDelimiter::Paren(_)) {
+                    return Err(CreateUseItemError::UnrecognizedAttribute);
+                }
+
+                if attr.path.is_ident("cfg") {
+                    configs.insert(Config::from_tokens(&attr.tokens));
+                } else if attr.path.is_ident("doc") {
+                    doc_attrs.push(attr.tokens.to_string());
+                } else {
+                    return Err(CreateUseItemError::UnrecognizedAttribute);
+                }
+            }
+            Meta::NameValue(attr) => {
+                if attr.path.is_ident("doc") {
+                    // Doc attributes should contain precisely a single string,
+                    // or a macro call that produces one
+                    match attr.value {
+                        Expr::Lit(ExprLit {
+                            attrs,
+                            lit: Lit::Str(content),
+                        }) if attrs.is_empty() => {
+                            docs.push(DocBlock::Text(content.value()));
+                        }
+                        Expr::Macro(ExprMacro { attrs, mac }) if attrs.is_empty() => {
+                            docs.push(DocBlock::Expr(macro_source(&mac)));
+                        }
+                        _ => return Err(CreateUseItemError::MalformedDocAttribute),
+                    }
+                } else {
+                    return Err(CreateUseItemError::UnrecognizedAttribute);
+                }
+            }
+            Meta::Path(_) => return Err(CreateUseItemError::UnrecognizedAttribute),
+        }
+    }
+
+    Ok((
+        DocsList::new(docs),
+        ConfigsList(configs),
+        DocAttrsList::new(doc_attrs),
+    ))
+}
+
+/// Render a macro call (from a doc attribute) back into source code
+fn macro_source(mac: &Macro) -> String {
+    let leading_colon = match mac.path.leading_colon {
+        Some(_) => "::",
+        None => "",
+    };
+
+    let path = mac
+        .path
+        .segments
+        .iter()
+        .map(|segment| &segment.ident)
+        .join_with("::");
+
+    let tokens = &mac.tokens;
+
+    match mac.delimiter {
+        MacroDelimiter::Paren(_) => format!("{leading_colon}{path}!({tokens})"),
+        MacroDelimiter::Brace(_) => format!("{leading_colon}{path}!{{{tokens}}}"),
+        MacroDelimiter::Bracket(_) => format!("{leading_colon}{path}![{tokens}]"),
+    }
+}
+
 fn build_use_item_children_root(
     tree: UseTree,
     rooted: Rooted,
     children: &mut HashMap<TreeRoot, Branches>,
+    roots: &mut Vec<Ident>,
 ) -> Result<(), CreateUseItemError> {
+    // The tree is walked in source order, so we can record the order of the
+    // roots as we find them
+    let mut add_root = |ident: syn::Ident| {
+        let identifier = Ident::from(ident);
+
+        if !roots.contains(&identifier) {
+            roots.push(identifier.clone());
+        }
+
+        TreeRoot { rooted, identifier }
+    };
+
     match tree {
         UseTree::Path(path) => {
-            let subtree = children
-                .entry(TreeRoot {
-                    rooted,
-                    identifier: path.ident,
-                })
-                .or_default();
+            let subtree = children.entry(add_root(path.ident)).or_default();
 
             build_use_item_children_branches(*path.tree, subtree);
             Ok(())
         }
         UseTree::Name(UseName { ident }) => {
-            let subtree = children
-                .entry(TreeRoot {
-                    rooted,
-                    identifier: ident,
-                })
-                .or_default();
-
+            let subtree = children.entry(add_root(ident)).or_default();
             subtree.used.insert(NameUse::Used);
 
             Ok(())
         }
         UseTree::Rename(rename) => {
-            let subtree = children
-                .entry(TreeRoot {
-                    rooted,
-                    identifier: rename.ident,
-                })
-                .or_default();
-
-            subtree.used.insert(NameUse::Renamed(rename.rename));
+            let subtree = children.entry(add_root(rename.ident)).or_default();
+            subtree.used.insert(NameUse::Renamed(rename.rename.into()));
 
             Ok(())
         }
@@ -363,23 +829,23 @@ fn build_use_item_children_root(
         UseTree::Group(group) => group
             .items
             .into_iter()
-            .try_for_each(|tree| build_use_item_children_root(tree, rooted, children)),
+            .try_for_each(|tree| build_use_item_children_root(tree, rooted, children, roots)),
     }
 }
 
 fn build_use_item_children_branches(tree: UseTree, branches: &mut Branches) {
     match tree {
         UseTree::Path(path) => {
-            let subtree = branches.get_subtree(path.ident);
+            let subtree = branches.get_subtree(path.ident.into());
             build_use_item_children_branches(*path.tree, subtree)
         }
         UseTree::Name(UseName { ident }) => {
-            let subtree = branches.get_subtree(ident);
+            let subtree = branches.get_subtree(ident.into());
             subtree.used.insert(NameUse::Used);
         }
         UseTree::Rename(UseRename { ident, rename, .. }) => {
-            let subtree = branches.get_subtree(ident);
-            subtree.used.insert(NameUse::Renamed(rename));
+            let subtree = branches.get_subtree(ident.into());
+            subtree.used.insert(NameUse::Renamed(rename.into()));
         }
         UseTree::Glob(_) => {
             branches.wildcard = true;
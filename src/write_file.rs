@@ -1,113 +1,390 @@
 use std::{collections::HashSet, io};
 
-use crate::gitfile::{Chunk, Conflict, ConflictHalf, GitFile, Line, LineNumber};
+use crate::{
+    gitfile::{Chunk, Conflict, ConflictHalf, GitFile, Line, LineNumber, Side},
+    region::REGION_END,
+};
+
+/// A conflict that's left in the corrected file, with its lines filtered and
+/// any use items injected into each half (see
+/// `filtered_lines_inject_content`)
+struct PrintableConflict<'a> {
+    left: PrintableConflictHalf<'a>,
+    right: PrintableConflictHalf<'a>,
+
+    /// The common ancestors of the original conflicts, if they were written
+    /// in diff3 style. A conflict has only one, but a coalesced conflict
+    /// has the ancestors of every conflict it was made from (see
+    /// `PrintableConflict::coalesce`).
+    bases: Vec<&'a ConflictHalf<'a, Line<'a>>>,
+}
 
-struct PrintableConflict<'a, I1, I2> {
-    pub left: PrintableConflictHalf<'a, I1>,
-    pub right: PrintableConflictHalf<'a, I2>,
+struct PrintableConflictHalf<'a> {
+    name: &'a str,
+    lines: Vec<Output<'a>>,
 }
 
-impl<'a, I> PrintableConflict<'a, I, I> {
-    pub fn map_lines<T>(self, mut f: impl FnMut(I) -> T) -> PrintableConflict<'a, T, T> {
-        PrintableConflict {
-            left: PrintableConflictHalf {
-                name: self.left.name,
-                lines: f(self.left.lines),
-            },
-            right: PrintableConflictHalf {
-                name: self.right.name,
-                lines: f(self.right.lines),
-            },
+impl<'a> PrintableConflict<'a> {
+    /// True if this conflict doesn't need to be written as a conflict at
+    /// all: either its halves are identical (usually because the conflicted
+    /// lines were consumed by usefix in the course of its work), or neither
+    /// of them has anything but blank lines.
+    fn is_resolved(&self) -> bool {
+        let is_blank = |output: &Output<'_>| match *output {
+            Output::Line(line) => line.trim_ascii().is_empty(),
+            Output::UseItems(use_items) => trim_blank_lines(use_items).is_empty(),
+        };
+
+        self.left.lines == self.right.lines
+            || (self.left.lines.iter().all(is_blank) && self.right.lines.iter().all(is_blank))
+    }
+
+    /// Try to merge the following conflict into this one, so that two
+    /// conflicts with nothing between them are written as one. This is only
+    /// possible if they have the same labels, and are in the same style.
+    fn coalesce(&mut self, next: PrintableConflict<'a>) -> Result<(), PrintableConflict<'a>> {
+        let base_name =
+            |conflict: &PrintableConflict<'a>| conflict.bases.first().map(|base| base.name());
+
+        if self.left.name != next.left.name
+            || self.right.name != next.right.name
+            || base_name(self) != base_name(&next)
+        {
+            return Err(next);
         }
+
+        self.left.lines.extend(next.left.lines);
+        self.right.lines.extend(next.right.lines);
+
+        // The pieces of a split conflict share its ancestor, which only
+        // needs to be written once
+        for base in next.bases {
+            if !self
+                .bases
+                .iter()
+                .any(|&existing| std::ptr::eq(existing, base))
+            {
+                self.bases.push(base);
+            }
+        }
+
+        Ok(())
     }
 }
 
-impl<'a: 'file, 'file> PrintableConflict<'file, &'a [Line<'file>], &'a [Line<'file>]> {
-    pub fn from_conflict(conflict: &'a Conflict<'a, Line<'a>>) -> Self {
-        PrintableConflict {
-            left: PrintableConflictHalf {
-                name: conflict.left.name(),
-                lines: conflict.left.lines(),
-            },
-            right: PrintableConflictHalf {
-                name: conflict.right.name(),
-                lines: conflict.right.lines(),
-            },
+/// A piece of the corrected file, before it's written. Conflicts are
+/// collected first, so that adjacent ones can be coalesced (see
+/// `coalesce_conflicts`).
+enum Piece<'a> {
+    Output(Output<'a>),
+    Conflict(PrintableConflict<'a>),
+}
+
+/// A single piece of the corrected file: either a line from the original
+/// file, or a block of formatted use items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Output<'a> {
+    Line(&'a [u8]),
+    UseItems(&'a [u8]),
+}
+
+/// The kind of the most recent non-blank line written to an `OutputWriter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PreviousLine {
+    /// Either nothing has been written yet, or the previous line opened a
+    /// block or started a conflict half. Use items here don't need a blank
+    /// line above them.
+    BlockStart,
+
+    /// A plain `//` comment, which is probably about the use items
+    Comment,
+
+    /// Some other line
+    Other,
+
+    /// A block of use items
+    UseItems,
+}
+
+/// A writer that normalizes the blank lines around blocks of use items. The
+/// lines from the original file that contained use items were discarded,
+/// along with some of the blank lines around them, so the blank lines that
+/// remain can end up doubled or missing. This writer holds on to blank lines
+/// until it knows what comes after them, which allows it to guarantee that
+/// every block of use items has exactly one blank line before it (and
+/// `trailing_blank_lines` after it), except at the start or end of a block
+/// (or the file), where it has none.
+struct OutputWriter<'a, W> {
+    dest: W,
+    pending_blank_lines: Vec<&'a [u8]>,
+    previous_line: PreviousLine,
+    trailing_blank_lines: usize,
+
+    /// The line ending of the original file, which is used for everything
+    /// that isn't copied from one of its lines
+    line_ending: &'static str,
+}
+
+impl<'a, W: io::Write> OutputWriter<'a, W> {
+    fn new(dest: W, trailing_blank_lines: usize, line_ending: &'static str) -> Self {
+        Self {
+            dest,
+            pending_blank_lines: Vec::new(),
+            previous_line: PreviousLine::BlockStart,
+            trailing_blank_lines,
+            line_ending,
+        }
+    }
+
+    fn write(&mut self, output: Output<'a>) -> io::Result<()> {
+        match output {
+            Output::Line(line) => self.write_line(line),
+            Output::UseItems(use_items) => self.write_use_items(use_items),
+        }
+    }
+
+    /// Write any pending blank lines, in preparation for writing a line with
+    /// the given content.
+    fn flush_blank_lines(&mut self, next: &[u8]) -> io::Result<()> {
+        let blank_lines = std::mem::take(&mut self.pending_blank_lines);
+
+        if self.previous_line != PreviousLine::UseItems {
+            return blank_lines
+                .into_iter()
+                .try_for_each(|line| self.dest.write_all(line));
+        }
+
+        if next.starts_with(b"}")
+            || ends_conflict_half(next)
+            || next.trim_ascii() == REGION_END.as_bytes()
+        {
+            Ok(())
+        } else {
+            self.dest.write_all(
+                self.line_ending
+                    .repeat(self.trailing_blank_lines)
+                    .as_bytes(),
+            )
         }
     }
+
+    fn write_line(&mut self, line: &'a [u8]) -> io::Result<()> {
+        let trimmed = line.trim_ascii();
+
+        if trimmed.is_empty() {
+            self.pending_blank_lines.push(line);
+            return Ok(());
+        }
+
+        self.flush_blank_lines(trimmed)?;
+        self.dest.write_all(line)?;
+
+        self.previous_line = if trimmed.ends_with(b"{") {
+            PreviousLine::BlockStart
+        } else if trimmed.starts_with(b"//") && !trimmed.starts_with(b"//!") {
+            PreviousLine::Comment
+        } else {
+            PreviousLine::Other
+        };
+
+        Ok(())
+    }
+
+    /// Write text that usefix generated, rather than copied from the
+    /// original file. Its lines always end with `\n`, so they're given the
+    /// file's line ending instead.
+    fn write_generated(&mut self, text: &[u8]) -> io::Result<()> {
+        if self.line_ending == "\n" {
+            return self.dest.write_all(text);
+        }
+
+        text.split_inclusive(|&b| b == b'\n')
+            .try_for_each(|line| match line.strip_suffix(b"\n") {
+                Some(line) => {
+                    self.dest.write_all(line)?;
+                    self.dest.write_all(self.line_ending.as_bytes())
+                }
+                None => self.dest.write_all(line),
+            })
+    }
+
+    /// Write a conflict marker line (without its line ending)
+    fn write_marker(&mut self, marker: &str) -> io::Result<()> {
+        self.flush_blank_lines(marker.as_bytes())?;
+        self.dest.write_all(marker.as_bytes())?;
+        self.dest.write_all(self.line_ending.as_bytes())?;
+
+        self.previous_line = match starts_conflict_half(marker.as_bytes()) {
+            true => PreviousLine::BlockStart,
+            false => PreviousLine::Other,
+        };
+
+        Ok(())
+    }
+
+    fn write_use_items(&mut self, use_items: &'a [u8]) -> io::Result<()> {
+        let use_items = trim_blank_lines(use_items);
+
+        if use_items.is_empty() {
+            return Ok(());
+        }
+
+        let blank_lines = std::mem::take(&mut self.pending_blank_lines);
+
+        match self.previous_line {
+            PreviousLine::BlockStart => {}
+            PreviousLine::Comment if blank_lines.is_empty() => {}
+            _ => self.dest.write_all(self.line_ending.as_bytes())?,
+        }
+
+        self.write_generated(use_items)?;
+        self.dest.write_all(self.line_ending.as_bytes())?;
+        self.previous_line = PreviousLine::UseItems;
+
+        Ok(())
+    }
+
+    /// Write any trailing blank lines at the end of the file. Use items at
+    /// the end of the file don't get a blank line after them.
+    fn finish(mut self) -> io::Result<()> {
+        if self.previous_line == PreviousLine::UseItems {
+            return Ok(());
+        }
+
+        std::mem::take(&mut self.pending_blank_lines)
+            .into_iter()
+            .try_for_each(|line| self.dest.write_all(line))
+    }
 }
 
-struct PrintableConflictHalf<'a, I> {
-    pub name: &'a str,
-    pub lines: I,
+/// Check if a line is a conflict marker that comes at the start of a
+/// conflict half (or the common ancestor, in a diff3 conflict)
+fn starts_conflict_half(line: &[u8]) -> bool {
+    [b"<<<<<<<", b"|||||||", b"======="]
+        .iter()
+        .any(|marker| line.starts_with(*marker))
 }
 
-impl<'a, I: Iterator<Item = &'a [u8]> + Clone> PrintableConflictHalf<'a, I> {
-    pub fn write_lines(mut self, dest: &mut impl io::Write) -> io::Result<()> {
-        self.lines.try_for_each(|line| dest.write_all(line))
-    }
+/// Check if a line is a conflict marker that comes at the end of a conflict
+/// half (or the common ancestor, in a diff3 conflict)
+fn ends_conflict_half(line: &[u8]) -> bool {
+    [b"|||||||", b"=======", b">>>>>>>"]
+        .iter()
+        .any(|marker| line.starts_with(*marker))
 }
 
-/// Write a conflict to the destination. If the conflict halves are identical,
-/// the non-conflicted version is written (usually this will happen because
-/// the conflicted lines were consumed by usefix in the course of its work).
-/// Otherwise, it will be written as-is, with the typical git conflict markers.
-fn write_conflict<'a, I1, I2>(
-    dest: &mut impl io::Write,
-    conflict: PrintableConflict<'a, I1, I2>,
-) -> io::Result<()>
-where
-    I1: Iterator<Item = &'a [u8]> + Clone,
-    I2: Iterator<Item = &'a [u8]> + Clone,
-{
-    let left_lines = conflict.left.lines.clone();
-    let right_lines = conflict.right.lines.clone();
+/// Remove any blank lines from the start and end of a block of use items,
+/// as well as the trailing newline.
+fn trim_blank_lines(use_items: &[u8]) -> &[u8] {
+    let start = use_items
+        .split_inclusive(|&b| b == b'\n')
+        .take_while(|line| line.trim_ascii().is_empty())
+        .map(|line| line.len())
+        .sum();
 
-    if Iterator::eq(left_lines, right_lines) {
-        conflict.left.write_lines(dest)
-    } else {
-        let left_name = conflict.left.name;
-        let right_name = conflict.right.name;
+    use_items[start..].trim_ascii_end()
+}
 
-        write!(dest, "<<<<<<< {}\n", left_name)?;
-        conflict.left.write_lines(dest)?;
+/// Add a conflict to the pieces of the corrected file. If it's resolved
+/// (see `PrintableConflict::is_resolved`), its left half is added as regular
+/// lines instead.
+fn push_conflict<'a>(pieces: &mut Vec<Piece<'a>>, conflict: PrintableConflict<'a>) {
+    match conflict.is_resolved() {
+        true => pieces.extend(conflict.left.lines.into_iter().map(Piece::Output)),
+        false => pieces.push(Piece::Conflict(conflict)),
+    }
+}
 
-        dest.write_all(b"=======\n")?;
+/// Merge each run of conflicts with nothing between them into a single
+/// conflict. Splitting conflicts around insert points, and discarding the
+/// lines of use items, can leave several conflicts back to back, and fewer,
+/// larger conflicts are easier to read and resolve.
+fn coalesce_conflicts(pieces: Vec<Piece<'_>>) -> Vec<Piece<'_>> {
+    let mut coalesced: Vec<Piece<'_>> = Vec::with_capacity(pieces.len());
+
+    // An empty block of use items isn't written at all, so it doesn't keep
+    // the conflicts around it apart
+    let is_empty = |piece: &Piece<'_>| {
+        matches!(*piece, Piece::Output(Output::UseItems(use_items))
+            if trim_blank_lines(use_items).is_empty())
+    };
+
+    for piece in pieces.into_iter().filter(|piece| !is_empty(piece)) {
+        let piece = match (coalesced.last_mut(), piece) {
+            (Some(Piece::Conflict(previous)), Piece::Conflict(conflict)) => {
+                match previous.coalesce(conflict) {
+                    Ok(()) => continue,
+                    Err(conflict) => Piece::Conflict(conflict),
+                }
+            }
+            (_, piece) => piece,
+        };
 
-        conflict.right.write_lines(dest)?;
-        write!(dest, ">>>>>>> {}\n", right_name)
+        coalesced.push(piece);
     }
+
+    coalesced
 }
 
-fn filtered_lines<'file: 'a, 'a, I>(
-    lines: I,
-    discarded_lines: &'a HashSet<LineNumber>,
-) -> impl Iterator<Item = &'file [u8]> + Clone + 'a
-where
-    I: IntoIterator<Item = &'a Line<'file>, IntoIter: Clone + 'a>,
-{
-    lines
+/// Write a conflict to the destination, with the typical git conflict
+/// markers, including the common ancestor if the original conflict was in
+/// diff3 style. If the conflict turned out to be resolved after all (because
+/// it was coalesced), the non-conflicted version is written instead.
+fn write_conflict<'a>(
+    dest: &mut OutputWriter<'a, impl io::Write>,
+    conflict: PrintableConflict<'a>,
+) -> io::Result<()> {
+    if conflict.is_resolved() {
+        return conflict
+            .left
+            .lines
+            .into_iter()
+            .try_for_each(|output| dest.write(output));
+    }
+
+    dest.write_marker(&format!("<<<<<<< {}", conflict.left.name))?;
+    conflict
+        .left
+        .lines
+        .into_iter()
+        .try_for_each(|output| dest.write(output))?;
+
+    if let Some(first) = conflict.bases.first() {
+        dest.write_marker(&format!("||||||| {}", first.name()))?;
+        conflict
+            .bases
+            .iter()
+            .flat_map(|base| base.lines())
+            .try_for_each(|line| dest.write_line(line.content.as_bytes()))?;
+    }
+
+    dest.write_marker("=======")?;
+    conflict
+        .right
+        .lines
         .into_iter()
-        .filter(move |line| !discarded_lines.contains(&line.line_number))
-        .map(|line| line.content.as_bytes())
+        .try_for_each(|output| dest.write(output))?;
+    dest.write_marker(&format!(">>>>>>> {}", conflict.right.name))
 }
 
 fn filtered_lines_inject_content<'file: 'a, 'a, I>(
     lines: I,
     discarded_lines: &'a HashSet<LineNumber>,
-    formatted_use_items: &'file [u8],
-    insert_point: &'a InsertPoint,
-) -> impl Iterator<Item = &'file [u8]> + Clone + 'a
+    insertions: &'a [Insertion<'file>],
+) -> impl Iterator<Item = Output<'file>> + Clone + 'a
 where
     I: IntoIterator<Item = &'a Line<'file>, IntoIter: Clone + 'a>,
 {
     lines.into_iter().filter_map(move |line| {
-        if insert_point.contains_line(line.line_number) {
-            Some(formatted_use_items)
+        if let Some(insertion) = insertions
+            .iter()
+            .find(|insertion| insertion.point.contains_line(line.line_number))
+        {
+            Some(Output::UseItems(insertion.formatted_use_items))
         } else if discarded_lines.contains(&line.line_number) {
             None
         } else {
-            Some(line.content.as_bytes())
+            Some(Output::Line(line.content.as_bytes()))
         }
     })
 }
@@ -138,88 +415,89 @@ impl InsertPoint {
         }
     }
 
-    pub fn try_split_conflict<'file, 'a: 'file>(
-        &self,
-        conflict: &'a Conflict<'file, Line<'file>>,
-    ) -> Option<(
-        PrintableConflict<'file, &'a [Line<'file>], &'a [Line<'file>]>,
-        PrintableConflict<'file, &'a [Line<'file>], &'a [Line<'file>]>,
-    )> {
+    /// If this insert point is on both sides of the given conflict, get the
+    /// indexes of the lines in each half at which the conflict should be
+    /// split.
+    pub fn split_points(&self, conflict: &Conflict<'_, Line<'_>>) -> Option<(usize, usize)> {
         match *self {
             InsertPoint::Nowhere | InsertPoint::Once(_) => None,
-            InsertPoint::IntoConflict(left, right) => {
-                let left_lines = conflict.left.lines();
-                let right_lines = conflict.right.lines();
-
-                let left_split_point = find_split_point(&conflict.left, left);
-                let right_split_point = find_split_point(&conflict.right, right);
-
-                let (Some(left_split_point), Some(right_split_point)) =
-                    (left_split_point, right_split_point)
-                else {
-                    return None;
-                };
-
-                let left_top_lines = &left_lines[..left_split_point];
-                let left_bottom_lines = &left_lines[left_split_point + 1..];
-
-                let right_top_lines = &right_lines[..right_split_point];
-                let right_bottom_lines = &right_lines[right_split_point + 1..];
-
-                let top_conflict = PrintableConflict {
-                    left: PrintableConflictHalf {
-                        name: conflict.left.name(),
-                        lines: left_top_lines,
-                    },
-                    right: PrintableConflictHalf {
-                        name: conflict.right.name(),
-                        lines: right_top_lines,
-                    },
-                };
-
-                let bottom_conflict = PrintableConflict {
-                    left: PrintableConflictHalf {
-                        name: conflict.left.name(),
-                        lines: left_bottom_lines,
-                    },
-                    right: PrintableConflictHalf {
-                        name: conflict.right.name(),
-                        lines: right_bottom_lines,
-                    },
-                };
-
-                Some((top_conflict, bottom_conflict))
-            }
+            InsertPoint::IntoConflict(left, right) => Some((
+                find_split_point(&conflict.left, left)?,
+                find_split_point(&conflict.right, right)?,
+            )),
+        }
+    }
+}
+
+/// The last line of the inner attributes and docs (`#![...]` and `//!`) at
+/// the top of a module, in each version of the file. Use items must always
+/// be inserted after these, or the file won't compile.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Preamble {
+    pub left: Option<LineNumber>,
+    pub right: Option<LineNumber>,
+}
+
+impl Preamble {
+    /// Check if a line from one side of a conflict (or from neither side, if
+    /// it isn't conflicted) is after the preamble in that version of the
+    /// file. Line numbers in the original file are consistent with the order
+    /// of lines in each version of the file, so we only need to be careful
+    /// not to compare lines from opposite sides of the same conflict.
+    fn is_after(&self, line: LineNumber, side: Option<Side>) -> bool {
+        let after = |end: Option<LineNumber>| end.is_none_or(|end| line > end);
+
+        match side {
+            None => after(self.left) && after(self.right),
+            Some(Side::Left) => after(self.left),
+            Some(Side::Right) => after(self.right),
         }
     }
 }
 
 fn first_matching_line_number_in_conflict_half(
     half: &ConflictHalf<'_, Line<'_>>,
+    side: Side,
     discarded_lines: &HashSet<LineNumber>,
+    preamble: &Preamble,
 ) -> Option<LineNumber> {
     half.lines()
         .iter()
         .map(|line| line.line_number)
+        .filter(|&line_number| preamble.is_after(line_number, Some(side)))
         .find(|line_number| discarded_lines.contains(line_number))
 }
 
-fn find_insert_point(original: &GitFile<'_>, discarded_lines: &HashSet<LineNumber>) -> InsertPoint {
+fn find_insert_point(
+    original: &GitFile<'_>,
+    discarded_lines: &HashSet<LineNumber>,
+    preamble: &Preamble,
+) -> InsertPoint {
     let mut left_point = None;
     let mut right_point = None;
 
     for chunk in original.chunks() {
         match chunk {
             Chunk::Line(line) => {
-                if discarded_lines.contains(&line.line_number) {
+                if discarded_lines.contains(&line.line_number)
+                    && preamble.is_after(line.line_number, None)
+                {
                     return InsertPoint::Once(line.line_number);
                 }
             }
             Chunk::Conflict(conflict) => {
-                let local_left_point =
-                    first_matching_line_number_in_conflict_half(&conflict.left, discarded_lines);
-                let local_right_point =
-                    first_matching_line_number_in_conflict_half(&conflict.right, discarded_lines);
+                let local_left_point = first_matching_line_number_in_conflict_half(
+                    &conflict.left,
+                    Side::Left,
+                    discarded_lines,
+                    preamble,
+                );
+                let local_right_point = first_matching_line_number_in_conflict_half(
+                    &conflict.right,
+                    Side::Right,
+                    discarded_lines,
+                    preamble,
+                );
 
                 match (local_left_point, local_right_point) {
                     (Some(left), Some(right)) => return InsertPoint::IntoConflict(left, right),
@@ -242,18 +520,111 @@ fn find_insert_point(original: &GitFile<'_>, discarded_lines: &HashSet<LineNumbe
     }
 }
 
-pub fn write_corrected_file(
-    dest: &mut impl io::Write,
-    original: &GitFile<'_>,
-    discarded_lines: &HashSet<LineNumber>,
+/// A section of formatted use items, which replaces a set of use items from
+/// the original file.
+pub struct FormattedSection {
+    /// The lines in the original file that include any part of a use item
+    /// in this section.
+    pub discarded_lines: HashSet<LineNumber>,
+
+    /// The inner attributes and docs of the module containing this section,
+    /// which the use items must be inserted after.
+    pub preamble: Preamble,
 
     // This could be a string, but sometimes the conversion process turns it
     // into a byte array, and we don't care to pay the penalty of verifying it's
     // still UTF-8 (even though it certainly is)
-    formatted_use_items: &[u8],
+    pub formatted_use_items: Vec<u8>,
+}
+
+/// A section of formatted use items, along with the point where they'll be
+/// inserted into the file.
+#[derive(Clone, Copy)]
+struct Insertion<'a> {
+    point: InsertPoint,
+    formatted_use_items: &'a [u8],
+}
+
+/// Add a conflict to the pieces of the corrected file, splitting it wherever
+/// any of the insertions need to be inserted on both sides of it, and
+/// injecting any insertions that only appear on one side of it. The common
+/// ancestor of a diff3 conflict can't be meaningfully split, so if more than
+/// one piece of a split conflict remains conflicted, each of them gets all
+/// of it.
+fn push_conflict_with_insertions<'a>(
+    pieces: &mut Vec<Piece<'a>>,
+    conflict: &'a Conflict<'a, Line<'a>>,
+    discarded_lines: &HashSet<LineNumber>,
+    insertions: &[Insertion<'a>],
+) {
+    let (mut splits, injections): (Vec<_>, Vec<_>) = insertions
+        .iter()
+        .map(|insertion| (insertion, insertion.point.split_points(conflict)))
+        .partition(|(_, split)| split.is_some());
+
+    let injections: Vec<Insertion<'a>> = injections
+        .into_iter()
+        .map(|(&insertion, _)| insertion)
+        .collect();
+
+    splits.sort_by_key(|(_, split)| *split);
+
+    let left_lines = conflict.left.lines();
+    let right_lines = conflict.right.lines();
+
+    let piece = |left: &'a [Line<'a>], right: &'a [Line<'a>]| PrintableConflict {
+        left: PrintableConflictHalf {
+            name: conflict.left.name(),
+            lines: filtered_lines_inject_content(left, discarded_lines, &injections).collect(),
+        },
+        right: PrintableConflictHalf {
+            name: conflict.right.name(),
+            lines: filtered_lines_inject_content(right, discarded_lines, &injections).collect(),
+        },
+        bases: conflict.base.iter().collect(),
+    };
+
+    let mut left_start = 0;
+    let mut right_start = 0;
+
+    // Each split point is a discarded line, so it's filtered out of the
+    // conflict piece after it; we don't need to skip over it. The split
+    // points should be in the same order on both sides, but in case they
+    // aren't, we never move backwards.
+    for (insertion, split) in splits {
+        let (left_split, right_split) = split.expect("partitioned by split points");
+        let left_split = left_split.max(left_start);
+        let right_split = right_split.max(right_start);
+
+        push_conflict(
+            pieces,
+            piece(
+                &left_lines[left_start..left_split],
+                &right_lines[right_start..right_split],
+            ),
+        );
+        pieces.push(Piece::Output(Output::UseItems(
+            insertion.formatted_use_items,
+        )));
+
+        left_start = left_split;
+        right_start = right_split;
+    }
+
+    push_conflict(
+        pieces,
+        piece(&left_lines[left_start..], &right_lines[right_start..]),
+    );
+}
+
+pub fn write_corrected_file<'a>(
+    dest: &mut impl io::Write,
+    original: &'a GitFile<'a>,
+    sections: &'a [FormattedSection],
+    trailing_blank_lines: usize,
 ) -> io::Result<()> {
-    // First, we need to choose where to insert the formatted use items. In
-    // order of preference:
+    // First, we need to choose where to insert each section of formatted use
+    // items. In order of preference:
     //
     // - Either the first line containing a use item that isn't part of a
     //   conflict, or the first conflict that contains use items on both sides
@@ -271,77 +642,248 @@ pub fn write_corrected_file(
     // In practice we expect that this will basically never matter, because
     // these cases require extremely conflicted files that share hardly any
     // internal structure to create odd outputs.
-    let insert_point = find_insert_point(original, discarded_lines);
+    let insertions: Vec<Insertion<'_>> = sections
+        .iter()
+        .map(|section| Insertion {
+            // If every use item is above the preamble (which can only happen
+            // if some of them are in conflicts), we just insert them at the
+            // first use item, as usual, rather than not at all.
+            point: match find_insert_point(original, &section.discarded_lines, &section.preamble) {
+                InsertPoint::Nowhere => {
+                    find_insert_point(original, &section.discarded_lines, &Preamble::default())
+                }
+                point => point,
+            },
+            formatted_use_items: &section.formatted_use_items,
+        })
+        .collect();
 
-    let mut chunks = original.chunks().iter();
-    // This for loop is the one that's attempting to insert the use items.
-    // We'll break out of it once we do that, so we can write the rest of the
-    // file unconditionally.
-    for chunk in chunks.by_ref() {
+    let discarded_lines: HashSet<LineNumber> = sections
+        .iter()
+        .flat_map(|section| &section.discarded_lines)
+        .copied()
+        .collect();
+
+    let mut pieces: Vec<Piece<'_>> = Vec::new();
+
+    for chunk in original.chunks() {
         match chunk {
             Chunk::Line(line) => {
-                if insert_point.contains_line(line.line_number) {
-                    dest.write_all(formatted_use_items)?;
-                    break;
-                } else if discarded_lines.contains(&line.line_number) {
-                } else {
-                    dest.write_all(line.content.as_bytes())?;
+                if let Some(insertion) = insertions
+                    .iter()
+                    .find(|insertion| insertion.point.contains_line(line.line_number))
+                {
+                    pieces.push(Piece::Output(Output::UseItems(
+                        insertion.formatted_use_items,
+                    )));
+                } else if !discarded_lines.contains(&line.line_number) {
+                    pieces.push(Piece::Output(Output::Line(line.content.as_bytes())));
                 }
             }
             Chunk::Conflict(conflict) => {
-                if let Some((top_conflict, bottom_conflict)) =
-                    insert_point.try_split_conflict(conflict)
-                {
-                    // Don't need to filter lines for the top conflict,
-                    // because by definition it contains only all the lines
-                    // before the first discarded line (the insert point)
-                    let top_conflict = top_conflict
-                        .map_lines(|lines| lines.iter().map(|line| line.content.as_bytes()));
-
-                    let bottom_conflict =
-                        bottom_conflict.map_lines(|lines| filtered_lines(lines, discarded_lines));
-
-                    write_conflict(dest, top_conflict)?;
-                    dest.write_all(formatted_use_items)?;
-                    write_conflict(dest, bottom_conflict)?;
-
-                    break;
-                } else {
-                    // At this point, we're certain that only the left or
-                    // right side of the conflict (or neither) contain
-                    // discarded lines where we need to insert a conflict.
-                    let conflict = PrintableConflict::from_conflict(conflict).map_lines(|lines| {
-                        filtered_lines_inject_content(
-                            lines,
-                            discarded_lines,
-                            formatted_use_items,
-                            &insert_point,
-                        )
-                    });
-
-                    write_conflict(dest, conflict)?;
-                }
+                push_conflict_with_insertions(&mut pieces, conflict, &discarded_lines, &insertions);
             }
         }
     }
 
-    // At this point, it's guaranteed that we've written the use items. Write
-    // out the rest of the file.
-    for chunk in chunks {
-        match chunk {
-            Chunk::Line(line) => {
-                if !discarded_lines.contains(&line.line_number) {
-                    dest.write_all(line.content.as_bytes())?;
-                }
-            }
-            Chunk::Conflict(conflict) => {
-                let conflict = PrintableConflict::from_conflict(conflict)
-                    .map_lines(|lines| filtered_lines(lines, discarded_lines));
+    let mut dest = OutputWriter::new(dest, trailing_blank_lines, original.line_ending());
 
-                write_conflict(dest, conflict)?;
-            }
+    for piece in coalesce_conflicts(pieces) {
+        match piece {
+            Piece::Output(output) => dest.write(output)?,
+            Piece::Conflict(conflict) => write_conflict(&mut dest, conflict)?,
         }
     }
 
-    Ok(())
+    dest.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use Output::{Line, UseItems};
+
+    /// Write a sequence of outputs with an `OutputWriter`, with one trailing
+    /// blank line after use items
+    fn written(outputs: &[Output<'static>]) -> String {
+        let mut dest = Vec::new();
+        let mut writer = OutputWriter::new(&mut dest, 1, "\n");
+
+        outputs
+            .iter()
+            .try_for_each(|&output| writer.write(output))
+            .unwrap();
+        writer.finish().unwrap();
+
+        String::from_utf8(dest).unwrap()
+    }
+
+    #[test]
+    fn doubled_blank_lines_are_collapsed() {
+        let output = written(&[
+            Line(b"fn a() {}\n"),
+            Line(b"\n"),
+            Line(b"\n"),
+            UseItems(b"\nuse a::b;\nuse c::d;\n\n"),
+            Line(b"\n"),
+            Line(b"   \n"),
+            Line(b"\n"),
+            Line(b"fn b() {}\n"),
+        ]);
+
+        assert_eq!(output, "fn a() {}\n\nuse a::b;\nuse c::d;\n\nfn b() {}\n");
+    }
+
+    #[test]
+    fn missing_blank_lines_are_added() {
+        let output = written(&[
+            Line(b"fn a() {}\n"),
+            UseItems(b"use a::b;\n"),
+            Line(b"fn b() {}\n"),
+        ]);
+
+        assert_eq!(output, "fn a() {}\n\nuse a::b;\n\nfn b() {}\n");
+    }
+
+    #[test]
+    fn no_blank_lines_at_the_edges_of_a_block() {
+        let output = written(&[
+            Line(b"mod inner {\n"),
+            Line(b"\n"),
+            UseItems(b"    use a::b;\n"),
+            Line(b"\n"),
+            Line(b"}\n"),
+        ]);
+
+        assert_eq!(output, "mod inner {\n    use a::b;\n}\n");
+    }
+
+    #[test]
+    fn no_blank_lines_at_the_edges_of_the_file() {
+        let output = written(&[
+            Line(b"\n"),
+            UseItems(b"use a::b;\n"),
+            Line(b"\n"),
+            Line(b"\n"),
+        ]);
+
+        assert_eq!(output, "use a::b;\n");
+    }
+
+    #[test]
+    fn comments_stay_attached() {
+        let attached = written(&[
+            Line(b"// Imports\n"),
+            UseItems(b"use a::b;\n"),
+            Line(b"fn a() {}\n"),
+        ]);
+
+        assert_eq!(attached, "// Imports\nuse a::b;\n\nfn a() {}\n");
+
+        let detached = written(&[
+            Line(b"// Imports\n"),
+            Line(b"\n"),
+            Line(b"\n"),
+            UseItems(b"use a::b;\n"),
+        ]);
+
+        assert_eq!(detached, "// Imports\n\nuse a::b;\n");
+
+        // Module docs are never about the use items
+        let docs = written(&[Line(b"//! Docs\n"), UseItems(b"use a::b;\n")]);
+        assert_eq!(docs, "//! Docs\n\nuse a::b;\n");
+    }
+
+    #[test]
+    fn consecutive_use_items() {
+        let output = written(&[
+            UseItems(b"use a::b;\n"),
+            Line(b"\n"),
+            Line(b"\n"),
+            UseItems(b"use c::d;\n"),
+        ]);
+
+        assert_eq!(output, "use a::b;\n\nuse c::d;\n");
+    }
+
+    #[test]
+    fn blank_lines_away_from_use_items_are_kept() {
+        let output = written(&[
+            Line(b"fn a() {}\n"),
+            Line(b"\n"),
+            Line(b"\n"),
+            Line(b"fn b() {}\n"),
+            Line(b"\n"),
+        ]);
+
+        assert_eq!(output, "fn a() {}\n\n\nfn b() {}\n\n");
+    }
+
+    #[test]
+    fn empty_use_items_are_skipped() {
+        let output = written(&[
+            Line(b"fn a() {}\n"),
+            Line(b"\n"),
+            UseItems(b"\n\n"),
+            Line(b"\n"),
+            Line(b"fn b() {}\n"),
+        ]);
+
+        assert_eq!(output, "fn a() {}\n\n\nfn b() {}\n");
+    }
+
+    #[test]
+    fn trailing_blank_lines() {
+        let mut dest = Vec::new();
+        let mut writer = OutputWriter::new(&mut dest, 2, "\n");
+
+        writer.write_use_items(b"use a::b;\n").unwrap();
+        writer.write_line(b"\n").unwrap();
+        writer.write_line(b"fn a() {}\n").unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(dest, b"use a::b;\n\n\nfn a() {}\n");
+    }
+
+    #[test]
+    fn generated_lines_use_the_file_line_ending() {
+        let mut dest = Vec::new();
+        let mut writer = OutputWriter::new(&mut dest, 1, "\r\n");
+
+        writer.write_line(b"fn a() {}\r\n").unwrap();
+        writer.write_line(b"\r\n").unwrap();
+        writer.write_line(b"\r\n").unwrap();
+        writer.write_use_items(b"use a::b;\nuse c::d;\n").unwrap();
+        writer.write_line(b"fn b() {}\r\n").unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            dest,
+            b"fn a() {}\r\n\r\nuse a::b;\r\nuse c::d;\r\n\r\nfn b() {}\r\n"
+        );
+    }
+
+    #[test]
+    fn no_blank_lines_at_the_edges_of_a_conflict_half() {
+        let mut dest = Vec::new();
+        let mut writer = OutputWriter::new(&mut dest, 1, "\n");
+
+        writer.write_marker("<<<<<<< HEAD").unwrap();
+        writer.write_line(b"\n").unwrap();
+        writer.write_use_items(b"use a::b;\n").unwrap();
+        writer.write_line(b"\n").unwrap();
+        writer.write_marker("||||||| base").unwrap();
+        writer.write_line(b"use a::c;\n").unwrap();
+        writer.write_marker("=======").unwrap();
+        writer.write_use_items(b"use a::d;\n").unwrap();
+        writer.write_line(b"\n").unwrap();
+        writer.write_marker(">>>>>>> branch").unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(
+            String::from_utf8(dest).unwrap(),
+            "<<<<<<< HEAD\nuse a::b;\n||||||| base\nuse a::c;\n=======\nuse a::d;\n>>>>>>> branch\n"
+        );
+    }
 }
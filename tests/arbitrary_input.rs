@@ -0,0 +1,139 @@
+/*!
+Feed usefix a large number of arbitrary inputs, and check that it always
+either fixes them or returns an error, without ever panicking. The inputs
+are built from fragments of rust syntax and conflict markers, chosen by a
+small deterministic random number generator, so that they're mostly
+nonsense, but close enough to real files to get past the parsers.
+ */
+
+use std::panic::{self, AssertUnwindSafe};
+
+use usefix::{fix_file, MergeOptions};
+
+/// Whole lines, which are mostly valid on their own
+const LINES: &[&str] = &[
+    "use std::io::{self, Read};\n",
+    "use foo::*;\n",
+    "pub use crate::Bar as Baz;\n",
+    "use super::{a::b, c as _};\n",
+    "use self::inner::{x, y::{z, *}};\n",
+    "#[cfg(unix)]\n",
+    "/// docs\n",
+    "extern crate serde as json;\n",
+    "mod inner {\n",
+    "}\n",
+    "fn main() {}\n",
+    "\n",
+    "<<<<<<< HEAD\n",
+    "||||||| base\n",
+    "=======\n",
+    ">>>>>>> branch\n",
+];
+
+/// Fragments of lines, which are mostly nonsense when they're put together
+const FRAGMENTS: &[&str] = &[
+    "use ",
+    "pub ",
+    "pub(crate) ",
+    "std",
+    "core",
+    "crate",
+    "self",
+    "super",
+    "foo",
+    "Bar",
+    "r#type",
+    "::",
+    "{",
+    "}",
+    ", ",
+    ";",
+    " as ",
+    "_",
+    "*",
+    "\n",
+    "\n\n",
+    "    ",
+    "<<<<<<< HEAD\n",
+    "||||||| base\n",
+    "=======\n",
+    ">>>>>>> branch\n",
+    "#[cfg(test)]\n",
+    "#[cfg(any(unix, windows))]\n",
+    "#[doc(hidden)]\n",
+    "/// docs\n",
+    "#![allow(unused)]\n",
+    "// comment\n",
+    "/* block */",
+    "mod inner ",
+    "fn main() ",
+    "extern crate alloc;\n",
+    "#[macro_use]\n",
+    "\"string\"",
+    "'c'",
+    "é",
+    "\t",
+    "\r\n",
+];
+
+/// A xorshift generator, so that every run tests the same inputs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[self.next() % choices.len()]
+    }
+
+    /// A file of up to 20 lines, most of which are picked from `LINES`, and
+    /// the rest of which are a few random fragments
+    fn input(&mut self) -> String {
+        let mut input = String::new();
+
+        for _ in 0..self.next() % 20 {
+            match self.next() % 12 {
+                0 => (0..self.next() % 8).for_each(|_| input.push_str(self.pick(FRAGMENTS))),
+                _ => input.push_str(self.pick(LINES)),
+            }
+        }
+
+        input
+    }
+}
+
+#[test]
+fn arbitrary_input_never_panics() {
+    let option_sets: Vec<MergeOptions> = [
+        &[][..],
+        &["--hoist"],
+        &["--fragment"],
+        &["--cfg-if"],
+        &["--preserve-unchanged"],
+        &["--modernize-extern-crate", "--edition", "2021"],
+        &["--max-paths", "2", "--self-import", "separate"],
+    ]
+    .iter()
+    .map(|args| MergeOptions::from_args(*args).expect("the options are valid"))
+    .collect();
+
+    let mut rng = Rng(0x5eed_1234_abcd_ef01);
+
+    for _ in 0..2000 {
+        let input = rng.input();
+
+        for options in &option_sets {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| fix_file(&input, options)));
+
+            assert!(
+                result.is_ok(),
+                "usefix panicked with {options:?} on this input:\n{input}"
+            );
+        }
+    }
+}
@@ -0,0 +1,32 @@
+/*!
+Check how `--cfg-if` interacts with the other options that group use items.
+ */
+
+use usefix::{fix_file, MergeOptions};
+
+#[test]
+fn trailing_anonymous_group() {
+    let options = MergeOptions::from_args(["--cfg-if", "--trailing-anonymous-group"])
+        .expect("the options are valid");
+
+    let input = "<<<<<<< HEAD\n\
+                 use std::io::Write as _;\n\
+                 use std::fmt;\n\
+                 =======\n\
+                 #[cfg(unix)]\n\
+                 use std::os::unix;\n\
+                 >>>>>>> branch\n\n\
+                 fn main() {}\n";
+
+    assert_eq!(
+        fix_file(input, &options).expect("the input can be fixed"),
+        "use std::fmt;\n\n\
+         cfg_if::cfg_if! {\n    \
+         if #[cfg(unix)] {\n        \
+         use std::os::unix;\n    \
+         }\n\
+         }\n\n\
+         use std::io::Write as _;\n\n\
+         fn main() {}\n"
+    );
+}
@@ -0,0 +1,130 @@
+/*!
+Check that the docs on use items (usually re-exports) survive being merged
+and written back out, and that writing them back out is stable.
+ */
+
+use usefix::{fix_file, MergeOptions};
+
+fn fix(input: &str) -> String {
+    let fixed = fix_file(input, &MergeOptions::default()).expect("the input can be fixed");
+    let refixed = fix_file(&fixed, &MergeOptions::default()).expect("the output can be fixed");
+
+    assert_eq!(fixed, refixed, "fixing the output again changed it");
+    fixed
+}
+
+#[test]
+fn documented_reexports() {
+    let input = "\
+/// The reader
+pub use std::io::Read;
+/// The writer,
+/// which takes two lines to describe
+pub use std::io::Write;
+#[doc(hidden)]
+pub use std::fmt;
+
+fn main() {}
+";
+
+    assert_eq!(
+        fix(input),
+        "\
+#[doc(hidden)]
+pub use std::fmt;
+
+/// The reader
+pub use std::io::Read;
+
+/// The writer,
+/// which takes two lines to describe
+pub use std::io::Write;
+
+fn main() {}
+"
+    );
+}
+
+#[test]
+fn block_docs_and_doc_attributes() {
+    let input = "\
+mod inner {
+    /** Block docs */
+    pub use std::io::Read;
+    /// Line docs
+    #[doc(inline)]
+    pub use std::io::Write;
+}
+";
+
+    assert_eq!(
+        fix(input),
+        "\
+mod inner {
+    /// Block docs
+    pub use std::io::Read;
+
+    /// Line docs
+    #[doc(inline)]
+    pub use std::io::Write;
+}
+"
+    );
+}
+
+#[test]
+fn conflicting_docs() {
+    // One side's docs extend the other's, so the longer ones are kept
+    let extended = "\
+<<<<<<< HEAD
+/// The reader
+pub use std::io::Read;
+=======
+/// The reader
+/// which reads
+pub use std::io::Read;
+/// The writer
+pub use std::io::Write;
+>>>>>>> branch
+
+fn main() {}
+";
+
+    assert_eq!(
+        fix(extended),
+        "\
+/// The reader
+/// which reads
+pub use std::io::Read;
+
+/// The writer
+pub use std::io::Write;
+
+fn main() {}
+"
+    );
+
+    // Otherwise, they're combined
+    let different = "\
+<<<<<<< HEAD
+/// Docs from HEAD
+pub use a::X;
+=======
+/// Docs from branch
+pub use a::X;
+>>>>>>> branch
+
+fn main() {}
+";
+
+    assert_eq!(
+        fix(different),
+        "\
+/// Docs from HEAD
+/// Docs from branch
+pub use a::X;
+
+fn main() {}
+"
+    );
+}
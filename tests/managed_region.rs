@@ -0,0 +1,57 @@
+/*!
+Check `--managed-region`: only the use items between the region markers are
+rewritten, and a conflict outside of them is an error rather than being left
+in the file.
+ */
+
+use usefix::{fix_file, Error, MergeOptions};
+
+fn options() -> MergeOptions {
+    MergeOptions::builder()
+        .managed_region(true)
+        .build()
+        .expect("the options are valid")
+}
+
+#[test]
+fn indented_region() {
+    let input = "mod a {\n    \
+                 // usefix:begin\n\
+                 <<<<<<< HEAD\n    \
+                 use std::io;\n\
+                 =======\n    \
+                 use std::env;\n\
+                 >>>>>>> branch\n    \
+                 // usefix:end\n\n    \
+                 fn main() {}\n\
+                 }\n";
+
+    assert_eq!(
+        fix_file(input, &options()).expect("the input can be fixed"),
+        "mod a {\n    \
+         // usefix:begin\n    \
+         use std::{env, io};\n    \
+         // usefix:end\n\n    \
+         fn main() {}\n\
+         }\n"
+    );
+}
+
+#[test]
+fn conflict_outside_region() {
+    // On the first run, only the first section becomes the managed region,
+    // so the conflict in the second section would be left unresolved
+    let input = "use std::fmt;\n\n\
+                 fn f() {}\n\n\
+                 <<<<<<< HEAD\n\
+                 use std::io;\n\
+                 =======\n\
+                 use std::env;\n\
+                 >>>>>>> branch\n\n\
+                 fn main() {}\n";
+
+    match fix_file(input, &options()) {
+        Err(Error::ConflictOutsideRegion { line: 6 }) => {}
+        result => panic!("expected a conflict outside of the region, got {result:?}"),
+    }
+}
@@ -0,0 +1,27 @@
+/*!
+Check that use items split up by `--max-nesting` are still sorted by path,
+even when they're split at different depths.
+ */
+
+use usefix::{fix_file, MergeOptions};
+
+#[test]
+fn mixed_depths() {
+    let options = MergeOptions::from_args(["--max-nesting", "1"]).expect("the options are valid");
+
+    let input = "<<<<<<< HEAD\n\
+                 use a::b::{c::{d, e}, f};\n\
+                 use a::z;\n\
+                 =======\n\
+                 use a::y;\n\
+                 >>>>>>> branch\n\n\
+                 fn main() {}\n";
+
+    assert_eq!(
+        fix_file(input, &options).expect("the input can be fixed"),
+        "use a::b::c::{d, e};\n\
+         use a::b::f;\n\
+         use a::{y, z};\n\n\
+         fn main() {}\n"
+    );
+}
@@ -0,0 +1,25 @@
+/*!
+Check the exact output of `--no-format`, which writes the use items just as
+they're rendered, without a formatter.
+ */
+
+use usefix::{fix_file, MergeOptions};
+
+#[test]
+fn rendered_use_items() {
+    let options = MergeOptions::from_args(["--no-format"]).expect("the options are valid");
+
+    let input = "<<<<<<< HEAD\n\
+                 use std::collections::HashMap;\n\
+                 use std::io::{self, Read};\n\
+                 =======\n\
+                 use std::collections::BTreeMap;\n\
+                 >>>>>>> branch\n\n\
+                 fn main() {}\n";
+
+    assert_eq!(
+        fix_file(input, &options).expect("the input can be fixed"),
+        "use std::{collections::{BTreeMap,HashMap},io::{self,Read}};\n\n\
+         fn main() {}\n"
+    );
+}
@@ -0,0 +1,62 @@
+/*!
+Check that files with nothing to fix are written back byte for byte, without
+any of the normalization usefix does to the files it does fix.
+ */
+
+use usefix::{fix_file, MergeOptions};
+
+fn fix(input: &str) -> String {
+    fix_file(input, &MergeOptions::default()).expect("the input can be fixed")
+}
+
+#[test]
+fn canonical_use_items() {
+    // The blank lines around the use items would be normalized if the file
+    // were rewritten
+    let input = "\n\nuse std::{collections::HashMap, io::{self, Read}};\n\n\
+                 use serde::Deserialize;\n\n\n\nfn main() {}";
+
+    assert_eq!(fix(input), input);
+}
+
+#[test]
+fn canonical_use_items_with_crlf() {
+    let input = "//! Docs\r\n\r\nuse std::io;\r\n\r\n\r\nfn main() {}\r\n\r\n";
+
+    assert_eq!(fix(input), input);
+}
+
+#[test]
+fn conflicts_without_use_items() {
+    let input = "use std::io;\r\n\r\n\
+                 <<<<<<< HEAD\r\n\
+                 fn a() {}\r\n\
+                 ||||||| base\r\n\
+                 =======\r\n\
+                 fn b() {}\r\n\
+                 >>>>>>> branch\r\n\
+                 \r\n\r\n";
+
+    assert_eq!(fix(input), input);
+}
+
+#[test]
+fn no_use_items() {
+    let input = "fn main() {\n\n\n    println!(\"hello\");\n}  \n\n";
+
+    assert_eq!(fix(input), input);
+}
+
+#[test]
+fn changed_use_items_are_normalized() {
+    // The same file as `canonical_use_items`, but with the use items out of
+    // order, is rewritten in full
+    let input = "\n\nuse std::{io::{self, Read}, collections::HashMap};\n\n\
+                 use serde::Deserialize;\n\n\n\nfn main() {}";
+
+    assert_eq!(
+        fix(input),
+        "use std::{collections::HashMap, io::{self, Read}};\n\n\
+         use serde::Deserialize;\n\nfn main() {}"
+    );
+}
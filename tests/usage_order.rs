@@ -0,0 +1,113 @@
+/*!
+Check that multiple usages of one name (`B`, `B as C`, and `B as _`) are
+always written in the same order: the plain usage, then the renames ordered
+by their unescaped aliases, then the anonymous rename, no matter what order
+they appeared in.
+ */
+
+use itertools::Itertools;
+use usefix::{fix_file, MergeOptions};
+
+/// Fix every ordering of the given lines, and check that they all have the
+/// same result, which is returned
+fn fix_every_order(lines: &[&str], options: &MergeOptions) -> String {
+    let mut results = lines.iter().permutations(lines.len()).map(|lines| {
+        let input: String = lines.into_iter().copied().collect();
+        let fixed = fix_file(&input, options).expect("the input can be fixed");
+        (input, fixed)
+    });
+
+    let (first_input, first) = results.next().expect("there's at least one ordering");
+
+    for (input, fixed) in results {
+        assert_eq!(
+            fixed, first,
+            "these inputs were fixed differently:\n{first_input}\n{input}"
+        );
+    }
+
+    first
+}
+
+#[test]
+fn renames() {
+    let fixed = fix_every_order(
+        &[
+            "use a::B as _;\n",
+            "use a::B as r#type;\n",
+            "use a::B as C;\n",
+            "use a::B as d;\n",
+        ],
+        &MergeOptions::default(),
+    );
+
+    assert_eq!(
+        fixed,
+        "use a::B::{self as C, self as d, self as r#type, self as _};\n"
+    );
+}
+
+#[test]
+fn plain_usage_and_renames() {
+    let fixed = fix_every_order(
+        &[
+            "pub use a::B as C;\n",
+            "pub use a::B;\n",
+            "pub use a::B as r#type;\n",
+        ],
+        &MergeOptions::default(),
+    );
+
+    assert_eq!(fixed, "pub use a::B::{self, self as C, self as r#type};\n");
+}
+
+#[test]
+fn self_usages() {
+    let fixed = fix_every_order(
+        &[
+            "use a::{self as _};\n",
+            "use a::b;\n",
+            "use a::{self as x};\n",
+        ],
+        &MergeOptions::default(),
+    );
+
+    assert_eq!(fixed, "use a::{self as x, self as _, b};\n");
+}
+
+#[test]
+fn anonymous_usage_is_subsumed() {
+    let fixed = fix_every_order(
+        &["use a::B as _;\n", "use a::B;\n"],
+        &MergeOptions::default(),
+    );
+
+    assert_eq!(fixed, "use a::B;\n");
+}
+
+#[test]
+fn shadowed_anonymous_usage() {
+    let lines = [
+        "#[cfg(unix)]\nuse a::B;\n",
+        "use a::B as _;\n",
+        "use c::*;\n",
+        "use c::D as _;\n",
+    ];
+
+    // By default, `as _` is only discarded alongside an import with the
+    // same configs
+    assert_eq!(
+        fix_every_order(&lines, &MergeOptions::default()),
+        "use a::B as _;\nuse c::*;\n\n#[cfg(unix)]\nuse a::B;\n"
+    );
+
+    let options = MergeOptions::builder()
+        .discard_shadowed_anonymous(true)
+        .build()
+        .expect("the options are valid");
+
+    assert_eq!(
+        fix_every_order(&lines, &options),
+        "use c::*;\n\n#[cfg(unix)]\nuse a::B;\n"
+    );
+}